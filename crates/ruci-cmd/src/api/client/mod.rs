@@ -15,21 +15,160 @@ pub enum Commands {
     Stop {
         addr: Option<String>,
     },
+
+    /// list connections being relayed, as json
+    Connections {
+        /// only show connections whose cid is not less than this one
+        #[arg(short, long)]
+        from: Option<String>,
+
+        addr: Option<String>,
+    },
+
+    /// close a connection being relayed
+    Kill {
+        cid: String,
+
+        addr: Option<String>,
+    },
+
+    /// show outbounds with their dial results and traffic, as json
+    Outbounds {
+        addr: Option<String>,
+    },
+
+    /// follow connection and traffic events, one json per line
+    Events {
+        /// don't show the traffic rate events sent every second
+        #[arg(long)]
+        no_traffic: bool,
+
+        addr: Option<String>,
+    },
+
+    /// show the running config, as json
+    Config {
+        addr: Option<String>,
+    },
+
+    /// show traffic (ub db) by user, in tag or out tag
+    Traffic {
+        /// user, in or out
+        kind: String,
+
+        /// show all if not given
+        #[arg(short, long)]
+        key: Option<String>,
+
+        addr: Option<String>,
+    },
+
+    /// reset traffic by user, in tag or out tag
+    ResetTraffic {
+        /// user, in or out
+        kind: String,
+
+        /// reset all if not given
+        #[arg(short, long)]
+        key: Option<String>,
+
+        addr: Option<String>,
+    },
+
+    /// list users added at runtime
+    Users {
+        /// plaintext (socks5, http) or trojan
+        kind: String,
+
+        addr: Option<String>,
+    },
+
+    /// add a user at runtime
+    AddUser {
+        /// plaintext (socks5, http) or trojan
+        kind: String,
+
+        /// "user pass" for plaintext, password for trojan
+        user: String,
+
+        addr: Option<String>,
+    },
+
+    /// remove a user added at runtime
+    RemoveUser {
+        /// plaintext (socks5, http) or trojan
+        kind: String,
+
+        /// user name for plaintext, sha224 hex of the password for trojan
+        id: String,
+
+        addr: Option<String>,
+    },
+}
+/// api server 开启 鉴权 时, 以 环境变量 RUCI_API_TOKEN 给出 token;
+/// 使用 自签 证书 的 https 时, 以 RUCI_API_CA 给出 ca 文件
+pub fn http_client() -> Result<reqwest::Client> {
+    let mut b = reqwest::Client::builder();
+    if let Ok(t) = std::env::var("RUCI_API_TOKEN") {
+        let mut v = reqwest::header::HeaderValue::from_str(&format!("Bearer {t}"))
+            .context("invalid RUCI_API_TOKEN")?;
+        v.set_sensitive(true);
+        let mut h = reqwest::header::HeaderMap::new();
+        h.insert(reqwest::header::AUTHORIZATION, v);
+        b = b.default_headers(h);
+    }
+    if let Ok(ca) = std::env::var("RUCI_API_CA") {
+        let pem = std::fs::read(&ca).with_context(|| format!("read RUCI_API_CA {ca} failed"))?;
+        b = b.add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
+    }
+    Ok(b.build()?)
+}
+
+pub fn get_real_addr(addr: Option<String>) -> String {
+    addr.unwrap_or_else(|| String::from("http://") + DEFAULT_API_ADDR)
 }
+async fn timeout_get(ad: String, url: &str) -> Result<reqwest::Response> {
+    timeout_send(http_client()?.get(ad + url)).await
+}
+async fn timeout_send(rb: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+    Ok(tokio::time::timeout(Duration::from_secs(10), rb.send())
+        .await
+        .context("request waiting for too long")??)
+}
+/// 非 2xx 时 返回 [`types::ApiError`] 中 的 信息
+pub async fn get_json<T: serde::de::DeserializeOwned>(ad: String, url: &str) -> Result<T> {
+    let r = timeout_get(ad, url).await?;
+    if !r.status().is_success() {
+        let status = r.status();
+        let e = match r.json::<types::ApiError>().await {
+            Ok(e) => e.error,
+            Err(_) => String::new(),
+        };
+        anyhow::bail!("{url} returned {status}: {e}")
+    }
+    Ok(r.json().await?)
+}
+
+/// 关闭 一个 正在 转发 的 连接
+pub async fn kill_connection(ad: &str, cid: &str) -> Result<()> {
+    let rb = http_client()?.delete(format!("{ad}/v1/connections/{cid}"));
+
+    let response = timeout_send(rb).await?;
+    if !response.status().is_success() {
+        let e: types::ApiError = response.json().await?;
+        anyhow::bail!(e.error)
+    }
+    Ok(())
+}
+
 pub async fn deal_cmds(command: Option<Commands>) -> anyhow::Result<()> {
     let cmd = match command {
         Some(c) => c,
         None => return Ok(()),
     };
-    fn get_real_addr(addr: Option<String>) -> String {
-        addr.unwrap_or_else(|| String::from("http://") + DEFAULT_API_ADDR)
-    }
-    async fn timeout_get(ad: String, url: &str) -> Result<reqwest::Response> {
-        Ok(
-            tokio::time::timeout(Duration::from_secs(10), reqwest::get(ad + url))
-                .await
-                .context("request waiting for too long")??,
-        )
+    fn print_json<T: serde::Serialize>(v: &T) -> Result<()> {
+        println!("{}", serde_json::to_string_pretty(v)?);
+        Ok(())
     }
 
     match cmd {
@@ -45,9 +184,138 @@ pub async fn deal_cmds(command: Option<Commands>) -> anyhow::Result<()> {
 
             let response = timeout_get(ad, "/stop_core").await?;
 
+            println!("response:{}", response.text().await?)
+        }
+        Commands::Connections { from, addr } => {
+            let ad = get_real_addr(addr);
+            let url = match from {
+                Some(f) => format!("/v1/connections?from={f}"),
+                None => String::from("/v1/connections"),
+            };
+
+            let v: Vec<types::Connection> = get_json(ad, &url).await?;
+
+            print_json(&v)?
+        }
+        Commands::Kill { cid, addr } => {
+            let ad = get_real_addr(addr);
+            kill_connection(&ad, &cid).await?;
+            println!("killed {cid}")
+        }
+        Commands::Events { no_traffic, addr } => {
+            let ad = get_real_addr(addr);
+
+            watch_events(&ad, |e| {
+                if !(no_traffic && matches!(e, types::Event::Traffic(_))) {
+                    match serde_json::to_string(&e) {
+                        Ok(s) => println!("{s}"),
+                        Err(e) => eprintln!("{e}"),
+                    }
+                }
+                true
+            })
+            .await?
+        }
+        Commands::Outbounds { addr } => {
+            let ad = get_real_addr(addr);
+
+            let v: Vec<types::Outbound> = get_json(ad, "/v1/outbounds").await?;
+
+            print_json(&v)?
+        }
+        Commands::Config { addr } => {
+            let ad = get_real_addr(addr);
+
+            let c: types::Config = get_json(ad, "/v1/config").await?;
+
+            print_json(&c)?
+        }
+        Commands::Traffic { kind, key, addr } => {
+            let ad = get_real_addr(addr);
+
+            let t: types::Traffic = get_json(ad, "/v1/traffic").await?;
+
+            let m = t.by_kind(&kind).with_context(|| {
+                format!("unknown traffic kind {kind}, should be user, in or out")
+            })?;
+            match key {
+                Some(k) => match m.get(&k) {
+                    Some(b) => println!("{} {}", b.up, b.down),
+                    None => println!("None"),
+                },
+                None => {
+                    for (k, b) in m {
+                        println!("{k} {} {}", b.up, b.down)
+                    }
+                }
+            }
+        }
+        Commands::ResetTraffic { kind, key, addr } => {
+            let ad = get_real_addr(addr);
+            let url = match key {
+                Some(k) => format!("/t_reset/{kind}/{k}"),
+                None => format!("/t_reset/{kind}"),
+            };
+
+            let response = timeout_get(ad, &url).await?;
+
+            println!("response:{}", response.text().await?)
+        }
+        Commands::Users { kind, addr } => {
+            let ad = get_real_addr(addr);
+
+            let response = timeout_get(ad, &format!("/users/{kind}")).await?;
+
+            println!("{}", response.text().await?)
+        }
+        Commands::AddUser { kind, user, addr } => {
+            let ad = get_real_addr(addr);
+            let rb = http_client()?.post(format!("{ad}/users/{kind}")).body(user);
+
+            let response = timeout_send(rb).await?;
+
+            println!("response:{}", response.text().await?)
+        }
+        Commands::RemoveUser { kind, id, addr } => {
+            let ad = get_real_addr(addr);
+            let rb = http_client()?.delete(format!("{ad}/users/{kind}/{id}"));
+
+            let response = timeout_send(rb).await?;
+
             println!("response:{}", response.text().await?)
         }
     };
 
     Ok(())
 }
+
+/// 读取 /v1/events, 直到 f 返回 false 或 连接 断开
+pub async fn watch_events(ad: &str, mut f: impl FnMut(types::Event) -> bool) -> Result<()> {
+    let mut r = http_client()?
+        .get(format!("{ad}/v1/events"))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let mut buf = Vec::new();
+    while let Some(chunk) = r.chunk().await? {
+        buf.extend_from_slice(&chunk);
+
+        // sse 每行 一个 字段, 只关心 data
+        while let Some(i) = buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buf.drain(..=i).collect();
+            let Some(data) = line.strip_prefix(b"data:") else {
+                continue;
+            };
+            match serde_json::from_slice::<types::Event>(data) {
+                Ok(e) => {
+                    if !f(e) {
+                        return Ok(());
+                    }
+                }
+                Err(e) => tracing::warn!("bad event: {e}"),
+            }
+        }
+    }
+    Ok(())
+}
@@ -2,6 +2,7 @@
 pub mod client;
 #[cfg(feature = "api_server")]
 pub mod server;
+pub mod types;
 
 use super::*;
 
@@ -0,0 +1,299 @@
+/*!
+api server 的 鉴权
+
+token 可 以 `Authorization: Bearer <token>` 给出, 也可 以 http basic auth 给出,
+此时 "user:pass" 整体 作为 token.
+
+admin token 可 访问 所有 api; read token 只能 访问 不改变 状态 的 api, 见 [`needs_admin`].
+两种 token 都 没有 配置 时 不 鉴权.
+*/
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::prelude::*;
+
+use crate::api::types::ApiError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Read,
+    Admin,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ApiAuth {
+    pub admin_tokens: Vec<String>,
+    pub read_tokens: Vec<String>,
+}
+
+/// 不 依赖 于 第一个 不同 字节 的 位置 的 比较
+fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// 从 Authorization 头 中 取出 token
+fn token_of(value: &str) -> Option<String> {
+    let (scheme, v) = value.split_once(' ')?;
+    let v = v.trim();
+    if scheme.eq_ignore_ascii_case("bearer") {
+        Some(v.to_string())
+    } else if scheme.eq_ignore_ascii_case("basic") {
+        let b = BASE64_STANDARD.decode(v).ok()?;
+        String::from_utf8(b).ok()
+    } else {
+        None
+    }
+}
+
+/// 改变 状态 的 api. 注意 一些 旧的 api 用 GET 改变 状态
+pub fn needs_admin(method: &Method, path: &str) -> bool {
+    if method != Method::GET && method != Method::HEAD {
+        return true;
+    }
+    ["/stop_core", "/t_reset/", "/m_on", "/m_off"]
+        .iter()
+        .any(|p| path.starts_with(p))
+}
+
+impl ApiAuth {
+    pub fn is_enabled(&self) -> bool {
+        !self.admin_tokens.is_empty() || !self.read_tokens.is_empty()
+    }
+
+    fn role_of(&self, token: &str) -> Option<Role> {
+        let t = token.as_bytes();
+        if self.admin_tokens.iter().any(|a| ct_eq(a.as_bytes(), t)) {
+            return Some(Role::Admin);
+        }
+        if self.read_tokens.iter().any(|r| ct_eq(r.as_bytes(), t)) {
+            return Some(Role::Read);
+        }
+        None
+    }
+
+    /// 返回 拒绝 的 原因
+    fn check(
+        &self,
+        method: &Method,
+        path: &str,
+        authorization: Option<&str>,
+    ) -> Result<(), (StatusCode, &'static str)> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let role = authorization
+            .and_then(token_of)
+            .and_then(|t| self.role_of(&t))
+            .ok_or((StatusCode::UNAUTHORIZED, "unauthorized"))?;
+
+        if role != Role::Admin && needs_admin(method, path) {
+            return Err((StatusCode::FORBIDDEN, "admin token required"));
+        }
+        Ok(())
+    }
+}
+
+pub async fn check(State(auth): State<Arc<ApiAuth>>, req: Request, next: Next) -> Response {
+    let authorization = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+
+    match auth.check(req.method(), req.uri().path(), authorization) {
+        Ok(_) => next.run(req).await,
+        Err((code, e)) => {
+            let mut r = (
+                code,
+                Json(ApiError {
+                    error: e.to_string(),
+                }),
+            )
+                .into_response();
+            if code == StatusCode::UNAUTHORIZED {
+                r.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    header::HeaderValue::from_static("Basic realm=\"ruci\""),
+                );
+            }
+            r
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn auth() -> ApiAuth {
+        ApiAuth {
+            admin_tokens: vec!["a".to_string()],
+            read_tokens: vec!["r".to_string(), "u:p".to_string()],
+        }
+    }
+
+    #[test]
+    fn token() {
+        assert_eq!(token_of("Bearer abc").as_deref(), Some("abc"));
+        assert_eq!(token_of("bearer  abc ").as_deref(), Some("abc"));
+        let basic = format!("Basic {}", BASE64_STANDARD.encode("u:p"));
+        assert_eq!(token_of(&basic).as_deref(), Some("u:p"));
+
+        for v in ["", "abc", "Bearer", "Token abc", "Basic !!!", "Basic //8="] {
+            assert_eq!(token_of(v), None, "{v}");
+        }
+    }
+
+    #[test]
+    fn check() {
+        let a = auth();
+        let bearer = |t: &str| format!("Bearer {t}");
+
+        for h in [
+            None,
+            Some("Bearer x".to_string()),
+            Some("Basic !!!".to_string()),
+        ] {
+            assert_eq!(
+                a.check(&Method::GET, "/gt/u", h.as_deref()).unwrap_err().0,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+
+        let read = bearer("r");
+        let read = Some(read.as_str());
+        assert!(a.check(&Method::GET, "/gt/u", read).is_ok());
+        let basic = format!("Basic {}", BASE64_STANDARD.encode("u:p"));
+        assert!(a.check(&Method::GET, "/v1/traffic", Some(&basic)).is_ok());
+
+        let admin = bearer("a");
+        let admin = Some(admin.as_str());
+        for (m, p) in [
+            (Method::POST, "/users/plaintext/s"),
+            (Method::DELETE, "/v1/connections/1"),
+            (Method::GET, "/stop_core"),
+            (Method::GET, "/t_reset/user"),
+            (Method::GET, "/t_reset/user/u1"),
+            (Method::GET, "/m_on"),
+            (Method::GET, "/m_off"),
+        ] {
+            assert_eq!(
+                a.check(&m, p, read).unwrap_err().0,
+                StatusCode::FORBIDDEN,
+                "{m} {p}"
+            );
+            assert!(a.check(&m, p, admin).is_ok(), "{m} {p}");
+        }
+
+        assert!(ApiAuth::default()
+            .check(&Method::POST, "/stop_core", None)
+            .is_ok());
+    }
+
+    /// 只读 的 GET api. 新加 的 GET api 须 加到 这里, 或 在 [`needs_admin`] 中 要求 admin
+    const READ_ONLY_GETS: &[&str] = &[
+        "/gt/acc",
+        "/gt/lci",
+        "/metrics",
+        "/gt/u",
+        "/gt/d",
+        "/t/:kind",
+        "/t/:kind/:key",
+        "/users/:kind",
+        "/users/:kind/:store",
+        "/all_c",
+        "/gt/loci",
+        "/cr/:cid",
+        "/cc",
+        "/c/:cid",
+        "/m",
+        "/d/:cid",
+        "/u/:cid",
+        "/v1/connections",
+        "/v1/connections/:cid",
+        "/v1/traffic",
+        "/v1/outbounds",
+        "/v1/config",
+        "/v1/events",
+        "/v1/openapi.json",
+    ];
+
+    /// 从 源码 中 找出 所有 `.route("path", method(..)...)`, 返回 path 与 其 方法
+    fn routes_in(src: &str, prefix: &str) -> Vec<(String, Vec<Method>)> {
+        let mut v = Vec::new();
+        for part in src.split("route(").skip(1) {
+            let part = part.trim_start();
+            let Some(part) = part.strip_prefix('"') else {
+                continue;
+            };
+            let (path, rest) = part.split_once('"').unwrap();
+            let rest = rest.trim_start().trim_start_matches(',');
+
+            // 本 route 调用 的 参数, 到 配对的 ')' 为止
+            let mut depth = 1;
+            let end = rest
+                .char_indices()
+                .find(|(_, c)| {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    depth == 0
+                })
+                .map(|(i, _)| i)
+                .unwrap();
+            let args = rest[..end].trim_start();
+
+            let mut methods = Vec::new();
+            for (f, m) in [
+                ("get(", Method::GET),
+                ("post(", Method::POST),
+                ("put(", Method::PUT),
+                ("patch(", Method::PATCH),
+                ("delete(", Method::DELETE),
+            ] {
+                if args.starts_with(f) || args.contains(&format!(".{f}")) {
+                    methods.push(m);
+                }
+            }
+            assert!(!methods.is_empty(), "unknown method of {path}: {args}");
+            v.push((format!("{prefix}{path}"), methods));
+        }
+        v
+    }
+
+    #[test]
+    fn registered_routes() {
+        let mut routes = routes_in(include_str!("mod.rs"), "");
+        routes.extend(routes_in(include_str!("v1.rs"), "/v1"));
+        for p in READ_ONLY_GETS {
+            assert!(routes.iter().any(|(r, _)| r == p), "{p} is not registered");
+        }
+
+        for (path, methods) in routes {
+            let concrete = path
+                .split('/')
+                .map(|s| if s.starts_with(':') { "x" } else { s })
+                .collect::<Vec<_>>()
+                .join("/");
+            for m in methods {
+                let read_only = m == Method::GET && READ_ONLY_GETS.contains(&path.as_str());
+                assert_eq!(
+                    needs_admin(&m, &concrete),
+                    !read_only,
+                    "{m} {path}: a new GET api must be added to READ_ONLY_GETS or needs_admin"
+                );
+            }
+        }
+    }
+}
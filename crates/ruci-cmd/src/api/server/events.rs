@@ -0,0 +1,238 @@
+/*!
+/v1/events, 以 server-sent events 推送 新连接, 连接关闭 与 每秒 的 流量 速率
+
+新连接 来自 engine 的 new_conn_recorder, 关闭 来自
+[`ruci::relay::ActiveConns::subscribe_closed`], 速率 由 正在 转发 的 连接 的 计数 算出
+*/
+
+use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
+
+use axum::{
+    extract::State,
+    response::sse::{self, KeepAlive, Sse},
+};
+use futures::Stream;
+use ruci::{
+    net::{GlobalTrafficRecorder, CID},
+    relay::NewConnInfo,
+};
+use tokio::sync::broadcast;
+
+use crate::api::types::{self, Event};
+
+pub const TRAFFIC_EVENT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+pub struct Events {
+    tx: broadcast::Sender<Event>,
+}
+
+impl Events {
+    /// 启动 连接关闭 与 流量速率 的 推送
+    pub fn new(gtr: Arc<GlobalTrafficRecorder>) -> Self {
+        let (tx, _) = broadcast::channel(1024);
+        let s = Events { tx };
+        s.spawn_closed(gtr.clone());
+        s.spawn_traffic(gtr);
+        s
+    }
+
+    /// 没有 订阅者 时 丢弃
+    pub fn send(&self, e: Event) {
+        let _ = self.tx.send(e);
+    }
+
+    pub fn send_new_conn(&self, info: &NewConnInfo) {
+        if self.tx.receiver_count() == 0 {
+            return;
+        }
+        self.send(Event::NewConnection(types::Connection {
+            cid: info.cid.to_string(),
+            time: chrono::Utc::now().to_rfc3339(),
+            in_tag: info.in_tag.clone(),
+            out_tag: info.out_tag.clone(),
+            target_addr: info.target_addr.to_string(),
+            user: info.user.clone(),
+            bytes: types::Bytes::default(),
+        }))
+    }
+
+    fn spawn_closed(&self, gtr: Arc<GlobalTrafficRecorder>) {
+        let mut rx = gtr.active_conns.subscribe_closed();
+        let s = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(c) => s.send(Event::ClosedConnection {
+                        cid: c.info.cid.to_string(),
+                        bytes: c.traffic.get().into(),
+                    }),
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// 没有 订阅者 时 也 更新 各连接 的 基准, 只是 不发送, 否则 订阅 后 的
+    /// 第一个 事件 会 把 连接 的 总流量 当作 一秒 的 速率
+    fn spawn_traffic(&self, gtr: Arc<GlobalTrafficRecorder>) {
+        let s = self.clone();
+        tokio::spawn(async move {
+            let mut last: HashMap<CID, (u64, u64)> = HashMap::new();
+            let mut interval = tokio::time::interval(TRAFFIC_EVENT_INTERVAL);
+            loop {
+                interval.tick().await;
+                let conns = gtr.active_conns.list(None);
+                let rate = traffic_rate(
+                    conns.iter().map(|c| (&c.info.cid, c.traffic.get())),
+                    &mut last,
+                    TRAFFIC_EVENT_INTERVAL.as_secs_f64(),
+                );
+                if s.tx.receiver_count() > 0 {
+                    s.send(Event::Traffic(rate));
+                }
+            }
+        });
+    }
+}
+
+/// 由 各连接 当前 的 (上传, 下载) 计数 与 上次 的 计数 算出 每秒 速率, 并 用 当前
+/// 计数 替换 last. 不在 last 中 的 连接 从 0 算起, 速率 为 0 的 连接 不列出
+fn traffic_rate<'a>(
+    conns: impl Iterator<Item = (&'a CID, (u64, u64))>,
+    last: &mut HashMap<CID, (u64, u64)>,
+    secs: f64,
+) -> types::TrafficRate {
+    let mut rate = types::TrafficRate::default();
+    let mut now = HashMap::with_capacity(last.len());
+    for (cid, (u, d)) in conns {
+        let (lu, ld) = last.get(cid).copied().unwrap_or_default();
+        let b = types::Bytes {
+            up: (u.saturating_sub(lu) as f64 / secs) as u64,
+            down: (d.saturating_sub(ld) as f64 / secs) as u64,
+        };
+        rate.total.up += b.up;
+        rate.total.down += b.down;
+        if b != types::Bytes::default() {
+            rate.connections.insert(cid.to_string(), b);
+        }
+        now.insert(cid.clone(), (u, d));
+    }
+    rate.alive_connections = now.len();
+    *last = now;
+    rate
+}
+
+pub async fn events(
+    State(s): State<Events>,
+) -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>> {
+    let rx = s.tx.subscribe();
+    let stream = futures::stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(e) => {
+                    let se = sse::Event::default()
+                        .event(e.name())
+                        .json_data(&e)
+                        .unwrap_or_default();
+                    return Some((Ok(se), rx));
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::Ordering;
+
+    use ruci::net::{self, traffic::Traffic};
+
+    use super::*;
+
+    fn cid(s: &str) -> CID {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn rate_delta() {
+        let (c1, c2) = (cid("1"), cid("2"));
+        let mut last = HashMap::new();
+
+        // 新 连接 从 0 算起
+        let r = traffic_rate([(&c1, (100, 200))].into_iter(), &mut last, 1.0);
+        assert_eq!(r.alive_connections, 1);
+        assert_eq!(r.total, types::Bytes { up: 100, down: 200 });
+
+        let r = traffic_rate(
+            [(&c1, (300, 200)), (&c2, (10, 20))].into_iter(),
+            &mut last,
+            2.0,
+        );
+        assert_eq!(r.alive_connections, 2);
+        assert_eq!(r.total, types::Bytes { up: 105, down: 10 });
+        assert_eq!(r.connections["1"], types::Bytes { up: 100, down: 0 });
+        assert_eq!(r.connections["2"], types::Bytes { up: 5, down: 10 });
+
+        // 没有 变化 的 连接 不列出, 关闭 的 连接 从 last 中 移除
+        let r = traffic_rate([(&c2, (10, 20))].into_iter(), &mut last, 1.0);
+        assert_eq!(r.alive_connections, 1);
+        assert!(r.connections.is_empty());
+        assert_eq!(r.total, types::Bytes::default());
+        assert_eq!(last.len(), 1);
+    }
+
+    async fn next_traffic(rx: &mut broadcast::Receiver<Event>) -> types::TrafficRate {
+        loop {
+            let e = tokio::time::timeout(TRAFFIC_EVENT_INTERVAL * 3, rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if let Event::Traffic(r) = e {
+                return r;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn first_subscriber() {
+        let gtr = Arc::new(GlobalTrafficRecorder::default());
+        let traffic = Arc::new(Traffic::default());
+        traffic.ub.store(1_000_000, Ordering::Relaxed);
+        let _shutdown_rx = gtr.active_conns.register(
+            ruci::relay::NewConnInfo {
+                cid: cid("1"),
+                in_tag: "in".to_string(),
+                out_tag: "out".to_string(),
+                target_addr: net::Addr::from_network_addr_url("127.0.0.1:80").unwrap(),
+                user: None,
+
+                #[cfg(feature = "trace")]
+                in_trace: Vec::new(),
+
+                #[cfg(feature = "trace")]
+                out_trace: Vec::new(),
+            },
+            traffic.clone(),
+        );
+
+        let events = Events::new(gtr);
+
+        // 第一次 tick 在 没有 订阅者 时 就 记下 了 基准
+        tokio::time::sleep(TRAFFIC_EVENT_INTERVAL / 4).await;
+        let mut rx = events.tx.subscribe();
+
+        let r = next_traffic(&mut rx).await;
+        assert_eq!(r.alive_connections, 1);
+        assert_eq!(r.total, types::Bytes::default());
+
+        traffic.ub.fetch_add(500, Ordering::Relaxed);
+        let r = next_traffic(&mut rx).await;
+        assert_eq!(r.total.up, 500);
+        assert_eq!(r.connections["1"].up, 500);
+    }
+}
@@ -0,0 +1,281 @@
+/*!
+以 prometheus text format (0.0.4) 导出 [`GlobalTrafficRecorder`] 中 的 数据
+*/
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{atomic::Ordering, Arc},
+};
+
+use axum::{extract::State, http::header, response::IntoResponse};
+use ruci::net::{metrics::HistogramSnapshot, traffic::TrafficMap, GlobalTrafficRecorder};
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn header(s: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(s, "# HELP {name} {help}");
+    let _ = writeln!(s, "# TYPE {name} {kind}");
+}
+
+fn traffic(s: &mut String, name: &str, label: &str, help: &str, tm: &TrafficMap) {
+    header(s, name, "counter", help);
+    for (k, (u, d)) in tm.snapshot() {
+        let k = escape(&k);
+        let _ = writeln!(s, "{name}{{{label}=\"{k}\",direction=\"up\"}} {u}");
+        let _ = writeln!(s, "{name}{{{label}=\"{k}\",direction=\"down\"}} {d}");
+    }
+}
+
+fn failures(s: &mut String, side: &str, m: BTreeMap<String, u64>) {
+    for (k, v) in m {
+        let _ = writeln!(
+            s,
+            "ruci_handshake_failures_total{{side=\"{side}\",map=\"{}\"}} {v}",
+            escape(&k)
+        );
+    }
+}
+
+fn histogram(s: &mut String, name: &str, side: &str, h: HistogramSnapshot) {
+    let (buckets, sum, count) = h;
+    for (b, c) in buckets {
+        let le = if b.is_infinite() {
+            String::from("+Inf")
+        } else {
+            b.to_string()
+        };
+        let _ = writeln!(s, "{name}_bucket{{side=\"{side}\",le=\"{le}\"}} {c}");
+    }
+    let _ = writeln!(s, "{name}_sum{{side=\"{side}\"}} {sum}");
+    let _ = writeln!(s, "{name}_count{{side=\"{side}\"}} {count}");
+}
+
+pub fn render(gtr: &GlobalTrafficRecorder) -> String {
+    let mut s = String::new();
+
+    header(
+        &mut s,
+        "ruci_connections_alive",
+        "gauge",
+        "alive connections",
+    );
+    let _ = writeln!(
+        s,
+        "ruci_connections_alive {}",
+        gtr.alive_connection_count.load(Ordering::Relaxed)
+    );
+    header(
+        &mut s,
+        "ruci_connections_total",
+        "counter",
+        "accepted connections since start",
+    );
+    let _ = writeln!(
+        s,
+        "ruci_connections_total {}",
+        gtr.last_connection_id.load(Ordering::Relaxed)
+    );
+
+    header(&mut s, "ruci_bytes_total", "counter", "total bytes");
+    let _ = writeln!(
+        s,
+        "ruci_bytes_total{{direction=\"up\"}} {}",
+        gtr.ub.load(Ordering::Relaxed)
+    );
+    let _ = writeln!(
+        s,
+        "ruci_bytes_total{{direction=\"down\"}} {}",
+        gtr.db.load(Ordering::Relaxed)
+    );
+    traffic(
+        &mut s,
+        "ruci_user_bytes_total",
+        "user",
+        "bytes by user",
+        &gtr.user_traffic,
+    );
+    traffic(
+        &mut s,
+        "ruci_inbound_bytes_total",
+        "tag",
+        "bytes by inbound tag",
+        &gtr.in_tag_traffic,
+    );
+    traffic(
+        &mut s,
+        "ruci_outbound_bytes_total",
+        "tag",
+        "bytes by outbound tag",
+        &gtr.out_tag_traffic,
+    );
+
+    let m = &gtr.metrics;
+    header(
+        &mut s,
+        "ruci_handshake_failures_total",
+        "counter",
+        "failed handshakes by the name of the failed map",
+    );
+    failures(&mut s, "in", m.in_failures.snapshot());
+    failures(&mut s, "out", m.out_failures.snapshot());
+
+    header(
+        &mut s,
+        "ruci_fold_duration_seconds",
+        "histogram",
+        "time spent folding the inbound or outbound chain",
+    );
+    histogram(
+        &mut s,
+        "ruci_fold_duration_seconds",
+        "in",
+        m.in_fold_latency.snapshot(),
+    );
+    histogram(
+        &mut s,
+        "ruci_fold_duration_seconds",
+        "out",
+        m.out_fold_latency.snapshot(),
+    );
+
+    let obs = m.outbounds();
+    header(
+        &mut s,
+        "ruci_outbound_dials_total",
+        "counter",
+        "outbound folds by result",
+    );
+    for (k, (ok, failed, _)) in &obs {
+        let k = escape(k);
+        let _ = writeln!(
+            s,
+            "ruci_outbound_dials_total{{tag=\"{k}\",result=\"ok\"}} {ok}"
+        );
+        let _ = writeln!(
+            s,
+            "ruci_outbound_dials_total{{tag=\"{k}\",result=\"error\"}} {failed}"
+        );
+    }
+    header(
+        &mut s,
+        "ruci_outbound_up",
+        "gauge",
+        "1 if the last outbound fold succeeded",
+    );
+    for (k, (_, _, up)) in &obs {
+        let _ = writeln!(s, "ruci_outbound_up{{tag=\"{}\"}} {}", escape(k), *up as u8);
+    }
+    s
+}
+
+pub async fn get_metrics(State(gtr): State<Arc<GlobalTrafficRecorder>>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], render(&gtr))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeSet, sync::atomic::Ordering, time::Duration};
+
+    use ruci::net::metrics::FOLD_LATENCY_BOUNDS;
+
+    use super::*;
+
+    fn filled() -> GlobalTrafficRecorder {
+        let g = GlobalTrafficRecorder::default();
+        g.ub.store(100, Ordering::Relaxed);
+        g.db.store(200, Ordering::Relaxed);
+        for (k, u) in [("u1", 1), ("a\"b\\c", 2)] {
+            g.user_traffic
+                .get_or_insert(k)
+                .ub
+                .store(u, Ordering::Relaxed);
+        }
+        g.in_tag_traffic.get_or_insert("in1");
+        g.out_tag_traffic.get_or_insert("out\"1");
+
+        let m = &g.metrics;
+        m.in_failures.inc("Socks5");
+        m.out_failures.inc("Trojan\\x");
+        for ms in [1, 1, 30, 700, 20_000] {
+            m.in_fold_latency.observe(Duration::from_millis(ms));
+        }
+        m.out_fold_latency.observe(Duration::from_millis(3));
+        m.record_outbound("direct", true);
+        m.record_outbound("px", false);
+        g
+    }
+
+    #[test]
+    fn families() {
+        let s = render(&filled());
+
+        let mut helps = BTreeSet::new();
+        let mut types = BTreeSet::new();
+        for l in s.lines() {
+            if let Some(r) = l.strip_prefix("# HELP ") {
+                let name = r.split(' ').next().unwrap();
+                assert!(helps.insert(name.to_string()), "HELP {name} twice");
+            } else if let Some(r) = l.strip_prefix("# TYPE ") {
+                let name = r.split(' ').next().unwrap();
+                assert!(types.insert(name.to_string()), "TYPE {name} twice");
+            } else {
+                // 每个 样本 都 属于 之前 声明 过 的 family
+                let name = l.split(['{', ' ']).next().unwrap();
+                let family = ["_bucket", "_sum", "_count"]
+                    .iter()
+                    .find_map(|s| name.strip_suffix(s).filter(|f| types.contains(*f)))
+                    .unwrap_or(name);
+                assert!(types.contains(family), "{l}");
+            }
+        }
+        assert_eq!(helps, types);
+        assert_eq!(types.len(), 10);
+    }
+
+    #[test]
+    fn buckets() {
+        let s = render(&filled());
+        for (side, count) in [("in", 5), ("out", 1)] {
+            let prefix = format!("ruci_fold_duration_seconds_bucket{{side=\"{side}\",le=\"");
+            let bs: Vec<(&str, u64)> = s
+                .lines()
+                .filter_map(|l| l.strip_prefix(&prefix))
+                .map(|r| {
+                    let (le, v) = r.split_once("\"} ").unwrap();
+                    (le, v.parse().unwrap())
+                })
+                .collect();
+            assert_eq!(bs.len(), FOLD_LATENCY_BOUNDS.len() + 1);
+            assert!(bs.windows(2).all(|w| w[0].1 <= w[1].1), "{bs:?}");
+            assert_eq!(bs.last(), Some(&("+Inf", count)));
+            assert!(s.contains(&format!(
+                "ruci_fold_duration_seconds_count{{side=\"{side}\"}} {count}"
+            )));
+        }
+        // 20s 的 一次 只在 +Inf 中
+        assert!(s.contains("ruci_fold_duration_seconds_bucket{side=\"in\",le=\"10\"} 4"));
+        assert!(s.contains("ruci_fold_duration_seconds_bucket{side=\"in\",le=\"0.005\"} 2"));
+    }
+
+    #[test]
+    fn escaped() {
+        let s = render(&filled());
+        for l in [
+            r#"ruci_user_bytes_total{user="a\"b\\c",direction="up"} 2"#,
+            r#"ruci_outbound_bytes_total{tag="out\"1",direction="down"} 0"#,
+            r#"ruci_handshake_failures_total{side="out",map="Trojan\\x"} 1"#,
+            r#"ruci_outbound_up{tag="px"} 0"#,
+            r#"ruci_outbound_dials_total{tag="direct",result="ok"} 1"#,
+        ] {
+            assert!(s.lines().any(|x| x == l), "{l} not in\n{s}");
+        }
+        assert_eq!(escape("a\nb"), "a\\nb");
+    }
+}
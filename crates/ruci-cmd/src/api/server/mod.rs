@@ -1,7 +1,11 @@
+pub mod auth;
+pub mod events;
 mod folder_serve;
+mod metrics;
+mod v1;
 
 use std::{
-    collections::BTreeMap,
+    path::PathBuf,
     sync::{atomic::Ordering, Arc},
 };
 
@@ -12,13 +16,15 @@ use chrono::{DateTime, Utc};
 
 use parking_lot::RwLock;
 use ruci::{
-    net::{GlobalTrafficRecorder, CID},
-    relay::NewConnInfo,
+    map::trojan::{self, TROJAN_USERS},
+    net::{traffic::TrafficMap, GlobalTrafficRecorder, CID},
+    relay::ActiveConn,
+    user,
 };
 #[cfg(feature = "trace")]
 use tinyufo::TinyUfo;
 use tokio::sync::mpsc;
-use tracing::info;
+use tracing::{debug, info, warn};
 
 use super::*;
 
@@ -36,13 +42,36 @@ pub async fn deal_args(
     args: &crate::Args,
 ) -> Option<(Server, mpsc::Receiver<()>, Arc<GlobalTrafficRecorder>)> {
     match cmd {
-        Command::Run => return Some(Server::new(args.api_addr.clone()).await),
+        Command::Run => {
+            let listen = ListenOptions {
+                addr: args.api_addr.clone(),
+                auth: auth::ApiAuth {
+                    admin_tokens: args.api_token.clone(),
+                    read_tokens: args.api_read_token.clone(),
+                },
+                tls: args.api_cert.clone().zip(args.api_key.clone()),
+            };
+            return Some(Server::new(listen).await);
+        }
         Command::FileServer => folder_serve::serve_static(args.file_server_addr.clone()).await,
     }
     None
 }
 
-type NewConnInfoMap = Arc<RwLock<BTreeMap<CID, (DateTime<Utc>, NewConnInfo)>>>;
+/// api server 的 监听 选项
+#[derive(Debug, Clone, Default)]
+pub struct ListenOptions {
+    /// default is [`DEFAULT_API_ADDR`]
+    pub addr: Option<String>,
+
+    pub auth: auth::ApiAuth,
+
+    /// cert 与 key 文件, 给出 时 使用 https
+    pub tls: Option<(PathBuf, PathBuf)>,
+}
+
+/// 最近 一个 开始 转发 的 连接 的 cid, 见 /gt/loci
+type LastOkCid = Arc<RwLock<Option<CID>>>;
 
 /// 缓存 某cid的 某时间点的流量
 #[cfg(feature = "trace")]
@@ -64,12 +93,18 @@ pub struct TracePart {
 }
 
 pub struct Server {
-    listen_addr: Option<String>,
+    listen: ListenOptions,
 
     //pub global_traffic: Arc<ruci::net::GlobalTrafficRecorder>,
     pub close_tx: mpsc::Sender<()>,
 
-    pub new_conn_info_map: NewConnInfoMap,
+    pub last_ok_cid: LastOkCid,
+
+    /// 由 engine 在 启动时 填写, 见 /v1/config
+    pub config: Arc<RwLock<types::Config>>,
+
+    /// 见 /v1/events
+    pub events: events::Events,
 
     #[cfg(feature = "trace")]
     pub flux_trace: TracePart,
@@ -78,13 +113,16 @@ pub struct Server {
 impl Server {
     /// non-blocking, init the server and run it
     pub async fn new(
-        listen_addr: Option<String>,
+        listen: ListenOptions,
     ) -> (Self, mpsc::Receiver<()>, Arc<GlobalTrafficRecorder>) {
         let (tx, rx) = mpsc::channel(10);
+        let global_traffic = Arc::new(GlobalTrafficRecorder::default());
         let s = Server {
-            listen_addr,
+            listen,
             close_tx: tx,
-            new_conn_info_map: Arc::new(RwLock::new(BTreeMap::new())),
+            last_ok_cid: Arc::default(),
+            config: Arc::default(),
+            events: events::Events::new(global_traffic.clone()),
 
             #[cfg(feature = "trace")]
             flux_trace: TracePart {
@@ -93,14 +131,16 @@ impl Server {
                 d_cache: new_cache(),
             },
         };
-        let global_traffic = Arc::new(GlobalTrafficRecorder::default());
         serve(&s, global_traffic.clone()).await;
         (s, rx, global_traffic)
     }
 }
 
 use axum::extract::{Path, State};
-use axum::{routing::get, Router};
+use axum::{
+    routing::{delete, get},
+    Router,
+};
 
 #[cfg(feature = "trace")]
 async fn is_monitoring_flux(State(is_monitoring_flux): State<Arc<AtomicBool>>) -> String {
@@ -117,23 +157,24 @@ async fn disable_monitor(State(is_monitoring_flux): State<Arc<AtomicBool>>) -> &
     "ok"
 }
 
-async fn get_conn_infos(State(all_conn): State<NewConnInfoMap>) -> String {
-    let mut s = String::new();
-    let m = all_conn.read();
-    for i in m.iter() {
-        let x = i.1 .0.to_string();
-        s.push_str(&x);
-        s.push_str(" , ");
-        let x = i.1 .1.to_string();
-        s.push_str(&x);
-        s.push('\n')
+/// "时间 , 连接信息", 时间 为 连接 开始 转发 的 时间
+fn conn_info_line(c: &ActiveConn) -> String {
+    format!("{} , {}", DateTime::<Utc>::from(c.start), c.info)
+}
+
+/// 连接 信息 都 来自 [`ruci::relay::ActiveConns`], 只含 正在 转发 的 连接
+async fn get_conn_infos(State(s): State<Arc<GlobalTrafficRecorder>>) -> String {
+    let mut r = String::new();
+    for c in s.active_conns.list(None) {
+        r.push_str(&conn_info_line(&c));
+        r.push('\n')
     }
-    s
+    r
 }
 
 async fn get_conn_infos_range(
     Path(cid): Path<String>,
-    State(all_conn): State<NewConnInfoMap>,
+    State(s): State<Arc<GlobalTrafficRecorder>>,
 ) -> String {
     use std::str::FromStr;
     let cid = CID::from_str(&cid);
@@ -142,32 +183,23 @@ async fn get_conn_infos_range(
         Err(_) => return String::from("None"),
     };
 
-    let mut s = String::new();
-    let m = all_conn.read();
-    for i in m.range(cid..) {
-        let x = i.1 .0.to_string();
-        s.push_str(&x);
-        s.push_str(" , ");
-        let x = i.1 .1.to_string();
-        s.push_str(&x);
-        s.push('\n')
+    let mut r = String::new();
+    for c in s.active_conns.list(Some(&cid)) {
+        r.push_str(&conn_info_line(&c));
+        r.push('\n')
     }
-    s
+    r
 }
 
-async fn get_last_ok_cid(State(all_conn): State<NewConnInfoMap>) -> String {
-    let mut s = String::new();
-    let m = all_conn.read();
-    let last_kv = m.last_key_value();
-    match last_kv {
-        Some(e) => s.push_str(&e.0.to_string()),
-        None => {}
+async fn get_last_ok_cid(State(last): State<LastOkCid>) -> String {
+    match &*last.read() {
+        Some(cid) => cid.to_string(),
+        None => String::new(),
     }
-    s
 }
 
-async fn get_conn_count(State(all_conn): State<NewConnInfoMap>) -> String {
-    format!("{}", all_conn.read().len())
+async fn get_conn_count(State(s): State<Arc<GlobalTrafficRecorder>>) -> String {
+    format!("{}", s.active_conns.len())
 }
 
 async fn get_alive_conn_count(State(s): State<Arc<ruci::net::GlobalTrafficRecorder>>) -> String {
@@ -186,9 +218,146 @@ async fn get_gt_d(State(s): State<Arc<ruci::net::GlobalTrafficRecorder>>) -> Str
     format!("{}", s.db.load(Ordering::Relaxed))
 }
 
-async fn get_conn_info(Path(cid): Path<String>, State(all_conn): State<NewConnInfoMap>) -> String {
+fn get_traffic_map<'a>(
+    gtr: &'a ruci::net::GlobalTrafficRecorder,
+    kind: &str,
+) -> Option<&'a TrafficMap> {
+    match kind {
+        "user" => Some(&gtr.user_traffic),
+        "in" => Some(&gtr.in_tag_traffic),
+        "out" => Some(&gtr.out_tag_traffic),
+        _ => None,
+    }
+}
+
+/// kind 为 user, in 或 out; 每行 为 "key ub db"
+async fn get_traffic(
+    Path(kind): Path<String>,
+    State(s): State<Arc<ruci::net::GlobalTrafficRecorder>>,
+) -> String {
+    let tm = match get_traffic_map(&s, &kind) {
+        Some(tm) => tm,
+        None => return String::from("None"),
+    };
     let mut s = String::new();
-    let m = all_conn.read();
+    for (k, (u, d)) in tm.snapshot() {
+        s.push_str(&format!("{k} {u} {d}\n"));
+    }
+    s
+}
+
+async fn get_traffic_for(
+    Path((kind, key)): Path<(String, String)>,
+    State(s): State<Arc<ruci::net::GlobalTrafficRecorder>>,
+) -> String {
+    match get_traffic_map(&s, &kind).and_then(|tm| tm.get(&key)) {
+        Some((u, d)) => format!("{u} {d}"),
+        None => String::from("None"),
+    }
+}
+
+async fn reset_traffic(
+    Path(kind): Path<String>,
+    State(s): State<Arc<ruci::net::GlobalTrafficRecorder>>,
+) -> String {
+    match get_traffic_map(&s, &kind) {
+        Some(tm) => {
+            tm.reset_all();
+            String::from("ok")
+        }
+        None => String::from("None"),
+    }
+}
+
+async fn reset_traffic_for(
+    Path((kind, key)): Path<(String, String)>,
+    State(s): State<Arc<ruci::net::GlobalTrafficRecorder>>,
+) -> String {
+    match get_traffic_map(&s, &kind).map(|tm| tm.reset(&key)) {
+        Some(true) => String::from("ok"),
+        _ => String::from("None"),
+    }
+}
+
+fn lines(v: Vec<String>) -> String {
+    let mut s = String::new();
+    for id in v {
+        s.push_str(&id);
+        s.push('\n');
+    }
+    s
+}
+
+/// kind 为 plaintext (socks5 与 http) 或 trojan; 每行 为 一个 入站 启用 的 存储 的 名称
+async fn list_user_stores(Path(kind): Path<String>) -> String {
+    match kind.as_str() {
+        "plaintext" => lines(user::PLAINTEXT_USERS.names()),
+        "trojan" => lines(TROJAN_USERS.names()),
+        _ => String::from("None"),
+    }
+}
+
+/// 每行 为 一个 identity_str
+async fn list_users(Path((kind, store)): Path<(String, String)>) -> String {
+    let v = match kind.as_str() {
+        "plaintext" => user::PLAINTEXT_USERS.get(&store).map(|s| s.list()),
+        "trojan" => TROJAN_USERS.get(&store).map(|s| s.list()),
+        _ => None,
+    };
+    match v {
+        Some(v) => lines(v),
+        None => String::from("None"),
+    }
+}
+
+/// body 对 plaintext 为 "user pass", 对 trojan 为 密码. 只能 加到 已被 入站 启用 的 存储 中
+async fn add_user(Path((kind, store)): Path<(String, String)>, body: String) -> String {
+    let body = body.trim();
+    match kind.as_str() {
+        "plaintext" => {
+            let Some(s) = user::PLAINTEXT_USERS.get(&store) else {
+                return String::from("None");
+            };
+            let u = user::PlainText::from(body.to_string());
+            if !u.strict_valid() {
+                return String::from("invalid user");
+            }
+            s.add(u);
+        }
+        "trojan" => {
+            let Some(s) = TROJAN_USERS.get(&store) else {
+                return String::from("None");
+            };
+            if body.is_empty() {
+                return String::from("invalid user");
+            }
+            s.add(trojan::User::new(body));
+        }
+        _ => return String::from("None"),
+    }
+    String::from("ok")
+}
+
+/// id 为 identity_str, 对 trojan 为 密码 的 sha224 hex
+async fn remove_user(Path((kind, store, id)): Path<(String, String, String)>) -> String {
+    let ok = match kind.as_str() {
+        "plaintext" => user::PLAINTEXT_USERS
+            .get(&store)
+            .is_some_and(|s| s.remove(&id)),
+        "trojan" => TROJAN_USERS.get(&store).is_some_and(|s| s.remove(&id)),
+        _ => false,
+    };
+    if ok {
+        String::from("ok")
+    } else {
+        String::from("None")
+    }
+}
+
+async fn get_conn_info(
+    Path(cid): Path<String>,
+    State(s): State<Arc<GlobalTrafficRecorder>>,
+) -> String {
     use std::str::FromStr;
     let cid = CID::from_str(&cid);
     let cid = match cid {
@@ -196,17 +365,10 @@ async fn get_conn_info(Path(cid): Path<String>, State(all_conn): State<NewConnIn
         Err(_) => return String::from("None"),
     };
 
-    let x = m.get(&cid);
-    let i = match x {
-        Some(x) => x,
-        None => return String::from("None"),
-    };
-    let x = i.0.to_string();
-    s.push_str(&x);
-    s.push_str(" , ");
-    let x = i.1.to_string();
-    s.push_str(&x);
-    s
+    match s.active_conns.get(&cid) {
+        Some(c) => conn_info_line(&c),
+        None => String::from("None"),
+    }
 }
 
 #[cfg(feature = "trace")]
@@ -250,7 +412,8 @@ async fn stop_core(State(tx): State<mpsc::Sender<()>>) -> String {
 /// non-blocking
 pub async fn serve(s: &Server, global_traffic: Arc<ruci::net::GlobalTrafficRecorder>) {
     let addr = s
-        .listen_addr
+        .listen
+        .addr
         .clone()
         .unwrap_or_else(|| String::from(DEFAULT_API_ADDR));
     info!("api server starting {addr}");
@@ -265,29 +428,61 @@ pub async fn serve(s: &Server, global_traffic: Arc<ruci::net::GlobalTrafficRecor
             "/gt/lci",
             get(get_last_conn_id).with_state(global_traffic.clone()),
         )
+        .route(
+            "/metrics",
+            get(metrics::get_metrics).with_state(global_traffic.clone()),
+        )
         .route("/gt/u", get(get_gt_u).with_state(global_traffic.clone()))
         .route("/gt/d", get(get_gt_d).with_state(global_traffic.clone()))
+        .route(
+            "/t/:kind",
+            get(get_traffic).with_state(global_traffic.clone()),
+        )
+        .route(
+            "/t/:kind/:key",
+            get(get_traffic_for).with_state(global_traffic.clone()),
+        )
+        .route(
+            "/t_reset/:kind",
+            get(reset_traffic).with_state(global_traffic.clone()),
+        )
+        .route(
+            "/t_reset/:kind/:key",
+            get(reset_traffic_for).with_state(global_traffic.clone()),
+        )
+        .route("/users/:kind", get(list_user_stores))
+        .route("/users/:kind/:store", get(list_users).post(add_user))
+        .route("/users/:kind/:store/:id", delete(remove_user))
         .route(
             "/all_c",
-            get(get_conn_infos).with_state(s.new_conn_info_map.clone()),
+            get(get_conn_infos).with_state(global_traffic.clone()),
         )
         .route(
             "/gt/loci",
-            get(get_last_ok_cid).with_state(s.new_conn_info_map.clone()),
+            get(get_last_ok_cid).with_state(s.last_ok_cid.clone()),
         )
         .route(
             "/cr/:cid",
-            get(get_conn_infos_range).with_state(s.new_conn_info_map.clone()),
+            get(get_conn_infos_range).with_state(global_traffic.clone()),
         )
         .route(
             "/cc",
-            get(get_conn_count).with_state(s.new_conn_info_map.clone()),
+            get(get_conn_count).with_state(global_traffic.clone()),
         )
         .route(
             "/c/:cid",
-            get(get_conn_info).with_state(s.new_conn_info_map.clone()),
+            get(get_conn_info).with_state(global_traffic.clone()),
         );
 
+    app = app.nest(
+        "/v1",
+        v1::router(v1::V1State {
+            gtr: global_traffic.clone(),
+            config: s.config.clone(),
+            events: s.events.clone(),
+        }),
+    );
+
     #[cfg(feature = "trace")]
     {
         let ism = s.flux_trace.is_monitoring.clone();
@@ -307,26 +502,141 @@ pub async fn serve(s: &Server, global_traffic: Arc<ruci::net::GlobalTrafficRecor
         );
     }
 
+    if s.listen.auth.is_enabled() {
+        app = app.layer(axum::middleware::from_fn_with_state(
+            Arc::new(s.listen.auth.clone()),
+            auth::check,
+        ));
+    } else if !is_loopback(&addr) {
+        warn!("api server listening on {addr} without auth, consider --api-token");
+    }
+
     // RUST_LOG=tower_http=trace
 
-    use axum::http::Method;
+    use axum::http::{header, Method};
     use tower_http::cors::{Any, CorsLayer};
     use tower_http::trace::TraceLayer;
 
+    let app = app.layer(TraceLayer::new_for_http()).layer(
+        CorsLayer::new()
+            .allow_origin(Any)
+            .allow_methods([Method::GET, Method::POST, Method::DELETE])
+            .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE]),
+    );
+
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
 
-    tokio::spawn(async move {
-        axum::serve(
-            listener,
-            app.layer(TraceLayer::new_for_http()).layer(
-                CorsLayer::new()
-                    .allow_origin(Any)
-                    .allow_methods([Method::GET]),
-            ),
-        )
-        .await
-        .unwrap();
-    });
+    match &s.listen.tls {
+        Some((cert, key)) => {
+            let config = ruci::map::tls::load_ser_config(&ruci::map::tls::server::ServerOptions {
+                cert: cert.clone(),
+                key: key.clone(),
+                alpn: Some(vec!["h2".to_string(), "http/1.1".to_string()]),
+                ..Default::default()
+            })
+            .expect("api server tls config valid");
+            let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+            tokio::spawn(serve_tls(listener, acceptor, app));
+        }
+        None => {
+            tokio::spawn(async move {
+                axum::serve(listener, app).await.unwrap();
+            });
+        }
+    }
 
-    info!("api server started {addr}");
+    info!(tls = s.listen.tls.is_some(), "api server started {addr}");
+}
+
+fn is_loopback(addr: &str) -> bool {
+    match addr.parse::<std::net::SocketAddr>() {
+        Ok(a) => a.ip().is_loopback(),
+        Err(_) => addr.starts_with("localhost:"),
+    }
+}
+
+/// 与 axum::serve 相同, 但 先 进行 tls 握手
+async fn serve_tls(
+    listener: tokio::net::TcpListener,
+    acceptor: tokio_rustls::TlsAcceptor,
+    app: Router,
+) {
+    use hyper_util::{
+        rt::{TokioExecutor, TokioIo},
+        server::conn::auto,
+        service::TowerToHyperService,
+    };
+
+    loop {
+        let (tcp, ra) = match listener.accept().await {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("api server accept failed: {e}");
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let tls = match acceptor.accept(tcp).await {
+                Ok(t) => t,
+                Err(e) => {
+                    debug!(ra = %ra, "api server tls handshake failed: {e}");
+                    return;
+                }
+            };
+            let r = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(tls), TowerToHyperService::new(app))
+                .await;
+            if let Err(e) = r {
+                debug!(ra = %ra, "api server connection end with error: {e}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use ruci::net;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn closed_conns_removed() {
+        let gtr = Arc::new(GlobalTrafficRecorder::default());
+        let shutdown_rx = gtr.active_conns.register(
+            ruci::relay::NewConnInfo {
+                cid: "1".parse().unwrap(),
+                in_tag: "in".to_string(),
+                out_tag: "out".to_string(),
+                target_addr: net::Addr::from_network_addr_url("127.0.0.1:80").unwrap(),
+                user: None,
+
+                #[cfg(feature = "trace")]
+                in_trace: Vec::new(),
+
+                #[cfg(feature = "trace")]
+                out_trace: Vec::new(),
+            },
+            Arc::default(),
+        );
+        let c = |cid: &str| get_conn_info(Path(cid.to_string()), State(gtr.clone()));
+
+        assert_eq!(get_conn_count(State(gtr.clone())).await, "1");
+        assert!(c("1").await.ends_with("in -> out => tcp://127.0.0.1:80 , "));
+        assert_eq!(get_conn_infos(State(gtr.clone())).await.lines().count(), 1);
+        assert_eq!(
+            get_conn_infos_range(Path("2".to_string()), State(gtr.clone())).await,
+            ""
+        );
+
+        // 转发 结束
+        drop(shutdown_rx);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(get_conn_count(State(gtr.clone())).await, "0");
+        assert_eq!(c("1").await, "None");
+        assert_eq!(get_conn_infos(State(gtr.clone())).await, "");
+    }
 }
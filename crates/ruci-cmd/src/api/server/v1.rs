@@ -0,0 +1,387 @@
+/*!
+/v1 json api, 类型 见 [`crate::api::types`]
+*/
+
+use std::{collections::BTreeSet, str::FromStr, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use ruci::{net::CID, relay::ActiveConn};
+use serde::Deserialize;
+
+use super::events::{self, Events};
+use crate::api::types::{self, ApiError};
+
+type ApiResult<T> = Result<Json<T>, (StatusCode, Json<ApiError>)>;
+
+fn err(code: StatusCode, e: impl ToString) -> (StatusCode, Json<ApiError>) {
+    (
+        code,
+        Json(ApiError {
+            error: e.to_string(),
+        }),
+    )
+}
+
+fn parse_cid(s: &str) -> Result<CID, (StatusCode, Json<ApiError>)> {
+    CID::from_str(s).map_err(|_| err(StatusCode::BAD_REQUEST, format!("invalid cid: {s}")))
+}
+
+#[derive(Clone)]
+pub struct V1State {
+    pub gtr: Arc<ruci::net::GlobalTrafficRecorder>,
+    pub config: Arc<RwLock<types::Config>>,
+    pub events: Events,
+}
+
+fn to_connection(c: &ActiveConn) -> types::Connection {
+    let t: DateTime<Utc> = c.start.into();
+    types::Connection {
+        cid: c.info.cid.to_string(),
+        time: t.to_rfc3339(),
+        in_tag: c.info.in_tag.clone(),
+        out_tag: c.info.out_tag.clone(),
+        target_addr: c.info.target_addr.to_string(),
+        user: c.info.user.clone(),
+        bytes: c.traffic.get().into(),
+    }
+}
+
+#[derive(Deserialize)]
+struct ConnectionsQuery {
+    /// 只返回 不小于 from 的 cid
+    from: Option<String>,
+}
+
+/// 正在 转发 的 连接
+async fn connections(
+    Query(q): Query<ConnectionsQuery>,
+    State(s): State<V1State>,
+) -> ApiResult<Vec<types::Connection>> {
+    let from = q.from.as_deref().map(parse_cid).transpose()?;
+    let v = s
+        .gtr
+        .active_conns
+        .list(from.as_ref())
+        .iter()
+        .map(|c| to_connection(c))
+        .collect();
+    Ok(Json(v))
+}
+
+async fn connection(
+    Path(cid): Path<String>,
+    State(s): State<V1State>,
+) -> ApiResult<types::Connection> {
+    let id = parse_cid(&cid)?;
+    match s.gtr.active_conns.get(&id) {
+        Some(c) => Ok(Json(to_connection(&c))),
+        None => Err(err(
+            StatusCode::NOT_FOUND,
+            format!("connection not found: {cid}"),
+        )),
+    }
+}
+
+/// 关闭 一个 连接
+async fn kill_connection(
+    Path(cid): Path<String>,
+    State(s): State<V1State>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    let id = parse_cid(&cid)?;
+    if s.gtr.active_conns.kill(&id) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(err(
+            StatusCode::NOT_FOUND,
+            format!("connection not found: {cid}"),
+        ))
+    }
+}
+
+async fn traffic(State(s): State<V1State>) -> ApiResult<types::Traffic> {
+    use std::sync::atomic::Ordering;
+
+    let g = &s.gtr;
+    let conv = |tm: &ruci::net::traffic::TrafficMap| {
+        tm.snapshot()
+            .into_iter()
+            .map(|(k, v)| (k, v.into()))
+            .collect()
+    };
+    Ok(Json(types::Traffic {
+        total: types::Bytes {
+            up: g.ub.load(Ordering::Relaxed),
+            down: g.db.load(Ordering::Relaxed),
+        },
+        alive_connections: g.alive_connection_count.load(Ordering::Relaxed),
+        total_connections: g.last_connection_id.load(Ordering::Relaxed),
+        users: conv(&g.user_traffic),
+        inbounds: conv(&g.in_tag_traffic),
+        outbounds: conv(&g.out_tag_traffic),
+    }))
+}
+
+/// 包括 配置 中 的 出站 与 有过 流量 或 拨号 记录 的 出站
+async fn outbounds(State(s): State<V1State>) -> ApiResult<Vec<types::Outbound>> {
+    let stats = s.gtr.metrics.outbounds();
+    let traffic = s.gtr.out_tag_traffic.snapshot();
+
+    let mut tags: BTreeSet<String> = s.config.read().outbound_tags.iter().cloned().collect();
+    tags.extend(stats.keys().cloned());
+    tags.extend(traffic.keys().cloned());
+
+    let v = tags
+        .into_iter()
+        .map(|tag| {
+            let (dials_ok, dials_failed, up) = match stats.get(&tag) {
+                Some((ok, failed, up)) => (*ok, *failed, Some(*up)),
+                None => (0, 0, None),
+            };
+            types::Outbound {
+                bytes: traffic.get(&tag).copied().unwrap_or_default().into(),
+                tag,
+                dials_ok,
+                dials_failed,
+                up,
+            }
+        })
+        .collect();
+    Ok(Json(v))
+}
+
+async fn config(State(s): State<V1State>) -> ApiResult<types::Config> {
+    Ok(Json(s.config.read().clone()))
+}
+
+async fn openapi() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/json")], types::OPENAPI)
+}
+
+async fn not_found() -> (StatusCode, Json<ApiError>) {
+    err(StatusCode::NOT_FOUND, "no such api")
+}
+
+pub fn router(s: V1State) -> Router {
+    Router::new()
+        .route("/connections", get(connections))
+        .route("/connections/:cid", get(connection).delete(kill_connection))
+        .route("/traffic", get(traffic))
+        .route("/outbounds", get(outbounds))
+        .route("/config", get(config))
+        .route("/events", get(events::events).with_state(s.events.clone()))
+        .route("/openapi.json", get(openapi))
+        .fallback(not_found)
+        .with_state(s)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::atomic::Ordering, time::Duration};
+
+    use axum::{
+        body::{self, Body},
+        http::{Method, Request},
+    };
+    use ruci::{
+        net::{self, traffic::Traffic, GlobalTrafficRecorder},
+        relay::NewConnInfo,
+    };
+    use serde::de::DeserializeOwned;
+    use tokio::sync::oneshot;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn info(cid: &str, user: Option<&str>) -> NewConnInfo {
+        NewConnInfo {
+            cid: cid.parse().unwrap(),
+            in_tag: "in1".to_string(),
+            out_tag: "out1".to_string(),
+            target_addr: net::Addr::from_network_addr_url("127.0.0.1:80").unwrap(),
+            user: user.map(String::from),
+
+            #[cfg(feature = "trace")]
+            in_trace: Vec::new(),
+
+            #[cfg(feature = "trace")]
+            out_trace: Vec::new(),
+        }
+    }
+
+    /// 两个 正在 转发 的 连接 1 与 2, 返回 state 与 它们 的 shutdown_rx
+    fn state() -> (V1State, Vec<oneshot::Receiver<()>>) {
+        let gtr = Arc::new(GlobalTrafficRecorder::default());
+        gtr.ub.store(100, Ordering::Relaxed);
+        gtr.db.store(200, Ordering::Relaxed);
+        gtr.last_connection_id.store(2, Ordering::Relaxed);
+        gtr.alive_connection_count.store(2, Ordering::Relaxed);
+        gtr.user_traffic
+            .get_or_insert("u1")
+            .ub
+            .store(10, Ordering::Relaxed);
+        gtr.out_tag_traffic
+            .get_or_insert("out1")
+            .db
+            .store(20, Ordering::Relaxed);
+        gtr.metrics.record_outbound("out1", true);
+        gtr.metrics.record_outbound("out2", false);
+
+        let t = Arc::new(Traffic::default());
+        t.ub.store(5, Ordering::Relaxed);
+        let rxs = vec![
+            gtr.active_conns.register(info("1", Some("u1")), t),
+            gtr.active_conns.register(info("2", None), Arc::default()),
+        ];
+
+        let config = types::Config {
+            version: "0.0.3".to_string(),
+            outbound_tags: vec!["direct".to_string(), "out1".to_string()],
+            ..Default::default()
+        };
+        let s = V1State {
+            events: Events::new(gtr.clone()),
+            gtr,
+            config: Arc::new(RwLock::new(config)),
+        };
+        (s, rxs)
+    }
+
+    async fn call(s: &V1State, method: Method, uri: &str) -> (StatusCode, Vec<u8>) {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let r = router(s.clone()).oneshot(req).await.unwrap();
+        let code = r.status();
+        let b = body::to_bytes(r.into_body(), usize::MAX).await.unwrap();
+        (code, b.to_vec())
+    }
+
+    async fn get_json<T: DeserializeOwned>(s: &V1State, uri: &str) -> T {
+        let (code, b) = call(s, Method::GET, uri).await;
+        assert_eq!(code, StatusCode::OK, "{uri}");
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    async fn api_error(s: &V1State, method: Method, uri: &str, code: StatusCode) -> String {
+        let (c, b) = call(s, method, uri).await;
+        assert_eq!(c, code, "{uri}");
+        serde_json::from_slice::<ApiError>(&b).unwrap().error
+    }
+
+    #[tokio::test]
+    async fn get_connections() {
+        let (s, _rxs) = state();
+
+        let v: Vec<types::Connection> = get_json(&s, "/connections").await;
+        assert_eq!(v.len(), 2);
+        assert_eq!(v[0].cid, "1");
+        assert_eq!(v[0].user.as_deref(), Some("u1"));
+        assert_eq!(v[0].bytes, types::Bytes { up: 5, down: 0 });
+        assert_eq!(v[0].target_addr, "tcp://127.0.0.1:80");
+        assert!(DateTime::parse_from_rfc3339(&v[0].time).is_ok());
+
+        let v: Vec<types::Connection> = get_json(&s, "/connections?from=2").await;
+        assert_eq!(v.len(), 1);
+        assert_eq!(v[0].cid, "2");
+
+        let c: types::Connection = get_json(&s, "/connections/2").await;
+        assert_eq!(c.out_tag, "out1");
+
+        api_error(
+            &s,
+            Method::GET,
+            "/connections?from=x",
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn kill_connections() {
+        let (s, mut rxs) = state();
+
+        let (code, b) = call(&s, Method::DELETE, "/connections/1").await;
+        assert_eq!(code, StatusCode::NO_CONTENT);
+        assert!(b.is_empty());
+
+        // 转发 收到 shutdown, 连接 被 注销
+        let rx = rxs.remove(0);
+        tokio::time::timeout(Duration::from_secs(1), rx)
+            .await
+            .unwrap()
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let v: Vec<types::Connection> = get_json(&s, "/connections").await;
+        assert_eq!(v.len(), 1);
+
+        let e = api_error(&s, Method::DELETE, "/connections/1", StatusCode::NOT_FOUND).await;
+        assert_eq!(e, "connection not found: 1");
+        let e = api_error(&s, Method::DELETE, "/connections/9", StatusCode::NOT_FOUND).await;
+        assert_eq!(e, "connection not found: 9");
+
+        let e = api_error(
+            &s,
+            Method::DELETE,
+            "/connections/a-b",
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+        assert_eq!(e, "invalid cid: a-b");
+    }
+
+    #[tokio::test]
+    async fn get_traffic() {
+        let (s, _rxs) = state();
+        let t: types::Traffic = get_json(&s, "/traffic").await;
+        assert_eq!(t.total, types::Bytes { up: 100, down: 200 });
+        assert_eq!(t.alive_connections, 2);
+        assert_eq!(t.total_connections, 2);
+        assert_eq!(t.users["u1"], types::Bytes { up: 10, down: 0 });
+        assert!(t.inbounds.is_empty());
+        assert_eq!(t.outbounds["out1"], types::Bytes { up: 0, down: 20 });
+    }
+
+    #[tokio::test]
+    async fn get_outbounds() {
+        let (s, _rxs) = state();
+        let v: Vec<types::Outbound> = get_json(&s, "/outbounds").await;
+        let tags: Vec<&str> = v.iter().map(|o| o.tag.as_str()).collect();
+        assert_eq!(tags, ["direct", "out1", "out2"]);
+
+        // 只在 配置 中 出现, 未 拨号过
+        assert_eq!((v[0].dials_ok, v[0].dials_failed, v[0].up), (0, 0, None));
+        assert_eq!((v[1].dials_ok, v[1].up), (1, Some(true)));
+        assert_eq!(v[1].bytes, types::Bytes { up: 0, down: 20 });
+        assert_eq!((v[2].dials_failed, v[2].up), (1, Some(false)));
+    }
+
+    #[tokio::test]
+    async fn get_config() {
+        let (s, _rxs) = state();
+        let c: types::Config = get_json(&s, "/config").await;
+        assert_eq!(c.version, "0.0.3");
+        assert_eq!(c.outbound_tags, ["direct", "out1"]);
+    }
+
+    #[tokio::test]
+    async fn not_found_json() {
+        let (s, _rxs) = state();
+        let (code, b) = call(&s, Method::GET, "/nope").await;
+        assert_eq!(code, StatusCode::NOT_FOUND);
+        let v: serde_json::Value = serde_json::from_slice(&b).unwrap();
+        assert_eq!(v, serde_json::json!({"error": "no such api"}));
+
+        let e = api_error(&s, Method::GET, "/connections/9", StatusCode::NOT_FOUND).await;
+        assert_eq!(e, "connection not found: 9");
+    }
+}
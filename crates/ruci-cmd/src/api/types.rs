@@ -0,0 +1,132 @@
+/*!
+/v1 json api 的 类型, server 与 client 共用
+
+文档 见 /v1/openapi.json (即 openapi.json 文件). 出错 时 返回 4xx 与 [`ApiError`].
+*/
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "api_server")]
+pub const OPENAPI: &str = include_str!("openapi.json");
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiError {
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bytes {
+    pub up: u64,
+    pub down: u64,
+}
+
+impl From<(u64, u64)> for Bytes {
+    fn from((up, down): (u64, u64)) -> Self {
+        Self { up, down }
+    }
+}
+
+/// 一个 正在 转发 的 连接
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Connection {
+    pub cid: String,
+
+    /// 开始 转发 的 时间, rfc3339
+    pub time: String,
+    pub in_tag: String,
+    pub out_tag: String,
+    pub target_addr: String,
+    pub user: Option<String>,
+
+    /// 至今 的 流量
+    pub bytes: Bytes,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Traffic {
+    pub total: Bytes,
+    pub alive_connections: u32,
+    pub total_connections: u32,
+
+    /// by user identity
+    pub users: BTreeMap<String, Bytes>,
+
+    /// by inbound tag
+    pub inbounds: BTreeMap<String, Bytes>,
+
+    /// by outbound tag
+    pub outbounds: BTreeMap<String, Bytes>,
+}
+
+#[cfg(feature = "api_client")]
+impl Traffic {
+    /// kind 为 user, in 或 out
+    pub fn by_kind(&self, kind: &str) -> Option<&BTreeMap<String, Bytes>> {
+        match kind {
+            "user" => Some(&self.users),
+            "in" => Some(&self.inbounds),
+            "out" => Some(&self.outbounds),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Outbound {
+    pub tag: String,
+    pub dials_ok: u64,
+    pub dials_failed: u64,
+
+    /// 最近 一次 拨号 是否 成功; 未 拨号过 时 为 None
+    pub up: Option<bool>,
+    pub bytes: Bytes,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    pub version: String,
+    pub mode: String,
+    pub config_file: String,
+    pub features: Vec<String>,
+    pub inbounds_count: usize,
+    pub outbound_tags: Vec<String>,
+}
+
+/// 一个 间隔 内 的 流量 速率, 单位 为 bytes/s
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrafficRate {
+    pub total: Bytes,
+    pub alive_connections: usize,
+
+    /// by cid, 只含 该间隔 内 有 流量 的 连接
+    pub connections: BTreeMap<String, Bytes>,
+}
+
+/// /v1/events 推送 的 事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    NewConnection(Connection),
+
+    /// bytes 为 该连接 的 总 流量
+    ClosedConnection {
+        cid: String,
+        bytes: Bytes,
+    },
+
+    Traffic(TrafficRate),
+}
+
+#[cfg(feature = "api_server")]
+impl Event {
+    /// 作为 sse 的 event 字段
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::NewConnection(_) => "new_connection",
+            Event::ClosedConnection { .. } => "closed_connection",
+            Event::Traffic(_) => "traffic",
+        }
+    }
+}
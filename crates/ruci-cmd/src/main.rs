@@ -1,7 +1,7 @@
 /*!
 具有综合功能的命令行程序
 
-可选功能 api_client, api_server, utils
+可选功能 api_client, api_server, utils, tui
 
 针对 rucimp 核心的 可选功能:
 trace, tproxy, quic, quinn, lua, lua54, use-native-tls, native-tls-vendored
@@ -13,6 +13,9 @@ mod api;
 #[cfg(feature = "utils")]
 mod utils;
 
+#[cfg(feature = "tui")]
+mod tui;
+
 mod mode;
 
 use std::env::{self, set_var};
@@ -82,6 +85,27 @@ struct Args {
     #[arg(long)]
     api_addr: Option<String>,
 
+    /// token that can access all apis, as "Bearer <token>" or http basic auth
+    /// "user:pass". no auth if neither it nor --api-read-token is given
+    #[cfg(feature = "api_server")]
+    #[arg(long)]
+    api_token: Vec<String>,
+
+    /// token that can only access the apis that don't change state
+    #[cfg(feature = "api_server")]
+    #[arg(long)]
+    api_read_token: Vec<String>,
+
+    /// serve the api over https with this cert file
+    #[cfg(feature = "api_server")]
+    #[arg(long, requires = "api_key")]
+    api_cert: Option<std::path::PathBuf>,
+
+    /// key file for --api-cert
+    #[cfg(feature = "api_server")]
+    #[arg(long, requires = "api_cert")]
+    api_key: Option<std::path::PathBuf>,
+
     /// default is "0.0.0.0:18143"
     #[cfg(feature = "api_server")]
     #[arg(long)]
@@ -100,6 +124,13 @@ enum SubCommands {
         command: Option<api::client::Commands>,
     },
 
+    /// terminal dashboard, using the data of the api server
+    #[cfg(feature = "tui")]
+    Tui {
+        /// api server address, default is "http://127.0.0.1:40681"
+        addr: Option<String>,
+    },
+
     /// utils
     #[cfg(feature = "utils")]
     Utils {
@@ -147,6 +178,11 @@ async fn main() -> anyhow::Result<()> {
                 }
             }
 
+            #[cfg(feature = "tui")]
+            SubCommands::Tui { addr } => {
+                tui::run(addr).await?;
+            }
+
             #[cfg(feature = "utils")]
             SubCommands::Utils { command } => {
                 utils::deal_cmds(command).await?;
@@ -233,6 +269,23 @@ fn log_setup(args: Args) -> Option<tracing_appender::non_blocking::WorkerGuard>
         std::env::var(RL).map_or_else(|_| String::new(), |v| v)
     );
 
+    let fl = enabled_features();
+
+    info!(
+        ruci_cmd = env!("CARGO_PKG_VERSION"),
+        rucimp = rucimp::VERSION,
+        features = ?fl
+    );
+
+    if no_file {
+        info!("Empty log-file name specified, no log file would be generated.")
+    }
+
+    guard
+}
+
+/// 编译时 打开 的 feature
+fn enabled_features() -> Vec<&'static str> {
     #[allow(unused_mut)]
     let mut fl: Vec<&str> = Vec::new();
 
@@ -245,6 +298,9 @@ fn log_setup(args: Args) -> Option<tracing_appender::non_blocking::WorkerGuard>
     #[cfg(feature = "utils")]
     fl.push("utils");
 
+    #[cfg(feature = "tui")]
+    fl.push("tui");
+
     #[cfg(feature = "lua")]
     fl.push("lua");
 
@@ -272,17 +328,7 @@ fn log_setup(args: Args) -> Option<tracing_appender::non_blocking::WorkerGuard>
     #[cfg(feature = "tun")]
     fl.push("tun");
 
-    info!(
-        ruci_cmd = env!("CARGO_PKG_VERSION"),
-        rucimp = rucimp::VERSION,
-        features = ?fl
-    );
-
-    if no_file {
-        info!("Empty log-file name specified, no log file would be generated.")
-    }
-
-    guard
+    fl
 }
 
 /// blocking
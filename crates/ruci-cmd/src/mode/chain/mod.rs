@@ -44,14 +44,7 @@ pub(crate) async fn run(
     #[cfg(feature = "api_server")]
     {
         if let Some(mut s) = opts {
-            setup_api_server_with_chain_engine(
-                &mut se,
-                #[cfg(feature = "trace")]
-                args,
-                &mut s.0,
-                s.2,
-            )
-            .await;
+            setup_api_server_with_chain_engine(&mut se, args, &mut s.0, s.2).await;
 
             run_engine(&mut se, Some(s.1)).await?;
 
@@ -94,12 +87,24 @@ async fn run_engine(e: &mut Engine, close_rx: Option<mpsc::Receiver<()>>) -> any
 #[cfg(feature = "api_server")]
 async fn setup_api_server_with_chain_engine(
     e: &mut Engine,
-    #[cfg(feature = "trace")] args: crate::Args,
+    args: crate::Args,
     api_ser: &mut api::server::Server,
     gtr: Arc<ruci::net::GlobalTrafficRecorder>,
 ) {
     e.gtr = gtr;
 
+    *api_ser.config.write() = api::types::Config {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        mode: format!("{:?}", args.mode),
+        config_file: args.config.clone(),
+        features: crate::enabled_features()
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        inbounds_count: e.inbounds_count(),
+        outbound_tags: e.outbound_tags(),
+    };
+
     setup_record_new_conn_info(e, api_ser).await;
     #[cfg(feature = "trace")]
     if args.trace {
@@ -114,19 +119,16 @@ async fn setup_record_new_conn_info(e: &mut Engine, api_ser: &mut api::server::S
 
     e.new_conn_recorder = Some(nci_tx);
 
-    let aci = api_ser.new_conn_info_map.clone();
+    let last_ok_cid = api_ser.last_ok_cid.clone();
+    let events = api_ser.events.clone();
 
     tokio::spawn(async move {
         loop {
             let x = nci_rx.recv().await;
             match x {
                 Some(nc) => {
-                    let mut aci = aci.write();
-                    let cid = nc.cid.clone();
-
-                    use chrono::Utc;
-                    let now: chrono::DateTime<Utc> = Utc::now();
-                    aci.insert(cid, (now, nc));
+                    events.send_new_conn(&nc);
+                    *last_ok_cid.write() = Some(nc.cid);
                 }
                 None => break,
             }
@@ -0,0 +1,399 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ruci::net::CID;
+
+use crate::api::types::{self, Bytes, Event};
+
+/// 吞吐 图 保留 的 点数, 每秒 一个
+pub const HISTORY_LEN: usize = 120;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Cid,
+    Rate,
+    Bytes,
+    Tag,
+    User,
+    Target,
+}
+
+impl SortKey {
+    pub fn next(self) -> Self {
+        match self {
+            SortKey::Cid => SortKey::Rate,
+            SortKey::Rate => SortKey::Bytes,
+            SortKey::Bytes => SortKey::Tag,
+            SortKey::Tag => SortKey::User,
+            SortKey::User => SortKey::Target,
+            SortKey::Target => SortKey::Cid,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortKey::Cid => "cid",
+            SortKey::Rate => "rate",
+            SortKey::Bytes => "bytes",
+            SortKey::Tag => "tag",
+            SortKey::User => "user",
+            SortKey::Target => "target",
+        }
+    }
+}
+
+pub struct ConnRow {
+    pub c: types::Connection,
+
+    /// 最近 一秒 的 速率
+    pub rate: Bytes,
+}
+
+pub enum Action {
+    None,
+    Quit,
+    Kill(String),
+}
+
+#[derive(Default)]
+pub struct App {
+    pub conns: BTreeMap<CID, ConnRow>,
+    pub outbounds: Vec<types::Outbound>,
+
+    /// 每秒 的 总 速率 (up, down)
+    pub history: VecDeque<(u64, u64)>,
+    pub total_rate: Bytes,
+
+    pub sort: SortKey,
+    pub reverse: bool,
+
+    /// 以 空格 分隔 的 多个 条件, 都 满足 才 显示. 条件 可 为 tag:, in:, out:,
+    /// user:, target: 加 子串, 或 直接 为 子串 以 匹配 任一 字段
+    pub filter: String,
+    pub editing_filter: bool,
+
+    pub selected: usize,
+    pub status: String,
+}
+
+fn contains(field: &str, s: &str) -> bool {
+    field.to_lowercase().contains(s)
+}
+
+fn matches(c: &types::Connection, filter: &str) -> bool {
+    let user = c.user.as_deref().unwrap_or_default();
+    filter.split_whitespace().all(|term| {
+        let term = term.to_lowercase();
+        match term.split_once(':') {
+            Some(("tag", v)) => contains(&c.in_tag, v) || contains(&c.out_tag, v),
+            Some(("in", v)) => contains(&c.in_tag, v),
+            Some(("out", v)) => contains(&c.out_tag, v),
+            Some(("user", v)) => contains(user, v),
+            Some(("target", v)) => contains(&c.target_addr, v),
+            _ => [&c.in_tag, &c.out_tag, user, &c.target_addr]
+                .iter()
+                .any(|f| contains(f, &term)),
+        }
+    })
+}
+
+impl App {
+    /// 用 /v1/connections 的 结果 替换, 保留 已知 的 速率
+    pub fn set_connections(&mut self, v: Vec<types::Connection>) {
+        let mut m = BTreeMap::new();
+        for c in v {
+            let Ok(cid) = c.cid.parse::<CID>() else {
+                continue;
+            };
+            let rate = self
+                .conns
+                .get(&cid)
+                .map(|r| r.rate.clone())
+                .unwrap_or_default();
+            m.insert(cid, ConnRow { c, rate });
+        }
+        self.conns = m;
+    }
+
+    pub fn apply(&mut self, e: Event) {
+        match e {
+            Event::NewConnection(c) => {
+                if let Ok(cid) = c.cid.parse::<CID>() {
+                    self.conns.insert(
+                        cid,
+                        ConnRow {
+                            c,
+                            rate: Bytes::default(),
+                        },
+                    );
+                }
+            }
+            Event::ClosedConnection { cid, .. } => {
+                if let Ok(cid) = cid.parse::<CID>() {
+                    self.conns.remove(&cid);
+                }
+            }
+            Event::Traffic(t) => {
+                for r in self.conns.values_mut() {
+                    r.rate = Bytes::default();
+                }
+                for (cid, b) in t.connections {
+                    let Ok(cid) = cid.parse::<CID>() else {
+                        continue;
+                    };
+                    if let Some(r) = self.conns.get_mut(&cid) {
+                        // 速率 为 每秒, 在 下次 拉取 列表 前 以此 估计 流量
+                        r.c.bytes.up += b.up;
+                        r.c.bytes.down += b.down;
+                        r.rate = b;
+                    }
+                }
+                self.history.push_back((t.total.up, t.total.down));
+                while self.history.len() > HISTORY_LEN {
+                    self.history.pop_front();
+                }
+                self.total_rate = t.total;
+            }
+        }
+    }
+
+    /// 过滤 并 排序 后 的 连接
+    pub fn visible(&self) -> Vec<&ConnRow> {
+        let mut v: Vec<&ConnRow> = self
+            .conns
+            .values()
+            .filter(|r| matches(&r.c, &self.filter))
+            .collect();
+
+        // conns 已按 cid 排序, sort_by 是 稳定 的
+        match self.sort {
+            SortKey::Cid => {}
+            SortKey::Rate => v.sort_by_key(|r| std::cmp::Reverse(r.rate.up + r.rate.down)),
+            SortKey::Bytes => v.sort_by_key(|r| std::cmp::Reverse(r.c.bytes.up + r.c.bytes.down)),
+            SortKey::Tag => {
+                v.sort_by(|a, b| (&a.c.in_tag, &a.c.out_tag).cmp(&(&b.c.in_tag, &b.c.out_tag)))
+            }
+            SortKey::User => v.sort_by(|a, b| a.c.user.cmp(&b.c.user)),
+            SortKey::Target => v.sort_by(|a, b| a.c.target_addr.cmp(&b.c.target_addr)),
+        }
+        if self.reverse {
+            v.reverse();
+        }
+        v
+    }
+
+    /// 列表 变短 后 保持 选中 行 在 范围 内
+    pub fn clamp_selected(&mut self) {
+        let n = self.visible().len();
+        if self.selected >= n {
+            self.selected = n.saturating_sub(1);
+        }
+    }
+
+    pub fn on_key(&mut self, k: KeyEvent) -> Action {
+        if self.editing_filter {
+            match k.code {
+                KeyCode::Enter => self.editing_filter = false,
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.editing_filter = false;
+                }
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(c) => self.filter.push(c),
+                _ => {}
+            }
+            self.selected = 0;
+            return Action::None;
+        }
+
+        match k.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('c') if k.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Action::Quit
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.selected += 1,
+            KeyCode::Char('s') => self.sort = self.sort.next(),
+            KeyCode::Char('r') => self.reverse = !self.reverse,
+            KeyCode::Char('/') => self.editing_filter = true,
+            KeyCode::Char('x') | KeyCode::Delete => {
+                if let Some(r) = self.visible().get(self.selected) {
+                    return Action::Kill(r.c.cid.clone());
+                }
+            }
+            _ => {}
+        }
+        Action::None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn conn(
+        cid: u32,
+        in_tag: &str,
+        out_tag: &str,
+        user: Option<&str>,
+        target: &str,
+        bytes: u64,
+    ) -> types::Connection {
+        types::Connection {
+            cid: cid.to_string(),
+            time: String::new(),
+            in_tag: in_tag.to_string(),
+            out_tag: out_tag.to_string(),
+            target_addr: target.to_string(),
+            user: user.map(|u| u.to_string()),
+            bytes: (bytes, 0).into(),
+        }
+    }
+
+    fn app() -> App {
+        let mut a = App::default();
+        a.set_connections(vec![
+            conn(1, "socks_in", "direct", Some("alice"), "www.b.com:443", 30),
+            conn(2, "http_in", "proxy", None, "www.a.com:80", 10),
+            conn(3, "socks_in", "proxy", Some("bob"), "1.1.1.1:53", 20),
+        ]);
+        a
+    }
+
+    fn cids(a: &App) -> Vec<String> {
+        a.visible().iter().map(|r| r.c.cid.clone()).collect()
+    }
+
+    fn key(c: KeyCode) -> KeyEvent {
+        KeyEvent::new(c, KeyModifiers::NONE)
+    }
+
+    fn traffic(total: (u64, u64), conns: &[(u32, (u64, u64))]) -> Event {
+        Event::Traffic(types::TrafficRate {
+            total: total.into(),
+            alive_connections: conns.len(),
+            connections: conns
+                .iter()
+                .map(|(cid, b)| (cid.to_string(), (*b).into()))
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn filter() {
+        let mut a = app();
+        for (f, want) in [
+            ("", vec!["1", "2", "3"]),
+            ("tag:proxy", vec!["2", "3"]),
+            ("tag:socks", vec!["1", "3"]),
+            ("in:socks", vec!["1", "3"]),
+            ("out:DIRECT", vec!["1"]),
+            ("user:bob", vec!["3"]),
+            ("target:www", vec!["1", "2"]),
+            ("www proxy", vec!["2"]),
+            ("alice", vec!["1"]),
+            ("in:http user:alice", vec![]),
+        ] {
+            a.filter = f.to_string();
+            assert_eq!(cids(&a), want, "{f}");
+        }
+    }
+
+    #[test]
+    fn sort() {
+        let mut a = app();
+        a.apply(traffic((6, 0), &[(2, (5, 0)), (3, (1, 0))]));
+
+        let mut seen = vec![];
+        loop {
+            let want = match a.sort {
+                SortKey::Cid => vec!["1", "2", "3"],
+                SortKey::Rate => vec!["2", "3", "1"],
+                SortKey::Bytes => vec!["1", "3", "2"],
+                SortKey::Tag => vec!["2", "1", "3"],
+                SortKey::User => vec!["2", "1", "3"],
+                SortKey::Target => vec!["3", "2", "1"],
+            };
+            assert_eq!(cids(&a), want, "{}", a.sort.name());
+
+            a.reverse = true;
+            let mut r = want.clone();
+            r.reverse();
+            assert_eq!(cids(&a), r, "reverse {}", a.sort.name());
+            a.reverse = false;
+
+            seen.push(a.sort);
+            a.on_key(key(KeyCode::Char('s')));
+            if a.sort == SortKey::Cid {
+                break;
+            }
+        }
+        assert_eq!(seen.len(), 6);
+    }
+
+    #[test]
+    fn kill_after_filter() {
+        let mut a = app();
+        for c in "/user:bob".chars() {
+            a.on_key(key(KeyCode::Char(c)));
+        }
+        a.on_key(key(KeyCode::Enter));
+        assert!(!a.editing_filter);
+        assert!(matches!(a.on_key(key(KeyCode::Char('x'))), Action::Kill(cid) if cid == "3"));
+
+        a.filter = "nothing".to_string();
+        assert!(matches!(a.on_key(key(KeyCode::Delete)), Action::None));
+    }
+
+    #[test]
+    fn clamp() {
+        let mut a = app();
+        a.on_key(key(KeyCode::Down));
+        a.on_key(key(KeyCode::Down));
+        a.on_key(key(KeyCode::Down));
+        a.clamp_selected();
+        assert_eq!(a.selected, 2);
+
+        a.apply(Event::ClosedConnection {
+            cid: "3".to_string(),
+            bytes: Bytes::default(),
+        });
+        a.clamp_selected();
+        assert_eq!(a.selected, 1);
+
+        a.filter = "nothing".to_string();
+        a.clamp_selected();
+        assert_eq!(a.selected, 0);
+
+        a.on_key(key(KeyCode::Up));
+        assert_eq!(a.selected, 0);
+    }
+
+    #[test]
+    fn traffic_rate() {
+        let mut a = app();
+        a.apply(traffic((7, 3), &[(1, (5, 2)), (2, (2, 1)), (9, (1, 1))]));
+        assert_eq!(a.conns[&"1".parse().unwrap()].rate, (5, 2).into());
+        assert_eq!(a.conns[&"1".parse().unwrap()].c.bytes, (35, 2).into());
+        assert_eq!(a.total_rate, (7, 3).into());
+
+        // 没有 出现 在 下一个 事件 中 的 连接 速率 归零
+        a.apply(traffic((1, 0), &[(2, (1, 0))]));
+        assert_eq!(a.conns[&"1".parse().unwrap()].rate, Bytes::default());
+        assert_eq!(a.conns[&"2".parse().unwrap()].c.bytes, (13, 1).into());
+
+        // 拉取 列表 后 保留 已知 的 速率
+        a.set_connections(vec![conn(2, "http_in", "proxy", None, "www.a.com:80", 100)]);
+        assert_eq!(a.conns.len(), 1);
+        assert_eq!(a.conns[&"2".parse().unwrap()].rate, (1, 0).into());
+
+        for _ in 0..HISTORY_LEN + 5 {
+            a.apply(traffic((0, 0), &[]));
+        }
+        assert_eq!(a.history.len(), HISTORY_LEN);
+        assert_eq!(a.history.back(), Some(&(0, 0)));
+    }
+}
@@ -0,0 +1,170 @@
+/*!
+终端 仪表盘, 数据 来自 api server 的 /v1 api 与 /v1/events
+
+显示 吞吐 图, 正在 转发 的 连接 (可 排序, 过滤, kill) 与 出站 的 状态
+*/
+
+mod app;
+mod ui;
+
+use std::{io, time::Duration};
+
+use anyhow::Context;
+use crossterm::{
+    event::{self, KeyEvent, KeyEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{backend::CrosstermBackend, Terminal};
+use tokio::sync::mpsc;
+
+use crate::api::{client, types};
+use app::{Action, App};
+
+/// 重新 拉取 连接 列表 与 出站 的 间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+enum Msg {
+    Event(types::Event),
+    Connections(Vec<types::Connection>),
+    Outbounds(Vec<types::Outbound>),
+    Key(KeyEvent),
+    Status(String),
+}
+
+/// drop 时 恢复 终端
+struct TermGuard;
+
+impl TermGuard {
+    fn new() -> io::Result<Self> {
+        enable_raw_mode()?;
+        if let Err(e) = execute!(io::stdout(), EnterAlternateScreen) {
+            let _ = disable_raw_mode();
+            return Err(e);
+        }
+        Ok(TermGuard)
+    }
+}
+
+impl Drop for TermGuard {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+    }
+}
+
+fn spawn_events(ad: String, tx: mpsc::UnboundedSender<Msg>) {
+    tokio::spawn(async move {
+        loop {
+            let r = client::watch_events(&ad, |e| tx.send(Msg::Event(e)).is_ok()).await;
+            if tx.is_closed() {
+                break;
+            }
+            let s = match r {
+                Ok(_) => "event stream ended, reconnecting".to_string(),
+                Err(e) => format!("event stream: {e}, reconnecting"),
+            };
+            if tx.send(Msg::Status(s)).is_err() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+    });
+}
+
+fn spawn_poll(ad: String, tx: mpsc::UnboundedSender<Msg>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let r = client::get_json(ad.clone(), "/v1/connections").await;
+            let m = match r {
+                Ok(v) => Msg::Connections(v),
+                Err(e) => Msg::Status(e.to_string()),
+            };
+            if tx.send(m).is_err() {
+                break;
+            }
+            let r = client::get_json(ad.clone(), "/v1/outbounds").await;
+            let m = match r {
+                Ok(v) => Msg::Outbounds(v),
+                Err(e) => Msg::Status(e.to_string()),
+            };
+            if tx.send(m).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+fn spawn_keys(tx: mpsc::UnboundedSender<Msg>) {
+    tokio::task::spawn_blocking(move || loop {
+        if tx.is_closed() {
+            break;
+        }
+        match event::poll(Duration::from_millis(200)) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(_) => break,
+        }
+        if let Ok(event::Event::Key(k)) = event::read() {
+            if k.kind == KeyEventKind::Press && tx.send(Msg::Key(k)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// blocking, 直到 按下 q
+pub async fn run(addr: Option<String>) -> anyhow::Result<()> {
+    let ad = client::get_real_addr(addr);
+
+    // 在 进入 全屏 前 检查 api server 是否 可用
+    let mut app = App::default();
+    app.set_connections(
+        client::get_json(ad.clone(), "/v1/connections")
+            .await
+            .with_context(|| format!("can't get connections from {ad}"))?,
+    );
+    app.outbounds = client::get_json(ad.clone(), "/v1/outbounds").await?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    spawn_events(ad.clone(), tx.clone());
+    spawn_poll(ad.clone(), tx.clone());
+    spawn_keys(tx.clone());
+
+    let _g = TermGuard::new()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    loop {
+        app.clamp_selected();
+        terminal.draw(|f| ui::draw(f, &app))?;
+
+        let Some(m) = rx.recv().await else {
+            break;
+        };
+        match m {
+            Msg::Event(e) => app.apply(e),
+            Msg::Connections(v) => app.set_connections(v),
+            Msg::Outbounds(v) => app.outbounds = v,
+            Msg::Status(s) => app.status = s,
+            Msg::Key(k) => match app.on_key(k) {
+                Action::None => {}
+                Action::Quit => break,
+                Action::Kill(cid) => {
+                    let ad = ad.clone();
+                    let tx = tx.clone();
+                    tokio::spawn(async move {
+                        let s = match client::kill_connection(&ad, &cid).await {
+                            Ok(_) => format!("killed {cid}"),
+                            Err(e) => format!("kill {cid} failed: {e}"),
+                        };
+                        let _ = tx.send(Msg::Status(s));
+                    });
+                }
+            },
+        }
+    }
+    Ok(())
+}
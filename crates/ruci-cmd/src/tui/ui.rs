@@ -0,0 +1,210 @@
+use bytesize::ByteSize;
+use ratatui::{
+    prelude::*,
+    widgets::{
+        Axis, Block, Borders, Cell, Chart, Dataset, GraphType, Paragraph, Row, Table, TableState,
+    },
+};
+
+use super::app::{App, HISTORY_LEN};
+
+fn human(n: u64) -> String {
+    ByteSize(n).to_string()
+}
+
+/// 连接 已 存在 的 时长
+fn age(time: &str) -> String {
+    let Ok(t) = chrono::DateTime::parse_from_rfc3339(time) else {
+        return String::new();
+    };
+    let s = (chrono::Utc::now() - t.with_timezone(&chrono::Utc))
+        .num_seconds()
+        .max(0);
+    match s {
+        0..=59 => format!("{s}s"),
+        60..=3599 => format!("{}m{}s", s / 60, s % 60),
+        _ => format!("{}h{}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+pub fn draw(f: &mut Frame, app: &App) {
+    let [chart, body, footer] = Layout::vertical([
+        Constraint::Length(10),
+        Constraint::Min(5),
+        Constraint::Length(1),
+    ])
+    .areas(f.size());
+    let [conns, outbounds] =
+        Layout::horizontal([Constraint::Percentage(75), Constraint::Percentage(25)]).areas(body);
+
+    draw_chart(f, app, chart);
+    draw_conns(f, app, conns);
+    draw_outbounds(f, app, outbounds);
+    draw_footer(f, app, footer);
+}
+
+fn draw_chart(f: &mut Frame, app: &App, area: Rect) {
+    // 新的 点 在 右边
+    let offset = (HISTORY_LEN - app.history.len()) as f64;
+    let points = |up: bool| -> Vec<(f64, f64)> {
+        app.history
+            .iter()
+            .enumerate()
+            .map(|(i, (u, d))| (offset + i as f64, if up { *u } else { *d } as f64))
+            .collect()
+    };
+    let up = points(true);
+    let down = points(false);
+    let max = app
+        .history
+        .iter()
+        .map(|(u, d)| *u.max(d))
+        .max()
+        .unwrap_or_default()
+        .max(1024);
+
+    let datasets = vec![
+        Dataset::default()
+            .name("up")
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Yellow))
+            .data(&up),
+        Dataset::default()
+            .name("down")
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Cyan))
+            .data(&down),
+    ];
+    let title = format!(
+        " throughput  ↑ {}/s  ↓ {}/s ",
+        human(app.total_rate.up),
+        human(app.total_rate.down)
+    );
+    let c = Chart::new(datasets)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .x_axis(Axis::default().bounds([0.0, HISTORY_LEN as f64]))
+        .y_axis(
+            Axis::default()
+                .bounds([0.0, max as f64 * 1.1])
+                .labels(vec![Span::raw("0"), Span::raw(format!("{}/s", human(max)))]),
+        );
+    f.render_widget(c, area);
+}
+
+fn draw_conns(f: &mut Frame, app: &App, area: Rect) {
+    let v = app.visible();
+    let rows = v.iter().map(|r| {
+        let c = &r.c;
+        Row::new(vec![
+            Cell::from(c.cid.clone()),
+            Cell::from(age(&c.time)),
+            Cell::from(format!("{} → {}", c.in_tag, c.out_tag)),
+            Cell::from(c.user.clone().unwrap_or_default()),
+            Cell::from(c.target_addr.clone()),
+            Cell::from(human(c.bytes.up)),
+            Cell::from(human(c.bytes.down)),
+            Cell::from(human(r.rate.up)),
+            Cell::from(human(r.rate.down)),
+        ])
+    });
+    let widths = [
+        Constraint::Length(6),
+        Constraint::Length(7),
+        Constraint::Fill(2),
+        Constraint::Length(10),
+        Constraint::Fill(3),
+        Constraint::Length(9),
+        Constraint::Length(9),
+        Constraint::Length(9),
+        Constraint::Length(9),
+    ];
+    let title = format!(
+        " connections {}/{}  sort: {}{} ",
+        v.len(),
+        app.conns.len(),
+        app.sort.name(),
+        if app.reverse { " (rev)" } else { "" }
+    );
+    let t = Table::new(rows, widths)
+        .header(
+            Row::new(vec![
+                "cid",
+                "age",
+                "in → out",
+                "user",
+                "target",
+                "↑",
+                "↓",
+                "↑/s",
+                "↓/s",
+            ])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    let mut state = TableState::default();
+    if !v.is_empty() {
+        state.select(Some(app.selected));
+    }
+    f.render_stateful_widget(t, area, &mut state);
+}
+
+fn draw_outbounds(f: &mut Frame, app: &App, area: Rect) {
+    let rows = app.outbounds.iter().map(|o| {
+        let (s, color) = match o.up {
+            Some(true) => ("up", Color::Green),
+            Some(false) => ("down", Color::Red),
+            None => ("-", Color::Gray),
+        };
+        Row::new(vec![
+            Cell::from(o.tag.clone()),
+            Cell::from(s).style(Style::default().fg(color)),
+            Cell::from(format!("{}/{}", o.dials_ok, o.dials_failed)),
+            Cell::from(human(o.bytes.up + o.bytes.down)),
+        ])
+    });
+    let widths = [
+        Constraint::Min(8),
+        Constraint::Length(5),
+        Constraint::Length(10),
+        Constraint::Length(10),
+    ];
+    let t = Table::new(rows, widths)
+        .header(
+            Row::new(vec!["tag", "", "ok/fail", "bytes"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::default().borders(Borders::ALL).title(" outbounds "));
+    f.render_widget(t, area);
+}
+
+fn draw_footer(f: &mut Frame, app: &App, area: Rect) {
+    let line = if app.editing_filter {
+        Line::from(vec![
+            Span::styled("filter: ", Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(format!("{}_", app.filter)),
+            Span::raw("   (tag: in: out: user: target:, enter to apply, esc to clear)"),
+        ])
+    } else {
+        let mut spans = vec![Span::raw(
+            "q quit  ↑↓ select  s sort  r reverse  / filter  x kill",
+        )];
+        if !app.filter.is_empty() {
+            spans.push(Span::styled(
+                format!("   filter: {}", app.filter),
+                Style::default().fg(Color::Yellow),
+            ));
+        }
+        if !app.status.is_empty() {
+            spans.push(Span::styled(
+                format!("   {}", app.status),
+                Style::default().fg(Color::Magenta),
+            ));
+        }
+        Line::from(spans)
+    };
+    f.render_widget(Paragraph::new(line), area);
+}
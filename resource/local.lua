@@ -90,13 +90,43 @@ local tlsout = {
         insecure = true
         -- alpn = {"http"}
 
+        -- ClientHello 的样子, preset 可为 rustls, chrome, firefox,
+        -- 其它项 (cipher_suites, kx_groups, versions, enable_sni) 会覆盖 preset 中的值.
+        -- rustls 不能控制 extension 顺序, GREASE 和 padding, 写了 extension_order, grease
+        -- 或 padding 的配置会加载失败
+        -- client_hello = { preset = "chrome" }
+
+        -- 若服务端要求验证客户端证书:
+        -- cert = "test_client.crt",
+        -- key = "test_client.key",
+
+        -- 用自定义 CA 或 系统信任库 验证服务端证书, 而不是内置的 webpki roots
+        -- ca = "test_server_ca.crt",
+        -- system_roots = true,
+
+        -- 固定服务端证书公钥的 sha256 (base64), 可用
+        -- openssl x509 -in test.crt -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64
+        -- 得到. 与 insecure = true 同用时, 只验证 pin, 适用于自签名证书
+        -- pinned_spki_sha256 = { "nh3X+2TUzdDqFvntdEH4Uf+iYgU3whi4iacDxiDEPp4=" },
+
     }
 }
 
 local tlsin = {
     TLS = {
         cert = "test.crt",
-        key = "test.key"
+        key = "test.key",
+        -- 验证客户端证书, 验证通过的用户可在 rule_route 的 userset 中用 "x509:证书的CN" 表示
+        -- client_ca = "test_client_ca.crt",
+
+        -- 后台每 3600 秒检查一次证书文件, 有变化则重新加载, 加载失败时保留当前证书. 不影响已建立的连接
+        -- reload_interval = 3600
+
+        -- 按客户端的 SNI 选择证书, 未匹配时使用上面的 cert/key. names 省略时从证书的 SAN 与 CN 中读取
+        -- 选出的 SNI 可在 rule_route 的 sni_matcher 中匹配
+        -- sni_certs = {
+        --     { names = { "*.mytest.com" }, cert = "test_server.crt", key = "test_server.key" },
+        -- },
     }
 }
 
@@ -152,6 +182,38 @@ local websocket_out = {
 local dial_trojan_chain = { dial, tlsout, trojan_out }
 local dial_ws_trojan_chain = { dial, tlsout, websocket_out, trojan_out }
 
+-- websocket over h2 (RFC 8441 extended CONNECT), tls 的 alpn 须为 h2.
+-- 像 H2Mux 一样, 在 动态链 中 可 复用 一个 h2 连接 (见 local_mux_h2.lua)
+local websocket_h2_out = {
+    WebSocketH2 = {
+        authority = "myhost",
+        path = "/path1",
+    }
+}
+local dial_ws_h2_trojan_chain = { dial, tlsout, websocket_h2_out, trojan_out }
+
+-- http upgrade: 握手 与 websocket 相同, 101 之后 为 原始数据流
+local http_upgrade_out = {
+    HttpUpgrade = {
+        authority = "myhost",
+        path = "/path1",
+    }
+}
+local dial_http_upgrade_trojan_chain = { dial, tlsout, http_upgrade_out, trojan_out }
+
+-- split http: 上行 为 多个 POST, 下行 为 一个 GET. 它 要 建立 多个 连接,
+-- 所以 自己 用 dial 链 拨号, 要 放在 链 的 开头
+local split_http_trojan_chain = { {
+    SplitHttp = {
+        http_config = {
+            authority = "myhost",
+            path = "/path1",
+        },
+        dial = { dial, tlsout },
+        max_post_size = 1000000,
+    }
+}, trojan_out }
+
 local h2_single_out = {
     H2Single = {
         is_grpc = true,
@@ -177,7 +239,65 @@ local quic_out_chain = { {
 
         server_name = "www.mytest.com",
 
-        alpn = { "h3" } --要明确指定 alpn
+        alpn = { "h3" }, --要明确指定 alpn
+
+        -- 连接池, 以下均为默认值. 每个连接的流数 达到 max_streams_per_conn 后 新建连接,
+        -- 空闲 idle_timeout 秒后 关闭连接, 建立连接失败时 按 reconnect_backoff_ms 指数退避重试
+        -- max_conns = 4,
+        -- max_streams_per_conn = 100,
+        -- idle_timeout = 60,
+        -- reconnect_attempts = 3,
+        -- reconnect_backoff_ms = 200,
+
+        -- 传输参数, 未给出时 使用 quic 实现的 默认值. 服务端 也可设置
+        -- congestion_control 可为 cubic (默认), bbr, new_reno (仅 quinn);
+        -- initial_window 为 初始拥塞窗口 字节数, 仅 quinn 支持. 高带宽 高延迟 的 链路 可 调大
+        -- congestion_control = "bbr",
+        -- initial_window = 1048576,
+        -- keep_alive_interval = 10,
+        -- max_idle_timeout = 30,
+
+        -- 恢复会话时 使用 0-RTT, 仅 quinn 支持, 服务端 也要打开 zero_rtt
+        -- zero_rtt = true,
+    }
+}, trojan_out }
+
+-- hysteria2 需要 quinn feature. 给出 up_mbps 时 使用 Brutal 拥塞控制, 否则 使用 BBR
+local hysteria2_out_chain = { {
+    Hysteria2 = {
+        server_addr = "127.0.0.1:10802",
+        server_name = "www.mytest.com",
+        auth = "mypassword",
+        cert_path = "test2.crt",
+        -- up_mbps = 50,
+        -- down_mbps = 100,
+    }
+} }
+
+-- tuic v5 需要 quinn feature. udp_relay_mode 为 native (datagram) 或 quic (单向流)
+local tuic_out_chain = { {
+    Tuic = {
+        server_addr = "127.0.0.1:10803",
+        server_name = "www.mytest.com",
+        cert_path = "test2.crt",
+        alpn = { "h3" },
+        uuid = "a3482e88-686a-4a58-8126-99c9df64b7bf",
+        password = "mypassword",
+        -- udp_relay_mode = "quic",
+    }
+} }
+
+-- h3 需要 quinn feature. H3Single 每个流 一个 quic 连接, H3Mux 使用 连接池. 配置 同 quic 与 h2
+local h3_out_chain = { {
+    H3Mux = {
+        server_addr = "127.0.0.1:10804",
+        server_name = "www.mytest.com",
+        cert_path = "test2.crt",
+        is_grpc = true,
+        http_config = {
+            authority = "myhost",
+            path = "/service1/Tun"
+        }
     }
 }, trojan_out }
 
@@ -313,6 +433,23 @@ local config_6_ws = {
 
 }
 
+local config_detour = {
+    inbounds = { { chain = listen_socks5http, tag = "listen1" } },
+    outbounds = {
+        { tag = "dial1", chain = { { Detour = { tag = "dial_a", dial_addr = "tcp://127.0.0.1:10801" } }, tlsout, trojan_out } },
+        { tag = "dial_a", chain = { { BindDialer = { dial_addr = "tcp://127.0.0.1:10802" } }, { Socks5 = {} } } },
+    },
+
+    --[[
+演示 串联 代理: client -> dial_a (socks5) -> dial1 (trojan) -> target
+
+dial1 的 Detour 代替了 BindDialer, 它 通过 dial_a 这个 出站 拨号 dial_addr, 得到 的 流 即 dial1 的 基础连接.
+被引用的 出站 会 在 每个 引用处 单独 初始化
+
+suit 模式 的 toml 中 对应的 是 dial 的 detour 项, 如 detour = "dial_a"
+--]]
+}
+
 local config_7_h2 = {
     inbounds = { { chain = listen_socks5http, tag = "listen1" } },
     outbounds = { { tag = "dial1", chain = dial_h2_trojan_chain } },
@@ -521,6 +658,10 @@ local config_15_tun = {
         -- 表示 255.255.255.0; ruci这里采用与 tcp 端口写法一致的格式, 便于处理
 
         { chain = { { BindDialer = { bind_addr = "ip://10.0.0.1:24#utun321" } } }, tag = "listen1" },
+
+        -- 加上 TunStack 后, ip 包会被 用户态协议栈 还原为 tcp/udp 流, 带有正确的目标地址,
+        -- 于是可以像其它 inbound 一样 接 direct 或 其它代理的 outbound, 或用 rule_route 分流
+        -- { chain = { { BindDialer = { bind_addr = "ip://10.0.0.1:24#utun321" } }, { TunStack = { mtu = 1500 } } }, tag = "listen1" },
     },
 
     --[[
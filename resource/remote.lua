@@ -25,6 +25,7 @@ local opt_direct_chain = { {
 
 local socks5_chain = { tcp, {
     Socks5 = {}
+    -- Socks5 = { user_store = "s1" } -- 启用 运行时 用户 存储 后 即使 没有 用户 也 须 鉴权
 } }
 local http_chain = { tcp, {
     Http = {}
@@ -46,6 +47,7 @@ local tls = {
 local trojan_in = {
     Trojan = {
         password = "mypassword"
+        -- user_store = "t1", -- 启用 名为 t1 的 运行时 用户 存储, 可 通过 api server 的 /users/trojan/t1 增删 用户
     }
 }
 
@@ -81,6 +83,36 @@ local ws_trojans_chain = { tcp, tls, http_filter, basic_ws, trojan_in }
 
 -- ws_trojans_chain = {tcp, tls, ws, trojan_in}
 
+-- websocket over h2, 为 流发生器. 不能 放在 http_filter 之后, 因为 h2 不是 http/1.1
+local ws_h2_trojans_chain = { tcp, tls, {
+    WebSocketH2 = {
+        http_config = {
+            authority = "myhost",
+            path = "/path1"
+        }
+    }
+}, trojan_in }
+
+-- http upgrade 与 split http 都 可像 http_filter 一样 回落
+local http_upgrade_trojans_chain = { tcp, tls, {
+    HttpUpgrade = {
+        http_config = {
+            authority = "myhost",
+            path = "/path1"
+        }
+    }
+}, trojan_in }
+
+-- split http 的 POST 连接 会被 消耗, 只有 GET 连接 产生 代理流
+local split_http_trojans_chain = { tcp, tls, {
+    SplitHttp = {
+        http_config = {
+            authority = "myhost",
+            path = "/path1"
+        }
+    }
+}, trojan_in }
+
 local in_h2_trojans_chain = { tcp, tls, {
     H2 = {
         is_grpc = true,
@@ -100,6 +132,47 @@ local in_quic_chain = { {
     }
 }, trojan_in }
 
+-- hysteria2 需要 quinn feature. users 中的 用户 以 user:pass 作为 auth
+local in_hysteria2_chain = { {
+    Hysteria2 = {
+        key_path = "test2.key",
+        cert_path = "test2.crt",
+        listen_addr = "127.0.0.1:10802",
+        password = "mypassword",
+        -- users = { "u1:p1" },
+        -- up_mbps = 100,
+        -- down_mbps = 100,
+        -- ignore_client_bandwidth = false,
+        -- disable_udp = false,
+    }
+} }
+
+-- tuic v5 需要 quinn feature. users 为 uuid:password
+local in_tuic_chain = { {
+    Tuic = {
+        key_path = "test2.key",
+        cert_path = "test2.crt",
+        listen_addr = "127.0.0.1:10803",
+        alpn = { "h3" },
+        users = { "a3482e88-686a-4a58-8126-99c9df64b7bf:mypassword" },
+        -- auth_timeout = 3,
+    }
+} }
+
+-- h3 需要 quinn feature. alpn 默认为 h3
+local in_h3_trojans_chain = { {
+    H3 = {
+        key_path = "test2.key",
+        cert_path = "test2.crt",
+        listen_addr = "127.0.0.1:10804",
+        is_grpc = true,
+        http_config = {
+            authority = "myhost",
+            path = "/service1/Tun"
+        }
+    }
+}, trojan_in }
+
 local dial = {
     BindDialer = {
         dial_addr = "tcp://0.0.0.0:10801"
@@ -120,8 +193,14 @@ Config = {
     inbounds = { --  { chain = trojan_chain,  tag = "listen1"}
         { chain = trojans_chain, tag = "listen1" },
         -- { chain = ws_trojans_chain,  tag = "listen1"  }
+        -- { chain = ws_h2_trojans_chain,  tag = "listen1"  }
+        -- { chain = http_upgrade_trojans_chain,  tag = "listen1"  }
+        -- { chain = split_http_trojans_chain,  tag = "listen1"  }
         -- { chain = in_h2_trojans_chain, tag = "listen1" }
         -- { chain = in_quic_chain, tag = "listen1" }
+        -- { chain = in_hysteria2_chain, tag = "listen1" }
+        -- { chain = in_tuic_chain, tag = "listen1" }
+        -- { chain = in_h3_trojans_chain, tag = "listen1" }
         -- { chain = socks5http_chain, tag = "listen1"} ,
         -- { chain =  { unix,tls, trojan_in }, tag = "listen1"} ,
         --[[
@@ -167,6 +246,29 @@ Config = {
 
     -- outbounds = { { tag="dial1", chain = out_stdio_chain  } }, --以命令行为出口
 
-    fallback_route = { { "listen1", "fallback_d" } }
+    fallback_route = { { "listen1", "fallback_d" } },
+
+    --[[
+    -- 用户 的 流量配额(字节), 到期时间(unix 秒) 与 每方向 限速(字节/秒).
+    -- user 为 用户 的 identity_str: socks5/http 为 用户名, trojan 为 密码 的 sha224 hex
+    user_limits = { {
+        user = "u0",
+        quota = 10 * 1024 * 1024 * 1024,
+        expire_at = 1893456000,
+        rate_limit = 1024 * 1024,
+    } },
+    -- ]]
+
+    --[[
+    -- 外部 鉴权 后端, 需 编译 时 打开 auth_file, auth_webhook 或 auth_sqlite feature.
+    -- 只用于 设置了 use_auth_providers = true 的 socks5/http 与 trojan 入站, 如
+    -- Socks5 = { use_auth_providers = true }; 启用 的 入站 即使 没有 配置 用户 也 须 鉴权.
+    -- cache_secs 为 成功结果 的 缓存 时长
+    auth_providers = {
+        { File = { path = "users.htpasswd", cache_secs = 300 } },
+        { Webhook = { url = "http://127.0.0.1:8080/auth", timeout_secs = 3, cache_secs = 60, trojan = false } },
+        { Sqlite = { path = "users.db", cache_secs = 300 } },
+    },
+    -- ]]
 
 }
@@ -0,0 +1,263 @@
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::bail;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use http::{Request, StatusCode};
+use macro_map::*;
+use quinn::Endpoint;
+use ruci::{
+    map::{self, Map, MapExtFields, MapResult, ProxyBehavior},
+    net::{self, CID},
+};
+use tracing::debug;
+
+use crate::map::{
+    quic_common::pool::{Guarded, Pool, PoolConn, PoolOptions},
+    rustls21,
+};
+
+use super::*;
+
+type SendRequest = ::h3::client::SendRequest<h3_quinn::OpenStreams, Bytes>;
+
+/// SendRequest 不是 Sync 的, 而 连接池 要求 Sync, 所以 放在 Mutex 中, 用时 clone 一份 出来
+#[derive(Clone)]
+struct ConnState {
+    conn: quinn::Connection,
+    send_request: Arc<Mutex<SendRequest>>,
+}
+
+impl ConnState {
+    fn send_request(&self) -> SendRequest {
+        self.send_request.lock().expect("lock send_request").clone()
+    }
+}
+
+impl Debug for ConnState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnState")
+            .field("conn", &self.conn.stable_id())
+            .finish()
+    }
+}
+
+impl PoolConn for ConnState {
+    fn is_closed(&self) -> bool {
+        self.conn.close_reason().is_some()
+    }
+
+    fn close(&self) {
+        self.conn.close(0u32.into(), b"idle");
+    }
+}
+
+/// SingleClient 与 MuxClient 共用的 部分
+#[derive(Debug, Clone)]
+struct Dialer {
+    c: Endpoint,
+    server_addr: SocketAddr,
+    server_name: String,
+
+    is_grpc: bool,
+    req: Request<()>,
+}
+
+impl Dialer {
+    fn new(c: &ClientConfig) -> anyhow::Result<Self> {
+        let tls = rustls21::cc(rustls21::ClientOptions {
+            is_insecure: c.quic.is_insecure.unwrap_or_default(),
+            alpn: alpn(c.quic.alpn.clone()),
+            cert_path: c.quic.cert_path.clone(),
+            early_data: false,
+        })?;
+        let mut qc = quinn::ClientConfig::new(Arc::new(tls));
+        qc.transport_config(Arc::new(crate::map::quinn::transport_config(
+            &c.quic.transport,
+        )?));
+        let mut endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
+        endpoint.set_default_client_config(qc);
+
+        let is_grpc = c.is_grpc.unwrap_or_default();
+        let req = match &c.http_config {
+            Some(hc) if is_grpc => grpc::build_grpc_request_from(hc),
+            Some(hc) => crate::net::http::build_request_from(hc, "https://"),
+            None => {
+                let mut r = Request::post(format!("https://{}/", c.quic.server_name));
+                if is_grpc {
+                    r = r.header(grpc::CONTENT_TYPE, grpc::GRPC_CONTENT_TYPE);
+                }
+                r.body(())?
+            }
+        };
+
+        Ok(Self {
+            c: endpoint,
+            server_addr: c.quic.server_addr.parse()?,
+            server_name: c.quic.server_name.clone(),
+            is_grpc,
+            req,
+        })
+    }
+
+    async fn connect(&self, cid: &CID) -> anyhow::Result<ConnState> {
+        let conn = self.c.connect(self.server_addr, &self.server_name)?.await?;
+
+        let (mut driver, send_request) =
+            ::h3::client::new(h3_quinn::Connection::new(conn.clone())).await?;
+        let cidc = cid.clone();
+        tokio::spawn(async move {
+            let r = futures::future::poll_fn(|cx| driver.poll_close(cx)).await;
+            debug!(cid = %cidc, "h3 connection closed: {r:?}");
+        });
+
+        debug!(cid = %cid, "inited new h3 connection");
+        Ok(ConnState {
+            conn,
+            send_request: Arc::new(Mutex::new(send_request)),
+        })
+    }
+
+    /// 发出 请求, 收到 200 后 返回 数据流
+    async fn open(
+        &self,
+        send_request: &mut SendRequest,
+    ) -> anyhow::Result<(DuplexStream, JoinHandle<()>)> {
+        let mut stream = send_request.send_request(self.req.clone()).await?;
+        let resp = stream.recv_response().await?;
+        if resp.status() != StatusCode::OK {
+            bail!("h3 server responded {}", resp.status())
+        }
+        let (send, recv) = stream.split();
+        Ok(pump(send, recv, self.is_grpc))
+    }
+}
+
+/// SingleClient 不使用 h3 的多路复用特性, 每个流 一个 quic 连接, 流结束后 关闭连接
+#[map_ext_fields]
+#[derive(Clone, Debug, MapExt)]
+pub struct SingleClient {
+    d: Dialer,
+}
+
+impl ruci::Name for SingleClient {
+    fn name(&self) -> &str {
+        "h3_single_client"
+    }
+}
+
+impl SingleClient {
+    pub fn new(c: ClientConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            d: Dialer::new(&c)?,
+            ext_fields: Some(MapExtFields::default()),
+        })
+    }
+
+    async fn handshake(
+        &self,
+        cid: CID,
+        a: Option<net::Addr>,
+        early_data: Option<BytesMut>,
+    ) -> anyhow::Result<map::MapResult> {
+        let state = self.d.connect(&cid).await?;
+        let (stream, h) = self.d.open(&mut state.send_request()).await?;
+
+        // 要 持有 整个 state 直到 流 结束: SendRequest 全部 drop 后 h3 就会 关闭 连接
+        tokio::spawn(async move {
+            let _ = h.await;
+            state.conn.close(0u32.into(), b"");
+            drop(state);
+            debug!(cid = %cid, "h3 single stream ended");
+        });
+
+        Ok(MapResult::new_c(Box::new(stream))
+            .a(a)
+            .b(early_data)
+            .build())
+    }
+}
+
+#[async_trait]
+impl Map for SingleClient {
+    async fn maps(&self, cid: CID, _behavior: ProxyBehavior, params: map::MapParams) -> MapResult {
+        if let net::Stream::None = params.c {
+            let r = self.handshake(cid, params.a, params.b).await;
+            match r {
+                anyhow::Result::Ok(r) => r,
+                Err(e) => MapResult::from_e(e.context("h3_single_client handshake failed")),
+            }
+        } else {
+            MapResult::err_str("h3_single_client only support None stream")
+        }
+    }
+}
+
+/// MuxClient 在 连接池 的 连接上 为 每个流 发出 一个 请求
+#[map_ext_fields]
+#[derive(Clone, Debug, MapExt)]
+pub struct MuxClient {
+    d: Dialer,
+    pool: Pool<ConnState>,
+}
+
+impl ruci::Name for MuxClient {
+    fn name(&self) -> &str {
+        "h3_mux_client"
+    }
+}
+
+impl MuxClient {
+    pub fn new(c: ClientConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            d: Dialer::new(&c)?,
+            pool: Pool::new(PoolOptions::from_config(&c.quic)),
+            ext_fields: Some(MapExtFields::default()),
+        })
+    }
+
+    async fn handshake(
+        &self,
+        cid: CID,
+        a: Option<net::Addr>,
+        early_data: Option<BytesMut>,
+    ) -> anyhow::Result<map::MapResult> {
+        // 连接可能 在 取出后 才发现已断开, 此时 关闭它 并重试一次
+        let mut retried = false;
+        loop {
+            let (state, guard) = self.pool.get(|| self.d.connect(&cid)).await?;
+
+            let stream = match self.d.open(&mut state.send_request()).await {
+                Ok((s, _)) => s,
+                // 只有 h3 本身的错误 才可能是 连接 已断开, 状态码 不对 不用重试
+                Err(e) if !retried && e.downcast_ref::<::h3::Error>().is_some() => {
+                    debug!(cid = %cid, "h3 mux client open stream failed, reconnecting: {e}");
+                    state.close();
+                    retried = true;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let c: net::Conn = Box::new(Guarded::new(stream, guard));
+
+            return Ok(MapResult::new_c(c).a(a).b(early_data).build());
+        }
+    }
+}
+
+#[async_trait]
+impl Map for MuxClient {
+    async fn maps(&self, cid: CID, _behavior: ProxyBehavior, params: map::MapParams) -> MapResult {
+        if let net::Stream::None = params.c {
+            let r = self.handshake(cid, params.a, params.b).await;
+            match r {
+                anyhow::Result::Ok(r) => r,
+                Err(e) => MapResult::from_e(e.context("h3_mux_client handshake failed")),
+            }
+        } else {
+            MapResult::err_str("h3_mux_client only support None stream")
+        }
+    }
+}
@@ -0,0 +1,187 @@
+/*!
+Defines Maps for h3 and grpc over h3. uses quinn.
+
+与 [`crate::map::h2`] 的 形状 相同: SingleClient 每个流 建立 一个 quic 连接, MuxClient 复用 连接池 中的 连接,
+Server 为 流发生器. 不同的是 h3 要 自己持有 quic 连接, 所以 都只接受 None stream.
+
+被代理的 数据 放在 请求 与 响应 的 body 中. h3 的 RequestStream 只有 async 方法, 所以 用 [`pump`]
+在 一个 duplex 管道 与 它 之间 搬运 数据, 管道的 另一端 即为 Conn.
+ */
+
+pub mod client;
+pub mod server;
+
+#[cfg(test)]
+mod test;
+
+use std::io;
+
+use async_trait::async_trait;
+use bytes::{Buf, Bytes, BytesMut};
+use ruci::net::http::CommonConfig;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, DuplexStream},
+    task::JoinHandle,
+};
+use tracing::debug;
+
+use crate::map::{h2::grpc, quic_common};
+
+const BUFFER_CAP: usize = 0x4000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerConfig {
+    #[serde(flatten)]
+    pub quic: quic_common::ServerConfig,
+
+    pub is_grpc: Option<bool>,
+    pub http_config: Option<CommonConfig>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClientConfig {
+    #[serde(flatten)]
+    pub quic: quic_common::ClientConfig,
+
+    pub is_grpc: Option<bool>,
+    pub http_config: Option<CommonConfig>,
+}
+
+/// 未指定 alpn 时 使用 h3
+pub fn alpn(a: Option<Vec<String>>) -> Option<Vec<String>> {
+    Some(a.unwrap_or_else(|| vec!["h3".to_string()]))
+}
+
+/// h3 client 与 server 的 RequestStream 是 不同的类型, 用它 统一 body 的 发送
+#[async_trait]
+pub trait BodySend: Send + 'static {
+    async fn send(&mut self, b: Bytes) -> Result<(), ::h3::Error>;
+    async fn finish(&mut self) -> Result<(), ::h3::Error>;
+}
+
+#[async_trait]
+pub trait BodyRecv: Send + 'static {
+    async fn recv(&mut self) -> Result<Option<Bytes>, ::h3::Error>;
+}
+
+macro_rules! impl_body {
+    ($m:ident) => {
+        #[async_trait]
+        impl BodySend for ::h3::$m::RequestStream<h3_quinn::SendStream<Bytes>, Bytes> {
+            async fn send(&mut self, b: Bytes) -> Result<(), ::h3::Error> {
+                self.send_data(b).await
+            }
+            async fn finish(&mut self) -> Result<(), ::h3::Error> {
+                ::h3::$m::RequestStream::finish(self).await
+            }
+        }
+
+        #[async_trait]
+        impl BodyRecv for ::h3::$m::RequestStream<h3_quinn::RecvStream, Bytes> {
+            async fn recv(&mut self) -> Result<Option<Bytes>, ::h3::Error> {
+                Ok(self
+                    .recv_data()
+                    .await?
+                    .map(|mut b| b.copy_to_bytes(b.remaining())))
+            }
+        }
+    };
+}
+
+impl_body!(client);
+impl_body!(server);
+
+/// 从 grpc 帧 中 取出 数据, 帧 为 `flag(u8) | len(u32) | 0x0a | uvarint | data`
+#[derive(Debug, Default)]
+pub struct GrpcDecoder {
+    buf: BytesMut,
+}
+
+impl GrpcDecoder {
+    pub fn feed(&mut self, b: &[u8]) {
+        self.buf.extend_from_slice(b);
+    }
+
+    /// 没有 完整的帧 时 返回 None
+    pub fn next_frame(&mut self) -> io::Result<Option<BytesMut>> {
+        if self.buf.len() < 5 {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes([self.buf[1], self.buf[2], self.buf[3], self.buf[4]]) as usize;
+        if self.buf.len() < 5 + len {
+            return Ok(None);
+        }
+        let mut frame = self.buf.split_to(5 + len);
+        frame.advance(5);
+        if frame.is_empty() {
+            return Ok(Some(frame));
+        }
+        if frame.get_u8() != 0x0a {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "grpc frame is not a bytes field",
+            ));
+        }
+        let (l, e) = grpc::read_uvarint(&mut frame);
+        if let Some(e) = e {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+        }
+        if frame.len() < l as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "grpc frame data too short",
+            ));
+        }
+        frame.truncate(l as usize);
+        Ok(Some(frame))
+    }
+}
+
+/// 在 返回的 DuplexStream 与 h3 的 body 间 搬运 数据, 两个方向 都结束后 返回的 JoinHandle 完成
+pub fn pump<S: BodySend, R: BodyRecv>(
+    mut send: S,
+    mut recv: R,
+    is_grpc: bool,
+) -> (DuplexStream, JoinHandle<()>) {
+    let (a, b) = tokio::io::duplex(BUFFER_CAP);
+    let (mut br, mut bw) = tokio::io::split(b);
+
+    let h = tokio::spawn(async move {
+        let up = async {
+            let mut buf = vec![0u8; BUFFER_CAP];
+            loop {
+                let n = br.read(&mut buf).await?;
+                if n == 0 {
+                    send.finish().await.map_err(io::Error::other)?;
+                    return io::Result::Ok(());
+                }
+                let data = if is_grpc {
+                    grpc::encode(&buf[..n], false).freeze()
+                } else {
+                    Bytes::copy_from_slice(&buf[..n])
+                };
+                send.send(data).await.map_err(io::Error::other)?;
+            }
+        };
+        let down = async {
+            let mut dec = GrpcDecoder::default();
+            while let Some(b) = recv.recv().await.map_err(io::Error::other)? {
+                if !is_grpc {
+                    bw.write_all(&b).await?;
+                    continue;
+                }
+                dec.feed(&b);
+                while let Some(f) = dec.next_frame()? {
+                    bw.write_all(&f).await?;
+                }
+            }
+            bw.shutdown().await
+        };
+        let (r1, r2) = tokio::join!(up, down);
+        if let Err(e) = r1.and(r2) {
+            debug!("h3 pump ended with e: {e}");
+        }
+    });
+    (a, h)
+}
@@ -0,0 +1,163 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use http::{header::CONTENT_TYPE, Response, StatusCode};
+use macro_map::*;
+use quinn::Endpoint;
+use ruci::{
+    map::{self, Map, MapExtFields, MapResult, ProxyBehavior},
+    net::{self, CID},
+};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::map::{h2::grpc::GRPC_CONTENT_TYPE, rustls21};
+
+use super::*;
+
+type H3Conn = ::h3::server::Connection<h3_quinn::Connection, Bytes>;
+
+#[map_ext_fields]
+#[derive(Clone, Debug, MapExt)]
+pub struct Server {
+    tls_key_path: String,
+    tls_cert_path: String,
+    listen_addr: String,
+    alpn: Option<Vec<String>>,
+    transport: quic_common::TransportOptions,
+
+    is_grpc: bool,
+    http_config: Option<CommonConfig>,
+
+    a_next_cid: Arc<AtomicU32>,
+}
+
+impl ruci::Name for Server {
+    fn name(&self) -> &str {
+        "h3_server"
+    }
+}
+
+impl Server {
+    pub fn new(c: ServerConfig) -> Self {
+        Self {
+            tls_key_path: c.quic.key_path,
+            tls_cert_path: c.quic.cert_path,
+            listen_addr: c.quic.listen_addr,
+            alpn: alpn(c.quic.alpn),
+            transport: c.quic.transport,
+            is_grpc: c.is_grpc.unwrap_or_default(),
+            http_config: c.http_config,
+            a_next_cid: Arc::new(AtomicU32::new(1)),
+            ext_fields: Some(MapExtFields::default()),
+        }
+    }
+
+    async fn start_listen(&self, cid: CID) -> anyhow::Result<map::MapResult> {
+        let tls = rustls21::sc(rustls21::ServerOptions {
+            alpn: self.alpn.clone(),
+            cert_path: self.tls_cert_path.clone(),
+            key_path: self.tls_key_path.clone(),
+            early_data: false,
+        })?;
+        let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(tls));
+        server_config.transport_config(Arc::new(crate::map::quinn::transport_config(
+            &self.transport,
+        )?));
+
+        let endpoint = Endpoint::server(server_config, self.listen_addr.parse()?)?;
+
+        let (tx, rx) = mpsc::channel(100);
+
+        let s = self.clone();
+        let cidc = cid.clone();
+        tokio::spawn(async move {
+            while let Some(connecting) = endpoint.accept().await {
+                let mut new_cid = cidc.clone();
+                new_cid.push_num(s.a_next_cid.fetch_add(1, Ordering::Relaxed));
+
+                let s = s.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = s.handle_conn(new_cid.clone(), connecting, tx).await {
+                        debug!(cid = %new_cid, "h3 server conn ended: {e:#}");
+                    }
+                });
+            }
+        });
+
+        debug!(cid = %cid , laddr= self.listen_addr.as_str(), "h3 server started");
+        Ok(MapResult::builder().c(net::Stream::Generator(rx)).build())
+    }
+
+    async fn handle_conn(
+        &self,
+        cid: CID,
+        connecting: quinn::Connecting,
+        tx: mpsc::Sender<MapResult>,
+    ) -> anyhow::Result<()> {
+        let conn = connecting.await?;
+        debug!(cid = %cid, raddr = ?conn.remote_address(), "h3 server got new conn");
+
+        let mut h3c: H3Conn =
+            ::h3::server::Connection::new(h3_quinn::Connection::new(conn)).await?;
+        let mut s_count = 0u32;
+
+        while let Some((req, mut stream)) = h3c.accept().await? {
+            if let Some(c) = &self.http_config {
+                let r = if self.is_grpc {
+                    grpc::match_grpc_request_header(&req)
+                        .and_then(|_| crate::net::http::match_request_http_header(c, &req))
+                } else {
+                    crate::net::http::match_request_http_header(c, &req)
+                };
+                if let Err(e) = r {
+                    warn!(cid = %cid, e = %e, "h3 server accept got wrong http header");
+                    let resp = Response::builder().status(StatusCode::NOT_FOUND).body(())?;
+                    let _ = stream.send_response(resp).await;
+                    let _ = stream.finish().await;
+                    continue;
+                }
+            }
+
+            let mut resp = Response::builder().status(StatusCode::OK);
+            if self.is_grpc {
+                resp = resp.header(CONTENT_TYPE, GRPC_CONTENT_TYPE);
+            }
+            if let Err(e) = stream.send_response(resp.body(())?).await {
+                warn!(cid = %cid, "h3 server send response got error: {e}");
+                continue;
+            }
+
+            s_count += 1;
+            let mut ncid = cid.clone();
+            ncid.push_num(s_count);
+
+            let (send, recv) = stream.split();
+            let (c, _) = pump(send, recv, self.is_grpc);
+
+            let m = MapResult::new_c(Box::new(c)).new_id(ncid).build();
+            if let Err(e) = tx.send(m).await {
+                warn!(cid = %cid, "h3 send tx got error: {e}");
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Map for Server {
+    async fn maps(&self, cid: CID, _behavior: ProxyBehavior, params: map::MapParams) -> MapResult {
+        if let net::Stream::None = params.c {
+            let r = self.start_listen(cid).await;
+            match r {
+                anyhow::Result::Ok(r) => r,
+                Err(e) => MapResult::from_e(e.context("h3_server maps failed")),
+            }
+        } else {
+            MapResult::err_str("h3_server only support None stream")
+        }
+    }
+}
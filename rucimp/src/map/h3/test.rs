@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use ruci::{
+    map::{Map, MapParams, ProxyBehavior},
+    net::{Stream, CID},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::mpsc,
+};
+
+use super::*;
+use crate::net::http::test_config;
+
+#[test]
+fn grpc_decoder() -> io::Result<()> {
+    let mut d = GrpcDecoder::default();
+    let mut all = BytesMut::new();
+    all.extend_from_slice(&grpc::encode(b"hello", false));
+    all.extend_from_slice(&grpc::encode(&[7u8; 300], false));
+
+    // 分开 喂入, 帧 跨越 边界
+    d.feed(&all[..3]);
+    assert!(d.next_frame()?.is_none());
+    d.feed(&all[3..20]);
+    assert_eq!(&d.next_frame()?.expect("first")[..], b"hello");
+    assert!(d.next_frame()?.is_none());
+    d.feed(&all[20..]);
+    assert_eq!(&d.next_frame()?.expect("second")[..], &[7u8; 300]);
+    assert!(d.next_frame()?.is_none());
+
+    d.feed(&[0, 0, 0, 0, 2, 0x0b, 0]);
+    assert!(d.next_frame().is_err());
+    Ok(())
+}
+
+fn configs(port: u16, is_grpc: bool) -> (ServerConfig, ClientConfig) {
+    let res = concat!(env!("CARGO_MANIFEST_DIR"), "/../resource/");
+    let http_config = test_config("/path1");
+    (
+        ServerConfig {
+            quic: quic_common::ServerConfig {
+                key_path: format!("{res}test.key"),
+                cert_path: format!("{res}test.crt"),
+                listen_addr: format!("127.0.0.1:{port}"),
+                ..Default::default()
+            },
+            is_grpc: Some(is_grpc),
+            http_config: Some(http_config.clone()),
+        },
+        ClientConfig {
+            quic: quic_common::ClientConfig {
+                server_addr: format!("127.0.0.1:{port}"),
+                server_name: "www.mytest.com".to_string(),
+                is_insecure: Some(true),
+                ..Default::default()
+            },
+            is_grpc: Some(is_grpc),
+            http_config: Some(http_config),
+        },
+    )
+}
+
+async fn start_server(sc: ServerConfig) -> mpsc::Receiver<ruci::map::MapResult> {
+    let r = server::Server::new(sc)
+        .maps(CID::default(), ProxyBehavior::DECODE, MapParams::default())
+        .await;
+    let Stream::Generator(g) = r.c else {
+        panic!("h3 server should return a generator, {:?}", r.e)
+    };
+    g
+}
+
+async fn echo_once(
+    client: &dyn Map,
+    g: &mut mpsc::Receiver<ruci::map::MapResult>,
+) -> anyhow::Result<()> {
+    let r = client
+        .maps(CID::default(), ProxyBehavior::ENCODE, MapParams::default())
+        .await;
+    let Stream::Conn(mut c) = r.c else {
+        panic!("client should return a conn, {:?}", r.e)
+    };
+    let sr = tokio::time::timeout(Duration::from_secs(5), g.recv())
+        .await?
+        .expect("server got stream");
+    let Stream::Conn(mut s) = sr.c else {
+        panic!("server should return a conn")
+    };
+
+    let big = vec![3u8; 100_000];
+    c.write_all(&big).await?;
+    let mut buf = vec![0u8; big.len()];
+    tokio::time::timeout(Duration::from_secs(5), s.read_exact(&mut buf)).await??;
+    assert_eq!(buf, big);
+
+    s.write_all(b"world").await?;
+    s.shutdown().await?;
+    let mut buf = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), c.read_to_end(&mut buf)).await??;
+    assert_eq!(buf, b"world");
+    Ok(())
+}
+
+#[tokio::test]
+async fn single_and_mux() -> anyhow::Result<()> {
+    for is_grpc in [false, true] {
+        let port = ruci::net::gen_random_higher_port();
+        let (sc, cc) = configs(port, is_grpc);
+        let mut g = start_server(sc).await;
+
+        let single = client::SingleClient::new(cc.clone())?;
+        echo_once(&single, &mut g).await?;
+
+        let mux = client::MuxClient::new(cc)?;
+        echo_once(&mux, &mut g).await?;
+        echo_once(&mux, &mut g).await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn wrong_path() -> anyhow::Result<()> {
+    let port = ruci::net::gen_random_higher_port();
+    let (sc, mut cc) = configs(port, false);
+    let mut g = start_server(sc).await;
+
+    cc.http_config.as_mut().unwrap().path = "/other".to_string();
+    let client = client::MuxClient::new(cc)?;
+    let r = client
+        .maps(CID::default(), ProxyBehavior::ENCODE, MapParams::default())
+        .await;
+    assert!(r.e.is_some());
+    assert!(tokio::time::timeout(Duration::from_millis(300), g.recv())
+        .await
+        .is_err());
+    Ok(())
+}
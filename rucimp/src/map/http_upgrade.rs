@@ -0,0 +1,263 @@
+/*!
+Defines Maps for HTTPUpgrade.
+
+与 websocket 的 握手 相同, 但 101 之后 直接 是 原始的 数据流, 没有 websocket 的 帧.
+用于 会破坏 websocket 帧 但 允许 http upgrade 的 cdn.
+
+服务端 像 [`ruci::map::http_filter`] 一样 匹配 请求, 不匹配时 返回 原数据 以 支持 回落.
+ */
+
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use bytes::BytesMut;
+use macro_map::*;
+use ruci::{
+    map::{self, Map, MapResult, ProxyBehavior},
+    net::{
+        self,
+        helpers::EarlyDataWrapper,
+        http::{parse_h1_request, CommonConfig, Method},
+        CID,
+    },
+};
+use tokio::io::AsyncWriteExt;
+use tracing::debug;
+
+use crate::net::http::{match_h1_host, parse_h1_status, push_h1_headers, read_h1_head};
+
+const RESPONSE: &str =
+    "HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\r\n";
+
+#[map_ext_fields]
+#[derive(Clone, Debug, Default, MapExt)]
+pub struct Client {
+    request: String,
+}
+
+impl ruci::Name for Client {
+    fn name(&self) -> &str {
+        "http_upgrade_client"
+    }
+}
+
+impl Client {
+    pub fn new(c: CommonConfig) -> Self {
+        let mut request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n",
+            c.path, c.authority
+        );
+        push_h1_headers(&mut request, &c);
+        request.push_str("\r\n");
+
+        Self {
+            request,
+            ..Default::default()
+        }
+    }
+
+    async fn handshake(
+        &self,
+        mut conn: net::Conn,
+        a: Option<net::Addr>,
+        b: Option<BytesMut>,
+    ) -> anyhow::Result<map::MapResult> {
+        conn.write_all(self.request.as_bytes()).await?;
+        conn.flush().await?;
+
+        let (head, rest) = read_h1_head(&mut conn, BytesMut::new()).await?;
+        let status = parse_h1_status(&head)?;
+        if status != 101 {
+            bail!("http_upgrade client got resp status not 101: {status}");
+        }
+
+        let c: net::Conn = if rest.is_empty() {
+            conn
+        } else {
+            Box::new(EarlyDataWrapper::from(rest, conn))
+        };
+        Ok(MapResult::new_c(c).a(a).b(b).build())
+    }
+}
+
+#[async_trait]
+impl Map for Client {
+    async fn maps(&self, _cid: CID, _behavior: ProxyBehavior, params: map::MapParams) -> MapResult {
+        if let net::Stream::Conn(conn) = params.c {
+            let r = self.handshake(conn, params.a, params.b).await;
+            match r {
+                anyhow::Result::Ok(r) => r,
+                Err(e) => MapResult::from_e(e.context("http_upgrade_client maps failed")),
+            }
+        } else {
+            MapResult::err_str("http_upgrade_client only support tcplike stream")
+        }
+    }
+}
+
+#[map_ext_fields]
+#[derive(Clone, Debug, Default, MapExt)]
+pub struct Server {
+    pub config: Option<CommonConfig>,
+}
+
+impl ruci::Name for Server {
+    fn name(&self) -> &str {
+        "http_upgrade_server"
+    }
+}
+
+impl Server {
+    async fn handshake(
+        &self,
+        cid: CID,
+        mut conn: net::Conn,
+        a: Option<net::Addr>,
+        b: Option<BytesMut>,
+    ) -> MapResult {
+        let (head, rest) = match read_h1_head(&mut conn, b.unwrap_or_default()).await {
+            Ok(r) => r,
+            Err(e) => return MapResult::from_e(e.context("http_upgrade_server read head failed")),
+        };
+
+        let r = parse_h1_request(&head, false);
+        let e = if r.parse_result != Ok(()) {
+            Some(anyhow!("http_upgrade parse failed {:?}", r.parse_result))
+        } else if r.method != Method::GET {
+            Some(anyhow!("http_upgrade got method {:?}", r.method))
+        } else if let Some(c) = &self.config {
+            if let Err(e) = match_h1_host(c, &r) {
+                Some(anyhow!("http_upgrade got wrong host, cid={cid}, {e}"))
+            } else if c.path != r.path {
+                Some(anyhow!(
+                    "http_upgrade got wrong path, cid={cid}, given={}, expected={}",
+                    r.path,
+                    c.path
+                ))
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+        if let Some(e) = e {
+            let mut buf = head;
+            buf.unsplit(rest);
+            return MapResult::ebc(e, buf, conn);
+        }
+
+        if let Err(e) = conn.write_all(RESPONSE.as_bytes()).await {
+            return MapResult::from_e(e);
+        }
+        debug!(cid = %cid, "http_upgrade server upgraded");
+
+        MapResult::new_c(conn)
+            .a(a)
+            .b(if rest.is_empty() { None } else { Some(rest) })
+            .build()
+    }
+}
+
+#[async_trait]
+impl Map for Server {
+    async fn maps(&self, cid: CID, _behavior: ProxyBehavior, params: map::MapParams) -> MapResult {
+        if let net::Stream::Conn(conn) = params.c {
+            self.handshake(cid, conn, params.a, params.b).await
+        } else {
+            MapResult::err_str("http_upgrade_server only support tcplike stream")
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ruci::map::MapParams;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::net::http::test_config;
+
+    #[tokio::test]
+    async fn upgrade() -> anyhow::Result<()> {
+        let (c, s) = tokio::io::duplex(4096);
+        let server = Server {
+            config: Some(test_config("/path1")),
+            ..Default::default()
+        };
+        let sh = tokio::spawn(async move {
+            server
+                .maps(
+                    CID::default(),
+                    ProxyBehavior::DECODE,
+                    MapParams::new(Box::new(s)),
+                )
+                .await
+        });
+
+        let r = Client::new(test_config("/path1"))
+            .maps(
+                CID::default(),
+                ProxyBehavior::ENCODE,
+                MapParams::new(Box::new(c)),
+            )
+            .await;
+        let net::Stream::Conn(mut c) = r.c else {
+            panic!("client should return a conn, {:?}", r.e)
+        };
+        c.write_all(b"hello").await?;
+
+        let r = sh.await?;
+        let net::Stream::Conn(mut s) = r.c else {
+            panic!("server should return a conn, {:?}", r.e)
+        };
+        let mut buf = r.b.map(|b| b.to_vec()).unwrap_or_default();
+        while buf.len() < 5 {
+            let mut b = [0u8; 5];
+            let n = s.read(&mut b).await?;
+            buf.extend_from_slice(&b[..n]);
+        }
+        assert_eq!(buf, b"hello");
+
+        s.write_all(b"world").await?;
+        let mut buf = [0u8; 5];
+        c.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"world");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn wrong_path_fallback() -> anyhow::Result<()> {
+        let (c, s) = tokio::io::duplex(4096);
+        let server = Server {
+            config: Some(test_config("/path1")),
+            ..Default::default()
+        };
+        let sh = tokio::spawn(async move {
+            server
+                .maps(
+                    CID::default(),
+                    ProxyBehavior::DECODE,
+                    MapParams::new(Box::new(s)),
+                )
+                .await
+        });
+        let ch = tokio::spawn(async move {
+            Client::new(test_config("/other"))
+                .maps(
+                    CID::default(),
+                    ProxyBehavior::ENCODE,
+                    MapParams::new(Box::new(c)),
+                )
+                .await
+        });
+
+        let r = sh.await?;
+        assert!(r.e.is_some());
+        assert!(r.c.is_some());
+        assert!(r.b.expect("fallback data").starts_with(b"GET /other "));
+
+        // 回落的 conn 被 drop 后 客户端 读到 eof
+        drop(r.c);
+        assert!(ch.await?.e.is_some());
+        Ok(())
+    }
+}
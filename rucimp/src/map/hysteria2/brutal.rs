@@ -0,0 +1,196 @@
+/*!
+Brutal 拥塞控制: 不因丢包 降速, 按 给定的速率 发送, 并按 确认率 补偿丢包.
+
+窗口 = 速率 * rtt * 2 / 确认率, 确认率 为 最近 5 秒 内 确认的字节 / (确认的 + 丢失的), 最低 0.8.
+quinn 的 pacer 按 窗口 与 rtt 发送, 所以 只需 计算窗口.
+
+速率 为 0 时 (如 认证前, 或 服务端 要求 自动), 退化为 BBR.
+
+服务端 的 TransportConfig 由 所有连接 共用, 所以 用 [`PerConnBrutal`], 每个连接 有 自己的 速率,
+认证后 用 [`rate_of`] 取得 并 设置.
+ */
+
+use std::{
+    any::Any,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use quinn::congestion::{BbrConfig, Controller, ControllerFactory};
+use quinn_proto::RttEstimator;
+
+const SLOT_COUNT: usize = 5;
+const MIN_SAMPLE_COUNT: u64 = 50;
+const MIN_ACK_RATE: f64 = 0.8;
+const MIN_WINDOW_PACKETS: u64 = 10;
+const INITIAL_RTT: Duration = Duration::from_millis(333);
+
+/// 速率 单位为 字节/秒, 创建后 仍可通过 [`BrutalConfig::rate`] 修改
+#[derive(Debug, Clone, Default)]
+pub struct BrutalConfig {
+    pub rate: Arc<AtomicU64>,
+}
+
+impl BrutalConfig {
+    pub fn new(bps: u64) -> Self {
+        Self {
+            rate: Arc::new(AtomicU64::new(bps)),
+        }
+    }
+}
+
+impl ControllerFactory for BrutalConfig {
+    fn build(&self, now: Instant, current_mtu: u16) -> Box<dyn Controller> {
+        Box::new(Brutal::new(self.rate.clone(), now, current_mtu))
+    }
+}
+
+/// 每个 连接 的 速率 独立, 初始为 0, 即 BBR
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PerConnBrutal;
+
+impl ControllerFactory for PerConnBrutal {
+    fn build(&self, now: Instant, current_mtu: u16) -> Box<dyn Controller> {
+        Box::new(Brutal::new(Arc::new(AtomicU64::new(0)), now, current_mtu))
+    }
+}
+
+/// 取得 控制器 的 速率. c 可为 [`quinn::Connection::congestion_state`] 返回的 副本,
+/// 副本 与 连接中 正在使用的 控制器 共享 速率. 不是 Brutal 时 返回 None
+pub fn rate_of(c: Box<dyn Controller>) -> Option<Arc<AtomicU64>> {
+    c.into_any().downcast::<Brutal>().ok().map(|b| b.rate)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Slot {
+    sec: u64,
+    acked: u64,
+    lost: u64,
+}
+
+pub struct Brutal {
+    rate: Arc<AtomicU64>,
+    fallback: Box<dyn Controller>,
+    start: Instant,
+    mtu: u16,
+    rtt: Duration,
+    slots: [Slot; SLOT_COUNT],
+}
+
+impl Brutal {
+    pub fn new(rate: Arc<AtomicU64>, now: Instant, mtu: u16) -> Self {
+        Self {
+            rate,
+            fallback: Arc::new(BbrConfig::default()).build(now, mtu),
+            start: now,
+            mtu,
+            rtt: INITIAL_RTT,
+            slots: [Slot::default(); SLOT_COUNT],
+        }
+    }
+
+    fn slot(&mut self, now: Instant) -> &mut Slot {
+        let sec = now.saturating_duration_since(self.start).as_secs();
+        let s = &mut self.slots[sec as usize % SLOT_COUNT];
+        if s.sec != sec {
+            *s = Slot {
+                sec,
+                ..Default::default()
+            };
+        }
+        s
+    }
+
+    pub fn ack_rate(&self) -> f64 {
+        // 只统计 最近 SLOT_COUNT 秒 的, 过期的 slot 还没被覆盖时 按 sec 排除
+        let newest = self.slots.iter().map(|s| s.sec).max().unwrap_or_default();
+        let (acked, lost) = self
+            .slots
+            .iter()
+            .filter(|s| s.sec + SLOT_COUNT as u64 > newest)
+            .fold((0, 0), |(a, l), s| (a + s.acked, l + s.lost));
+        let total = acked + lost;
+        if total < MIN_SAMPLE_COUNT * self.mtu as u64 {
+            return 1.0;
+        }
+        (acked as f64 / total as f64).max(MIN_ACK_RATE)
+    }
+}
+
+impl Controller for Brutal {
+    fn on_sent(&mut self, now: Instant, bytes: u64, last_packet_number: u64) {
+        self.fallback.on_sent(now, bytes, last_packet_number)
+    }
+
+    fn on_ack(
+        &mut self,
+        now: Instant,
+        sent: Instant,
+        bytes: u64,
+        app_limited: bool,
+        rtt: &RttEstimator,
+    ) {
+        self.rtt = rtt.get();
+        self.slot(now).acked += bytes;
+        self.fallback.on_ack(now, sent, bytes, app_limited, rtt)
+    }
+
+    fn on_end_acks(
+        &mut self,
+        now: Instant,
+        in_flight: u64,
+        app_limited: bool,
+        largest_packet_num_acked: Option<u64>,
+    ) {
+        self.fallback
+            .on_end_acks(now, in_flight, app_limited, largest_packet_num_acked)
+    }
+
+    fn on_congestion_event(
+        &mut self,
+        now: Instant,
+        sent: Instant,
+        is_persistent_congestion: bool,
+        lost_bytes: u64,
+    ) {
+        self.slot(now).lost += lost_bytes;
+        self.fallback
+            .on_congestion_event(now, sent, is_persistent_congestion, lost_bytes)
+    }
+
+    fn on_mtu_update(&mut self, new_mtu: u16) {
+        self.mtu = new_mtu;
+        self.fallback.on_mtu_update(new_mtu)
+    }
+
+    fn window(&self) -> u64 {
+        let rate = self.rate.load(Ordering::Relaxed);
+        if rate == 0 {
+            return self.fallback.window();
+        }
+        let w = rate as f64 * self.rtt.as_secs_f64() * 2.0 / self.ack_rate();
+        (w as u64).max(MIN_WINDOW_PACKETS * self.mtu as u64)
+    }
+
+    fn clone_box(&self) -> Box<dyn Controller> {
+        Box::new(Self {
+            rate: self.rate.clone(),
+            fallback: self.fallback.clone_box(),
+            start: self.start,
+            mtu: self.mtu,
+            rtt: self.rtt,
+            slots: self.slots,
+        })
+    }
+
+    fn initial_window(&self) -> u64 {
+        self.fallback.initial_window()
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
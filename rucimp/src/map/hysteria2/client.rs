@@ -0,0 +1,217 @@
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use bytes::BytesMut;
+use quinn::Endpoint;
+use ruci::map::*;
+use ruci::net::{addr_conn::AsyncWriteAddrExt, helpers, Network, CID};
+use ruci::Name;
+use ruci::{map, net::Stream};
+
+use macro_map::*;
+use tracing::debug;
+
+use crate::map::{
+    quic_common::{
+        pool::{Guarded, Pool, PoolConn, PoolOptions},
+        udp::Sessions,
+    },
+    rustls21,
+};
+
+use super::brutal::BrutalConfig;
+use super::*;
+
+#[derive(Debug, Clone)]
+struct ConnState {
+    conn: quinn::Connection,
+    udp: Option<Sessions>,
+}
+
+impl PoolConn for ConnState {
+    fn is_closed(&self) -> bool {
+        self.conn.close_reason().is_some()
+    }
+
+    fn close(&self) {
+        self.conn.close(0u32.into(), b"idle");
+    }
+
+    fn other_active(&self) -> usize {
+        self.udp.as_ref().map(|u| u.len()).unwrap_or_default()
+    }
+}
+
+/// hysteria2 只使用 一个连接, 断开后 重连
+#[map_ext_fields]
+#[derive(Debug, Clone, MapExt)]
+pub struct Client {
+    c: Endpoint,
+    tls: Arc<rustls::ClientConfig>,
+    pool: Pool<ConnState>,
+
+    server_addr: SocketAddr,
+    server_name: String,
+    auth: String,
+    up_bps: u64,
+    down_bps: u64,
+}
+
+impl Name for Client {
+    fn name(&self) -> &'static str {
+        "hysteria2_client"
+    }
+}
+
+impl Client {
+    pub fn new(c: ClientConfig) -> anyhow::Result<Self> {
+        let tls = rustls21::cc(rustls21::ClientOptions {
+            is_insecure: c.is_insecure.unwrap_or_default(),
+            alpn: alpn(),
+            cert_path: c.cert_path.clone(),
+            early_data: false,
+        })?;
+        let endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
+
+        Ok(Self {
+            c: endpoint,
+            tls: Arc::new(tls),
+            pool: Pool::new(PoolOptions {
+                max_conns: 1,
+                max_streams_per_conn: usize::MAX,
+                ..Default::default()
+            }),
+            server_addr: c.server_addr.parse()?,
+            server_name: c.server_name,
+            auth: c.auth,
+            up_bps: c.up_mbps.map(mbps_to_bps).unwrap_or_default(),
+            down_bps: c.down_mbps.map(mbps_to_bps).unwrap_or_default(),
+            ext_fields: Some(MapExtFields::default()),
+        })
+    }
+
+    async fn connect(&self, cid: &CID) -> anyhow::Result<ConnState> {
+        // 速率 在认证后 才能确定, 之前为 0, 即 BBR
+        let brutal = BrutalConfig::default();
+        let mut qc = quinn::ClientConfig::new(self.tls.clone());
+        qc.transport_config(transport_config(brutal.clone()));
+
+        let conn = self
+            .c
+            .connect_with(qc, self.server_addr, &self.server_name)?
+            .await?;
+
+        let (mut driver, mut send_request) =
+            h3::client::new(h3_quinn::Connection::new(conn.clone())).await?;
+        tokio::spawn(async move {
+            let _ = futures::future::poll_fn(|cx| driver.poll_close(cx)).await;
+        });
+
+        let req = http::Request::post(format!("https://{AUTH_HOST}{AUTH_PATH}"))
+            .header(HEADER_AUTH, self.auth.as_str())
+            .header(HEADER_CC_RX, self.down_bps.to_string())
+            .header(HEADER_PADDING, padding(256, 2048))
+            .body(())?;
+        let mut stream = send_request.send_request(req).await?;
+        stream.finish().await?;
+        let resp = stream.recv_response().await?;
+        if resp.status().as_u16() != STATUS_AUTH_OK {
+            conn.close(0u32.into(), b"");
+            bail!("hysteria2 auth failed, status {}", resp.status())
+        }
+
+        let header = |k: &str| {
+            resp.headers()
+                .get(k)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+        };
+        let udp_enabled = header(HEADER_UDP) == "true";
+        let server_rx = header(HEADER_CC_RX);
+
+        if self.up_bps > 0 && server_rx != "auto" {
+            let server_rx: u64 = server_rx.parse().unwrap_or_default();
+            let rate = if server_rx > 0 {
+                self.up_bps.min(server_rx)
+            } else {
+                self.up_bps
+            };
+            brutal.rate.store(rate, Ordering::Relaxed);
+            debug!(cid = %cid, rate, "hysteria2 use brutal");
+        }
+
+        // http3 的 控制流 须 在连接期间 保持打开
+        let c = conn.clone();
+        tokio::spawn(async move {
+            let _send_request = send_request;
+            c.closed().await;
+        });
+
+        let udp = udp_enabled.then(|| {
+            let (udp, out_rx) = Sessions::new();
+            super::udp::spawn(conn.clone(), udp.clone(), out_rx, None);
+            udp
+        });
+
+        debug!(cid = %cid, udp_enabled, "hysteria2 connection authed");
+        Ok(ConnState { conn, udp })
+    }
+
+    async fn handshake(
+        &self,
+        cid: CID,
+        a: Option<ruci::net::Addr>,
+        b: Option<BytesMut>,
+    ) -> anyhow::Result<map::MapResult> {
+        let ta = a.clone().context("hysteria2 client needs a target addr")?;
+
+        let (state, guard) = self.pool.get(|| self.connect(&cid)).await?;
+
+        if ta.network == Network::UDP {
+            drop(guard);
+            let udp = state
+                .udp
+                .as_ref()
+                .context("hysteria2 server doesn't support udp")?;
+            let mut u = udp.open();
+            if let Some(b) = b.as_ref().filter(|b| !b.is_empty()) {
+                u.w.write(b, &ta).await?;
+            }
+            debug!(cid = %cid, "hysteria2 client opened new udp session");
+
+            return Ok(MapResult::new_u(u).a(a).build());
+        }
+
+        let (mut se, mut re) = state.conn.open_bi().await?;
+        let mut req = encode_tcp_request(&ta);
+        if let Some(b) = &b {
+            req.extend_from_slice(b);
+        }
+        se.write_all(&req).await?;
+        read_tcp_response(&mut re).await?;
+
+        let stream = Guarded::new(helpers::RWWrapper { w: se, r: re }, guard);
+        let c: ruci::net::Conn = Box::new(stream);
+
+        Ok(MapResult::new_c(c).a(a).build())
+    }
+}
+
+#[async_trait]
+impl Map for Client {
+    async fn maps(&self, cid: CID, _behavior: ProxyBehavior, params: MapParams) -> MapResult {
+        let conn = params.c;
+        if let Stream::None = conn {
+            let r = self.handshake(cid, params.a, params.b).await;
+            match r {
+                anyhow::Result::Ok(r) => r,
+                Err(e) => MapResult::from_e(e.context("hysteria2_client maps failed")),
+            }
+        } else {
+            MapResult::err_str("hysteria2_client only support None stream")
+        }
+    }
+}
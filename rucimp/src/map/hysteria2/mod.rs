@@ -0,0 +1,238 @@
+/*!
+Defines Maps for hysteria2 protocol. uses quinn.
+
+https://v2.hysteria.network/docs/developers/Protocol/
+
+认证: 连接建立后, 客户端 用 HTTP/3 发送 `POST https://hysteria/auth`, 头部 Hysteria-Auth 为 密码,
+服务端 认证成功 返回 状态码 233, 否则 当作普通 http 服务器 返回 404.
+
+tcp: 每个代理请求 开一个 双向流, 流开头为
+`varint 0x401 | varint 地址长度 | 地址 | varint padding 长度 | padding`,
+服务端 回复 `u8 状态 | varint 消息长度 | 消息 | varint padding 长度 | padding`, 之后为 原始数据.
+地址 为 `host:port` 格式的字符串.
+
+udp: 见 [`udp`]
+
+拥塞控制: 一方 配置了 上行带宽, 且 对方 告知了 下行带宽 (Hysteria-CC-RX) 时, 该方 使用 Brutal
+(见 [`brutal`]), 速率 为 两者 中 较小的一个; 否则 使用 BBR.
+ */
+
+pub mod brutal;
+pub mod client;
+pub mod server;
+pub mod udp;
+
+#[cfg(test)]
+mod test;
+
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use bytes::{Buf, BufMut, BytesMut};
+use rand::Rng;
+use ruci::net::{Addr, Network};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+pub const AUTH_HOST: &str = "hysteria";
+pub const AUTH_PATH: &str = "/auth";
+pub const STATUS_AUTH_OK: u16 = 233;
+
+pub const HEADER_AUTH: &str = "Hysteria-Auth";
+pub const HEADER_UDP: &str = "Hysteria-UDP";
+pub const HEADER_CC_RX: &str = "Hysteria-CC-RX";
+pub const HEADER_PADDING: &str = "Hysteria-Padding";
+
+pub const TCP_REQUEST_ID: u64 = 0x401;
+
+const STATUS_OK: u8 = 0;
+const STATUS_ERR: u8 = 1;
+
+const MAX_ADDR_LEN: usize = 2048;
+const MAX_MSG_LEN: usize = 2048;
+const MAX_PADDING_LEN: usize = 4096;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerConfig {
+    pub key_path: String,
+    pub cert_path: String,
+    pub listen_addr: String,
+
+    /// 所有用户共用的 密码
+    pub password: Option<String>,
+
+    /// `user:pass` 形式, 客户端 的 auth 为 `user:pass`
+    pub users: Option<Vec<String>>,
+
+    /// 服务端的 上行带宽. 给出时, 对 告知了 下行带宽 的 客户端 使用 Brutal,
+    /// 速率 为 两者 中 较小的一个; 否则 使用 BBR
+    pub up_mbps: Option<u64>,
+    /// 服务端的 下行带宽, 会告知客户端, 客户端的 发送速率 不超过它
+    pub down_mbps: Option<u64>,
+
+    /// 为 true 时 双方 都 使用 BBR, 不使用 Brutal
+    pub ignore_client_bandwidth: Option<bool>,
+
+    pub disable_udp: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClientConfig {
+    pub server_addr: String,
+    pub server_name: String,
+
+    pub auth: String,
+
+    pub cert_path: Option<String>,
+    pub is_insecure: Option<bool>,
+
+    /// 给出时 使用 Brutal, 发送速率 为 它 与 服务端 下行带宽 中 较小的一个
+    pub up_mbps: Option<u64>,
+    /// 会告知服务端, 服务端的 发送速率 不超过它
+    pub down_mbps: Option<u64>,
+}
+
+pub fn mbps_to_bps(mbps: u64) -> u64 {
+    mbps * 1_000_000 / 8
+}
+
+pub(crate) fn transport_config(
+    cc: impl quinn::congestion::ControllerFactory + Send + Sync + 'static,
+) -> Arc<quinn::TransportConfig> {
+    let mut t = quinn::TransportConfig::default();
+    t.congestion_controller_factory(cc);
+    Arc::new(t)
+}
+
+pub(crate) fn alpn() -> Option<Vec<String>> {
+    Some(vec!["h3".to_string()])
+}
+
+pub fn padding(min: usize, max: usize) -> String {
+    const CHARS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    let mut rng = rand::thread_rng();
+    let n = rng.gen_range(min..max);
+    (0..n)
+        .map(|_| CHARS[rng.gen_range(0..CHARS.len())] as char)
+        .collect()
+}
+
+fn random_padding_bytes(min: usize, max: usize) -> Vec<u8> {
+    padding(min, max).into_bytes()
+}
+
+pub fn varint_len(v: u64) -> usize {
+    match v {
+        0..=63 => 1,
+        64..=16383 => 2,
+        16384..=1073741823 => 4,
+        _ => 8,
+    }
+}
+
+/// quic varint
+pub fn put_varint(buf: &mut BytesMut, v: u64) {
+    match varint_len(v) {
+        1 => buf.put_u8(v as u8),
+        2 => buf.put_u16(0x4000 | v as u16),
+        4 => buf.put_u32(0x8000_0000 | v as u32),
+        _ => buf.put_u64(0xc000_0000_0000_0000 | v),
+    }
+}
+
+pub fn get_varint(buf: &mut impl Buf) -> anyhow::Result<u64> {
+    if !buf.has_remaining() {
+        bail!("hysteria2 varint: no data")
+    }
+    let first = buf.chunk()[0];
+    let len = 1 << (first >> 6);
+    if buf.remaining() < len {
+        bail!(
+            "hysteria2 varint: need {len} bytes, got {}",
+            buf.remaining()
+        )
+    }
+    let v = match len {
+        1 => buf.get_u8() as u64,
+        2 => (buf.get_u16() & 0x3fff) as u64,
+        4 => (buf.get_u32() & 0x3fff_ffff) as u64,
+        _ => buf.get_u64() & 0x3fff_ffff_ffff_ffff,
+    };
+    Ok(v)
+}
+
+pub async fn read_varint<R: AsyncRead + Unpin>(r: &mut R) -> anyhow::Result<u64> {
+    let first = r.read_u8().await?;
+    let len = 1 << (first >> 6);
+    let mut v = (first & 0x3f) as u64;
+    for _ in 1..len {
+        v = (v << 8) | r.read_u8().await? as u64;
+    }
+    Ok(v)
+}
+
+async fn read_limited<R: AsyncRead + Unpin>(
+    r: &mut R,
+    max: usize,
+    what: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let l = read_varint(r).await? as usize;
+    if l > max {
+        bail!("hysteria2 {what} too long: {l}")
+    }
+    let mut v = vec![0; l];
+    r.read_exact(&mut v).await?;
+    Ok(v)
+}
+
+pub fn encode_tcp_request(target: &Addr) -> BytesMut {
+    let addr = target.get_addr_str();
+    let pad = random_padding_bytes(64, 512);
+    let mut buf = BytesMut::with_capacity(16 + addr.len() + pad.len());
+    put_varint(&mut buf, TCP_REQUEST_ID);
+    put_varint(&mut buf, addr.len() as u64);
+    buf.extend_from_slice(addr.as_bytes());
+    put_varint(&mut buf, pad.len() as u64);
+    buf.extend_from_slice(&pad);
+    buf
+}
+
+/// 读取 tcp 请求, 不含开头的 0x401
+pub async fn read_tcp_request<R: AsyncRead + Unpin>(r: &mut R) -> anyhow::Result<Addr> {
+    let a = read_limited(r, MAX_ADDR_LEN, "address").await?;
+    read_limited(r, MAX_PADDING_LEN, "padding").await?;
+
+    let a = String::from_utf8(a).context("hysteria2 address is not utf8")?;
+    parse_addr(&a, Network::TCP)
+}
+
+pub fn encode_tcp_response(ok: bool, msg: &str) -> BytesMut {
+    let pad = random_padding_bytes(64, 512);
+    let mut buf = BytesMut::with_capacity(16 + msg.len() + pad.len());
+    buf.put_u8(if ok { STATUS_OK } else { STATUS_ERR });
+    put_varint(&mut buf, msg.len() as u64);
+    buf.extend_from_slice(msg.as_bytes());
+    put_varint(&mut buf, pad.len() as u64);
+    buf.extend_from_slice(&pad);
+    buf
+}
+
+/// 读取 tcp 回复, 服务端 返回错误时 返回 Err
+pub async fn read_tcp_response<R: AsyncRead + Unpin>(r: &mut R) -> anyhow::Result<()> {
+    let status = r.read_u8().await?;
+    let msg = read_limited(r, MAX_MSG_LEN, "message").await?;
+    read_limited(r, MAX_PADDING_LEN, "padding").await?;
+    if status != STATUS_OK {
+        bail!(
+            "hysteria2 server refused: {}",
+            String::from_utf8_lossy(&msg)
+        )
+    }
+    Ok(())
+}
+
+pub fn parse_addr(s: &str, network: Network) -> anyhow::Result<Addr> {
+    let mut a = Addr::from_addr_str(network.to_static_str(), s)?;
+    a.network = network;
+    Ok(a)
+}
@@ -0,0 +1,314 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use quinn::Endpoint;
+use ruci::map::*;
+use ruci::net::{helpers, CID};
+use ruci::user::{AsyncUserAuthenticator, PlainText, UsersMap};
+use ruci::Name;
+use ruci::{map, net::Stream};
+
+use macro_map::*;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::map::{quic_common, rustls21};
+
+use super::brutal::{rate_of, PerConnBrutal};
+use super::*;
+
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+type H3Conn = h3::server::Connection<h3_quinn::Connection, bytes::Bytes>;
+
+/// 服务端 对 一个连接 的 发送速率 (字节/秒), 为 自己的 上行带宽 与 客户端 的 下行带宽
+/// 中 较小的一个. 任一方 为 0 或 auto, 或 忽略 客户端带宽 时 返回 0, 即 使用 BBR
+pub fn send_rate(up_bps: u64, client_rx: &str, ignore_client_bandwidth: bool) -> u64 {
+    if up_bps == 0 || ignore_client_bandwidth {
+        return 0;
+    }
+    match client_rx.parse::<u64>() {
+        Ok(rx) if rx > 0 => up_bps.min(rx),
+        _ => 0,
+    }
+}
+
+#[map_ext_fields]
+#[derive(Debug, Clone, MapExt)]
+pub struct Server {
+    tls_key_path: String,
+    tls_cert_path: String,
+    listen_addr: String,
+
+    um: UsersMap<PlainText>,
+    up_bps: u64,
+    down_bps: u64,
+    ignore_client_bandwidth: bool,
+    udp_enabled: bool,
+
+    a_next_cid: Arc<AtomicU32>,
+}
+
+impl Name for Server {
+    fn name(&self) -> &'static str {
+        "hysteria2_server"
+    }
+}
+
+impl Server {
+    pub fn new(c: ServerConfig) -> anyhow::Result<Self> {
+        let mut um = UsersMap::new();
+        if let Some(p) = c.password {
+            um.add_user(PlainText::new(String::new(), p));
+        }
+        for u in c.users.unwrap_or_default() {
+            let Some((user, pass)) = u.split_once(':') else {
+                bail!("hysteria2 user should be like user:pass, got {u}")
+            };
+            um.add_user(PlainText::new(user.to_string(), pass.to_string()));
+        }
+        if um.is_empty() {
+            bail!("can't init a hysteria2 server without any password");
+        }
+
+        Ok(Self {
+            tls_key_path: c.key_path,
+            tls_cert_path: c.cert_path,
+            listen_addr: c.listen_addr,
+            um,
+            up_bps: c.up_mbps.map(mbps_to_bps).unwrap_or_default(),
+            down_bps: c.down_mbps.map(mbps_to_bps).unwrap_or_default(),
+            ignore_client_bandwidth: c.ignore_client_bandwidth.unwrap_or_default(),
+            udp_enabled: !c.disable_udp.unwrap_or_default(),
+            a_next_cid: Arc::new(AtomicU32::new(1)),
+            ext_fields: Some(MapExtFields::default()),
+        })
+    }
+
+    /// auth 为 `user:pass` 或 所有用户共用的密码
+    fn auth(&self, auth: &str) -> Option<PlainText> {
+        if let Some((user, pass)) = auth.split_once(':') {
+            let u = PlainText::new(user.to_string(), pass.to_string());
+            if let Some(u) = self.um.auth_user_by_authstr(u.auth_str()) {
+                return Some(u);
+            }
+        }
+        let u = PlainText::new(String::new(), auth.to_string());
+        self.um.auth_user_by_authstr(u.auth_str())
+    }
+
+    /// 处理 http3 请求, 直到 认证成功. 返回的 H3Conn 须在 连接期间 保持存活.
+    /// 还 返回 客户端 的 Hysteria-CC-RX
+    async fn accept_auth(
+        &self,
+        conn: quinn::Connection,
+    ) -> anyhow::Result<(H3Conn, PlainText, String)> {
+        let mut h3c: H3Conn = h3::server::Connection::new(h3_quinn::Connection::new(conn)).await?;
+
+        loop {
+            let Some((req, mut stream)) = h3c.accept().await? else {
+                bail!("hysteria2 connection closed before auth")
+            };
+            let header = |k: &str| {
+                req.headers()
+                    .get(k)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+
+            let is_auth = req.method() == http::Method::POST
+                && req.uri().host() == Some(AUTH_HOST)
+                && req.uri().path() == AUTH_PATH;
+            let user = if is_auth {
+                self.auth(&header(HEADER_AUTH))
+            } else {
+                None
+            };
+
+            let Some(user) = user else {
+                // 表现得像 普通的 http 服务器
+                let resp = http::Response::builder()
+                    .status(http::StatusCode::NOT_FOUND)
+                    .body(())?;
+                stream.send_response(resp).await?;
+                stream.finish().await?;
+                continue;
+            };
+
+            let cc_rx = if self.ignore_client_bandwidth {
+                "auto".to_string()
+            } else {
+                self.down_bps.to_string()
+            };
+            let resp = http::Response::builder()
+                .status(STATUS_AUTH_OK)
+                .header(HEADER_UDP, self.udp_enabled.to_string())
+                .header(HEADER_CC_RX, cc_rx)
+                .header(HEADER_PADDING, padding(256, 2048))
+                .body(())?;
+            stream.send_response(resp).await?;
+            stream.finish().await?;
+
+            return Ok((h3c, user, header(HEADER_CC_RX)));
+        }
+    }
+
+    async fn start_listen(&self, cid: CID) -> anyhow::Result<map::MapResult> {
+        let tls = rustls21::sc(rustls21::ServerOptions {
+            alpn: alpn(),
+            cert_path: self.tls_cert_path.clone(),
+            key_path: self.tls_key_path.clone(),
+            early_data: false,
+        })?;
+        let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(tls));
+
+        // 各连接 的 速率 在 认证后 按 客户端 的 下行带宽 设置, 见 send_rate
+        if self.up_bps > 0 && !self.ignore_client_bandwidth {
+            server_config.transport_config(transport_config(PerConnBrutal));
+        } else {
+            server_config.transport_config(transport_config(Arc::new(
+                quinn::congestion::BbrConfig::default(),
+            )));
+        }
+
+        let endpoint = Endpoint::server(server_config, self.listen_addr.parse()?)?;
+
+        let (tx, rx) = mpsc::channel(100);
+
+        let s = self.clone();
+        let cidc = cid.clone();
+        tokio::spawn(async move {
+            while let Some(connecting) = endpoint.accept().await {
+                let mut new_cid = cidc.clone();
+                new_cid.push_num(s.a_next_cid.fetch_add(1, Ordering::Relaxed));
+
+                let s = s.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = s.handle_conn(new_cid.clone(), connecting, tx).await {
+                        debug!(cid = %new_cid, "hysteria2 server conn ended: {e:#}");
+                    }
+                });
+            }
+        });
+
+        debug!(cid = %cid , laddr= self.listen_addr.as_str(), "hysteria2 server started");
+        let mr = MapResult::builder()
+            .c(ruci::net::Stream::Generator(rx))
+            .build();
+        Ok(mr)
+    }
+
+    async fn handle_conn(
+        &self,
+        cid: CID,
+        connecting: quinn::Connecting,
+        tx: mpsc::Sender<MapResult>,
+    ) -> anyhow::Result<()> {
+        let conn = connecting.await?;
+        debug!(cid = %cid, raddr = ?conn.remote_address(), "hysteria2 server got new conn");
+
+        let (h3c, user, client_rx) =
+            match tokio::time::timeout(AUTH_TIMEOUT, self.accept_auth(conn.clone())).await {
+                Ok(Ok(r)) => r,
+                Ok(Err(e)) => {
+                    conn.close(0u32.into(), b"");
+                    return Err(e);
+                }
+                Err(_) => {
+                    conn.close(0u32.into(), b"");
+                    bail!("hysteria2 auth timeout")
+                }
+            };
+        debug!(cid = %cid, user = user.user, "hysteria2 server authed");
+
+        let rate = send_rate(self.up_bps, &client_rx, self.ignore_client_bandwidth);
+        if rate > 0 {
+            if let Some(r) = rate_of(conn.congestion_state()) {
+                r.store(rate, Ordering::Relaxed);
+                debug!(cid = %cid, rate, "hysteria2 server use brutal");
+            }
+        }
+
+        let c = conn.clone();
+        tokio::spawn(async move {
+            let _h3c = h3c;
+            c.closed().await;
+        });
+
+        let s_count = Arc::new(AtomicU32::new(1));
+
+        if self.udp_enabled {
+            let (udp, out_rx) = quic_common::udp::Sessions::new();
+            super::udp::spawn(
+                conn.clone(),
+                udp,
+                out_rx,
+                Some(quic_common::udp::NewSessionSender {
+                    cid: cid.clone(),
+                    count: s_count.clone(),
+                    tx: tx.clone(),
+                }),
+            );
+        }
+
+        while let Ok((mut se, mut re)) = conn.accept_bi().await {
+            let mut new_cid = cid.clone();
+            new_cid.push_num(s_count.fetch_add(1, Ordering::Relaxed));
+
+            let tx = tx.clone();
+            let user = user.clone();
+            tokio::spawn(async move {
+                let r = async {
+                    let id = read_varint(&mut re).await?;
+                    if id != TCP_REQUEST_ID {
+                        bail!("hysteria2 unknown request id {id:#x}")
+                    }
+                    let ta = read_tcp_request(&mut re).await?;
+                    se.write_all(&encode_tcp_response(true, "")).await?;
+                    anyhow::Ok(ta)
+                }
+                .await;
+                let ta = match r {
+                    Ok(ta) => ta,
+                    Err(e) => {
+                        debug!(cid = %new_cid, "hysteria2 server read request failed: {e:#}");
+                        return;
+                    }
+                };
+                debug!(cid = %new_cid, target = %ta, "hysteria2 server got new stream");
+
+                let stream: ruci::net::Conn = Box::new(helpers::RWWrapper { w: se, r: re });
+                let mut m = MapResult::new_c(stream)
+                    .a(Some(ta))
+                    .new_id(new_cid.clone())
+                    .build();
+                m.d = Some(Box::new(user));
+                if let Err(e) = tx.send(m).await {
+                    warn!(cid = %new_cid, "hysteria2 send tx got error: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Map for Server {
+    async fn maps(&self, cid: CID, _behavior: ProxyBehavior, params: MapParams) -> MapResult {
+        let conn = params.c;
+        if let Stream::None = conn {
+            let r = self.start_listen(cid).await;
+            match r {
+                anyhow::Result::Ok(r) => r,
+                Err(e) => MapResult::from_e(e.context("hysteria2_server maps failed")),
+            }
+        } else {
+            MapResult::err_str("hysteria2_server only support None stream")
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use ruci::{
+    map::{Map, MapParams, ProxyBehavior},
+    net::{
+        addr_conn::{AsyncReadAddrExt, AsyncWriteAddrExt},
+        Addr, Stream, CID,
+    },
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::{udp::*, *};
+
+#[test]
+fn varint() -> anyhow::Result<()> {
+    for v in [0, 63, 64, 0x401, 16383, 16384, 1 << 30, (1 << 62) - 1] {
+        let mut buf = BytesMut::new();
+        put_varint(&mut buf, v);
+        assert_eq!(buf.len(), varint_len(v));
+        assert_eq!(get_varint(&mut buf.freeze())?, v);
+    }
+    // 0x401 在 协议中 固定为 两字节
+    let mut buf = BytesMut::new();
+    put_varint(&mut buf, TCP_REQUEST_ID);
+    assert_eq!(&buf[..], &[0x44, 0x01]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn tcp_request_response() -> anyhow::Result<()> {
+    let ta = Addr::from_strs("tcp", "www.example.com", "", 443)?;
+    let req = encode_tcp_request(&ta);
+    let mut r = &req[..];
+    assert_eq!(read_varint(&mut r).await?, TCP_REQUEST_ID);
+    assert_eq!(read_tcp_request(&mut r).await?, ta);
+    assert!(r.is_empty());
+
+    let mut r = &encode_tcp_response(true, "")[..];
+    read_tcp_response(&mut r).await?;
+    assert!(r.is_empty());
+
+    let mut r = &encode_tcp_response(false, "blocked")[..];
+    let e = read_tcp_response(&mut r).await.unwrap_err();
+    assert!(e.to_string().contains("blocked"));
+    Ok(())
+}
+
+#[test]
+fn udp_fragment() -> anyhow::Result<()> {
+    let m = Message {
+        sid: 3,
+        pid: 9,
+        frag_id: 0,
+        frag_count: 1,
+        addr: "8.8.8.8:53".to_string(),
+        data: Bytes::from(vec![7u8; 3000]),
+    };
+    assert_eq!(Message::decode(m.encode())?, m);
+
+    let frags = m.clone().fragment(1200);
+    assert_eq!(frags.len(), 3);
+    assert!(frags.iter().all(|f| f.encode().len() <= 1200));
+
+    let mut d = crate::map::quic_common::udp::Defragger::default();
+    let mut out = None;
+    for f in frags.into_iter().rev() {
+        assert!(out.is_none());
+        let f = Message::decode(f.encode())?;
+        assert_eq!(f.addr, m.addr);
+        out = d.feed(f.pid, f.frag_id, f.frag_count, f.data);
+    }
+    assert_eq!(out.expect("reassembled"), m.data);
+
+    // 太小, 放不下 包头
+    assert!(m.fragment(10).is_empty());
+    Ok(())
+}
+
+#[test]
+fn brutal_window() {
+    use quinn::congestion::ControllerFactory;
+
+    let c = brutal::BrutalConfig::default();
+    let ctl = c.build(Instant::now(), 1200);
+    let bbr_window = ctl.window();
+
+    // 10MB/s, 初始 rtt 333ms
+    c.rate
+        .store(10_000_000, std::sync::atomic::Ordering::Relaxed);
+    let w = ctl.window();
+    assert_ne!(w, bbr_window);
+    assert!((6_000_000..7_000_000).contains(&w), "{w}");
+}
+
+#[test]
+fn server_send_rate() {
+    use server::send_rate;
+
+    let up = mbps_to_bps(100);
+    assert_eq!(
+        send_rate(up, &mbps_to_bps(10).to_string(), false),
+        mbps_to_bps(10)
+    );
+    assert_eq!(send_rate(up, &mbps_to_bps(500).to_string(), false), up);
+
+    // 任一方 为 0 或 auto 时 用 BBR
+    assert_eq!(send_rate(up, "0", false), 0);
+    assert_eq!(send_rate(up, "auto", false), 0);
+    assert_eq!(send_rate(up, "", false), 0);
+    assert_eq!(send_rate(0, "1000", false), 0);
+    assert_eq!(send_rate(up, "1000", true), 0);
+}
+
+#[test]
+fn per_conn_brutal() {
+    use quinn::congestion::ControllerFactory;
+    use std::sync::atomic::Ordering;
+
+    let f = brutal::PerConnBrutal;
+    let a = f.build(Instant::now(), 1200);
+    let b = f.build(Instant::now(), 1200);
+    let bbr_window = a.window();
+
+    // 与 quinn::Connection::congestion_state 一样, 通过 副本 设置
+    let r = brutal::rate_of(a.clone_box()).expect("is brutal");
+    r.store(10_000_000, Ordering::Relaxed);
+    assert!((6_000_000..7_000_000).contains(&a.window()));
+
+    // 其它 连接 不受影响
+    assert_eq!(b.window(), bbr_window);
+    assert!(brutal::rate_of(
+        Arc::new(quinn::congestion::BbrConfig::default()).build(Instant::now(), 1200)
+    )
+    .is_none());
+}
+
+fn configs(port: u16) -> (ServerConfig, ClientConfig) {
+    let res = concat!(env!("CARGO_MANIFEST_DIR"), "/../resource/");
+    (
+        ServerConfig {
+            key_path: format!("{res}test.key"),
+            cert_path: format!("{res}test.crt"),
+            listen_addr: format!("127.0.0.1:{port}"),
+            users: Some(vec!["u1:p1".to_string()]),
+            password: Some("shared".to_string()),
+            up_mbps: Some(100),
+            ..Default::default()
+        },
+        ClientConfig {
+            server_addr: format!("127.0.0.1:{port}"),
+            server_name: "www.mytest.com".to_string(),
+            auth: "u1:p1".to_string(),
+            is_insecure: Some(true),
+            up_mbps: Some(50),
+            down_mbps: Some(20),
+            ..Default::default()
+        },
+    )
+}
+
+#[tokio::test]
+async fn tcp_and_udp() -> anyhow::Result<()> {
+    let port = ruci::net::gen_random_higher_port();
+    let (sc, cc) = configs(port);
+
+    let server = server::Server::new(sc)?;
+    let r = server
+        .maps(CID::default(), ProxyBehavior::DECODE, MapParams::default())
+        .await;
+    let Stream::Generator(mut g) = r.c else {
+        panic!("hysteria2 server should return a generator, {:?}", r.e)
+    };
+
+    let client = client::Client::new(cc)?;
+
+    // tcp, 带 early data
+    let ta = Addr::from_strs("tcp", "www.example.com", "", 443)?;
+    let r = client
+        .maps(
+            CID::default(),
+            ProxyBehavior::ENCODE,
+            MapParams {
+                a: Some(ta.clone()),
+                b: Some("hello".into()),
+                ..Default::default()
+            },
+        )
+        .await;
+    let Stream::Conn(mut c) = r.c else {
+        panic!("client should return a conn, {:?}", r.e)
+    };
+
+    let sr = tokio::time::timeout(Duration::from_secs(5), g.recv())
+        .await?
+        .expect("server got stream");
+    assert_eq!(sr.a, Some(ta));
+    let user = sr.d.as_ref().and_then(|d| d.get_user()).expect("has user");
+    assert_eq!(user.identity_str(), "u1");
+    let Stream::Conn(mut s) = sr.c else {
+        panic!("server should return a conn")
+    };
+    let mut buf = [0u8; 16];
+    s.read_exact(&mut buf[..5]).await?;
+    assert_eq!(&buf[..5], b"hello");
+    s.write_all(b"world").await?;
+    c.read_exact(&mut buf[..5]).await?;
+    assert_eq!(&buf[..5], b"world");
+
+    // udp, 大包 会被分片
+    let ua = Addr::from_strs("udp", "", "8.8.8.8", 53)?;
+    let r = client
+        .maps(
+            CID::default(),
+            ProxyBehavior::ENCODE,
+            MapParams {
+                a: Some(ua.clone()),
+                b: Some("query".into()),
+                ..Default::default()
+            },
+        )
+        .await;
+    let Stream::AddrConn(mut cu) = r.c else {
+        panic!("client should return an addr conn, {:?}", r.e)
+    };
+    let sr = tokio::time::timeout(Duration::from_secs(5), g.recv())
+        .await?
+        .expect("server got udp session");
+    assert_eq!(sr.a, Some(ua.clone()));
+    assert_eq!(&sr.b.expect("first payload")[..], b"query");
+    let Stream::AddrConn(mut su) = sr.c else {
+        panic!("server should return an addr conn")
+    };
+
+    let big = vec![5u8; 4000];
+    su.w.write(&big, &ua).await?;
+    let mut buf = vec![0u8; 8192];
+    let (n, a) = tokio::time::timeout(Duration::from_secs(5), cu.r.read(&mut buf)).await??;
+    assert_eq!(n, big.len());
+    assert_eq!(a, ua);
+
+    cu.w.write(&big[..3000], &ua).await?;
+    let (n, _) = tokio::time::timeout(Duration::from_secs(5), su.r.read(&mut buf)).await??;
+    assert_eq!(n, 3000);
+    Ok(())
+}
+
+#[tokio::test]
+async fn auth_failed() -> anyhow::Result<()> {
+    let port = ruci::net::gen_random_higher_port();
+    let (sc, mut cc) = configs(port);
+
+    let server = server::Server::new(sc)?;
+    let r = server
+        .maps(CID::default(), ProxyBehavior::DECODE, MapParams::default())
+        .await;
+    let Stream::Generator(_g) = r.c else {
+        panic!("hysteria2 server should return a generator, {:?}", r.e)
+    };
+
+    cc.auth = "wrong".to_string();
+    let client = client::Client::new(cc.clone())?;
+    let ta = Addr::from_strs("tcp", "www.example.com", "", 443)?;
+    let r = client
+        .maps(
+            CID::default(),
+            ProxyBehavior::ENCODE,
+            MapParams {
+                a: Some(ta.clone()),
+                ..Default::default()
+            },
+        )
+        .await;
+    assert!(r.e.is_some());
+
+    // 共用的 密码
+    cc.auth = "shared".to_string();
+    let client = client::Client::new(cc)?;
+    let r = client
+        .maps(
+            CID::default(),
+            ProxyBehavior::ENCODE,
+            MapParams {
+                a: Some(ta),
+                ..Default::default()
+            },
+        )
+        .await;
+    assert!(r.e.is_none(), "{:?}", r.e);
+    Ok(())
+}
@@ -0,0 +1,189 @@
+/*!
+hysteria2 的 udp, 只用 QUIC DATAGRAM 传输. 每个 datagram 为
+
+`u32 session id | u16 packet id | u8 fragment id | u8 fragment count | varint 地址长度 | 地址 | payload`
+
+包超过 datagram 上限时 被分片, 同一个包的 各分片 packet id 相同. 重组 见 [`Defragger`].
+
+会话的分发 复用 [`crate::map::quic_common::udp::Sessions`].
+ */
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Context};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use quinn::Connection;
+use ruci::net::Network;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::map::quic_common::udp::{dispatch_loop, Defragger, NewSessionSender, Packet, Sessions};
+
+use super::{get_varint, parse_addr, put_varint, varint_len};
+
+const MAX_DEFRAGGERS: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub sid: u32,
+    pub pid: u16,
+    pub frag_id: u8,
+    pub frag_count: u8,
+    pub addr: String,
+    pub data: Bytes,
+}
+
+impl Message {
+    pub fn header_len(&self) -> usize {
+        8 + varint_len(self.addr.len() as u64) + self.addr.len()
+    }
+
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(self.header_len() + self.data.len());
+        buf.put_u32(self.sid);
+        buf.put_u16(self.pid);
+        buf.put_u8(self.frag_id);
+        buf.put_u8(self.frag_count);
+        put_varint(&mut buf, self.addr.len() as u64);
+        buf.extend_from_slice(self.addr.as_bytes());
+        buf.extend_from_slice(&self.data);
+        buf.freeze()
+    }
+
+    pub fn decode(mut buf: Bytes) -> anyhow::Result<Self> {
+        if buf.len() < 9 {
+            bail!("hysteria2 udp message too short, {}", buf.len())
+        }
+        let sid = buf.get_u32();
+        let pid = buf.get_u16();
+        let frag_id = buf.get_u8();
+        let frag_count = buf.get_u8();
+        let l = get_varint(&mut buf)? as usize;
+        if buf.len() < l {
+            bail!("hysteria2 udp message address too long, {l}")
+        }
+        let addr = String::from_utf8(buf.split_to(l).to_vec())
+            .context("hysteria2 udp address is not utf8")?;
+        if frag_count == 0 || frag_id >= frag_count {
+            bail!("hysteria2 udp message bad fragment {frag_id}/{frag_count}")
+        }
+        Ok(Self {
+            sid,
+            pid,
+            frag_id,
+            frag_count,
+            addr,
+            data: buf,
+        })
+    }
+
+    /// 分片, 使 每个分片 编码后 不超过 max_size. 无法分片时 返回 空
+    pub fn fragment(self, max_size: usize) -> Vec<Message> {
+        let hl = self.header_len();
+        if hl + self.data.len() <= max_size {
+            return vec![self];
+        }
+        if max_size <= hl {
+            return vec![];
+        }
+        let chunk = max_size - hl;
+        let count = self.data.len().div_ceil(chunk);
+        if count > u8::MAX as usize {
+            return vec![];
+        }
+        (0..count)
+            .map(|i| Message {
+                sid: self.sid,
+                pid: self.pid,
+                frag_id: i as u8,
+                frag_count: count as u8,
+                addr: self.addr.clone(),
+                data: self
+                    .data
+                    .slice(i * chunk..((i + 1) * chunk).min(self.data.len())),
+            })
+            .collect()
+    }
+}
+
+/// 为 conn 启动 udp 包的 收发任务.
+///
+/// new_tx 不为 None 时 (服务端), 收到的 新会话 通过 new_tx 发出
+pub fn spawn(
+    conn: Connection,
+    sessions: Sessions,
+    out_rx: mpsc::Receiver<Packet>,
+    new_tx: Option<NewSessionSender>,
+) {
+    tokio::spawn(send_loop(conn.clone(), out_rx));
+
+    let (p_tx, p_rx) = mpsc::channel(256);
+    tokio::spawn(recv_loop(conn, p_tx));
+    tokio::spawn(dispatch_loop(sessions, p_rx, new_tx));
+}
+
+async fn send_loop(conn: Connection, mut out_rx: mpsc::Receiver<Packet>) {
+    let mut pid: u16 = 0;
+    while let Some(p) = out_rx.recv().await {
+        let Some(max) = conn.max_datagram_size() else {
+            debug!("hysteria2 udp: peer doesn't support datagram, dropped");
+            continue;
+        };
+        pid = pid.wrapping_add(1);
+        let m = Message {
+            sid: p.sid,
+            pid,
+            frag_id: 0,
+            frag_count: 1,
+            addr: p.addr.get_addr_str(),
+            data: p.payload.freeze(),
+        };
+        for f in m.fragment(max) {
+            if let Err(e) = conn.send_datagram(f.encode()) {
+                debug!("hysteria2 udp send datagram got e: {e}");
+                break;
+            }
+        }
+    }
+}
+
+async fn recv_loop(conn: Connection, p_tx: mpsc::Sender<Packet>) {
+    let mut defraggers: HashMap<u32, Defragger> = HashMap::new();
+    while let Ok(d) = conn.read_datagram().await {
+        let m = match Message::decode(d) {
+            Ok(m) => m,
+            Err(e) => {
+                debug!("hysteria2 udp got invalid message: {e}");
+                continue;
+            }
+        };
+        let data = if m.frag_count == 1 {
+            m.data
+        } else {
+            // 只有分片的包 需要重组, 会话结束时 这里不会得知, 所以 过多时 直接清空
+            if defraggers.len() > MAX_DEFRAGGERS {
+                defraggers.clear();
+            }
+            let d = defraggers.entry(m.sid).or_default();
+            match d.feed(m.pid, m.frag_id, m.frag_count, m.data) {
+                Some(d) => d,
+                None => continue,
+            }
+        };
+        let addr = match parse_addr(&m.addr, Network::UDP) {
+            Ok(a) => a,
+            Err(e) => {
+                debug!("hysteria2 udp got invalid address {}: {e}", m.addr);
+                continue;
+            }
+        };
+        let p = Packet {
+            sid: m.sid,
+            addr,
+            payload: BytesMut::from(&data[..]),
+        };
+        if p_tx.send(p).await.is_err() {
+            break;
+        }
+    }
+}
@@ -8,13 +8,26 @@ pub mod opt_net;
 #[cfg(all(feature = "sockopt", target_os = "linux"))]
 pub mod tproxy;
 
+#[cfg(feature = "tun")]
+pub mod tun;
+
 pub mod h2;
+#[cfg(feature = "quinn")]
+pub mod h3;
+pub mod http_upgrade;
 #[cfg(any(feature = "use-native-tls", feature = "native-tls-vendored"))]
 pub mod native_tls;
+pub mod split_http;
 pub mod ws;
 
 pub mod quic_common;
 
+#[cfg(feature = "quinn")]
+pub mod hysteria2;
+
+#[cfg(feature = "quinn")]
+pub mod tuic;
+
 #[cfg(feature = "quic")]
 pub mod quic;
 
@@ -1,25 +1,60 @@
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
 use async_trait::async_trait;
 use bytes::BytesMut;
 use ruci::map::*;
-use ruci::net::CID;
+use ruci::net::{addr_conn::AsyncWriteAddrExt, Network, CID};
 use ruci::Name;
 use ruci::{map, net::Stream};
 
 use macro_map::*;
-use s2n_quic::client::Connect;
-use tokio::sync::Mutex;
+use s2n_quic::{
+    client::Connect,
+    provider::congestion_controller::{Bbr, Cubic},
+};
 use tracing::debug;
 
-use crate::map::rustls21;
+use crate::map::{
+    quic_common::{
+        pool::{Guarded, Pool, PoolConn, PoolOptions},
+        udp::Sessions,
+        CongestionControl,
+    },
+    rustls21,
+};
+
+#[derive(Debug, Clone)]
+struct ConnState {
+    handle: s2n_quic::connection::Handle,
+    udp: Sessions,
+    closed: Arc<AtomicBool>,
+}
+
+impl PoolConn for ConnState {
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    fn close(&self) {
+        self.handle.close(s2n_quic::application::Error::UNKNOWN);
+        self.closed.store(true, Ordering::Relaxed);
+    }
 
+    fn other_active(&self) -> usize {
+        self.udp.len()
+    }
+}
+
+/// s2n-quic 暂不支持 0-RTT, 配置中的 zero_rtt 会被忽略
 #[map_ext_fields]
 #[derive(Debug, Clone, MapExt)]
 pub struct Client {
     c: s2n_quic::Client,
-    conn: Arc<Mutex<Option<s2n_quic::Connection>>>,
+    pool: Pool<ConnState>,
 
     server_addr: SocketAddr,
     server_name: String,
@@ -33,59 +68,96 @@ impl Name for Client {
 
 impl Client {
     pub fn new(c: crate::map::quic_common::ClientConfig) -> anyhow::Result<Self> {
+        let opts = PoolOptions::from_config(&c);
         let tls = {
             let cc = rustls21::cc(rustls21::ClientOptions {
                 is_insecure: c.is_insecure.unwrap_or_default(),
                 alpn: c.alpn,
                 cert_path: c.cert_path.clone(),
+                early_data: false,
             })?;
 
             s2n_quic_rustls::Client::from(cc)
         };
 
-        let client = s2n_quic::Client::builder()
+        let builder = s2n_quic::Client::builder()
             .with_tls(tls)?
             .with_io("0.0.0.0:0")?
-            .start()?;
+            .with_limits(super::limits(&c.transport)?)?;
+        let client = match super::congestion_control(&c.transport)? {
+            CongestionControl::Bbr => builder
+                .with_congestion_controller(Bbr::default())?
+                .start()?,
+            _ => builder
+                .with_congestion_controller(Cubic::default())?
+                .start()?,
+        };
 
         let a: SocketAddr = c.server_addr.parse()?;
 
         Ok(Self {
             c: client,
-            conn: Arc::new(Mutex::new(None)),
+            pool: Pool::new(opts),
             server_addr: a,
             server_name: c.server_name,
             ext_fields: Some(MapExtFields::default()),
         })
     }
+
+    async fn connect(&self, cid: &CID) -> anyhow::Result<ConnState> {
+        let connect = Connect::new(self.server_addr).with_server_name(self.server_name.clone());
+        let mut connection = self.c.connect(connect).await?;
+        connection.keep_alive(true)?;
+
+        let (handle, acceptor) = connection.split();
+        let (_, recv) = acceptor.split();
+        let (udp, out_rx) = Sessions::new();
+        let closed = super::udp::spawn(handle.clone(), recv, udp.clone(), out_rx, None);
+
+        debug!(cid = %cid, "inited new quic connection");
+        Ok(ConnState {
+            handle,
+            udp,
+            closed,
+        })
+    }
+
     async fn handshake(
         &self,
         cid: CID,
         a: Option<ruci::net::Addr>,
         b: Option<BytesMut>,
     ) -> anyhow::Result<map::MapResult> {
-        let mut conn = self.conn.lock().await;
-
+        // 连接可能 在 取出后 才发现已断开, 此时 关闭它 并重试一次
+        let mut retried = false;
         loop {
-            if conn.is_none() {
-                let connect =
-                    Connect::new(self.server_addr).with_server_name(self.server_name.clone());
-                let mut connection = self.c.connect(connect).await?;
-                connection.keep_alive(true)?;
+            let (state, guard) = self.pool.get(|| self.connect(&cid)).await?;
+
+            if let Some(ta) = a.as_ref().filter(|a| a.network == Network::UDP) {
+                drop(guard);
+                let mut u = state.udp.open();
+                if let Some(b) = b.as_ref().filter(|b| !b.is_empty()) {
+                    u.w.write(b, ta).await?;
+                }
+                debug!(cid = %cid, "quic client opened new udp session");
 
-                *conn = Some(connection);
-                debug!(cid = %cid, "inited new quic connection");
-            } else {
-                let mut real_conn = conn.take().unwrap();
-                let stream_r = real_conn.open_bidirectional_stream().await;
-                *conn = Some(real_conn);
+                return Ok(MapResult::new_u(u).a(a).build());
+            }
 
-                let stream = stream_r?;
+            let stream = match state.handle.clone().open_bidirectional_stream().await {
+                Ok(s) => s,
+                Err(e) if !retried => {
+                    debug!(cid = %cid, "quic client open stream failed, reconnecting: {e}");
+                    state.close();
+                    retried = true;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
 
-                let c: ruci::net::Conn = Box::new(stream);
+            let c: ruci::net::Conn = Box::new(Guarded::new(stream, guard));
 
-                return Ok(MapResult::new_c(c).a(a).b(b).build());
-            }
+            return Ok(MapResult::new_c(c).a(a).b(b).build());
         }
     }
 }
@@ -4,3 +4,35 @@ Defines Maps for quic protocol. uses s2n-quic.
  */
 pub mod client;
 pub mod server;
+pub mod udp;
+
+use std::time::Duration;
+
+use anyhow::bail;
+use s2n_quic::provider::limits::Limits;
+use tracing::warn;
+
+use crate::map::quic_common::{CongestionControl, TransportOptions};
+
+/// s2n-quic 只支持 cubic 与 bbr, 且 不能 设置 初始窗口
+pub fn congestion_control(o: &TransportOptions) -> anyhow::Result<CongestionControl> {
+    if o.initial_window.is_some() {
+        warn!("s2n-quic doesn't support setting initial_window, ignored");
+    }
+    let c = o.congestion_control.unwrap_or_default();
+    if c == CongestionControl::NewReno {
+        bail!("s2n-quic doesn't support new_reno congestion control")
+    }
+    Ok(c)
+}
+
+pub fn limits(o: &TransportOptions) -> anyhow::Result<Limits> {
+    let mut l = Limits::new();
+    if let Some(i) = o.keep_alive_interval {
+        l = l.with_max_keep_alive_period(Duration::from_secs(i))?;
+    }
+    if let Some(t) = o.max_idle_timeout {
+        l = l.with_max_idle_timeout(Duration::from_secs(t))?;
+    }
+    Ok(l)
+}
@@ -10,10 +10,14 @@ use ruci::Name;
 use ruci::{map, net::Stream};
 
 use macro_map::*;
+use s2n_quic::provider::congestion_controller::{Bbr, Cubic};
 use tokio::sync::mpsc;
 use tracing::{debug, warn};
 
-use crate::map::quic_common::ServerConfig;
+use crate::map::quic_common::{
+    udp::{NewSessionSender, Sessions},
+    CongestionControl, ServerConfig, TransportOptions,
+};
 
 #[map_ext_fields]
 #[derive(Debug, Clone, MapExt)]
@@ -22,6 +26,7 @@ pub struct Server {
     tls_cert_path: String,
     listen_addr: String,
     pub alpn: Option<Vec<String>>,
+    transport: TransportOptions,
 
     a_next_cid: Arc<AtomicU32>,
 }
@@ -39,6 +44,7 @@ impl Server {
             tls_cert_path: c.cert_path,
             listen_addr: c.listen_addr,
             alpn: c.alpn,
+            transport: c.transport,
             a_next_cid: Arc::new(AtomicU32::new(1)),
             ext_fields: Some(MapExtFields::default()),
         }
@@ -54,11 +60,17 @@ impl Server {
         }
         let tls = tls.build()?;
 
-        let mut server = s2n_quic::Server::builder()
+        let builder = s2n_quic::Server::builder()
             .with_tls(tls)?
             .with_io(self.listen_addr.as_str())?
-            .start()
-            .context("quic init server failed")?;
+            .with_limits(super::limits(&self.transport)?)?;
+        let mut server = match super::congestion_control(&self.transport)? {
+            CongestionControl::Bbr => builder.with_congestion_controller(Bbr::default())?.start(),
+            _ => builder
+                .with_congestion_controller(Cubic::default())?
+                .start(),
+        }
+        .context("quic init server failed")?;
 
         let (tx, rx) = mpsc::channel(100); //todo adjust this
 
@@ -70,7 +82,7 @@ impl Server {
 
             // 对比 quinn, 它返回一个 Connecting, 就好很多
 
-            while let Some(mut connection) = server.accept().await {
+            while let Some(connection) = server.accept().await {
                 let mut new_cid = cid.clone();
                 new_cid.push_num(a_ncid.fetch_add(1, Ordering::Relaxed));
                 debug!(cid = %cid, new_cid = %new_cid, raddr = ?connection.remote_addr(), "quic server got new conn");
@@ -79,13 +91,29 @@ impl Server {
                 let tx = tx.clone();
 
                 tokio::spawn(async move {
-                    let s_count: AtomicU32 = AtomicU32::new(1);
-
-                    while let Ok(Some(stream)) = connection.accept_bidirectional_stream().await {
+                    let s_count = Arc::new(AtomicU32::new(1));
+
+                    let (handle, acceptor) = connection.split();
+                    let (mut bidi, recv) = acceptor.split();
+
+                    let (udp, out_rx) = Sessions::new();
+                    super::udp::spawn(
+                        handle.clone(),
+                        recv,
+                        udp,
+                        out_rx,
+                        Some(NewSessionSender {
+                            cid: cc.clone(),
+                            count: s_count.clone(),
+                            tx: tx.clone(),
+                        }),
+                    );
+
+                    while let Ok(Some(stream)) = bidi.accept_bidirectional_stream().await {
                         let mut new_cid = cc.clone();
                         new_cid.push_num(s_count.fetch_add(1, Ordering::Relaxed));
 
-                        debug!(cid = %cc, new_cid = %new_cid, raddr = ?connection.remote_addr(), "quic server conn got new sub stream");
+                        debug!(cid = %cc, new_cid = %new_cid, raddr = ?handle.remote_addr(), "quic server conn got new sub stream");
 
                         let stream = Box::new(stream);
 
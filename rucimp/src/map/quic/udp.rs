@@ -0,0 +1,94 @@
+/*!
+udp over s2n-quic, 编码见 [`crate::map::quic_common::udp`]
+
+s2n-quic 的 datagram 需要 unstable 的 provider, 所以这里 只用 单向流 传输 udp 包.
+ */
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use bytes::BytesMut;
+use s2n_quic::connection::{Handle, ReceiveStreamAcceptor};
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::map::quic_common::udp::{
+    decode, dispatch_loop, encode, NewSessionSender, Packet, Sessions, MAX_PACKET_LEN,
+};
+
+/// 为 一个连接 启动 udp 包的 收发任务.
+///
+/// new_tx 不为 None 时 (服务端), 收到的 新会话 通过 new_tx 发出.
+///
+/// 返回的 flag 在 连接关闭 (接收单向流 失败) 后 被置为 true
+pub fn spawn(
+    handle: Handle,
+    recv: ReceiveStreamAcceptor,
+    sessions: Sessions,
+    out_rx: mpsc::Receiver<Packet>,
+    new_tx: Option<NewSessionSender>,
+) -> Arc<AtomicBool> {
+    tokio::spawn(send_loop(handle, out_rx));
+
+    let closed = Arc::new(AtomicBool::new(false));
+    let (p_tx, p_rx) = mpsc::channel(256);
+    let cc = closed.clone();
+    tokio::spawn(async move {
+        recv_loop(recv, p_tx).await;
+        cc.store(true, Ordering::Relaxed);
+    });
+    tokio::spawn(dispatch_loop(sessions, p_rx, new_tx));
+    closed
+}
+
+async fn send_loop(handle: Handle, mut out_rx: mpsc::Receiver<Packet>) {
+    while let Some(p) = out_rx.recv().await {
+        let p = encode(p.sid, &p.addr, &p.payload);
+        let mut h = handle.clone();
+        tokio::spawn(async move {
+            let r = async {
+                let mut s = h.open_send_stream().await?;
+                s.send(p).await?;
+                s.finish()?;
+                anyhow::Ok(())
+            }
+            .await;
+            if let Err(e) = r {
+                debug!("quic udp send by uni stream got e: {e}");
+            }
+        });
+    }
+}
+
+async fn recv_loop(mut recv: ReceiveStreamAcceptor, p_tx: mpsc::Sender<Packet>) {
+    while let Ok(Some(mut s)) = recv.accept_receive_stream().await {
+        let p_tx = p_tx.clone();
+        tokio::spawn(async move {
+            let mut buf = BytesMut::new();
+            loop {
+                match s.receive().await {
+                    Ok(Some(chunk)) => {
+                        buf.extend_from_slice(&chunk);
+                        if buf.len() > MAX_PACKET_LEN {
+                            debug!("quic udp uni stream too long");
+                            return;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        debug!("quic udp read uni stream got e: {e}");
+                        return;
+                    }
+                }
+            }
+            match decode(buf.freeze()) {
+                Ok(p) => {
+                    let _ = p_tx.send(p).await;
+                }
+                Err(e) => debug!("quic udp got invalid packet: {e}"),
+            }
+        });
+    }
+}
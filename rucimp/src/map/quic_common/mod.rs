@@ -0,0 +1,73 @@
+/*!
+Defines common part for various quic implementations.
+ */
+
+pub mod pool;
+pub mod udp;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerConfig {
+    pub key_path: String,
+    pub cert_path: String,
+    pub listen_addr: String,
+    pub alpn: Option<Vec<String>>,
+
+    /// 接受 客户端的 0-RTT 数据, 仅 quinn 支持
+    pub zero_rtt: Option<bool>,
+
+    #[serde(flatten)]
+    pub transport: TransportOptions,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClientConfig {
+    pub server_addr: String,
+    pub server_name: String,
+
+    pub cert_path: Option<String>,
+    pub alpn: Option<Vec<String>>,
+    pub is_insecure: Option<bool>,
+
+    /// 连接池 的 最大连接数, 默认 4
+    pub max_conns: Option<usize>,
+    /// 每个连接 承载的 最大流数, 超过后 新建连接, 默认 100
+    pub max_streams_per_conn: Option<usize>,
+    /// 空闲连接 的 关闭时间, 单位 秒, 默认 60
+    pub idle_timeout: Option<u64>,
+    /// 建立连接 失败时 的 重试次数, 默认 3
+    pub reconnect_attempts: Option<usize>,
+    /// 首次重试 的 等待时间, 单位 毫秒, 之后每次翻倍, 默认 200
+    pub reconnect_backoff_ms: Option<u64>,
+    /// 恢复会话时 使用 0-RTT, 仅 quinn 支持
+    pub zero_rtt: Option<bool>,
+
+    #[serde(flatten)]
+    pub transport: TransportOptions,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CongestionControl {
+    #[default]
+    Cubic,
+    Bbr,
+    /// 仅 quinn 支持
+    NewReno,
+}
+
+/// quic 的 传输参数, 未给出的 使用 各实现的 默认值.
+///
+/// 在 高带宽 高延迟 的 链路上, 可以 选用 bbr 并 调大 initial_window
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TransportOptions {
+    /// 拥塞控制算法, 默认 cubic
+    pub congestion_control: Option<CongestionControl>,
+    /// 初始 拥塞窗口, 单位 字节, 仅 quinn 支持
+    pub initial_window: Option<u64>,
+    /// 发送 keep-alive 的 间隔, 单位 秒
+    pub keep_alive_interval: Option<u64>,
+    /// 连接 空闲 多久后 被关闭, 单位 秒. 与 连接池的 idle_timeout 不同, 这是 quic 协议层 的 超时
+    pub max_idle_timeout: Option<u64>,
+}
@@ -0,0 +1,461 @@
+/*!
+quic 客户端的 连接池.
+
+每次开流时 选 负载最小 的连接; 所有连接的负载 都达到 max_streams_per_conn 时 新建连接,
+连接数 达到 max_conns 后 不再新建, 而是继续使用 负载最小 的连接.
+
+已关闭的连接 会被移除; 没有活跃流 超过 idle_timeout 的连接 会被关闭并移除.
+新建连接失败时 按 reconnect_backoff 指数退避 重试.
+
+新建连接 时 不持有 连接列表 的锁, 所以 拨号 期间 其它流 仍可 使用 已有的连接, 清理 也不会 被阻塞.
+同时 只有 一个 拨号, 等待 拨号 的 流 在 拨号 完成后 重新 选择 连接.
+ */
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::Mutex,
+};
+use tracing::{debug, warn};
+
+use super::ClientConfig;
+
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    pub max_conns: usize,
+    pub max_streams_per_conn: usize,
+    pub idle_timeout: Duration,
+    pub reconnect_attempts: usize,
+    pub reconnect_backoff: Duration,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            max_conns: 4,
+            // 与 quinn 默认的 max_concurrent_bidi_streams 一致
+            max_streams_per_conn: 100,
+            idle_timeout: Duration::from_secs(60),
+            reconnect_attempts: 3,
+            reconnect_backoff: Duration::from_millis(200),
+        }
+    }
+}
+
+impl PoolOptions {
+    pub fn from_config(c: &ClientConfig) -> Self {
+        let d = Self::default();
+        Self {
+            max_conns: c.max_conns.unwrap_or(d.max_conns).max(1),
+            max_streams_per_conn: c
+                .max_streams_per_conn
+                .unwrap_or(d.max_streams_per_conn)
+                .max(1),
+            idle_timeout: c
+                .idle_timeout
+                .map(Duration::from_secs)
+                .unwrap_or(d.idle_timeout),
+            reconnect_attempts: c.reconnect_attempts.unwrap_or(d.reconnect_attempts),
+            reconnect_backoff: c
+                .reconnect_backoff_ms
+                .map(Duration::from_millis)
+                .unwrap_or(d.reconnect_backoff),
+        }
+    }
+}
+
+/// 可放入 [`Pool`] 的连接
+pub trait PoolConn: Clone + Send + Sync + 'static {
+    fn is_closed(&self) -> bool;
+
+    fn close(&self);
+
+    /// 除 双向流 外 的 活跃会话数, 如 udp 会话. 会计入连接的负载
+    fn other_active(&self) -> usize {
+        0
+    }
+}
+
+struct Entry<C> {
+    conn: C,
+    active: Arc<AtomicUsize>,
+    idle_since: Arc<parking_lot::Mutex<Instant>>,
+}
+
+impl<C: PoolConn> Entry<C> {
+    fn new(conn: C) -> Self {
+        Self {
+            conn,
+            active: Arc::new(AtomicUsize::new(0)),
+            idle_since: Arc::new(parking_lot::Mutex::new(Instant::now())),
+        }
+    }
+
+    fn load(&self) -> usize {
+        self.active.load(Ordering::Relaxed) + self.conn.other_active()
+    }
+
+    fn acquire(&self) -> (C, StreamGuard) {
+        self.active.fetch_add(1, Ordering::Relaxed);
+        (
+            self.conn.clone(),
+            StreamGuard {
+                active: self.active.clone(),
+                idle_since: self.idle_since.clone(),
+            },
+        )
+    }
+}
+
+/// 一个流 对 连接的占用, drop 时释放
+pub struct StreamGuard {
+    active: Arc<AtomicUsize>,
+    idle_since: Arc<parking_lot::Mutex<Instant>>,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        if self.active.fetch_sub(1, Ordering::Relaxed) == 1 {
+            *self.idle_since.lock() = Instant::now();
+        }
+    }
+}
+
+/// 将 StreamGuard 与 流 绑定, 流 drop 时 释放占用
+pub struct Guarded<T> {
+    pub inner: T,
+    _guard: StreamGuard,
+}
+
+impl<T> Guarded<T> {
+    pub fn new(inner: T, guard: StreamGuard) -> Self {
+        Self {
+            inner,
+            _guard: guard,
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Guarded<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Guarded<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[derive(Clone)]
+pub struct Pool<C> {
+    entries: Arc<Mutex<Vec<Entry<C>>>>,
+    /// 保证 同时 只有 一个 拨号
+    dial_lock: Arc<Mutex<()>>,
+    reaper_started: Arc<AtomicBool>,
+    pub opts: PoolOptions,
+}
+
+impl<C> Debug for Pool<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pool").field("opts", &self.opts).finish()
+    }
+}
+
+impl<C: PoolConn> Pool<C> {
+    pub fn new(opts: PoolOptions) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(Vec::new())),
+            dial_lock: Arc::new(Mutex::new(())),
+            reaper_started: Arc::new(AtomicBool::new(false)),
+            opts,
+        }
+    }
+
+    /// 取得一个连接 及 对它的占用. 需要新建连接时 调用 connect
+    pub async fn get<F, Fut>(&self, connect: F) -> anyhow::Result<(C, StreamGuard)>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = anyhow::Result<C>>,
+    {
+        self.start_reaper();
+
+        if let Some(r) = self.try_acquire().await {
+            return Ok(r);
+        }
+
+        let _dial = self.dial_lock.lock().await;
+        // 等待 期间 可能 已有 别的流 新建了 连接
+        if let Some(r) = self.try_acquire().await {
+            return Ok(r);
+        }
+        let c = self.connect_with_backoff(&connect).await?;
+
+        let mut entries = self.entries.lock().await;
+        let e = Entry::new(c);
+        let r = e.acquire();
+        entries.push(e);
+        Ok(r)
+    }
+
+    /// 选 负载最小 的连接; 需要 新建连接 时 返回 None
+    async fn try_acquire(&self) -> Option<(C, StreamGuard)> {
+        let mut entries = self.entries.lock().await;
+        reap(&mut entries, self.opts.idle_timeout);
+
+        let e = entries.iter().min_by_key(|e| e.load())?;
+        if e.load() < self.opts.max_streams_per_conn || entries.len() >= self.opts.max_conns {
+            Some(e.acquire())
+        } else {
+            None
+        }
+    }
+
+    async fn connect_with_backoff<F, Fut>(&self, connect: &F) -> anyhow::Result<C>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = anyhow::Result<C>>,
+    {
+        let mut backoff = self.opts.reconnect_backoff;
+        let mut attempt = 0;
+        loop {
+            match connect().await {
+                Ok(c) => return Ok(c),
+                Err(e) if attempt < self.opts.reconnect_attempts => {
+                    attempt += 1;
+                    warn!(
+                        attempt,
+                        "quic connect failed, will retry in {:?}: {e:#}", backoff
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// 当前池中的连接数, 含已关闭 但未移除的
+    pub async fn len(&self) -> usize {
+        self.entries.lock().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// 定期清理 空闲连接, 池被 drop 后 自动退出
+    fn start_reaper(&self) {
+        if self.reaper_started.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let weak: Weak<Mutex<Vec<Entry<C>>>> = Arc::downgrade(&self.entries);
+        let idle = self.opts.idle_timeout;
+        let interval = (idle / 2).max(Duration::from_secs(1));
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(entries) = weak.upgrade() else {
+                    break;
+                };
+                reap(&mut *entries.lock().await, idle);
+            }
+        });
+    }
+}
+
+fn reap<C: PoolConn>(entries: &mut Vec<Entry<C>>, idle_timeout: Duration) {
+    entries.retain(|e| {
+        if e.conn.is_closed() {
+            debug!("quic pool removed a closed connection");
+            return false;
+        }
+        if e.load() == 0 && e.idle_since.lock().elapsed() >= idle_timeout {
+            debug!("quic pool closed an idle connection");
+            e.conn.close();
+            return false;
+        }
+        true
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicU32;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct MockConn {
+        id: u32,
+        closed: Arc<AtomicBool>,
+    }
+
+    impl PoolConn for MockConn {
+        fn is_closed(&self) -> bool {
+            self.closed.load(Ordering::Relaxed)
+        }
+        fn close(&self) {
+            self.closed.store(true, Ordering::Relaxed)
+        }
+    }
+
+    fn mock_connect(
+        n: &AtomicU32,
+        fail_first: u32,
+    ) -> impl Future<Output = anyhow::Result<MockConn>> {
+        let id = n.fetch_add(1, Ordering::Relaxed);
+        async move {
+            if id < fail_first {
+                anyhow::bail!("mock connect failed {id}")
+            }
+            Ok(MockConn {
+                id,
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn pool_limits() -> anyhow::Result<()> {
+        let pool = Pool::new(PoolOptions {
+            max_conns: 2,
+            max_streams_per_conn: 2,
+            ..Default::default()
+        });
+        let n = AtomicU32::new(0);
+        let connect = || mock_connect(&n, 0);
+
+        let (c1, g1) = pool.get(connect).await?;
+        let (c2, g2) = pool.get(connect).await?;
+        assert_eq!((c1.id, c2.id), (0, 0));
+
+        // 第一个连接满了, 新建连接
+        let (c3, _g3) = pool.get(connect).await?;
+        assert_eq!(c3.id, 1);
+
+        // 选负载最小的
+        let (c4, _g4) = pool.get(connect).await?;
+        assert_eq!(c4.id, 1);
+
+        // 连接数已满, 复用负载最小的, 而不是新建
+        let (c5, _g5) = pool.get(connect).await?;
+        assert!(c5.id <= 1);
+        assert_eq!(pool.len().await, 2);
+
+        drop((g1, g2));
+        c3.close();
+        let (c6, _g6) = pool.get(connect).await?;
+        assert_eq!(c6.id, 0);
+        assert_eq!(pool.len().await, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pool_idle_and_reconnect() -> anyhow::Result<()> {
+        let pool = Pool::new(PoolOptions {
+            idle_timeout: Duration::from_millis(50),
+            reconnect_attempts: 2,
+            reconnect_backoff: Duration::from_millis(1),
+            ..Default::default()
+        });
+        let n = AtomicU32::new(0);
+
+        // 前两次失败, 第三次成功
+        let (c, g) = pool.get(|| mock_connect(&n, 2)).await?;
+        assert_eq!(c.id, 2);
+        drop(g);
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        let (c2, _g) = pool.get(|| mock_connect(&n, 0)).await?;
+        assert!(c.is_closed(), "idle connection should be closed");
+        assert_eq!(c2.id, 3);
+
+        // 重试次数用尽
+        let pool2: Pool<MockConn> = Pool::new(PoolOptions {
+            reconnect_attempts: 1,
+            reconnect_backoff: Duration::from_millis(1),
+            ..Default::default()
+        });
+        let n = AtomicU32::new(0);
+        assert!(pool2.get(|| mock_connect(&n, 5)).await.is_err());
+        assert_eq!(n.load(Ordering::Relaxed), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pool_dial_without_lock() -> anyhow::Result<()> {
+        let pool = Pool::new(PoolOptions {
+            max_conns: 2,
+            max_streams_per_conn: 1,
+            ..Default::default()
+        });
+        let (c0, g0) = pool.get(|| mock_connect(&AtomicU32::new(0), 0)).await?;
+
+        // 第二个流 需要 新建连接, 拨号 一直 等到 go 被通知
+        let go = Arc::new(tokio::sync::Notify::new());
+        let dialing = {
+            let pool = pool.clone();
+            let go = go.clone();
+            tokio::spawn(async move {
+                pool.get(|| {
+                    let go = go.clone();
+                    async move {
+                        go.notified().await;
+                        Ok(MockConn {
+                            id: 1,
+                            ..Default::default()
+                        })
+                    }
+                })
+                .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        // 拨号 期间 仍可 访问 连接列表 并 使用 已有的连接
+        let t = Duration::from_millis(200);
+        assert_eq!(tokio::time::timeout(t, pool.len()).await?, 1);
+        drop(g0);
+        let (c, _g) =
+            tokio::time::timeout(t, pool.get(|| mock_connect(&AtomicU32::new(9), 0))).await??;
+        assert_eq!(c.id, c0.id);
+
+        go.notify_one();
+        let (c1, _g1) = dialing.await??;
+        assert_eq!(c1.id, 1);
+        assert_eq!(pool.len().await, 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,405 @@
+/*!
+udp over quic.
+
+每个 udp 包 被编码为 `sid(u32) | socks5 格式的地址 | payload`, 优先用 QUIC DATAGRAM frame 发送;
+对端不支持 datagram, 或包长超过 datagram 的上限时, 改为 每个包 开一个 单向流 发送, 编码不变.
+
+sid 由客户端分配, 同一个 sid 的包 属于 同一个 udp 会话, 即 一个 AddrConn.
+客户端发出的包中 地址是 目标地址, 服务端发回的包中 地址是 回包的来源地址.
+
+[`Sessions`] 只负责 会话的分发, 编码 与 收发 由各 quic 实现 完成:
+发送端 从 [`Sessions::new`] 返回的 Receiver 中取出 [`Packet`], 编码后发送, 接收端 将解码后的包 交给 [`Sessions::dispatch`].
+不同的协议 (如 hysteria2) 可以 用自己的编码 复用 [`Sessions`].
+ */
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use anyhow::bail;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use parking_lot::Mutex;
+use ruci::{
+    map::MapResult,
+    net::{
+        addr_conn::{AddrConn, AsyncReadAddr, AsyncWriteAddr},
+        helpers, Addr, Network, CID,
+    },
+    Name,
+};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::debug;
+
+/// 用单向流 接收 一个包 时的 长度上限
+pub const MAX_PACKET_LEN: usize = 4 + helpers::MAX_LEN_SOCKS5_BYTES + 65535;
+
+/// 每个会话 缓存的 待读取的包 的数量, 满了之后 新包会被丢弃
+const SESSION_BUF_NUM: usize = 64;
+
+/// 所有会话共用的 待发送的包 的数量, 满了之后 新包会被丢弃
+const OUT_BUF_NUM: usize = 256;
+
+/// 一个 udp 包. 客户端发出的包中 addr 是 目标地址, 服务端发出的包中 addr 是 回包的来源地址
+#[derive(Debug, Clone)]
+pub struct Packet {
+    pub sid: u32,
+    pub addr: Addr,
+    pub payload: BytesMut,
+}
+
+pub fn encode(sid: u32, addr: &Addr, payload: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(4 + helpers::MAX_LEN_SOCKS5_BYTES + payload.len());
+    buf.put_u32(sid);
+    helpers::addr_to_socks5_bytes(addr, &mut buf);
+    buf.extend_from_slice(payload);
+    buf.freeze()
+}
+
+pub fn decode(packet: Bytes) -> anyhow::Result<Packet> {
+    if packet.len() < 4 {
+        bail!("quic udp packet too short, {}", packet.len());
+    }
+    let mut buf = BytesMut::from(&packet[..]);
+    let sid = buf.get_u32();
+    let mut addr = helpers::socks5_bytes_to_addr(&mut buf)?;
+    addr.network = Network::UDP;
+    Ok(Packet {
+        sid,
+        addr,
+        payload: buf,
+    })
+}
+
+type PacketTx = mpsc::Sender<(BytesMut, Addr)>;
+
+/// 一个 quic 连接 上的 所有 udp 会话
+#[derive(Debug, Clone)]
+pub struct Sessions {
+    map: Arc<Mutex<HashMap<u32, PacketTx>>>,
+    next_sid: Arc<AtomicU32>,
+    sid_mask: u32,
+    out_tx: mpsc::Sender<Packet>,
+}
+
+/// 服务端 收到 未知 sid 的包 时 新建的会话
+pub struct NewSession {
+    pub conn: AddrConn,
+    pub target: Addr,
+    pub first_payload: BytesMut,
+}
+
+impl Sessions {
+    /// 返回的 Receiver 产出 所有会话 待发送的包
+    pub fn new() -> (Self, mpsc::Receiver<Packet>) {
+        Self::with_sid_mask(u32::MAX)
+    }
+
+    /// 分配的 sid 与 sid_mask 按位与, 用于 sid 不足 32 位 的协议, 如 tuic 为 0xffff
+    pub fn with_sid_mask(sid_mask: u32) -> (Self, mpsc::Receiver<Packet>) {
+        let (out_tx, out_rx) = mpsc::channel(OUT_BUF_NUM);
+        (
+            Self {
+                map: Arc::new(Mutex::new(HashMap::new())),
+                next_sid: Arc::new(AtomicU32::new(1)),
+                sid_mask,
+                out_tx,
+            },
+            out_rx,
+        )
+    }
+
+    /// 客户端 新建一个会话
+    pub fn open(&self) -> AddrConn {
+        let sid = self.next_sid.fetch_add(1, Ordering::Relaxed) & self.sid_mask;
+        self.new_conn(sid)
+    }
+
+    /// 关闭会话, 该会话的 读取 会返回错误
+    pub fn remove(&self, sid: u32) {
+        self.map.lock().remove(&sid);
+    }
+
+    fn new_conn(&self, sid: u32) -> AddrConn {
+        let (tx, rx) = mpsc::channel(SESSION_BUF_NUM);
+        self.map.lock().insert(sid, tx);
+
+        let r = Reader { rx };
+        let w = Writer {
+            sid,
+            out_tx: self.out_tx.clone(),
+            map: self.map.clone(),
+        };
+        let mut ac = AddrConn::new(Box::new(r), Box::new(w));
+        ac.cached_name = String::from("quic_udp");
+        ac
+    }
+
+    /// 将收到的包 分发到 对应的会话.
+    ///
+    /// sid 未知时, 若 accept_new 为 true (服务端) 则新建会话并返回, 否则丢弃该包.
+    pub fn dispatch(&self, packet: Packet, accept_new: bool) -> Option<NewSession> {
+        let Packet { sid, addr, payload } = packet;
+
+        let otx = self.map.lock().get(&sid).cloned();
+        match otx {
+            Some(tx) => {
+                if let Err(TrySendError::Closed(_)) = tx.try_send((payload, addr)) {
+                    self.map.lock().remove(&sid);
+                }
+                None
+            }
+            None if accept_new => Some(NewSession {
+                conn: self.new_conn(sid),
+                target: addr,
+                first_payload: payload,
+            }),
+            None => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// 一个会话的 分片重组, 只重组 最新的一个包
+#[derive(Debug, Default)]
+pub struct Defragger {
+    pid: u16,
+    frags: Vec<Option<Bytes>>,
+    received: usize,
+}
+
+impl Defragger {
+    /// 收齐 一个包的 所有分片 时 返回 重组后的 payload
+    pub fn feed(&mut self, pid: u16, frag_id: u8, frag_count: u8, data: Bytes) -> Option<Bytes> {
+        if frag_count <= 1 {
+            return Some(data);
+        }
+        if frag_id >= frag_count {
+            return None;
+        }
+        if pid != self.pid || self.frags.len() != frag_count as usize {
+            self.pid = pid;
+            self.frags = vec![None; frag_count as usize];
+            self.received = 0;
+        }
+        let slot = &mut self.frags[frag_id as usize];
+        if slot.is_none() {
+            *slot = Some(data);
+            self.received += 1;
+        }
+        if self.received < self.frags.len() {
+            return None;
+        }
+
+        let mut buf = BytesMut::new();
+        for f in self.frags.drain(..).flatten() {
+            buf.extend_from_slice(&f);
+        }
+        self.received = 0;
+        Some(buf.freeze())
+    }
+}
+
+/// 服务端 将 新会话 发往 Generator.
+///
+/// count 与 该连接的 双向流 共用, 以保证 子 id 不重复
+#[derive(Debug, Clone)]
+pub struct NewSessionSender {
+    pub cid: CID,
+    pub count: Arc<AtomicU32>,
+    pub tx: mpsc::Sender<MapResult>,
+}
+
+/// 将 p_rx 收到的包 交给 sessions 分发, 直到 p_rx 关闭.
+///
+/// new_tx 不为 None 时 (服务端), 新会话 通过它发出
+pub async fn dispatch_loop(
+    sessions: Sessions,
+    mut p_rx: mpsc::Receiver<Packet>,
+    new_tx: Option<NewSessionSender>,
+) {
+    while let Some(p) = p_rx.recv().await {
+        let Some(ns) = sessions.dispatch(p, new_tx.is_some()) else {
+            continue;
+        };
+        let Some(nt) = &new_tx else {
+            continue;
+        };
+        let mut ncid = nt.cid.clone();
+        ncid.push_num(nt.count.fetch_add(1, Ordering::Relaxed));
+        debug!(cid = %ncid, target = %ns.target, "quic server got new udp session");
+
+        let m = MapResult::new_u(ns.conn)
+            .a(Some(ns.target))
+            .b(Some(ns.first_payload))
+            .new_id(ncid)
+            .build();
+        if nt.tx.send(m).await.is_err() {
+            break;
+        }
+    }
+}
+
+pub struct Reader {
+    rx: mpsc::Receiver<(BytesMut, Addr)>,
+}
+
+impl Name for Reader {
+    fn name(&self) -> &str {
+        "quic_udp_r"
+    }
+}
+
+impl AsyncReadAddr for Reader {
+    fn poll_read_addr(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, Addr)>> {
+        match self.rx.poll_recv(cx) {
+            Poll::Ready(Some((b, a))) => {
+                // 与 udp 一样, 超出 buf 的部分 被丢弃
+                let n = b.len().min(buf.len());
+                buf[..n].copy_from_slice(&b[..n]);
+                Poll::Ready(Ok((n, a)))
+            }
+            Poll::Ready(None) => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "quic_udp read got session closed",
+            ))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub struct Writer {
+    sid: u32,
+    out_tx: mpsc::Sender<Packet>,
+    map: Arc<Mutex<HashMap<u32, PacketTx>>>,
+}
+
+impl Name for Writer {
+    fn name(&self) -> &str {
+        "quic_udp_w"
+    }
+}
+
+impl AsyncWriteAddr for Writer {
+    fn poll_write_addr(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+        addr: &Addr,
+    ) -> Poll<io::Result<usize>> {
+        let p = Packet {
+            sid: self.sid,
+            addr: addr.clone(),
+            payload: BytesMut::from(buf),
+        };
+        match self.out_tx.try_send(p) {
+            Ok(_) | Err(TrySendError::Full(_)) => Poll::Ready(Ok(buf.len())),
+            Err(TrySendError::Closed(_)) => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "quic_udp write got connection closed",
+            ))),
+        }
+    }
+
+    fn poll_flush_addr(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close_addr(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.map.lock().remove(&self.sid);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ruci::net::addr_conn::{AsyncReadAddrExt, AsyncWriteAddrExt};
+
+    use super::*;
+
+    #[test]
+    fn encode_decode() -> anyhow::Result<()> {
+        let a = Addr::from_strs("udp", "www.example.com", "", 53)?;
+        let p = decode(encode(7, &a, b"hello"))?;
+        assert_eq!(p.sid, 7);
+        assert_eq!(p.addr, a);
+        assert_eq!(&p.payload[..], b"hello");
+
+        let a = Addr::from_strs("udp", "", "1.2.3.4", 443)?;
+        let p = decode(encode(1, &a, b""))?;
+        assert_eq!(p.addr, a);
+        assert!(p.payload.is_empty());
+
+        assert!(decode(Bytes::from_static(&[0, 0])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn defragger() {
+        let mut d = Defragger::default();
+        assert_eq!(d.feed(1, 0, 1, Bytes::from_static(b"a")).unwrap(), "a");
+
+        assert!(d.feed(2, 1, 2, Bytes::from_static(b"c")).is_none());
+        // 新的包 使 旧的 被丢弃
+        assert!(d.feed(3, 1, 2, Bytes::from_static(b"e")).is_none());
+        assert!(d.feed(3, 1, 2, Bytes::from_static(b"e")).is_none());
+        assert_eq!(d.feed(3, 0, 2, Bytes::from_static(b"d")).unwrap(), "de");
+        assert!(d.feed(4, 2, 2, Bytes::from_static(b"x")).is_none());
+    }
+
+    #[tokio::test]
+    async fn sessions() -> anyhow::Result<()> {
+        let (client, mut client_out) = Sessions::new();
+        let (server, mut server_out) = Sessions::new();
+
+        let target = Addr::from_strs("udp", "", "8.8.8.8", 53)?;
+
+        let mut c = client.open();
+        c.w.write(b"query", &target).await?;
+        let p = client_out.recv().await.expect("has packet");
+
+        // 客户端不接受 未知的会话
+        assert!(client
+            .dispatch(decode(encode(99, &target, b""))?, false)
+            .is_none());
+        assert_eq!(client.len(), 1);
+
+        assert_eq!(p.sid, 1);
+        let ns = server.dispatch(p, true).expect("new session");
+        assert_eq!(ns.target, target);
+        assert_eq!(&ns.first_payload[..], b"query");
+        let mut s = ns.conn;
+        assert_eq!(server.len(), 1);
+
+        s.w.write(b"answer", &target).await?;
+        let p = server_out.recv().await.expect("has packet");
+        assert!(client.dispatch(p, false).is_none());
+
+        let mut buf = [0u8; 16];
+        let (n, a) = c.r.read(&mut buf).await?;
+        assert_eq!(&buf[..n], b"answer");
+        assert_eq!(a, target);
+
+        s.w.shutdown().await?;
+        assert!(server.is_empty());
+        Ok(())
+    }
+}
@@ -6,24 +6,49 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use bytes::BytesMut;
 use ruci::map::*;
-use ruci::net::{helpers, CID};
+use ruci::net::{addr_conn::AsyncWriteAddrExt, helpers, Network, CID};
 use ruci::Name;
 use ruci::{map, net::Stream};
 
 use macro_map::*;
-use tokio::sync::Mutex;
 use tracing::debug;
 
 use crate::map::{quic_common, rustls21};
 
+use quic_common::{
+    pool::{Guarded, Pool, PoolConn, PoolOptions},
+    udp::Sessions,
+};
+
+#[derive(Debug, Clone)]
+struct ConnState {
+    conn: quinn::Connection,
+    udp: Sessions,
+}
+
+impl PoolConn for ConnState {
+    fn is_closed(&self) -> bool {
+        self.conn.close_reason().is_some()
+    }
+
+    fn close(&self) {
+        self.conn.close(0u32.into(), b"idle");
+    }
+
+    fn other_active(&self) -> usize {
+        self.udp.len()
+    }
+}
+
 #[map_ext_fields]
 #[derive(Debug, Clone, MapExt)]
 pub struct Client {
     c: Endpoint,
-    conn: Arc<Mutex<Option<quinn::Connection>>>,
+    pool: Pool<ConnState>,
 
     server_addr: SocketAddr,
     server_name: String,
+    zero_rtt: bool,
 }
 
 impl Name for Client {
@@ -34,14 +59,19 @@ impl Name for Client {
 
 impl Client {
     pub fn new(c: quic_common::ClientConfig) -> anyhow::Result<Self> {
+        let zero_rtt = c.zero_rtt.unwrap_or_default();
+        let opts = PoolOptions::from_config(&c);
         let cc = {
             let cc = rustls21::cc(rustls21::ClientOptions {
                 is_insecure: c.is_insecure.unwrap_or_default(),
                 alpn: c.alpn,
                 cert_path: c.cert_path.clone(),
+                early_data: zero_rtt,
             })?;
 
-            quinn::ClientConfig::new(Arc::new(cc))
+            let mut cc = quinn::ClientConfig::new(Arc::new(cc));
+            cc.transport_config(Arc::new(super::transport_config(&c.transport)?));
+            cc
         };
         let mut endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
         endpoint.set_default_client_config(cc);
@@ -50,42 +80,78 @@ impl Client {
 
         Ok(Self {
             c: endpoint,
-            conn: Arc::new(Mutex::new(None)),
+            pool: Pool::new(opts),
             server_addr: a,
             server_name: c.server_name,
+            zero_rtt,
             ext_fields: Some(MapExtFields::default()),
         })
     }
+
+    async fn connect(&self, cid: &CID) -> anyhow::Result<ConnState> {
+        let connecting = self
+            .c
+            .connect(self.server_addr, self.server_name.as_str())?;
+
+        let connection = if self.zero_rtt {
+            match connecting.into_0rtt() {
+                Ok((c, _)) => {
+                    debug!(cid = %cid, "quic connection resumed with 0-rtt");
+                    c
+                }
+                Err(connecting) => connecting.await?,
+            }
+        } else {
+            connecting.await?
+        };
+
+        let (udp, out_rx) = Sessions::new();
+        super::udp::spawn(connection.clone(), udp.clone(), out_rx, None);
+
+        debug!(cid = %cid, "inited new quic connection");
+        Ok(ConnState {
+            conn: connection,
+            udp,
+        })
+    }
+
     async fn handshake(
         &self,
         cid: CID,
         a: Option<ruci::net::Addr>,
         b: Option<BytesMut>,
     ) -> anyhow::Result<map::MapResult> {
-        let mut conn = self.conn.lock().await;
-
+        // 连接可能 在 取出后 才发现已断开, 此时 关闭它 并重试一次
+        let mut retried = false;
         loop {
-            if conn.is_none() {
-                let connection = self
-                    .c
-                    .connect(self.server_addr, self.server_name.as_str())?
-                    .await?;
-                //connection.keep_alive(true)?;
-
-                *conn = Some(connection);
-                debug!(cid = %cid, "inited new quic connection");
-            } else {
-                let real_conn = conn.take().unwrap();
-                let stream_r = real_conn.open_bi().await;
-                *conn = Some(real_conn);
-
-                let (se, re) = stream_r?;
-                let stream = helpers::RWWrapper { w: se, r: re };
-
-                let c: ruci::net::Conn = Box::new(stream);
-
-                return Ok(MapResult::new_c(c).a(a).b(b).build());
+            let (state, guard) = self.pool.get(|| self.connect(&cid)).await?;
+
+            if let Some(ta) = a.as_ref().filter(|a| a.network == Network::UDP) {
+                drop(guard);
+                let mut u = state.udp.open();
+                if let Some(b) = b.as_ref().filter(|b| !b.is_empty()) {
+                    u.w.write(b, ta).await?;
+                }
+                debug!(cid = %cid, "quic client opened new udp session");
+
+                return Ok(MapResult::new_u(u).a(a).build());
             }
+
+            let (se, re) = match state.conn.open_bi().await {
+                Ok(s) => s,
+                Err(e) if !retried => {
+                    debug!(cid = %cid, "quic client open stream failed, reconnecting: {e}");
+                    state.close();
+                    retried = true;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let stream = Guarded::new(helpers::RWWrapper { w: se, r: re }, guard);
+
+            let c: ruci::net::Conn = Box::new(stream);
+
+            return Ok(MapResult::new_c(c).a(a).b(b).build());
         }
     }
 }
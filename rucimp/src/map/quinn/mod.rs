@@ -5,3 +5,78 @@ Defines Maps for quic protocol. uses quinn.
 
 pub mod client;
 pub mod server;
+pub mod udp;
+
+use std::{sync::Arc, time::Duration};
+
+use quinn::{
+    congestion::{BbrConfig, CubicConfig, NewRenoConfig},
+    IdleTimeout, TransportConfig,
+};
+
+use crate::map::quic_common::{CongestionControl, TransportOptions};
+
+/// 按 配置 生成 quinn 的 TransportConfig, 未给出的项 使用 quinn 的 默认值
+pub fn transport_config(o: &TransportOptions) -> anyhow::Result<TransportConfig> {
+    let mut tc = TransportConfig::default();
+
+    match o.congestion_control.unwrap_or_default() {
+        CongestionControl::Cubic => {
+            let mut c = CubicConfig::default();
+            if let Some(w) = o.initial_window {
+                c.initial_window(w);
+            }
+            tc.congestion_controller_factory(Arc::new(c));
+        }
+        CongestionControl::Bbr => {
+            let mut c = BbrConfig::default();
+            if let Some(w) = o.initial_window {
+                c.initial_window(w);
+            }
+            tc.congestion_controller_factory(Arc::new(c));
+        }
+        CongestionControl::NewReno => {
+            let mut c = NewRenoConfig::default();
+            if let Some(w) = o.initial_window {
+                c.initial_window(w);
+            }
+            tc.congestion_controller_factory(Arc::new(c));
+        }
+    }
+    if let Some(i) = o.keep_alive_interval {
+        tc.keep_alive_interval(Some(Duration::from_secs(i)));
+    }
+    if let Some(t) = o.max_idle_timeout {
+        tc.max_idle_timeout(Some(IdleTimeout::try_from(Duration::from_secs(t))?));
+    }
+    Ok(tc)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn transport() -> anyhow::Result<()> {
+        for cc in [
+            CongestionControl::Cubic,
+            CongestionControl::Bbr,
+            CongestionControl::NewReno,
+        ] {
+            transport_config(&TransportOptions {
+                congestion_control: Some(cc),
+                initial_window: Some(1 << 20),
+                keep_alive_interval: Some(5),
+                max_idle_timeout: Some(30),
+            })?;
+        }
+
+        // 超出 quic 可编码的 范围
+        assert!(transport_config(&TransportOptions {
+            max_idle_timeout: Some(u64::MAX / 1000),
+            ..Default::default()
+        })
+        .is_err());
+        Ok(())
+    }
+}
@@ -22,6 +22,8 @@ pub struct Server {
     tls_cert_path: String,
     listen_addr: String,
     pub alpn: Option<Vec<String>>,
+    zero_rtt: bool,
+    transport: quic_common::TransportOptions,
 
     a_next_cid: Arc<AtomicU32>,
 }
@@ -39,6 +41,8 @@ impl Server {
             tls_cert_path: c.cert_path,
             listen_addr: c.listen_addr,
             alpn: c.alpn,
+            zero_rtt: c.zero_rtt.unwrap_or_default(),
+            transport: c.transport,
             a_next_cid: Arc::new(AtomicU32::new(1)),
             ext_fields: Some(MapExtFields::default()),
         }
@@ -48,8 +52,10 @@ impl Server {
             alpn: self.alpn.clone(),
             cert_path: self.tls_cert_path.clone(),
             key_path: self.tls_key_path.clone(),
+            early_data: self.zero_rtt,
         })?;
-        let server_config = ServerConfig::with_crypto(Arc::new(server_config));
+        let mut server_config = ServerConfig::with_crypto(Arc::new(server_config));
+        server_config.transport_config(Arc::new(super::transport_config(&self.transport)?));
 
         let endpoint = Endpoint::server(server_config, self.listen_addr.parse()?)?;
 
@@ -57,6 +63,7 @@ impl Server {
 
         let cidc = cid.clone();
         let a_ncid = self.a_next_cid.clone();
+        let zero_rtt = self.zero_rtt;
         tokio::spawn(async move {
             let a_ncid = a_ncid.clone();
             let tx = tx.clone();
@@ -68,7 +75,14 @@ impl Server {
 
                 let tx = tx.clone();
                 tokio::spawn(async move {
-                    let connection = connecting.await;
+                    let connection = if zero_rtt {
+                        match connecting.into_0rtt() {
+                            Ok((c, _)) => Ok(c),
+                            Err(connecting) => connecting.await,
+                        }
+                    } else {
+                        connecting.await
+                    };
                     let connection = match connection {
                         Ok(c) => c,
                         Err(e) => {
@@ -83,7 +97,19 @@ impl Server {
                     let cc = new_cid.clone();
                     let tx = tx.clone();
 
-                    let s_count: AtomicU32 = AtomicU32::new(1);
+                    let s_count = Arc::new(AtomicU32::new(1));
+
+                    let (udp, out_rx) = quic_common::udp::Sessions::new();
+                    super::udp::spawn(
+                        connection.clone(),
+                        udp,
+                        out_rx,
+                        Some(quic_common::udp::NewSessionSender {
+                            cid: cc.clone(),
+                            count: s_count.clone(),
+                            tx: tx.clone(),
+                        }),
+                    );
 
                     while let Ok((se, re)) = connection.accept_bi().await {
                         let mut new_cid = cc.clone();
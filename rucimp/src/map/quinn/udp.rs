@@ -0,0 +1,178 @@
+/*!
+udp over quinn, 编码见 [`crate::map::quic_common::udp`]
+ */
+
+use bytes::Bytes;
+use quinn::Connection;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::map::quic_common::udp::{
+    decode, dispatch_loop, encode, NewSessionSender, Packet, Sessions, MAX_PACKET_LEN,
+};
+
+/// 为 conn 启动 udp 包的 收发任务.
+///
+/// new_tx 不为 None 时 (服务端), 收到的 新会话 通过 new_tx 发出
+pub fn spawn(
+    conn: Connection,
+    sessions: Sessions,
+    out_rx: mpsc::Receiver<Packet>,
+    new_tx: Option<NewSessionSender>,
+) {
+    tokio::spawn(send_loop(conn.clone(), out_rx));
+
+    let (p_tx, p_rx) = mpsc::channel(256);
+    tokio::spawn(recv_datagram_loop(conn.clone(), p_tx.clone()));
+    tokio::spawn(recv_uni_loop(conn, p_tx));
+    tokio::spawn(dispatch_loop(sessions, p_rx, new_tx));
+}
+
+async fn send_loop(conn: Connection, mut out_rx: mpsc::Receiver<Packet>) {
+    while let Some(p) = out_rx.recv().await {
+        let p = encode(p.sid, &p.addr, &p.payload);
+        if conn.max_datagram_size().is_some_and(|m| p.len() <= m)
+            && conn.send_datagram(p.clone()).is_ok()
+        {
+            continue;
+        }
+
+        // 不支持 datagram 或 包太大, 用单向流
+        let conn = conn.clone();
+        tokio::spawn(async move {
+            let r = async {
+                let mut s = conn.open_uni().await?;
+                s.write_all(&p).await?;
+                s.finish().await?;
+                anyhow::Ok(())
+            }
+            .await;
+            if let Err(e) = r {
+                debug!("quic udp send by uni stream got e: {e}");
+            }
+        });
+    }
+}
+
+async fn recv_datagram_loop(conn: Connection, p_tx: mpsc::Sender<Packet>) {
+    while let Ok(p) = conn.read_datagram().await {
+        let p = match decode(p) {
+            Ok(p) => p,
+            Err(e) => {
+                debug!("quic udp got invalid packet: {e}");
+                continue;
+            }
+        };
+        if p_tx.send(p).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn recv_uni_loop(conn: Connection, p_tx: mpsc::Sender<Packet>) {
+    while let Ok(mut s) = conn.accept_uni().await {
+        let p_tx = p_tx.clone();
+        tokio::spawn(async move {
+            match s
+                .read_to_end(MAX_PACKET_LEN)
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|p| decode(Bytes::from(p)))
+            {
+                Ok(p) => {
+                    let _ = p_tx.send(p).await;
+                }
+                Err(e) => debug!("quic udp read uni stream got e: {e}"),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use ruci::{
+        map::{Map, MapParams, ProxyBehavior},
+        net::{addr_conn::*, Addr, Stream, CID},
+    };
+    use tokio::time::timeout;
+
+    use crate::map::quic_common;
+
+    #[tokio::test]
+    async fn udp_session() -> anyhow::Result<()> {
+        let res = concat!(env!("CARGO_MANIFEST_DIR"), "/../resource/");
+        let port = ruci::net::gen_random_higher_port();
+
+        let server = super::super::server::Server::new(quic_common::ServerConfig {
+            key_path: format!("{res}test.key"),
+            cert_path: format!("{res}test.crt"),
+            listen_addr: format!("127.0.0.1:{port}"),
+            alpn: Some(vec!["h3".to_string()]),
+            ..Default::default()
+        });
+        let r = server
+            .maps(CID::default(), ProxyBehavior::DECODE, MapParams::default())
+            .await;
+        let Stream::Generator(mut g) = r.c else {
+            panic!("quic server should return a generator, {:?}", r.e)
+        };
+
+        let client = super::super::client::Client::new(quic_common::ClientConfig {
+            server_addr: format!("127.0.0.1:{port}"),
+            server_name: "www.mytest.com".to_string(),
+            alpn: Some(vec!["h3".to_string()]),
+            is_insecure: Some(true),
+            transport: quic_common::TransportOptions {
+                congestion_control: Some(quic_common::CongestionControl::Bbr),
+                keep_alive_interval: Some(5),
+                ..Default::default()
+            },
+            ..Default::default()
+        })?;
+
+        let target = Addr::from_strs("udp", "", "8.8.8.8", 53)?;
+        let r = client
+            .maps(
+                CID::default(),
+                ProxyBehavior::ENCODE,
+                MapParams {
+                    a: Some(target.clone()),
+                    b: Some("query".into()),
+                    ..Default::default()
+                },
+            )
+            .await;
+        assert!(r.e.is_none(), "{:?}", r.e);
+        let Stream::AddrConn(mut c) = r.c else {
+            panic!("quic client should return an AddrConn for udp target")
+        };
+
+        let sr = timeout(Duration::from_secs(5), g.recv())
+            .await?
+            .expect("server got new session");
+        assert_eq!(sr.a.as_ref(), Some(&target));
+        assert_eq!(&sr.b.expect("has first payload")[..], b"query");
+        let Stream::AddrConn(mut s) = sr.c else {
+            panic!("quic server should emit an AddrConn for udp session")
+        };
+
+        // 大于 datagram 上限的包 走单向流
+        let big = vec![7u8; 4000];
+        for p in [&b"answer"[..], &big[..]] {
+            s.w.write(p, &target).await?;
+
+            let mut buf = [0u8; 8192];
+            let (n, a) = timeout(Duration::from_secs(5), c.r.read(&mut buf)).await??;
+            assert_eq!(&buf[..n], p);
+            assert_eq!(a, target);
+        }
+
+        c.w.write(&big, &target).await?;
+        let mut buf = [0u8; 8192];
+        let (n, _) = timeout(Duration::from_secs(5), s.r.read(&mut buf)).await??;
+        assert_eq!(n, big.len());
+        Ok(())
+    }
+}
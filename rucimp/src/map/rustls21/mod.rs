@@ -21,6 +21,7 @@ pub struct ClientOptions {
     pub is_insecure: bool,
     pub alpn: Option<Vec<String>>,
     pub cert_path: Option<String>,
+    pub early_data: bool,
 }
 
 pub(crate) fn cc(opt: ClientOptions) -> anyhow::Result<ClientConfig> {
@@ -53,6 +54,7 @@ pub(crate) fn cc(opt: ClientOptions) -> anyhow::Result<ClientConfig> {
     if let Some(a) = opt.alpn {
         cc.alpn_protocols = a.iter().map(|s| s.as_bytes().to_vec()).collect()
     }
+    cc.enable_early_data = opt.early_data;
     Ok(cc)
 }
 
@@ -62,6 +64,9 @@ pub struct ServerOptions {
 
     pub cert_path: String,
     pub key_path: String,
+
+    /// 接受 0-RTT 数据
+    pub early_data: bool,
 }
 
 pub fn sc(opt: ServerOptions) -> anyhow::Result<ServerConfig> {
@@ -75,6 +80,9 @@ pub fn sc(opt: ServerOptions) -> anyhow::Result<ServerConfig> {
     if let Some(a) = opt.alpn {
         config.alpn_protocols = a.iter().map(|s| s.as_bytes().to_vec()).collect()
     }
+    if opt.early_data {
+        config.max_early_data_size = u32::MAX;
+    }
     Ok(config)
 }
 
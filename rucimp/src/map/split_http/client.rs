@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use bytes::BytesMut;
+use macro_map::*;
+use ruci::{
+    map::{
+        self,
+        fold::{fold, DynVecIterWrapper, FoldParams},
+        Map, MapBox, MapResult, ProxyBehavior,
+    },
+    net::{self, helpers::EarlyDataWrapper, http::CommonConfig, CID},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use tracing::debug;
+
+use crate::net::http::{parse_h1_status, push_h1_headers, read_h1_head};
+
+use super::*;
+
+#[map_ext_fields]
+#[derive(Clone, Debug, MapExt)]
+pub struct Client {
+    config: CommonConfig,
+
+    /// 用于 建立 每个 http 连接 的 链, 如 { BindDialer, TLS }
+    dial: Vec<Arc<MapBox>>,
+
+    max_post_size: usize,
+}
+
+impl ruci::Name for Client {
+    fn name(&self) -> &str {
+        "split_http_client"
+    }
+}
+
+impl Client {
+    pub fn new(config: CommonConfig, dial: Vec<MapBox>, max_post_size: Option<usize>) -> Self {
+        Self {
+            config,
+            dial: dial.into_iter().map(Arc::new).collect(),
+            max_post_size: max_post_size.unwrap_or(DEFAULT_MAX_POST_SIZE),
+            ext_fields: Some(map::MapExtFields::default()),
+        }
+    }
+
+    async fn dial(&self, cid: &CID) -> anyhow::Result<net::Conn> {
+        let r = fold(FoldParams {
+            cid: cid.clone(),
+            behavior: ProxyBehavior::ENCODE,
+            initial_state: MapResult::default(),
+            maps: Box::new(DynVecIterWrapper(self.dial.clone().into_iter())),
+            chain_tag: String::new(),
+
+            #[cfg(feature = "trace")]
+            trace: Vec::new(),
+        })
+        .await;
+        if let Some(e) = r.e {
+            return Err(e.context("split_http dial failed"));
+        }
+        match r.c {
+            net::Stream::Conn(c) => Ok(c),
+            _ => bail!("split_http dial chain should produce a tcplike stream"),
+        }
+    }
+
+    fn request_head(&self, method: &str, path: &str) -> String {
+        let mut s = format!(
+            "{method} {path} HTTP/1.1\r\nHost: {}\r\n",
+            self.config.authority
+        );
+        push_h1_headers(&mut s, &self.config);
+        s
+    }
+
+    /// 发出 一个 POST 并 等待 200
+    async fn post(&self, conn: &mut net::Conn, path: &str, body: &[u8]) -> anyhow::Result<()> {
+        let mut req = self.request_head("POST", path);
+        req.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+        conn.write_all(req.as_bytes()).await?;
+        conn.write_all(body).await?;
+        conn.flush().await?;
+
+        let (head, _) = read_h1_head(conn, BytesMut::new()).await?;
+        let status = parse_h1_status(&head)?;
+        if status != 200 {
+            bail!("split_http POST got resp status not 200: {status}")
+        }
+        Ok(())
+    }
+
+    /// 把 r 中 读到的 数据 依次 POST 出去, 读到 eof 时 发 一个 空的 POST
+    async fn upload<R: AsyncRead + Unpin>(
+        self,
+        cid: CID,
+        sid: String,
+        mut r: R,
+    ) -> anyhow::Result<()> {
+        let base = session_path(&self.config.path, &sid);
+        let mut conn: Option<net::Conn> = None;
+        let mut buf = vec![0u8; self.max_post_size];
+        let mut seq = 0u64;
+        loop {
+            let n = r.read(&mut buf).await?;
+            let path = format!("{base}/{seq}");
+
+            // 连接 可能 已被 对端 或 cdn 关闭, 此时 重连 并 重发 一次; 服务端 会 丢弃 重复的 seq
+            let mut retried = false;
+            loop {
+                let c = match conn.as_mut() {
+                    Some(c) => c,
+                    None => conn.insert(self.dial(&cid).await?),
+                };
+                match self.post(c, &path, &buf[..n]).await {
+                    Ok(_) => break,
+                    Err(e) if !retried => {
+                        debug!(cid = %cid, "split_http POST failed, will retry: {e:#}");
+                        conn = None;
+                        retried = true;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            if n == 0 {
+                return Ok(());
+            }
+            seq += 1;
+        }
+    }
+
+    async fn handshake(
+        &self,
+        cid: CID,
+        a: Option<net::Addr>,
+        b: Option<BytesMut>,
+    ) -> anyhow::Result<map::MapResult> {
+        let sid = format!("{:032x}", rand::random::<u128>());
+
+        let mut gc = self.dial(&cid).await?;
+        let mut req = self.request_head("GET", &session_path(&self.config.path, &sid));
+        req.push_str("\r\n");
+        gc.write_all(req.as_bytes()).await?;
+        gc.flush().await?;
+
+        let (head, rest) = read_h1_head(&mut gc, BytesMut::new())
+            .await
+            .context("split_http GET failed")?;
+        let status = parse_h1_status(&head)?;
+        if status != 200 {
+            bail!("split_http GET got resp status not 200: {status}")
+        }
+        debug!(cid = %cid, sid, "split_http session started");
+
+        let (c, s) = tokio::io::duplex(BUFFER_CAP);
+        let (sr, mut sw) = tokio::io::split(s);
+
+        let up = tokio::spawn(self.clone().upload(cid.clone(), sid, sr));
+
+        tokio::spawn(async move {
+            let mut gr = BufReader::new(EarlyDataWrapper::from(rest, gc));
+            match copy_chunked(&mut gr, &mut sw).await {
+                Ok(_) => {
+                    let _ = sw.shutdown().await;
+                }
+                Err(e) => {
+                    debug!(cid = %cid, "split_http download ended with e: {e}");
+                    return;
+                }
+            }
+            // 上行 也 结束后 才 关闭 GET 连接, 服务端 以此 判断 会话 结束
+            match up.await {
+                Ok(Err(e)) => debug!(cid = %cid, "split_http upload ended with e: {e:#}"),
+                Err(e) => debug!(cid = %cid, "split_http upload task failed: {e}"),
+                _ => {}
+            }
+        });
+
+        Ok(MapResult::new_c(Box::new(c)).a(a).b(b).build())
+    }
+}
+
+#[async_trait]
+impl Map for Client {
+    async fn maps(&self, cid: CID, _behavior: ProxyBehavior, params: map::MapParams) -> MapResult {
+        if let net::Stream::None = params.c {
+            let r = self.handshake(cid, params.a, params.b).await;
+            match r {
+                anyhow::Result::Ok(r) => r,
+                Err(e) => MapResult::from_e(e.context("split_http_client handshake failed")),
+            }
+        } else {
+            MapResult::err_str("split_http_client only support None stream")
+        }
+    }
+}
@@ -0,0 +1,146 @@
+/*!
+Defines Maps for split http.
+
+用于 只允许 普通 请求/响应 的 cdn. 一个 代理流 被 拆成 多个 http/1.1 请求:
+
+- 下行: `GET {path}/{sid}`, 服务端 以 chunked 编码 的 响应 body 持续 发送 数据
+- 上行: `POST {path}/{sid}/{seq}`, 每个 POST 的 body 为 一段 数据, 服务端 按 seq 排序 后 交给 代理流.
+  body 为空 的 POST 表示 上行 结束
+
+客户端 先 发出 GET 并 收到 200 后 才 发出 POST, 所以 服务端 在 GET 时 建立 会话 即可.
+
+由于 需要 多个 连接, 客户端 自己 通过 dial 链 拨号, 只接受 None stream; 服务端 则 与 http_filter 一样
+接在 tcp/tls 之后, 不匹配的 请求 返回 原数据 以 支持 回落.
+ */
+
+pub mod client;
+pub mod server;
+
+#[cfg(test)]
+mod test;
+
+use std::{collections::BTreeMap, io};
+
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
+
+pub const DEFAULT_MAX_POST_SIZE: usize = 1_000_000;
+
+/// 服务端 最多 缓存 的 乱序 上行包 数
+pub const MAX_PENDING_PACKETS: usize = 64;
+
+const BUFFER_CAP: usize = 0x10000;
+
+/// 去掉 path 末尾的 '/' 后 拼上 sid
+pub fn session_path(base: &str, sid: &str) -> String {
+    format!("{}/{sid}", base.trim_end_matches('/'))
+}
+
+/// 从 请求路径 中 解析出 sid 与 可能的 seq. 路径 须为 `{base}/{sid}` 或 `{base}/{sid}/{seq}`
+pub fn parse_session_path<'a>(base: &str, path: &'a str) -> Option<(&'a str, Option<u64>)> {
+    let path = path.split('?').next().unwrap_or_default();
+    let left = path
+        .strip_prefix(base.trim_end_matches('/'))?
+        .strip_prefix('/')?;
+    let mut parts = left.split('/');
+    let sid = parts.next().filter(|s| !s.is_empty())?;
+    let seq = match parts.next() {
+        Some(s) => Some(s.parse().ok()?),
+        None => None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((sid, seq))
+}
+
+/// 写出 一个 chunk; data 为空 时 即为 结束块
+pub async fn write_chunk<W: AsyncWrite + Unpin>(w: &mut W, data: &[u8]) -> io::Result<()> {
+    w.write_all(format!("{:x}\r\n", data.len()).as_bytes())
+        .await?;
+    w.write_all(data).await?;
+    w.write_all(b"\r\n").await?;
+    w.flush().await
+}
+
+/// 从 r 读 chunk 编码 的 body 写到 w, 读到 结束块 后 返回
+pub async fn copy_chunked<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin>(
+    r: &mut R,
+    w: &mut W,
+) -> io::Result<()> {
+    let mut line = String::new();
+    let mut buf = vec![0u8; BUFFER_CAP];
+    loop {
+        line.clear();
+        if r.read_line(&mut line).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let size = line.trim_end().split(';').next().unwrap_or_default();
+        let mut left = usize::from_str_radix(size, 16)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if left == 0 {
+            // 可能的 trailer 不会 出现, 只读 最后的 空行
+            line.clear();
+            r.read_line(&mut line).await?;
+            return Ok(());
+        }
+        while left > 0 {
+            let n = left.min(buf.len());
+            r.read_exact(&mut buf[..n]).await?;
+            w.write_all(&buf[..n]).await?;
+            left -= n;
+        }
+        w.flush().await?;
+
+        let mut crlf = [0u8; 2];
+        r.read_exact(&mut crlf).await?;
+        if &crlf != b"\r\n" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "chunk not ended with crlf",
+            ));
+        }
+    }
+}
+
+/// 按 seq 的 顺序 把 上行包 写入 w, 收到 空包 时 关闭 w
+pub async fn reorder<W: AsyncWrite + Unpin>(
+    mut rx: mpsc::Receiver<(u64, Bytes)>,
+    mut w: W,
+) -> io::Result<()> {
+    let mut next = 0u64;
+    let mut pending = BTreeMap::new();
+    while let Some((seq, b)) = rx.recv().await {
+        if seq < next {
+            continue;
+        }
+        pending.insert(seq, b);
+        if pending.len() > MAX_PENDING_PACKETS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "split http got too many out of order packets",
+            ));
+        }
+        while let Some(b) = pending.remove(&next) {
+            next += 1;
+            if b.is_empty() {
+                return w.shutdown().await;
+            }
+            w.write_all(&b).await?;
+        }
+    }
+    Ok(())
+}
+
+/// 读到 eof 或 出错 为止
+pub async fn wait_eof<R: AsyncRead + Unpin>(r: &mut R) {
+    let mut buf = [0u8; 64];
+    while let Ok(n) = r.read(&mut buf).await {
+        if n == 0 {
+            break;
+        }
+    }
+}
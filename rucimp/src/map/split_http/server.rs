@@ -0,0 +1,232 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{anyhow, bail, Context};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use macro_map::*;
+use parking_lot::Mutex;
+use ruci::{
+    map::{self, Map, MapResult, ProxyBehavior},
+    net::{
+        self,
+        http::{parse_h1_request, CommonConfig, Method, ParsedHttpRequest},
+        CID,
+    },
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{debug, warn};
+
+use crate::net::http::{get_h1_header, match_h1_host, read_h1_head};
+
+use super::*;
+
+const GET_RESPONSE: &str = "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nTransfer-Encoding: chunked\r\nCache-Control: no-store\r\nX-Accel-Buffering: no\r\n\r\n";
+const POST_RESPONSE: &str = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+const NOT_FOUND_RESPONSE: &str = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n";
+
+type Sessions = Arc<Mutex<HashMap<String, mpsc::Sender<(u64, Bytes)>>>>;
+
+#[map_ext_fields]
+#[derive(Clone, Debug, Default, MapExt)]
+pub struct Server {
+    pub config: Option<CommonConfig>,
+
+    sessions: Sessions,
+}
+
+impl ruci::Name for Server {
+    fn name(&self) -> &str {
+        "split_http_server"
+    }
+}
+
+impl Server {
+    pub fn new(config: Option<CommonConfig>) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    fn base_path(&self) -> &str {
+        self.config.as_ref().map(|c| c.path.as_str()).unwrap_or("")
+    }
+
+    /// 匹配时 返回 sid 与 seq
+    fn match_request(&self, r: &ParsedHttpRequest) -> anyhow::Result<(String, Option<u64>)> {
+        if r.parse_result != Ok(()) {
+            bail!("split_http parse failed {:?}", r.parse_result)
+        }
+        if let Some(c) = &self.config {
+            match_h1_host(c, r).map_err(|e| anyhow!("split_http got wrong host, {e}"))?;
+        }
+        let (sid, seq) = parse_session_path(self.base_path(), &r.path)
+            .ok_or_else(|| anyhow!("split_http got wrong path: {}", r.path))?;
+        match (&r.method, seq) {
+            (Method::GET, None) | (Method::POST, Some(_)) => Ok((sid.to_string(), seq)),
+            _ => bail!("split_http got wrong method {:?} for {}", r.method, r.path),
+        }
+    }
+
+    fn start_download(
+        &self,
+        cid: CID,
+        conn: net::Conn,
+        sid: String,
+    ) -> anyhow::Result<tokio::io::DuplexStream> {
+        let (up_tx, up_rx) = mpsc::channel(MAX_PENDING_PACKETS);
+        {
+            let mut ss = self.sessions.lock();
+            if ss.contains_key(&sid) {
+                bail!("split_http got duplicated session {sid}")
+            }
+            ss.insert(sid.clone(), up_tx);
+        }
+
+        let (a, b) = tokio::io::duplex(BUFFER_CAP);
+        let (mut br, bw) = tokio::io::split(b);
+        let (mut cr, mut cw) = tokio::io::split(conn);
+
+        let cidc = cid.clone();
+        tokio::spawn(async move {
+            if let Err(e) = reorder(up_rx, bw).await {
+                debug!(cid = %cidc, "split_http upload ended with e: {e}");
+            }
+        });
+
+        let sessions = self.sessions.clone();
+        tokio::spawn(async move {
+            let down = async {
+                cw.write_all(GET_RESPONSE.as_bytes()).await?;
+                cw.flush().await?;
+                let mut buf = vec![0u8; BUFFER_CAP];
+                loop {
+                    let n = br.read(&mut buf).await?;
+                    write_chunk(&mut cw, &buf[..n]).await?;
+                    if n == 0 {
+                        return std::io::Result::Ok(());
+                    }
+                }
+            };
+            // 客户端 在 上下行 都结束后 才 关闭 GET 连接; 它 提前关闭 则 说明 客户端 已断开
+            let watch = wait_eof(&mut cr);
+            tokio::pin!(watch);
+            tokio::select! {
+                _ = &mut watch => {
+                    debug!(cid = %cid, "split_http GET conn closed");
+                }
+                r = down => {
+                    if let Err(e) = r {
+                        debug!(cid = %cid, "split_http download ended with e: {e}");
+                    }
+                    watch.await;
+                }
+            }
+            sessions.lock().remove(&sid);
+        });
+
+        Ok(a)
+    }
+
+    /// 处理 一个 连接 上的 所有 POST, 直到 连接 关闭
+    async fn serve_posts(
+        &self,
+        cid: CID,
+        mut conn: net::Conn,
+        mut head: BytesMut,
+        mut rest: BytesMut,
+    ) -> anyhow::Result<()> {
+        loop {
+            let r = parse_h1_request(&head, false);
+            let (sid, seq) = self.match_request(&r)?;
+            let seq = seq.ok_or_else(|| anyhow!("split_http expect POST on a POST conn"))?;
+
+            let len: usize = get_h1_header(&r, "Content-Length")
+                .ok_or_else(|| anyhow!("split_http POST without Content-Length"))?
+                .parse()
+                .context("split_http POST Content-Length")?;
+            if len > DEFAULT_MAX_POST_SIZE {
+                bail!("split_http POST too large: {len}")
+            }
+
+            let body = if rest.len() >= len {
+                rest.split_to(len)
+            } else {
+                let mut b = std::mem::take(&mut rest);
+                let have = b.len();
+                b.resize(len, 0);
+                conn.read_exact(&mut b[have..]).await?;
+                b
+            };
+
+            let tx = self.sessions.lock().get(sid.as_str()).cloned();
+            let Some(tx) = tx else {
+                warn!(cid = %cid, sid, "split_http POST to unknown session");
+                conn.write_all(NOT_FOUND_RESPONSE.as_bytes()).await?;
+                return Ok(());
+            };
+            tx.send((seq, body.freeze()))
+                .await
+                .map_err(|_| anyhow!("split_http session {sid} closed"))?;
+
+            conn.write_all(POST_RESPONSE.as_bytes()).await?;
+            conn.flush().await?;
+
+            (head, rest) = match read_h1_head(&mut conn, rest).await {
+                Ok(r) => r,
+                Err(_) => return Ok(()),
+            };
+        }
+    }
+
+    async fn handshake(
+        &self,
+        cid: CID,
+        mut conn: net::Conn,
+        a: Option<net::Addr>,
+        b: Option<BytesMut>,
+    ) -> MapResult {
+        let (head, rest) = match read_h1_head(&mut conn, b.unwrap_or_default()).await {
+            Ok(r) => r,
+            Err(e) => return MapResult::from_e(e.context("split_http_server read head failed")),
+        };
+
+        let r = parse_h1_request(&head, false);
+        let (sid, seq) = match self.match_request(&r) {
+            Ok(r) => r,
+            Err(e) => {
+                let mut buf = head;
+                buf.unsplit(rest);
+                return MapResult::ebc(e.context(format!("cid={cid}")), buf, conn);
+            }
+        };
+
+        if seq.is_some() {
+            let s = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = s.serve_posts(cid.clone(), conn, head, rest).await {
+                    debug!(cid = %cid, "split_http POST conn ended with e: {e:#}");
+                }
+            });
+            // POST 连接 被 消耗, 不产生 代理流
+            return MapResult::default();
+        }
+
+        debug!(cid = %cid, sid, "split_http new session");
+        match self.start_download(cid, conn, sid) {
+            Ok(c) => MapResult::new_c(Box::new(c)).a(a).build(),
+            Err(e) => MapResult::from_e(e),
+        }
+    }
+}
+
+#[async_trait]
+impl Map for Server {
+    async fn maps(&self, cid: CID, _behavior: ProxyBehavior, params: map::MapParams) -> MapResult {
+        if let net::Stream::Conn(conn) = params.c {
+            self.handshake(cid, conn, params.a, params.b).await
+        } else {
+            MapResult::err_str("split_http_server only support tcplike stream")
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use ruci::{
+    map::{network::BindDialer, Map, MapExtFields, MapParams, ProxyBehavior},
+    net::{self, Stream, CID},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+use super::*;
+use crate::net::http::test_config;
+
+#[test]
+fn path() {
+    assert_eq!(session_path("/p/", "abc"), "/p/abc");
+    assert_eq!(parse_session_path("/p", "/p/abc"), Some(("abc", None)));
+    assert_eq!(
+        parse_session_path("/p/", "/p/abc/3?x=1"),
+        Some(("abc", Some(3)))
+    );
+    assert_eq!(parse_session_path("", "/abc/0"), Some(("abc", Some(0))));
+    assert_eq!(parse_session_path("/p", "/q/abc"), None);
+    assert_eq!(parse_session_path("/p", "/p/abc/x"), None);
+    assert_eq!(parse_session_path("/p", "/p/abc/1/2"), None);
+    assert_eq!(parse_session_path("/p", "/p/"), None);
+}
+
+#[tokio::test]
+async fn chunked_and_reorder() -> anyhow::Result<()> {
+    let mut v = Vec::new();
+    write_chunk(&mut v, b"hello").await?;
+    write_chunk(&mut v, &[1u8; 300]).await?;
+    write_chunk(&mut v, b"").await?;
+    assert!(v.starts_with(b"5\r\nhello\r\n12c\r\n"));
+
+    let mut out = Vec::new();
+    copy_chunked(&mut &v[..], &mut out).await?;
+    assert_eq!(out.len(), 305);
+
+    let (tx, rx) = mpsc::channel(10);
+    for (seq, d) in [(1, "b"), (0, "a"), (0, "a"), (3, ""), (2, "c")] {
+        tx.send((seq, Bytes::from_static(d.as_bytes()))).await?;
+    }
+    let mut out = Vec::new();
+    reorder(rx, &mut out).await?;
+    assert_eq!(out, b"abc");
+    Ok(())
+}
+
+/// 起一个 tcp 监听, 把 每个 连接 交给 split http server, 返回 产生的 代理流
+async fn start_server() -> anyhow::Result<(u16, tokio::sync::mpsc::Receiver<net::Conn>)> {
+    let l = TcpListener::bind("127.0.0.1:0").await?;
+    let port = l.local_addr()?.port();
+    let server = server::Server::new(Some(test_config("/split")));
+    let (tx, rx) = tokio::sync::mpsc::channel(10);
+    tokio::spawn(async move {
+        while let Ok((c, _)) = l.accept().await {
+            let server = server.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let r = server
+                    .maps(
+                        CID::default(),
+                        ProxyBehavior::DECODE,
+                        MapParams::new(Box::new(c)),
+                    )
+                    .await;
+                if let Stream::Conn(c) = r.c {
+                    let _ = tx.send(c).await;
+                }
+            });
+        }
+    });
+    Ok((port, rx))
+}
+
+fn client(port: u16, max_post_size: Option<usize>) -> client::Client {
+    let d = BindDialer {
+        dial_addr: Some(
+            net::Addr::from_name_network_addr_url(&format!("tcp://127.0.0.1:{port}")).unwrap(),
+        ),
+        bind_addr: None,
+        auto_route: None,
+        ext_fields: Some(MapExtFields::default()),
+    };
+    client::Client::new(test_config("/split"), vec![Box::new(d)], max_post_size)
+}
+
+#[tokio::test]
+async fn relay() -> anyhow::Result<()> {
+    let (port, mut rx) = start_server().await?;
+
+    // 小的 max_post_size 使 上行 被 拆成 多个 POST
+    let r = client(port, Some(1000))
+        .maps(CID::default(), ProxyBehavior::ENCODE, MapParams::default())
+        .await;
+    let Stream::Conn(mut c) = r.c else {
+        panic!("client should return a conn, {:?}", r.e)
+    };
+    let mut s = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await?
+        .expect("server got stream");
+
+    let up: Vec<u8> = (0..50_000u32).map(|i| i as u8).collect();
+    let upc = up.clone();
+    let w = tokio::spawn(async move {
+        c.write_all(&upc).await?;
+        c.shutdown().await?;
+        anyhow::Ok(c)
+    });
+    let mut buf = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), s.read_to_end(&mut buf)).await??;
+    assert_eq!(buf, up);
+
+    let mut c = w.await??;
+    s.write_all(b"world").await?;
+    s.shutdown().await?;
+    let mut buf = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), c.read_to_end(&mut buf)).await??;
+    assert_eq!(buf, b"world");
+    Ok(())
+}
+
+#[tokio::test]
+async fn fallback() -> anyhow::Result<()> {
+    let (c, s) = tokio::io::duplex(4096);
+    let server = server::Server::new(Some(test_config("/split")));
+    let h = tokio::spawn(async move {
+        server
+            .maps(
+                CID::default(),
+                ProxyBehavior::DECODE,
+                MapParams::new(Box::new(s)),
+            )
+            .await
+    });
+    let mut c = c;
+    c.write_all(b"GET /other HTTP/1.1\r\nHost: myhost\r\n\r\n")
+        .await?;
+    let r = h.await?;
+    assert!(r.e.is_some());
+    assert!(r.b.expect("fallback data").starts_with(b"GET /other "));
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use bytes::BytesMut;
+use quinn::Endpoint;
+use ruci::map::*;
+use ruci::net::{addr_conn::AsyncWriteAddrExt, helpers, Network, CID};
+use ruci::Name;
+use ruci::{map, net::Stream};
+
+use macro_map::*;
+use tracing::debug;
+
+use crate::map::{
+    quic_common::{
+        pool::{Guarded, Pool, PoolConn, PoolOptions},
+        udp::Sessions,
+    },
+    rustls21,
+};
+
+use super::*;
+
+#[derive(Debug, Clone)]
+struct ConnState {
+    conn: quinn::Connection,
+    udp: Sessions,
+}
+
+impl PoolConn for ConnState {
+    fn is_closed(&self) -> bool {
+        self.conn.close_reason().is_some()
+    }
+
+    fn close(&self) {
+        self.conn.close(0u32.into(), b"idle");
+    }
+
+    fn other_active(&self) -> usize {
+        self.udp.len()
+    }
+}
+
+#[map_ext_fields]
+#[derive(Debug, Clone, MapExt)]
+pub struct Client {
+    c: Endpoint,
+    pool: Pool<ConnState>,
+
+    server_addr: SocketAddr,
+    server_name: String,
+    zero_rtt: bool,
+
+    user: User,
+    udp_relay_mode: UdpRelayMode,
+}
+
+impl Name for Client {
+    fn name(&self) -> &'static str {
+        "tuic_client"
+    }
+}
+
+async fn authenticate(conn: &quinn::Connection, user: &User) -> anyhow::Result<()> {
+    let token = user.token(conn)?;
+    let mut s = conn.open_uni().await?;
+    s.write_all(&encode_authenticate(user.uuid_bytes(), token))
+        .await?;
+    s.finish().await?;
+    Ok(())
+}
+
+impl Client {
+    pub fn new(c: ClientConfig) -> anyhow::Result<Self> {
+        let user = User::new(&c.uuid, &c.password)?;
+        let c_quic = c.quic;
+        let zero_rtt = c_quic.zero_rtt.unwrap_or_default();
+        let opts = PoolOptions::from_config(&c_quic);
+        let cc = rustls21::cc(rustls21::ClientOptions {
+            is_insecure: c_quic.is_insecure.unwrap_or_default(),
+            alpn: c_quic.alpn,
+            cert_path: c_quic.cert_path.clone(),
+            early_data: zero_rtt,
+        })?;
+        let mut endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
+        let mut cc = quinn::ClientConfig::new(Arc::new(cc));
+        cc.transport_config(Arc::new(crate::map::quinn::transport_config(
+            &c_quic.transport,
+        )?));
+        endpoint.set_default_client_config(cc);
+
+        Ok(Self {
+            c: endpoint,
+            pool: Pool::new(opts),
+            server_addr: c_quic.server_addr.parse()?,
+            server_name: c_quic.server_name,
+            zero_rtt,
+            user,
+            udp_relay_mode: c.udp_relay_mode.unwrap_or_default(),
+            ext_fields: Some(MapExtFields::default()),
+        })
+    }
+
+    async fn connect(&self, cid: &CID) -> anyhow::Result<ConnState> {
+        let connecting = self
+            .c
+            .connect(self.server_addr, self.server_name.as_str())?;
+
+        let conn = if self.zero_rtt {
+            match connecting.into_0rtt() {
+                Ok((conn, accepted)) => {
+                    debug!(cid = %cid, "tuic connection resumed with 0-rtt");
+
+                    // keying material 在 握手完成后 才能导出, 服务端 会等待 认证 后 再处理 其它命令
+                    let c = conn.clone();
+                    let user = self.user.clone();
+                    let cid = cid.clone();
+                    tokio::spawn(async move {
+                        accepted.await;
+                        if let Err(e) = authenticate(&c, &user).await {
+                            debug!(cid = %cid, "tuic authenticate failed: {e:#}");
+                        }
+                    });
+                    conn
+                }
+                Err(connecting) => {
+                    let conn = connecting.await?;
+                    authenticate(&conn, &self.user).await?;
+                    conn
+                }
+            }
+        } else {
+            let conn = connecting.await?;
+            authenticate(&conn, &self.user).await?;
+            conn
+        };
+
+        let (udp, out_rx) = Sessions::with_sid_mask(u16::MAX as u32);
+        super::udp::spawn_client(
+            conn.clone(),
+            udp.clone(),
+            out_rx,
+            self.udp_relay_mode == UdpRelayMode::Quic,
+        );
+
+        debug!(cid = %cid, "inited new tuic connection");
+        Ok(ConnState { conn, udp })
+    }
+
+    async fn handshake(
+        &self,
+        cid: CID,
+        a: Option<ruci::net::Addr>,
+        b: Option<BytesMut>,
+    ) -> anyhow::Result<map::MapResult> {
+        let ta = a.clone().context("tuic client needs a target addr")?;
+
+        // 连接可能 在 取出后 才发现已断开, 此时 关闭它 并重试一次
+        let mut retried = false;
+        loop {
+            let (state, guard) = self.pool.get(|| self.connect(&cid)).await?;
+
+            if ta.network == Network::UDP {
+                drop(guard);
+                let mut u = state.udp.open();
+                if let Some(b) = b.as_ref().filter(|b| !b.is_empty()) {
+                    u.w.write(b, &ta).await?;
+                }
+                debug!(cid = %cid, "tuic client opened new udp session");
+
+                return Ok(MapResult::new_u(u).a(a).build());
+            }
+
+            let (mut se, re) = match state.conn.open_bi().await {
+                Ok(s) => s,
+                Err(e) if !retried => {
+                    debug!(cid = %cid, "tuic client open stream failed, reconnecting: {e}");
+                    state.close();
+                    retried = true;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let mut req = encode_connect(&ta);
+            if let Some(b) = &b {
+                req.extend_from_slice(b);
+            }
+            se.write_all(&req).await?;
+
+            let stream = Guarded::new(helpers::RWWrapper { w: se, r: re }, guard);
+            let c: ruci::net::Conn = Box::new(stream);
+
+            return Ok(MapResult::new_c(c).a(a).build());
+        }
+    }
+}
+
+#[async_trait]
+impl Map for Client {
+    async fn maps(&self, cid: CID, _behavior: ProxyBehavior, params: MapParams) -> MapResult {
+        let conn = params.c;
+        if let Stream::None = conn {
+            let r = self.handshake(cid, params.a, params.b).await;
+            match r {
+                anyhow::Result::Ok(r) => r,
+                Err(e) => MapResult::from_e(e.context("tuic_client maps failed")),
+            }
+        } else {
+            MapResult::err_str("tuic_client only support None stream")
+        }
+    }
+}
@@ -0,0 +1,281 @@
+/*!
+Defines Maps for tuic v5 protocol. uses quinn.
+
+https://github.com/EAimTY/tuic/blob/dev/SPEC.md
+
+每个命令 以 `VER(0x05) | TYPE` 开头:
+
+- Authenticate `UUID(16) | TOKEN(32)`, 单向流. TOKEN 为 以 UUID 为 label, 密码 为 context 导出的 TLS keying material
+- Connect `ADDR`, 双向流, 之后为 原始数据, 服务端 不回复
+- Packet `ASSOC_ID(u16) | PKT_ID(u16) | FRAG_TOTAL(u8) | FRAG_ID(u8) | SIZE(u16) | ADDR | payload`,
+  native 模式 用 datagram (过大时 分片), quic 模式 每个包 一个 单向流
+- Dissociate `ASSOC_ID(u16)`, 单向流
+- Heartbeat, datagram
+
+ADDR 为 `TYPE | addr | port`, TYPE 0xff 无地址, 0x00 域名 (首字节为长度), 0x01 ipv4, 0x02 ipv6.
+udp 会话的分发 复用 [`crate::map::quic_common::udp::Sessions`], ASSOC_ID 即 sid.
+ */
+
+pub mod client;
+pub mod server;
+pub mod udp;
+
+#[cfg(test)]
+mod test;
+
+use std::{mem, str::FromStr};
+
+use anyhow::{anyhow, bail};
+use bytes::{Buf, BufMut, BytesMut};
+use ruci::{
+    map::{Data, DataFlags},
+    net::{helpers, Addr, Network},
+    user::{self, UserTrait},
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use uuid::Uuid;
+
+use crate::map::quic_common;
+
+pub const VERSION: u8 = 0x05;
+
+pub const CMD_AUTHENTICATE: u8 = 0x00;
+pub const CMD_CONNECT: u8 = 0x01;
+pub const CMD_PACKET: u8 = 0x02;
+pub const CMD_DISSOCIATE: u8 = 0x03;
+pub const CMD_HEARTBEAT: u8 = 0x04;
+
+const ATYP_NONE: u8 = 0xff;
+const ATYP_DOMAIN: u8 = 0x00;
+const ATYP_IP4: u8 = 0x01;
+const ATYP_IP6: u8 = 0x02;
+
+const SOCKS5_ATYP_IP4: u8 = 1;
+const SOCKS5_ATYP_DOMAIN: u8 = 3;
+const SOCKS5_ATYP_IP6: u8 = 4;
+
+pub const TOKEN_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UdpRelayMode {
+    #[default]
+    Native,
+    Quic,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerConfig {
+    #[serde(flatten)]
+    pub quic: quic_common::ServerConfig,
+
+    /// `uuid:password` 形式
+    pub users: Vec<String>,
+
+    /// 连接建立后 须在 此时间内 完成认证, 单位 秒, 默认 3
+    pub auth_timeout: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClientConfig {
+    #[serde(flatten)]
+    pub quic: quic_common::ClientConfig,
+
+    pub uuid: String,
+    pub password: String,
+
+    pub udp_relay_mode: Option<UdpRelayMode>,
+}
+
+/// tuic 用户, 以 uuid 标识
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct User {
+    pub uuid: String,
+    pub password: String,
+
+    astr: String,
+}
+
+impl User {
+    pub fn new(uuid: &str, password: &str) -> anyhow::Result<Self> {
+        let uuid = Uuid::from_str(uuid)?.hyphenated().to_string();
+        Ok(Self {
+            astr: format!("tuic:{uuid}\n{password}"),
+            uuid,
+            password: password.to_string(),
+        })
+    }
+
+    /// uuid:password
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        let (u, p) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("tuic user should be like uuid:password, got {s}"))?;
+        Self::new(u, p)
+    }
+
+    pub fn uuid_bytes(&self) -> [u8; 16] {
+        Uuid::from_str(&self.uuid)
+            .map(|u| *u.as_bytes())
+            .unwrap_or_default()
+    }
+
+    pub fn token(&self, conn: &quinn::Connection) -> anyhow::Result<[u8; TOKEN_LEN]> {
+        let mut t = [0u8; TOKEN_LEN];
+        conn.export_keying_material(&mut t, &self.uuid_bytes(), self.password.as_bytes())
+            .map_err(|e| anyhow!("tuic export keying material failed: {e:?}"))?;
+        Ok(t)
+    }
+}
+
+#[typetag::serde]
+impl UserTrait for User {
+    fn identity_str(&self) -> String {
+        self.uuid.clone()
+    }
+
+    fn identity_bytes(&self) -> &[u8] {
+        self.uuid.as_bytes()
+    }
+
+    fn auth_str(&self) -> String {
+        self.astr.clone()
+    }
+
+    fn auth_bytes(&self) -> &[u8] {
+        self.astr.as_bytes()
+    }
+}
+
+#[typetag::serde]
+impl Data for User {
+    fn get_user(&self) -> Option<Box<dyn user::User>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn take_user(&mut self) -> Option<Box<dyn user::User>> {
+        Some(Box::new(mem::take(self)))
+    }
+
+    fn get_flags(&self) -> DataFlags {
+        DataFlags::User
+    }
+}
+
+/// tuic 的 地址 与 socks5 的 只有 类型字节 不同
+pub fn put_addr(buf: &mut BytesMut, addr: Option<&Addr>) {
+    let Some(addr) = addr else {
+        buf.put_u8(ATYP_NONE);
+        return;
+    };
+    let start = buf.len();
+    helpers::addr_to_socks5_bytes(addr, buf);
+    buf[start] = match buf[start] {
+        SOCKS5_ATYP_IP4 => ATYP_IP4,
+        SOCKS5_ATYP_IP6 => ATYP_IP6,
+        _ => ATYP_DOMAIN,
+    };
+}
+
+fn socks5_atyp(atyp: u8) -> anyhow::Result<u8> {
+    Ok(match atyp {
+        ATYP_DOMAIN => SOCKS5_ATYP_DOMAIN,
+        ATYP_IP4 => SOCKS5_ATYP_IP4,
+        ATYP_IP6 => SOCKS5_ATYP_IP6,
+        _ => bail!("tuic unknown address type {atyp}"),
+    })
+}
+
+pub fn get_addr(buf: &mut BytesMut, network: Network) -> anyhow::Result<Option<Addr>> {
+    if buf.is_empty() {
+        bail!("tuic address: no data")
+    }
+    if buf[0] == ATYP_NONE {
+        buf.advance(1);
+        return Ok(None);
+    }
+    buf[0] = socks5_atyp(buf[0])?;
+    let mut a = helpers::socks5_bytes_to_addr(buf)?;
+    a.network = network;
+    Ok(Some(a))
+}
+
+pub async fn read_addr<R: AsyncRead + Unpin>(
+    r: &mut R,
+    network: Network,
+) -> anyhow::Result<Option<Addr>> {
+    let atyp = r.read_u8().await?;
+    if atyp == ATYP_NONE {
+        return Ok(None);
+    }
+    let mut buf = BytesMut::with_capacity(helpers::MAX_LEN_SOCKS5_BYTES);
+    buf.put_u8(socks5_atyp(atyp)?);
+    let l = match atyp {
+        ATYP_IP4 => 4,
+        ATYP_IP6 => 16,
+        _ => {
+            let l = r.read_u8().await?;
+            buf.put_u8(l);
+            l as usize
+        }
+    };
+    let start = buf.len();
+    buf.resize(start + l + 2, 0);
+    r.read_exact(&mut buf[start..]).await?;
+    let mut a = helpers::socks5_bytes_to_addr(&mut buf)?;
+    a.network = network;
+    Ok(Some(a))
+}
+
+pub fn encode_connect(target: &Addr) -> BytesMut {
+    let mut buf = BytesMut::with_capacity(2 + helpers::MAX_LEN_SOCKS5_BYTES);
+    buf.put_u8(VERSION);
+    buf.put_u8(CMD_CONNECT);
+    put_addr(&mut buf, Some(target));
+    buf
+}
+
+pub fn encode_authenticate(uuid: [u8; 16], token: [u8; TOKEN_LEN]) -> BytesMut {
+    let mut buf = BytesMut::with_capacity(2 + 16 + TOKEN_LEN);
+    buf.put_u8(VERSION);
+    buf.put_u8(CMD_AUTHENTICATE);
+    buf.extend_from_slice(&uuid);
+    buf.extend_from_slice(&token);
+    buf
+}
+
+pub fn encode_dissociate(assoc_id: u16) -> BytesMut {
+    let mut buf = BytesMut::with_capacity(4);
+    buf.put_u8(VERSION);
+    buf.put_u8(CMD_DISSOCIATE);
+    buf.put_u16(assoc_id);
+    buf
+}
+
+pub fn encode_heartbeat() -> BytesMut {
+    let mut buf = BytesMut::with_capacity(2);
+    buf.put_u8(VERSION);
+    buf.put_u8(CMD_HEARTBEAT);
+    buf
+}
+
+/// 读取 命令头, 返回 TYPE
+pub async fn read_header<R: AsyncRead + Unpin>(r: &mut R) -> anyhow::Result<u8> {
+    let ver = r.read_u8().await?;
+    if ver != VERSION {
+        bail!("tuic version not supported: {ver}")
+    }
+    Ok(r.read_u8().await?)
+}
+
+pub fn get_header(buf: &mut impl Buf) -> anyhow::Result<u8> {
+    if buf.remaining() < 2 {
+        bail!("tuic header too short")
+    }
+    let ver = buf.get_u8();
+    if ver != VERSION {
+        bail!("tuic version not supported: {ver}")
+    }
+    Ok(buf.get_u8())
+}
@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use quinn::Endpoint;
+use ruci::map::*;
+use ruci::net::{helpers, Network, CID};
+use ruci::Name;
+use ruci::{map, net::Stream};
+
+use macro_map::*;
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, warn};
+
+use crate::map::{
+    quic_common::{
+        self,
+        udp::{NewSessionSender, Sessions},
+    },
+    rustls21,
+};
+
+use super::udp::{read_uni, Message};
+use super::*;
+
+const DEFAULT_AUTH_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[map_ext_fields]
+#[derive(Debug, Clone, MapExt)]
+pub struct Server {
+    tls_key_path: String,
+    tls_cert_path: String,
+    listen_addr: String,
+    alpn: Option<Vec<String>>,
+    zero_rtt: bool,
+    transport: quic_common::TransportOptions,
+
+    users: Arc<HashMap<[u8; 16], User>>,
+    auth_timeout: Duration,
+
+    a_next_cid: Arc<AtomicU32>,
+}
+
+impl Name for Server {
+    fn name(&self) -> &'static str {
+        "tuic_server"
+    }
+}
+
+/// 一个连接 的 状态. 认证前 收到的命令 会等待 认证完成
+#[derive(Clone)]
+struct ConnCtx {
+    cid: CID,
+    conn: quinn::Connection,
+    auth_rx: watch::Receiver<Option<User>>,
+    udp: Sessions,
+    m_tx: mpsc::Sender<Message>,
+    /// 回包 使用 客户端 最近一次 所用的 模式
+    use_quic: Arc<AtomicBool>,
+}
+
+impl ConnCtx {
+    async fn wait_auth(&self) -> Option<User> {
+        let mut rx = self.auth_rx.clone();
+        let u = rx.wait_for(|u| u.is_some()).await.ok()?;
+        u.clone()
+    }
+}
+
+impl Server {
+    pub fn new(c: ServerConfig) -> anyhow::Result<Self> {
+        let mut users = HashMap::new();
+        for u in c.users {
+            let u = User::parse(&u)?;
+            users.insert(u.uuid_bytes(), u);
+        }
+        if users.is_empty() {
+            bail!("can't init a tuic server without any user");
+        }
+
+        Ok(Self {
+            tls_key_path: c.quic.key_path,
+            tls_cert_path: c.quic.cert_path,
+            listen_addr: c.quic.listen_addr,
+            alpn: c.quic.alpn,
+            zero_rtt: c.quic.zero_rtt.unwrap_or_default(),
+            transport: c.quic.transport,
+            users: Arc::new(users),
+            auth_timeout: c
+                .auth_timeout
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_AUTH_TIMEOUT),
+            a_next_cid: Arc::new(AtomicU32::new(1)),
+            ext_fields: Some(MapExtFields::default()),
+        })
+    }
+
+    async fn start_listen(&self, cid: CID) -> anyhow::Result<map::MapResult> {
+        let tls = rustls21::sc(rustls21::ServerOptions {
+            alpn: self.alpn.clone(),
+            cert_path: self.tls_cert_path.clone(),
+            key_path: self.tls_key_path.clone(),
+            early_data: self.zero_rtt,
+        })?;
+        let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(tls));
+        server_config.transport_config(Arc::new(crate::map::quinn::transport_config(
+            &self.transport,
+        )?));
+
+        let endpoint = Endpoint::server(server_config, self.listen_addr.parse()?)?;
+
+        let (tx, rx) = mpsc::channel(100);
+
+        let s = self.clone();
+        let cidc = cid.clone();
+        tokio::spawn(async move {
+            while let Some(connecting) = endpoint.accept().await {
+                let mut new_cid = cidc.clone();
+                new_cid.push_num(s.a_next_cid.fetch_add(1, Ordering::Relaxed));
+
+                let s = s.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = s.handle_conn(new_cid.clone(), connecting, tx).await {
+                        debug!(cid = %new_cid, "tuic server conn ended: {e:#}");
+                    }
+                });
+            }
+        });
+
+        debug!(cid = %cid , laddr= self.listen_addr.as_str(), "tuic server started");
+        let mr = MapResult::builder()
+            .c(ruci::net::Stream::Generator(rx))
+            .build();
+        Ok(mr)
+    }
+
+    async fn handle_conn(
+        &self,
+        cid: CID,
+        connecting: quinn::Connecting,
+        tx: mpsc::Sender<MapResult>,
+    ) -> anyhow::Result<()> {
+        let conn = if self.zero_rtt {
+            match connecting.into_0rtt() {
+                Ok((c, _)) => c,
+                Err(connecting) => connecting.await?,
+            }
+        } else {
+            connecting.await?
+        };
+        debug!(cid = %cid, raddr = ?conn.remote_address(), "tuic server got new conn");
+
+        let (auth_tx, auth_rx) = watch::channel(None);
+        let s_count = Arc::new(AtomicU32::new(1));
+
+        let (udp, out_rx) = Sessions::with_sid_mask(u16::MAX as u32);
+        let use_quic = Arc::new(AtomicBool::new(false));
+        tokio::spawn(super::udp::send_loop(
+            conn.clone(),
+            out_rx,
+            use_quic.clone(),
+        ));
+        let (m_tx, m_rx) = mpsc::channel(256);
+        super::udp::spawn_dispatch(
+            udp.clone(),
+            m_rx,
+            Some(NewSessionSender {
+                cid: cid.clone(),
+                count: s_count.clone(),
+                tx: tx.clone(),
+            }),
+        );
+
+        let ctx = ConnCtx {
+            cid: cid.clone(),
+            conn: conn.clone(),
+            auth_rx,
+            udp,
+            m_tx,
+            use_quic,
+        };
+
+        let c = ctx.clone();
+        let timeout = self.auth_timeout;
+        tokio::spawn(async move {
+            if tokio::time::timeout(timeout, c.wait_auth()).await.is_err() {
+                debug!(cid = %c.cid, "tuic auth timeout");
+                c.conn.close(0u32.into(), b"auth timeout");
+            }
+        });
+
+        let s = self.clone();
+        let c = ctx.clone();
+        tokio::spawn(async move {
+            let auth_tx = Arc::new(auth_tx);
+            while let Ok(r) = c.conn.accept_uni().await {
+                let s = s.clone();
+                let c = c.clone();
+                let auth_tx = auth_tx.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = s.handle_uni(&c, r, &auth_tx).await {
+                        debug!(cid = %c.cid, "tuic server handle uni stream failed: {e:#}");
+                    }
+                });
+            }
+        });
+
+        let c = ctx.clone();
+        tokio::spawn(async move {
+            while let Ok(d) = c.conn.read_datagram().await {
+                let mut buf = BytesMut::from(&d[..]);
+                let r = async {
+                    match get_header(&mut buf)? {
+                        CMD_PACKET => {
+                            let m = Message::decode(&mut buf)?;
+                            if c.wait_auth().await.is_some() {
+                                c.use_quic.store(false, Ordering::Relaxed);
+                                let _ = c.m_tx.send(m).await;
+                            }
+                        }
+                        CMD_HEARTBEAT => {}
+                        cmd => bail!("tuic server got unexpected datagram command {cmd}"),
+                    }
+                    anyhow::Ok(())
+                }
+                .await;
+                if let Err(e) = r {
+                    debug!(cid = %c.cid, "tuic server got invalid datagram: {e}");
+                }
+            }
+        });
+
+        while let Ok((se, mut re)) = conn.accept_bi().await {
+            let mut new_cid = cid.clone();
+            new_cid.push_num(s_count.fetch_add(1, Ordering::Relaxed));
+
+            let tx = tx.clone();
+            let c = ctx.clone();
+            tokio::spawn(async move {
+                let r = async {
+                    let cmd = read_header(&mut re).await?;
+                    if cmd != CMD_CONNECT {
+                        bail!("tuic server got unexpected bidi stream command {cmd}")
+                    }
+                    let ta = read_addr(&mut re, Network::TCP)
+                        .await?
+                        .ok_or_else(|| anyhow!("tuic connect without address"))?;
+                    let user = c
+                        .wait_auth()
+                        .await
+                        .ok_or_else(|| anyhow!("tuic connection closed before auth"))?;
+                    anyhow::Ok((ta, user))
+                }
+                .await;
+                let (ta, user) = match r {
+                    Ok(r) => r,
+                    Err(e) => {
+                        debug!(cid = %new_cid, "tuic server read connect failed: {e:#}");
+                        return;
+                    }
+                };
+                debug!(cid = %new_cid, target = %ta, "tuic server got new stream");
+
+                let stream: ruci::net::Conn = Box::new(helpers::RWWrapper { w: se, r: re });
+                let mut m = MapResult::new_c(stream)
+                    .a(Some(ta))
+                    .new_id(new_cid.clone())
+                    .build();
+                m.d = Some(Box::new(user));
+                if let Err(e) = tx.send(m).await {
+                    warn!(cid = %new_cid, "tuic send tx got error: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    async fn handle_uni(
+        &self,
+        c: &ConnCtx,
+        r: quinn::RecvStream,
+        auth_tx: &watch::Sender<Option<User>>,
+    ) -> anyhow::Result<()> {
+        let mut buf = read_uni(r).await?;
+        match get_header(&mut buf)? {
+            CMD_AUTHENTICATE => {
+                if buf.len() < 16 + TOKEN_LEN {
+                    bail!("tuic authenticate too short, {}", buf.len())
+                }
+                let mut uuid = [0u8; 16];
+                uuid.copy_from_slice(&buf[..16]);
+                let token = &buf[16..16 + TOKEN_LEN];
+
+                let user = self.users.get(&uuid).cloned();
+                let ok = match &user {
+                    Some(u) => u.token(&c.conn)?[..] == *token,
+                    None => false,
+                };
+                if !ok {
+                    c.conn.close(0u32.into(), b"auth failed");
+                    bail!("tuic auth failed")
+                }
+                let user = user.expect("checked");
+                debug!(cid = %c.cid, user = user.uuid, "tuic server authed");
+                auth_tx.send_replace(Some(user));
+            }
+            CMD_PACKET => {
+                let m = Message::decode(&mut buf)?;
+                if c.wait_auth().await.is_some() {
+                    c.use_quic.store(true, Ordering::Relaxed);
+                    let _ = c.m_tx.send(m).await;
+                }
+            }
+            CMD_DISSOCIATE => {
+                if buf.len() < 2 {
+                    bail!("tuic dissociate too short")
+                }
+                if c.wait_auth().await.is_some() {
+                    c.udp.remove(buf.get_u16() as u32);
+                }
+            }
+            cmd => bail!("tuic server got unexpected uni stream command {cmd}"),
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Map for Server {
+    async fn maps(&self, cid: CID, _behavior: ProxyBehavior, params: MapParams) -> MapResult {
+        let conn = params.c;
+        if let Stream::None = conn {
+            let r = self.start_listen(cid).await;
+            match r {
+                anyhow::Result::Ok(r) => r,
+                Err(e) => MapResult::from_e(e.context("tuic_server maps failed")),
+            }
+        } else {
+            MapResult::err_str("tuic_server only support None stream")
+        }
+    }
+}
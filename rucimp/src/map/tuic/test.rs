@@ -0,0 +1,265 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use ruci::{
+    map::{Map, MapParams, ProxyBehavior},
+    net::{
+        addr_conn::{AsyncReadAddrExt, AsyncWriteAddrExt},
+        Addr, Stream, CID,
+    },
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::{udp::*, *};
+
+const UUID: &str = "a3482e88-686a-4a58-8126-99c9df64b7bf";
+
+#[tokio::test]
+async fn addr() -> anyhow::Result<()> {
+    for a in [
+        Addr::from_strs("tcp", "www.example.com", "", 443)?,
+        Addr::from_strs("tcp", "", "1.2.3.4", 80)?,
+        Addr::from_strs("tcp", "", "::1", 8080)?,
+    ] {
+        let mut buf = BytesMut::new();
+        put_addr(&mut buf, Some(&a));
+        assert!(buf[0] <= ATYP_IP6);
+
+        let mut r = &buf[..];
+        assert_eq!(read_addr(&mut r, Network::TCP).await?, Some(a.clone()));
+        assert!(r.is_empty());
+
+        assert_eq!(get_addr(&mut buf, Network::TCP)?, Some(a));
+        assert!(buf.is_empty());
+    }
+
+    let mut buf = BytesMut::new();
+    put_addr(&mut buf, None);
+    assert_eq!(&buf[..], &[ATYP_NONE]);
+    assert_eq!(get_addr(&mut buf, Network::UDP)?, None);
+
+    let mut buf = BytesMut::from(&[9u8, 0, 0][..]);
+    assert!(get_addr(&mut buf, Network::UDP).is_err());
+    Ok(())
+}
+
+#[test]
+fn user() -> anyhow::Result<()> {
+    let u = User::parse(&format!("{}:pass", UUID.to_uppercase()))?;
+    assert_eq!(u.uuid, UUID);
+    assert_eq!(u.identity_str(), UUID);
+    assert_eq!(u.auth_str(), format!("tuic:{UUID}\npass"));
+    assert_eq!(u.uuid_bytes()[0], 0xa3);
+
+    assert!(User::parse("not-a-uuid:pass").is_err());
+    assert!(User::parse(UUID).is_err());
+    Ok(())
+}
+
+#[test]
+fn packet_fragment() -> anyhow::Result<()> {
+    let m = Message {
+        assoc_id: 3,
+        pkt_id: 9,
+        frag_total: 1,
+        frag_id: 0,
+        addr: Some(Addr::from_strs("udp", "", "8.8.8.8", 53)?),
+        data: Bytes::from(vec![7u8; 3000]),
+    };
+    let mut buf = BytesMut::from(&m.encode()[..]);
+    assert_eq!(get_header(&mut buf)?, CMD_PACKET);
+    assert_eq!(Message::decode(&mut buf)?, m);
+
+    let frags = m.clone().fragment(1200);
+    assert_eq!(frags.len(), 3);
+    assert!(frags.iter().all(|f| f.encode().len() <= 1200));
+    assert!(frags[1..].iter().all(|f| f.addr.is_none()));
+
+    let mut a = Assembler::default();
+    let mut out = None;
+    for f in frags.into_iter().rev() {
+        assert!(out.is_none());
+        let mut buf = BytesMut::from(&f.encode()[..]);
+        get_header(&mut buf)?;
+        out = a.feed(Message::decode(&mut buf)?);
+    }
+    let p = out.expect("reassembled");
+    assert_eq!(p.sid, 3);
+    assert_eq!(p.addr, m.addr.clone().unwrap());
+    assert_eq!(&p.payload[..], &m.data[..]);
+
+    // 太小, 放不下 包头
+    assert!(m.fragment(10).is_empty());
+    Ok(())
+}
+
+fn configs(port: u16) -> (ServerConfig, ClientConfig) {
+    let res = concat!(env!("CARGO_MANIFEST_DIR"), "/../resource/");
+    (
+        ServerConfig {
+            quic: quic_common::ServerConfig {
+                key_path: format!("{res}test.key"),
+                cert_path: format!("{res}test.crt"),
+                listen_addr: format!("127.0.0.1:{port}"),
+                alpn: Some(vec!["h3".to_string()]),
+                ..Default::default()
+            },
+            users: vec![format!("{UUID}:p1")],
+            ..Default::default()
+        },
+        ClientConfig {
+            quic: quic_common::ClientConfig {
+                server_addr: format!("127.0.0.1:{port}"),
+                server_name: "www.mytest.com".to_string(),
+                alpn: Some(vec!["h3".to_string()]),
+                is_insecure: Some(true),
+                ..Default::default()
+            },
+            uuid: UUID.to_string(),
+            password: "p1".to_string(),
+            ..Default::default()
+        },
+    )
+}
+
+async fn udp_roundtrip(
+    client: &client::Client,
+    g: &mut tokio::sync::mpsc::Receiver<ruci::map::MapResult>,
+) -> anyhow::Result<()> {
+    let ua = Addr::from_strs("udp", "", "8.8.8.8", 53)?;
+    let r = client
+        .maps(
+            CID::default(),
+            ProxyBehavior::ENCODE,
+            MapParams {
+                a: Some(ua.clone()),
+                b: Some("query".into()),
+                ..Default::default()
+            },
+        )
+        .await;
+    let Stream::AddrConn(mut cu) = r.c else {
+        panic!("client should return an addr conn, {:?}", r.e)
+    };
+    let sr = tokio::time::timeout(Duration::from_secs(5), g.recv())
+        .await?
+        .expect("server got udp session");
+    assert_eq!(sr.a, Some(ua.clone()));
+    assert_eq!(&sr.b.expect("first payload")[..], b"query");
+    let Stream::AddrConn(mut su) = sr.c else {
+        panic!("server should return an addr conn")
+    };
+
+    // native 模式下 大包 会被分片
+    let big = vec![5u8; 4000];
+    su.w.write(&big, &ua).await?;
+    let mut buf = vec![0u8; 8192];
+    let (n, a) = tokio::time::timeout(Duration::from_secs(5), cu.r.read(&mut buf)).await??;
+    assert_eq!(n, big.len());
+    assert_eq!(a, ua);
+
+    cu.w.write(&big[..3000], &ua).await?;
+    let (n, _) = tokio::time::timeout(Duration::from_secs(5), su.r.read(&mut buf)).await??;
+    assert_eq!(n, 3000);
+    Ok(())
+}
+
+#[tokio::test]
+async fn tcp_and_udp() -> anyhow::Result<()> {
+    let port = ruci::net::gen_random_higher_port();
+    let (sc, cc) = configs(port);
+
+    let server = server::Server::new(sc)?;
+    let r = server
+        .maps(CID::default(), ProxyBehavior::DECODE, MapParams::default())
+        .await;
+    let Stream::Generator(mut g) = r.c else {
+        panic!("tuic server should return a generator, {:?}", r.e)
+    };
+
+    let client = client::Client::new(cc.clone())?;
+
+    // tcp, 带 early data
+    let ta = Addr::from_strs("tcp", "www.example.com", "", 443)?;
+    let r = client
+        .maps(
+            CID::default(),
+            ProxyBehavior::ENCODE,
+            MapParams {
+                a: Some(ta.clone()),
+                b: Some("hello".into()),
+                ..Default::default()
+            },
+        )
+        .await;
+    let Stream::Conn(mut c) = r.c else {
+        panic!("client should return a conn, {:?}", r.e)
+    };
+
+    let sr = tokio::time::timeout(Duration::from_secs(5), g.recv())
+        .await?
+        .expect("server got stream");
+    assert_eq!(sr.a, Some(ta));
+    let user = sr.d.as_ref().and_then(|d| d.get_user()).expect("has user");
+    assert_eq!(user.identity_str(), UUID);
+    let Stream::Conn(mut s) = sr.c else {
+        panic!("server should return a conn")
+    };
+    let mut buf = [0u8; 16];
+    s.read_exact(&mut buf[..5]).await?;
+    assert_eq!(&buf[..5], b"hello");
+    s.write_all(b"world").await?;
+    c.read_exact(&mut buf[..5]).await?;
+    assert_eq!(&buf[..5], b"world");
+
+    udp_roundtrip(&client, &mut g).await?;
+
+    let mut cc = cc;
+    cc.udp_relay_mode = Some(UdpRelayMode::Quic);
+    let client = client::Client::new(cc)?;
+    udp_roundtrip(&client, &mut g).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn auth_failed() -> anyhow::Result<()> {
+    let port = ruci::net::gen_random_higher_port();
+    let (sc, mut cc) = configs(port);
+
+    let server = server::Server::new(sc)?;
+    let r = server
+        .maps(CID::default(), ProxyBehavior::DECODE, MapParams::default())
+        .await;
+    let Stream::Generator(mut g) = r.c else {
+        panic!("tuic server should return a generator, {:?}", r.e)
+    };
+
+    cc.password = "wrong".to_string();
+    let client = client::Client::new(cc)?;
+    let ta = Addr::from_strs("tcp", "www.example.com", "", 443)?;
+    let r = client
+        .maps(
+            CID::default(),
+            ProxyBehavior::ENCODE,
+            MapParams {
+                a: Some(ta),
+                b: Some("hello".into()),
+                ..Default::default()
+            },
+        )
+        .await;
+
+    // tuic 的 connect 没有回复, 认证失败 表现为 连接被关闭
+    if let Stream::Conn(mut c) = r.c {
+        let mut buf = [0u8; 4];
+        let r = tokio::time::timeout(Duration::from_secs(5), c.read(&mut buf)).await?;
+        assert!(!matches!(r, Ok(n) if n > 0));
+    }
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), g.recv())
+            .await
+            .is_err(),
+        "server shouldn't emit streams before auth"
+    );
+    Ok(())
+}
@@ -0,0 +1,309 @@
+/*!
+tuic 的 udp. native 模式 每个包 为一个 datagram, 过大时 分片, 只有 第一个分片 带地址;
+quic 模式 每个包 开一个 单向流, 不分片.
+
+会话的分发 复用 [`crate::map::quic_common::udp::Sessions`], assoc_id 即 sid.
+ */
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::bail;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use quinn::Connection;
+use ruci::net::{helpers, Addr, Network};
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::map::quic_common::udp::{
+    dispatch_loop, Defragger, NewSessionSender, Packet, Sessions, MAX_PACKET_LEN,
+};
+
+use super::*;
+
+const MAX_DEFRAGGERS: usize = 1024;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Packet 命令, 不含 命令头
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub assoc_id: u16,
+    pub pkt_id: u16,
+    pub frag_total: u8,
+    pub frag_id: u8,
+    pub addr: Option<Addr>,
+    pub data: Bytes,
+}
+
+impl Message {
+    /// 含 命令头
+    pub fn encode(&self) -> Bytes {
+        let mut buf =
+            BytesMut::with_capacity(2 + 8 + 1 + helpers::MAX_LEN_SOCKS5_BYTES + self.data.len());
+        buf.put_u8(VERSION);
+        buf.put_u8(CMD_PACKET);
+        buf.put_u16(self.assoc_id);
+        buf.put_u16(self.pkt_id);
+        buf.put_u8(self.frag_total);
+        buf.put_u8(self.frag_id);
+        buf.put_u16(self.data.len() as u16);
+        put_addr(&mut buf, self.addr.as_ref());
+        buf.extend_from_slice(&self.data);
+        buf.freeze()
+    }
+
+    /// 不含 命令头
+    pub fn decode(buf: &mut BytesMut) -> anyhow::Result<Self> {
+        if buf.len() < 9 {
+            bail!("tuic packet too short, {}", buf.len())
+        }
+        let assoc_id = buf.get_u16();
+        let pkt_id = buf.get_u16();
+        let frag_total = buf.get_u8();
+        let frag_id = buf.get_u8();
+        let size = buf.get_u16() as usize;
+        if frag_total == 0 || frag_id >= frag_total {
+            bail!("tuic packet bad fragment {frag_id}/{frag_total}")
+        }
+        let addr = get_addr(buf, Network::UDP)?;
+        if buf.len() < size {
+            bail!("tuic packet payload too short, {} < {size}", buf.len())
+        }
+        let data = buf.split_to(size).freeze();
+        Ok(Self {
+            assoc_id,
+            pkt_id,
+            frag_total,
+            frag_id,
+            addr,
+            data,
+        })
+    }
+
+    fn header_len(&self) -> usize {
+        let mut buf = BytesMut::new();
+        put_addr(&mut buf, self.addr.as_ref());
+        2 + 8 + buf.len()
+    }
+
+    /// 分片, 使 每个分片 编码后 不超过 max_size. 无法分片时 返回 空
+    pub fn fragment(self, max_size: usize) -> Vec<Message> {
+        let hl = self.header_len();
+        if hl + self.data.len() <= max_size {
+            return vec![self];
+        }
+        if max_size <= hl {
+            return vec![];
+        }
+        // 后续分片 不带地址, 按 第一个的 包头 计算, 只会 更小
+        let chunk = max_size - hl;
+        let count = self.data.len().div_ceil(chunk);
+        if count > u8::MAX as usize {
+            return vec![];
+        }
+        (0..count)
+            .map(|i| Message {
+                assoc_id: self.assoc_id,
+                pkt_id: self.pkt_id,
+                frag_total: count as u8,
+                frag_id: i as u8,
+                addr: if i == 0 { self.addr.clone() } else { None },
+                data: self
+                    .data
+                    .slice(i * chunk..((i + 1) * chunk).min(self.data.len())),
+            })
+            .collect()
+    }
+}
+
+/// 将 分片 重组为 [`Packet`], 每个 assoc_id 一个 [`Defragger`]
+#[derive(Debug, Default)]
+pub struct Assembler {
+    map: HashMap<u16, (Defragger, Option<Addr>)>,
+}
+
+impl Assembler {
+    pub fn feed(&mut self, m: Message) -> Option<Packet> {
+        let (data, addr) = if m.frag_total == 1 {
+            (m.data, m.addr)
+        } else {
+            // 会话结束时 这里不会得知, 所以 过多时 直接清空
+            if self.map.len() > MAX_DEFRAGGERS {
+                self.map.clear();
+            }
+            let (d, addr) = self.map.entry(m.assoc_id).or_default();
+            if m.addr.is_some() {
+                *addr = m.addr;
+            }
+            let data = d.feed(m.pkt_id, m.frag_id, m.frag_total, m.data)?;
+            (data, addr.take())
+        };
+        let Some(addr) = addr else {
+            debug!(
+                assoc_id = m.assoc_id,
+                "tuic udp packet without address, dropped"
+            );
+            return None;
+        };
+        Some(Packet {
+            sid: m.assoc_id as u32,
+            addr,
+            payload: BytesMut::from(&data[..]),
+        })
+    }
+}
+
+/// 将 Message 重组后 交给 sessions 分发
+pub fn spawn_dispatch(
+    sessions: Sessions,
+    mut m_rx: mpsc::Receiver<Message>,
+    new_tx: Option<NewSessionSender>,
+) {
+    let (p_tx, p_rx) = mpsc::channel(256);
+    tokio::spawn(async move {
+        let mut a = Assembler::default();
+        while let Some(m) = m_rx.recv().await {
+            let Some(p) = a.feed(m) else {
+                continue;
+            };
+            if p_tx.send(p).await.is_err() {
+                break;
+            }
+        }
+    });
+    tokio::spawn(dispatch_loop(sessions, p_rx, new_tx));
+}
+
+/// 发送 sessions 的包. use_quic 为 true 时 用 单向流, 否则 用 datagram
+pub async fn send_loop(
+    conn: Connection,
+    mut out_rx: mpsc::Receiver<Packet>,
+    use_quic: Arc<AtomicBool>,
+) {
+    let mut pkt_id: u16 = 0;
+    while let Some(p) = out_rx.recv().await {
+        pkt_id = pkt_id.wrapping_add(1);
+        let m = Message {
+            assoc_id: p.sid as u16,
+            pkt_id,
+            frag_total: 1,
+            frag_id: 0,
+            addr: Some(p.addr),
+            data: p.payload.freeze(),
+        };
+
+        if use_quic.load(Ordering::Relaxed) {
+            let conn = conn.clone();
+            tokio::spawn(async move {
+                let r = async {
+                    let mut s = conn.open_uni().await?;
+                    s.write_all(&m.encode()).await?;
+                    s.finish().await?;
+                    anyhow::Ok(())
+                }
+                .await;
+                if let Err(e) = r {
+                    debug!("tuic udp send stream got e: {e}");
+                }
+            });
+            continue;
+        }
+
+        let Some(max) = conn.max_datagram_size() else {
+            debug!("tuic udp: peer doesn't support datagram, dropped");
+            continue;
+        };
+        for f in m.fragment(max) {
+            if let Err(e) = conn.send_datagram(f.encode()) {
+                debug!("tuic udp send datagram got e: {e}");
+                break;
+            }
+        }
+    }
+}
+
+/// 读取 单向流 的 全部内容
+pub async fn read_uni(mut r: quinn::RecvStream) -> anyhow::Result<BytesMut> {
+    let v = r.read_to_end(MAX_PACKET_LEN).await?;
+    Ok(BytesMut::from(&v[..]))
+}
+
+/// 客户端 的 udp 收发 与 心跳
+pub fn spawn_client(
+    conn: Connection,
+    sessions: Sessions,
+    out_rx: mpsc::Receiver<Packet>,
+    use_quic: bool,
+) {
+    tokio::spawn(send_loop(
+        conn.clone(),
+        out_rx,
+        Arc::new(AtomicBool::new(use_quic)),
+    ));
+
+    let (m_tx, m_rx) = mpsc::channel(256);
+    spawn_dispatch(sessions, m_rx, None);
+
+    let c = conn.clone();
+    let tx = m_tx.clone();
+    tokio::spawn(async move {
+        while let Ok(d) = c.read_datagram().await {
+            let mut buf = BytesMut::from(&d[..]);
+            match get_header(&mut buf).and_then(|cmd| match cmd {
+                CMD_PACKET => Message::decode(&mut buf),
+                _ => bail!("tuic client got unexpected datagram command {cmd}"),
+            }) {
+                Ok(m) => {
+                    if tx.send(m).await.is_err() {
+                        break;
+                    }
+                }
+                Err(e) => debug!("tuic udp got invalid datagram: {e}"),
+            }
+        }
+    });
+
+    let c = conn.clone();
+    tokio::spawn(async move {
+        while let Ok(r) = c.accept_uni().await {
+            let tx = m_tx.clone();
+            tokio::spawn(async move {
+                let r = async {
+                    let mut buf = read_uni(r).await?;
+                    match get_header(&mut buf)? {
+                        CMD_PACKET => Message::decode(&mut buf),
+                        cmd => bail!("tuic client got unexpected stream command {cmd}"),
+                    }
+                }
+                .await;
+                match r {
+                    Ok(m) => {
+                        let _ = tx.send(m).await;
+                    }
+                    Err(e) => debug!("tuic udp got invalid stream: {e}"),
+                }
+            });
+        }
+    });
+
+    tokio::spawn(async move {
+        let hb = encode_heartbeat().freeze();
+        loop {
+            tokio::select! {
+                _ = conn.closed() => break,
+                _ = tokio::time::sleep(HEARTBEAT_INTERVAL) => {
+                    if conn.send_datagram(hb.clone()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,264 @@
+/*!
+userspace tcp/ip stack for the tun inbound.
+
+[`Stack`] 接收一个 ip 层的 Conn (如 BindDialer 以 ip://10.0.0.1:24#utun321 为 bind_addr 创建的 tun 设备),
+用用户态的 tcp/ip 协议栈 (ipstack) 将其中的 ip 包 还原为一个个 tcp 连接和 udp 流,
+以 Stream::Generator 的形式输出, 每个输出都带有正确的 target_addr, 于是 tun 可以像其它 inbound 一样
+参与正常的 outbound 选择.
+
+每个 udp 流对应一个 (src, dst) 对, 它只能从 dst 回包; 写入其它地址的数据 也会以 dst 的名义发回.
+
+非 tcp/udp 的 包 (如 icmp) 会被丢弃.
+
+ipstack 的 tcp 流 在 drop 时 会调用 block_in_place, 因此 需要运行在 多线程的 tokio runtime 中.
+ */
+
+#[cfg(test)]
+mod test;
+
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use ipstack::{IpStack, IpStackConfig, IpStackStream, IpStackUdpStream};
+use macro_map::{map_ext_fields, MapExt};
+use ruci::{
+    map::{self, *},
+    net::{self, addr_conn::*, helpers::RWWrapper, *},
+    Name,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::{mpsc, oneshot},
+};
+use tracing::{debug, info};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Options {
+    /// 默认为 1280, 不能小于 1280
+    pub mtu: Option<u16>,
+
+    /// udp 流 在多少秒内无数据后关闭, 默认为 30
+    pub udp_timeout: Option<u64>,
+
+    /// tun 设备读写的包 是否带有 4字节的 packet information 头
+    pub packet_information: Option<bool>,
+}
+
+impl Options {
+    fn to_ipstack_config(&self) -> anyhow::Result<IpStackConfig> {
+        let mut c = IpStackConfig::default();
+        if let Some(m) = self.mtu {
+            c.mtu(m)?;
+        }
+        if let Some(t) = self.udp_timeout {
+            c.udp_timeout(Duration::from_secs(t));
+        }
+        c.packet_information(self.packet_information.unwrap_or_default());
+        Ok(c)
+    }
+}
+
+/// Stack 将 ip 包 的 Conn 转换为 tcp Conn 与 udp AddrConn 的 Generator
+#[map_ext_fields]
+#[derive(Debug, Clone, Default, MapExt)]
+pub struct Stack {
+    pub opts: Options,
+}
+
+impl Name for Stack {
+    fn name(&self) -> &'static str {
+        "tun_stack"
+    }
+}
+
+impl Stack {
+    pub fn new(opts: Options) -> Self {
+        Self {
+            opts,
+            ext_fields: Some(MapExtFields::default()),
+        }
+    }
+
+    async fn start(
+        &self,
+        cid: CID,
+        conn: net::Conn,
+        shutdown_rx: Option<oneshot::Receiver<()>>,
+    ) -> anyhow::Result<MapResult> {
+        let mut stack = IpStack::new(self.opts.to_ipstack_config()?, conn);
+
+        let (tx, rx) = mpsc::channel(100);
+
+        tokio::spawn(async move {
+            let shutdown = async move {
+                match shutdown_rx {
+                    Some(rx) => {
+                        let _ = rx.await;
+                    }
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::pin!(shutdown);
+
+            let mut count = 0;
+
+            loop {
+                let s = tokio::select! {
+                    _ = &mut shutdown => {
+                        debug!(cid = %cid, "tun_stack got shutdown_rx");
+                        break
+                    }
+                    r = stack.accept() => match r {
+                        Ok(s) => s,
+                        Err(e) => {
+                            info!(cid = %cid, "tun_stack accept got e: {e}");
+                            break
+                        }
+                    }
+                };
+
+                let (src, dst) = (s.local_addr(), s.peer_addr());
+                let mr = match s {
+                    IpStackStream::Tcp(t) => {
+                        let (r, w) = tokio::io::split(t);
+                        let dst = to_addr(Network::TCP, dst);
+                        MapResult::new_c(Box::new(RWWrapper { r, w }))
+                            .a(Some(dst.clone()))
+                            .d(Some(Box::new(map::RLAddr(to_addr(Network::TCP, src), dst))))
+                    }
+                    IpStackStream::Udp(u) => {
+                        let dst = to_addr(Network::UDP, dst);
+                        MapResult::new_u(new_addr_conn(u, dst.clone()))
+                            .a(Some(dst.clone()))
+                            .d(Some(Box::new(map::RLAddr(to_addr(Network::UDP, src), dst))))
+                    }
+                    _ => {
+                        debug!(cid = %cid, "tun_stack dropped a non tcp/udp packet");
+                        continue;
+                    }
+                };
+
+                count += 1;
+                let mut ncid = cid.clone();
+                ncid.push_num(count);
+
+                debug!(cid = %ncid, src = %src, dst = %dst, "tun_stack new stream");
+
+                if let Err(e) = tx.send(mr.new_id(ncid).build()).await {
+                    debug!(cid = %cid, "tun_stack tx.send got e: {e}");
+                    break;
+                }
+            }
+        });
+
+        Ok(MapResult::builder().c(Stream::Generator(rx)).build())
+    }
+}
+
+#[async_trait]
+impl Map for Stack {
+    /// Stack only has decode behavior
+    async fn maps(&self, cid: CID, behavior: ProxyBehavior, params: MapParams) -> MapResult {
+        if let ProxyBehavior::ENCODE = behavior {
+            return MapResult::err_str("tun_stack doesn't support ENCODE behavior");
+        }
+        match params.c {
+            Stream::Conn(c) => match self.start(cid, c, params.shutdown_rx).await {
+                Ok(r) => r,
+                Err(e) => MapResult::from_e(e.context("tun_stack start failed")),
+            },
+            _ => MapResult::err_str(&format!(
+                "tun_stack needs an ip packet stream, got {}",
+                params.c
+            )),
+        }
+    }
+}
+
+fn to_addr(network: Network, so: SocketAddr) -> net::Addr {
+    net::Addr {
+        addr: NetAddr::Socket(so),
+        network,
+    }
+}
+
+fn new_addr_conn(u: IpStackUdpStream, dst: net::Addr) -> AddrConn {
+    let (r, w) = tokio::io::split(u);
+    let mut ac = AddrConn::new(Box::new(Reader { r, dst }), Box::new(Writer { w }));
+    ac.cached_name = String::from("tun_udp");
+    ac
+}
+
+pub struct Reader {
+    r: tokio::io::ReadHalf<IpStackUdpStream>,
+    dst: net::Addr,
+}
+
+impl Name for Reader {
+    fn name(&self) -> &str {
+        "tun_udp_r"
+    }
+}
+
+impl AsyncReadAddr for Reader {
+    fn poll_read_addr(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, net::Addr)>> {
+        let mut rb = ReadBuf::new(buf);
+        match Pin::new(&mut self.r).poll_read(cx, &mut rb) {
+            Poll::Ready(Ok(())) => {
+                let n = rb.filled().len();
+
+                // ipstack 在 流关闭时 返回 0
+                if n == 0 {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::ConnectionAborted,
+                        "tun_udp read got stream closed",
+                    )));
+                }
+                Poll::Ready(Ok((n, self.dst.clone())))
+            }
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub struct Writer {
+    w: tokio::io::WriteHalf<IpStackUdpStream>,
+}
+
+impl Name for Writer {
+    fn name(&self) -> &str {
+        "tun_udp_w"
+    }
+}
+
+impl AsyncWriteAddr for Writer {
+    /// 该 udp 流只能以其 dst 的名义回包, 所以 addr 被忽略
+    fn poll_write_addr(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        _addr: &net::Addr,
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.w).poll_write(cx, buf)
+    }
+
+    fn poll_flush_addr(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.w).poll_flush(cx)
+    }
+
+    fn poll_close_addr(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.w).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use etherparse::{PacketBuilder, SlicedPacket, TransportSlice};
+use ruci::{
+    map::{Map, MapParams, ProxyBehavior},
+    net::{addr_conn::*, Stream, CID},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    sync::mpsc,
+    time::timeout,
+};
+
+use super::*;
+
+/// 按包读写的 mock tun 设备, 每次 read/write 都对应一个完整的 ip 包
+struct MockDevice {
+    rx: mpsc::UnboundedReceiver<Vec<u8>>,
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+impl AsyncRead for MockDevice {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.rx.poll_recv(cx) {
+            Poll::Ready(Some(p)) => {
+                buf.put_slice(&p);
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(None) => Poll::Ready(Ok(())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl AsyncWrite for MockDevice {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let _ = self.tx.send(buf.to_vec());
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+const CLIENT: [u8; 4] = [10, 0, 0, 2];
+const TARGET: [u8; 4] = [1, 2, 3, 4];
+
+async fn start_stack() -> (
+    mpsc::UnboundedSender<Vec<u8>>,
+    mpsc::UnboundedReceiver<Vec<u8>>,
+    net::StreamGenerator,
+) {
+    let (in_tx, in_rx) = mpsc::unbounded_channel();
+    let (out_tx, out_rx) = mpsc::unbounded_channel();
+    let dev = MockDevice {
+        rx: in_rx,
+        tx: out_tx,
+    };
+
+    let r = Stack::new(Options::default())
+        .maps(
+            CID::default(),
+            ProxyBehavior::DECODE,
+            MapParams::new(Box::new(dev)),
+        )
+        .await;
+    assert!(r.e.is_none(), "{:?}", r.e);
+    let Stream::Generator(g) = r.c else {
+        panic!("tun_stack should return a generator, got {}", r.c)
+    };
+    (in_tx, out_rx, g)
+}
+
+async fn recv_packet(out_rx: &mut mpsc::UnboundedReceiver<Vec<u8>>) -> Vec<u8> {
+    timeout(Duration::from_secs(3), out_rx.recv())
+        .await
+        .expect("device got a packet in time")
+        .expect("device open")
+}
+
+// ipstack 的 tcp 流在 drop 时 要用到 block_in_place
+#[tokio::test(flavor = "multi_thread")]
+async fn tcp_flow() -> anyhow::Result<()> {
+    let (in_tx, mut out_rx, mut g) = start_stack().await;
+
+    let mut p = Vec::new();
+    PacketBuilder::ipv4(CLIENT, TARGET, 64)
+        .tcp(40000, 80, 1000, 65535)
+        .syn()
+        .write(&mut p, &[])?;
+    in_tx.send(p)?;
+
+    let mr = timeout(Duration::from_secs(3), g.recv())
+        .await?
+        .expect("got a new stream");
+    assert_eq!(mr.a.expect("has target").to_string(), "tcp://1.2.3.4:80");
+    let d = mr.d.expect("has data");
+    assert_eq!(
+        d.get_raddr().expect("has raddr").to_string(),
+        "tcp://10.0.0.2:40000"
+    );
+    let mut conn = mr.c.try_unwrap_tcp()?;
+
+    let p = recv_packet(&mut out_rx).await;
+    let sp = SlicedPacket::from_ip(&p)?;
+    let Some(TransportSlice::Tcp(syn_ack)) = sp.transport else {
+        panic!("expect tcp packet")
+    };
+    assert!(syn_ack.syn() && syn_ack.ack());
+    assert_eq!(syn_ack.acknowledgment_number(), 1001);
+    let server_seq = syn_ack.sequence_number().wrapping_add(1);
+
+    let mut p = Vec::new();
+    PacketBuilder::ipv4(CLIENT, TARGET, 64)
+        .tcp(40000, 80, 1001, 65535)
+        .ack(server_seq)
+        .psh()
+        .write(&mut p, b"hello")?;
+    in_tx.send(p)?;
+
+    let mut buf = [0u8; 16];
+    let n = timeout(Duration::from_secs(3), conn.read(&mut buf)).await??;
+    assert_eq!(&buf[..n], b"hello");
+
+    conn.write_all(b"world").await?;
+    conn.flush().await?;
+
+    loop {
+        let p = recv_packet(&mut out_rx).await;
+        let sp = SlicedPacket::from_ip(&p)?;
+        let Some(TransportSlice::Tcp(t)) = sp.transport else {
+            continue;
+        };
+        if t.payload().is_empty() {
+            continue;
+        }
+        assert_eq!(t.source_port(), 80);
+        assert_eq!(t.destination_port(), 40000);
+        assert_eq!(t.payload(), b"world");
+        break;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn udp_flow() -> anyhow::Result<()> {
+    let (in_tx, mut out_rx, mut g) = start_stack().await;
+
+    let mut p = Vec::new();
+    PacketBuilder::ipv4(CLIENT, [8, 8, 8, 8], 64)
+        .udp(5353, 53)
+        .write(&mut p, b"query")?;
+    in_tx.send(p)?;
+
+    let mr = timeout(Duration::from_secs(3), g.recv())
+        .await?
+        .expect("got a new stream");
+    assert_eq!(mr.a.expect("has target").to_string(), "udp://8.8.8.8:53");
+    let mut ac = match mr.c {
+        Stream::AddrConn(ac) => ac,
+        c => panic!("expect AddrConn, got {c}"),
+    };
+
+    let mut buf = [0u8; 16];
+    let (n, a) = timeout(Duration::from_secs(3), ac.r.read(&mut buf)).await??;
+    assert_eq!(&buf[..n], b"query");
+    assert_eq!(a.to_string(), "udp://8.8.8.8:53");
+
+    ac.w.write(b"answer", &a).await?;
+
+    let p = recv_packet(&mut out_rx).await;
+    let sp = SlicedPacket::from_ip(&p)?;
+    let Some(TransportSlice::Udp(u)) = sp.transport else {
+        panic!("expect udp packet")
+    };
+    assert_eq!(u.source_port(), 53);
+    assert_eq!(u.destination_port(), 5353);
+    assert_eq!(u.payload(), b"answer");
+    Ok(())
+}
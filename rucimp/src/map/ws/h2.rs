@@ -0,0 +1,422 @@
+/*!
+websocket over h2, 即 RFC 8441 的 extended CONNECT.
+
+客户端 发出 `:method CONNECT`, `:protocol websocket` 的 请求, 服务端 回复 200 后, 该 h2 流 上
+直接 就是 websocket 帧, 不再有 http/1.1 的 upgrade 握手.
+
+与 [`crate::map::h2::client::MuxClient`] 一样, Client 收到 Conn 时 在其上 建立 新的 h2 连接,
+收到 None 时 在 最近建立的 h2 连接上 开 新的流, 于是 动态链 中 多个 ws 流 可 共用 一个 tls 连接.
+ */
+
+use std::{sync::Arc, time::Duration};
+
+use ::h2::{client::SendRequest, ext::Protocol, server};
+use anyhow::{anyhow, bail, Context};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use http::{Method, Request, Response, StatusCode};
+use macro_map::*;
+use ruci::{
+    map::{self, Map, MapResult, ProxyBehavior},
+    net::{self, helpers::EarlyDataWrapper, http::CommonConfig, CID},
+};
+use tokio::sync::{mpsc, Mutex};
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tracing::{debug, info, warn};
+
+use crate::map::h2::H2Stream;
+
+use super::*;
+
+pub const PROTOCOL: &str = "websocket";
+
+/// 等待 服务端 SETTINGS 中 的 ENABLE_CONNECT_PROTOCOL 的 最长 时间
+const EXTENDED_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// h2 中 不允许出现 的 连接相关 header, 以及 由 伪头 代替的 Host
+const SKIPPED_HEADERS: [&str; 5] = [
+    "host",
+    "connection",
+    "upgrade",
+    "keep-alive",
+    "sec-websocket-key",
+];
+
+/// 在 h2 流 上 建立 websocket, 返回 Conn
+async fn ws_conn(s: H2Stream, role: Role) -> net::Conn {
+    let ws = WebSocketStream::from_raw_socket(s, role, None).await;
+    Box::new(WsStreamToConnWrapper {
+        ws: Box::pin(ws),
+        r_buf: None,
+        w_buf: None,
+    })
+}
+
+/// 服务端 的 SETTINGS 由 connection task 处理, 握手 与 ready 返回 时 可能 还没 收到,
+/// 所以 轮询 SendRequest 的 标志, 直到 服务端 允许 extended CONNECT 或 超时
+async fn wait_extended_connect(
+    send_request: &SendRequest<Bytes>,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let r = tokio::time::timeout(timeout, async {
+        while !send_request.is_extended_connect_protocol_enabled() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+    if r.is_err() {
+        bail!("websocket h2 server didn't enable extended CONNECT in {timeout:?}")
+    }
+    Ok(())
+}
+
+pub fn build_request(c: &CommonConfig) -> anyhow::Result<Request<()>> {
+    let scheme = match c.scheme.as_deref() {
+        Some("http") | Some("ws") => "http",
+        _ => "https",
+    };
+    let mut request = Request::builder()
+        .method(Method::CONNECT)
+        .uri(format!("{scheme}://{}{}", c.authority, c.path))
+        .header("Sec-WebSocket-Version", "13")
+        .extension(Protocol::from_static(PROTOCOL));
+
+    if let Some(h) = &c.headers {
+        for (k, v) in h.iter() {
+            if !SKIPPED_HEADERS.contains(&k.to_ascii_lowercase().as_str()) {
+                request = request.header(k.as_str(), v.as_str());
+            }
+        }
+    }
+    Ok(request.body(())?)
+}
+
+#[map_ext_fields]
+#[derive(Clone, Debug, MapExt, Default)]
+pub struct Client {
+    req: Request<()>,
+
+    cache: Arc<Mutex<Option<SendRequest<Bytes>>>>,
+}
+
+impl ruci::Name for Client {
+    fn name(&self) -> &str {
+        "websocket_h2_client"
+    }
+}
+
+impl Client {
+    pub fn new(c: CommonConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            req: build_request(&c)?,
+            ..Default::default()
+        })
+    }
+
+    async fn new_stream(&self, send_request: SendRequest<Bytes>) -> anyhow::Result<net::Conn> {
+        let mut send_request = send_request.ready().await?;
+        wait_extended_connect(&send_request, EXTENDED_CONNECT_TIMEOUT).await?;
+
+        let (resp, send) = send_request.send_request(self.req.clone(), false)?;
+        let resp = resp.await?;
+        if resp.status() != StatusCode::OK {
+            bail!(
+                "websocket h2 client got resp status not OK: {}",
+                resp.status()
+            );
+        }
+
+        Ok(ws_conn(H2Stream::new(resp.into_body(), send, None), Role::Client).await)
+    }
+
+    async fn handshake(
+        &self,
+        cid: CID,
+        conn: Option<net::Conn>,
+        a: Option<net::Addr>,
+        b: Option<BytesMut>,
+    ) -> anyhow::Result<map::MapResult> {
+        let send_request = match conn {
+            Some(conn) => {
+                let (send_request, connection) = ::h2::client::handshake(conn)
+                    .await
+                    .context("websocket h2 client handshake failed")?;
+
+                tokio::spawn(async move {
+                    // 所有 SendRequest 与 流 都 drop 后, connection 才会 返回
+                    let r = connection.await;
+                    debug!(cid = %cid, r=?r, "websocket h2 connection closed");
+                });
+
+                // 新连接 替换 旧连接; 旧连接 上的 流 不受影响
+                *self.cache.lock().await = Some(send_request.clone());
+                send_request
+            }
+            None => self
+                .cache
+                .lock()
+                .await
+                .clone()
+                .ok_or(anyhow!("websocket h2 not established yet"))?,
+        };
+
+        let c = self.new_stream(send_request).await?;
+        Ok(MapResult::new_c(c).a(a).b(b).build())
+    }
+}
+
+#[async_trait]
+impl Map for Client {
+    async fn maps(&self, cid: CID, _behavior: ProxyBehavior, params: map::MapParams) -> MapResult {
+        let conn = match params.c {
+            net::Stream::Conn(c) => Some(c),
+            net::Stream::None => None,
+            _ => {
+                return MapResult::err_str(
+                    "websocket_h2_client only support tcplike stream or None stream",
+                )
+            }
+        };
+        let r = self.handshake(cid, conn, params.a, params.b).await;
+        match r {
+            anyhow::Result::Ok(r) => r,
+            Err(e) => MapResult::from_e(e.context("websocket_h2_client handshake failed")),
+        }
+    }
+}
+
+/// 流发生器, 每个 extended CONNECT 请求 产生 一个 websocket Conn
+#[map_ext_fields]
+#[derive(Clone, Debug, MapExt, Default)]
+pub struct Server {
+    pub config: Option<CommonConfig>,
+}
+
+impl ruci::Name for Server {
+    fn name(&self) -> &str {
+        "websocket_h2_server"
+    }
+}
+
+fn bad_request() -> Response<()> {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(())
+        .expect("ok")
+}
+
+impl Server {
+    pub fn new(config: Option<CommonConfig>) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    async fn start_listen(
+        &self,
+        cid: CID,
+        mut conn: net::Conn,
+        early_data: Option<BytesMut>,
+    ) -> anyhow::Result<map::MapResult> {
+        if let Some(b) = early_data {
+            if !b.is_empty() {
+                conn = Box::new(EarlyDataWrapper::from(b, conn));
+            }
+        }
+        let mut conn = server::Builder::new()
+            .enable_connect_protocol()
+            .handshake::<_, Bytes>(conn)
+            .await
+            .context("websocket h2 server handshake failed")?;
+
+        let (tx, rx) = mpsc::channel(100);
+        let config = self.config.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (req, mut resp) = match conn.accept().await {
+                    Some(Ok(r)) => r,
+                    Some(Err(e)) => {
+                        warn!(cid = %cid, "websocket h2 accept got error, will break: {e}");
+                        break;
+                    }
+                    None => {
+                        info!(cid = %cid, "websocket h2 accept got None, will break");
+                        break;
+                    }
+                };
+
+                let is_ws = req.method() == Method::CONNECT
+                    && req
+                        .extensions()
+                        .get::<Protocol>()
+                        .is_some_and(|p| p.as_str() == PROTOCOL);
+                if !is_ws {
+                    warn!(cid = %cid, method = %req.method(), "websocket h2 server got non websocket request");
+                    let _ = resp.send_response(bad_request(), true);
+                    continue;
+                }
+                if let Some(c) = &config {
+                    if let Err(e) = crate::net::http::match_request_http_header(c, &req) {
+                        warn!(cid = %cid, e = %e, "websocket h2 server got wrong http header");
+                        let _ = resp.send_response(bad_request(), true);
+                        continue;
+                    }
+                }
+
+                let (_, recv) = req.into_parts();
+                let send = match resp.send_response(
+                    Response::builder().status(StatusCode::OK).body(()).unwrap(),
+                    false,
+                ) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        warn!(cid = %cid, "websocket h2 server send response got error: {e}");
+                        break;
+                    }
+                };
+
+                let mut ncid = cid.clone();
+                ncid.push_num(recv.stream_id().as_u32());
+
+                let c = ws_conn(H2Stream::new(recv, send, None), Role::Server).await;
+                let m = MapResult::new_c(c).new_id(ncid).build();
+                if let Err(e) = tx.send(m).await {
+                    warn!(cid = %cid, "websocket h2 send tx got error: {e}");
+                    break;
+                }
+            }
+        });
+
+        Ok(MapResult::builder().c(net::Stream::Generator(rx)).build())
+    }
+}
+
+#[async_trait]
+impl Map for Server {
+    async fn maps(&self, cid: CID, _behavior: ProxyBehavior, params: map::MapParams) -> MapResult {
+        if let net::Stream::Conn(conn) = params.c {
+            let r = self.start_listen(cid, conn, params.b).await;
+            match r {
+                anyhow::Result::Ok(r) => r,
+                Err(e) => MapResult::from_e(e.context("websocket_h2_server handshake failed")),
+            }
+        } else {
+            MapResult::err_str("websocket_h2_server only support tcplike stream")
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use ruci::map::MapParams;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        task::JoinHandle,
+    };
+
+    use super::*;
+    use crate::net::http::test_config;
+
+    /// h2 server 的 握手 要等 客户端的 preface, 所以 要在 新的 task 中 进行
+    fn start_server(s: net::Conn) -> JoinHandle<mpsc::Receiver<MapResult>> {
+        tokio::spawn(async move {
+            let r = Server::new(Some(test_config("/ws")))
+                .maps(CID::default(), ProxyBehavior::DECODE, MapParams::new(s))
+                .await;
+            let net::Stream::Generator(g) = r.c else {
+                panic!("server should return a generator, {:?}", r.e)
+            };
+            g
+        })
+    }
+
+    #[tokio::test]
+    async fn mux_streams() -> anyhow::Result<()> {
+        let (c, s) = tokio::io::duplex(0x10000);
+        let mut server = Some(start_server(Box::new(s)));
+        let client = Client::new(test_config("/ws"))?;
+
+        // 第一个流 建立 h2 连接, 之后的 流 复用 它
+        let mut base: Option<net::Conn> = Some(Box::new(c));
+        let mut g = None;
+        for i in 0..3u8 {
+            let params = match base.take() {
+                Some(c) => MapParams::new(c),
+                None => MapParams::default(),
+            };
+            let r = client
+                .maps(CID::default(), ProxyBehavior::ENCODE, params)
+                .await;
+            let net::Stream::Conn(mut c) = r.c else {
+                panic!("client should return a conn, {:?}", r.e)
+            };
+            if let Some(h) = server.take() {
+                g = Some(h.await?);
+            }
+            let sr = tokio::time::timeout(Duration::from_secs(5), g.as_mut().unwrap().recv())
+                .await?
+                .expect("server got stream");
+            let net::Stream::Conn(mut s) = sr.c else {
+                panic!("server should return a conn")
+            };
+
+            c.write_all(&[i; 5000]).await?;
+            c.flush().await?;
+            let mut buf = [0u8; 5000];
+            s.read_exact(&mut buf).await?;
+            assert_eq!(buf, [i; 5000]);
+
+            s.write_all(b"world").await?;
+            s.flush().await?;
+            let mut buf = [0u8; 5];
+            c.read_exact(&mut buf).await?;
+            assert_eq!(&buf, b"world");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn wrong_path() -> anyhow::Result<()> {
+        let (c, s) = tokio::io::duplex(0x10000);
+        let server = start_server(Box::new(s));
+        let client = Client::new(test_config("/other"))?;
+
+        let r = client
+            .maps(
+                CID::default(),
+                ProxyBehavior::ENCODE,
+                MapParams::new(Box::new(c)),
+            )
+            .await;
+        assert!(r.e.is_some());
+        let mut g = server.await?;
+        assert!(tokio::time::timeout(Duration::from_millis(100), g.recv())
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn extended_connect_not_enabled() -> anyhow::Result<()> {
+        let (c, s) = tokio::io::duplex(0x10000);
+
+        // 普通 的 h2 server, 不 允许 extended CONNECT
+        tokio::spawn(async move {
+            let mut conn = ::h2::server::handshake(s).await?;
+            while conn.accept().await.is_some() {}
+            anyhow::Ok(())
+        });
+        let (send_request, connection) = ::h2::client::handshake(c).await?;
+        tokio::spawn(connection);
+        let send_request = send_request.ready().await?;
+
+        let r = wait_extended_connect(&send_request, Duration::from_millis(200)).await;
+        assert!(r.is_err());
+        assert!(!send_request.is_extended_connect_protocol_enabled());
+        Ok(())
+    }
+}
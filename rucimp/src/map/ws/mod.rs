@@ -1,4 +1,5 @@
 pub mod client;
+pub mod h2;
 pub mod server;
 
 use std::{io, pin::Pin, task::Poll};
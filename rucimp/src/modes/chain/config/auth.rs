@@ -0,0 +1,79 @@
+/*!
+外部 鉴权 后端 的 配置
+*/
+
+use std::{sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+/// 外部 鉴权 后端, 见 [`ruci::user::AuthProvider`].
+/// cache_secs 为 成功 结果 的 缓存 时长, 不给出 则 不缓存
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub enum AuthProviderConfig {
+    /// htpasswd 风格 的 `user:hash` 文件, 见 [`crate::user::file`]
+    #[cfg(feature = "auth_file")]
+    File {
+        path: String,
+        cache_secs: Option<u64>,
+    },
+
+    /// 见 [`crate::user::webhook`]. trojan 为 true 时 也 验证 trojan 的 凭证
+    #[cfg(feature = "auth_webhook")]
+    Webhook {
+        url: String,
+        timeout_secs: Option<u64>,
+        cache_secs: Option<u64>,
+        trojan: Option<bool>,
+    },
+
+    /// 见 [`crate::user::sqlite`]
+    #[cfg(feature = "auth_sqlite")]
+    Sqlite {
+        path: String,
+        table: Option<String>,
+        cache_secs: Option<u64>,
+    },
+}
+
+impl AuthProviderConfig {
+    pub fn to_provider(&self) -> anyhow::Result<Arc<dyn ruci::user::AuthProvider>> {
+        #[allow(unused_variables)]
+        let (p, cache_secs): (Box<dyn ruci::user::AuthProvider>, Option<u64>) = match self {
+            #[cfg(feature = "auth_file")]
+            AuthProviderConfig::File { path, cache_secs } => (
+                Box::new(crate::user::file::FileProvider::load(path)?),
+                *cache_secs,
+            ),
+            #[cfg(feature = "auth_webhook")]
+            AuthProviderConfig::Webhook {
+                url,
+                timeout_secs,
+                cache_secs,
+                trojan,
+            } => {
+                let mut p = crate::user::webhook::WebhookProvider::new(
+                    url.clone(),
+                    timeout_secs.map(Duration::from_secs),
+                )?;
+                p.trojan = trojan.unwrap_or_default();
+                (Box::new(p), *cache_secs)
+            }
+            #[cfg(feature = "auth_sqlite")]
+            AuthProviderConfig::Sqlite {
+                path,
+                table,
+                cache_secs,
+            } => (
+                Box::new(crate::user::sqlite::SqliteProvider::open(
+                    path,
+                    table.clone(),
+                )?),
+                *cache_secs,
+            ),
+        };
+        Ok(match cache_secs {
+            Some(s) => Arc::new(ruci::user::CachedProvider::new(p, Duration::from_secs(s))),
+            None => Arc::from(p),
+        })
+    }
+}
@@ -17,6 +17,13 @@ pub mod lua;
 
 pub mod dynamic;
 
+#[cfg(any(
+    feature = "auth_file",
+    feature = "auth_webhook",
+    feature = "auth_sqlite"
+))]
+pub mod auth;
+
 use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
 
 #[cfg(feature = "s2n-quic")]
@@ -54,9 +61,144 @@ pub struct StaticConfig {
 
     #[cfg(feature = "route")]
     pub rule_route: Option<Vec<RuleSetConfig>>,
+
+    pub user_limits: Option<Vec<UserLimitConfig>>,
+
+    #[cfg(any(
+        feature = "auth_file",
+        feature = "auth_webhook",
+        feature = "auth_sqlite"
+    ))]
+    pub auth_providers: Option<Vec<auth::AuthProviderConfig>>,
+
+    /// 未 编译 鉴权 后端 时 仍 解析, 以便 报错 而 不是 忽略
+    #[cfg(not(any(
+        feature = "auth_file",
+        feature = "auth_webhook",
+        feature = "auth_sqlite"
+    )))]
+    pub auth_providers: Option<toml::Value>,
+}
+
+/// 用户 的 流量配额, 到期时间 与 限速, 见 [`ruci::user::UserLimit`]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct UserLimitConfig {
+    /// 用户 的 identity_str. socks5 与 http 为 用户名, trojan 为 密码 的 sha224 hex
+    pub user: String,
+
+    /// 上传 与 下载 的 总 字节数
+    pub quota: Option<u64>,
+
+    /// unix timestamp, in seconds
+    pub expire_at: Option<u64>,
+
+    /// 每个方向 每秒 的 字节数
+    pub rate_limit: Option<u64>,
 }
 
 impl StaticConfig {
+    /// 启用 了 外部 鉴权 后端 的 入站 的 tag, 无 tag 的 用 序号 表示
+    fn inbounds_using_providers(&self) -> Vec<String> {
+        self.inbounds
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| {
+                c.chain.iter().any(|m| match m {
+                    InMapConfig::Http(p) | InMapConfig::Socks5(p) | InMapConfig::Socks5Http(p) => {
+                        p.use_auth_providers.unwrap_or_default()
+                    }
+                    InMapConfig::Trojan(p) => p.use_auth_providers.unwrap_or_default(),
+                    _ => false,
+                })
+            })
+            .map(|(i, c)| c.tag.clone().unwrap_or_else(|| format!("#{i}")))
+            .collect()
+    }
+
+    /// 启用 了 外部 后端 的 入站 须 有 可用 的 后端, 否则 没有 用户 能 通过 鉴权, 此时 报错.
+    /// 给出了 后端 却 没有 入站 启用 时 只 警告
+    pub fn check_auth_providers(&self) -> anyhow::Result<()> {
+        let using = self.inbounds_using_providers();
+        let Some(_v) = &self.auth_providers else {
+            if !using.is_empty() {
+                anyhow::bail!(
+                    "inbounds {using:?} set use_auth_providers, but auth_providers is not given"
+                );
+            }
+            return Ok(());
+        };
+
+        #[cfg(not(any(
+            feature = "auth_file",
+            feature = "auth_webhook",
+            feature = "auth_sqlite"
+        )))]
+        anyhow::bail!(
+            "auth_providers is given, but none of the auth_file, auth_webhook, auth_sqlite features is enabled. inbounds using them: {using:?}"
+        );
+
+        #[cfg(any(
+            feature = "auth_file",
+            feature = "auth_webhook",
+            feature = "auth_sqlite"
+        ))]
+        {
+            if _v.is_empty() && !using.is_empty() {
+                anyhow::bail!(
+                    "auth_providers is empty, but inbounds {using:?} set use_auth_providers"
+                );
+            }
+            if !_v.is_empty() && using.is_empty() {
+                warn!("auth_providers is given, but no inbound sets use_auth_providers, so they are not used");
+            }
+            Ok(())
+        }
+    }
+
+    /// 用 auth_providers 替换 全局的 [`ruci::user::AUTH_PROVIDERS`].
+    /// 只有 设置了 use_auth_providers 的 入站 会 查询 它们
+    ///
+    /// panic if auth_providers is invalid (see [`Self::check_auth_providers`])
+    /// or any provider can't be initialized
+    pub fn apply_auth_providers(&self) {
+        if let Err(e) = self.check_auth_providers() {
+            panic!("{e:#}")
+        }
+
+        #[cfg(any(
+            feature = "auth_file",
+            feature = "auth_webhook",
+            feature = "auth_sqlite"
+        ))]
+        {
+            let Some(v) = &self.auth_providers else {
+                return;
+            };
+            let ps = v
+                .iter()
+                .map(|c| {
+                    c.to_provider()
+                        .unwrap_or_else(|e| panic!("init auth provider failed: {e:#}"))
+                })
+                .collect();
+            ruci::user::AUTH_PROVIDERS.set(ps);
+        }
+    }
+
+    /// 将 user_limits 写入 全局的 [`ruci::user::USER_LIMITS`]
+    pub fn apply_user_limits(&self) {
+        for l in self.user_limits.iter().flatten() {
+            ruci::user::USER_LIMITS.set(
+                &l.user,
+                ruci::user::UserLimit {
+                    quota: l.quota,
+                    expire_at: l.expire_at,
+                    rate_limit: l.rate_limit,
+                },
+            );
+        }
+    }
+
     /// convert config chain to map chain
     pub fn get_inbounds(&self) -> Vec<Vec<MapBox>> {
         let listens: Vec<_> = self
@@ -87,29 +229,59 @@ impl StaticConfig {
     }
 
     /// convert config chain to map chain
+    ///
+    /// panic if a Detour refers to a tag that isn't presented in outbounds, or
+    /// the detours form a cycle
     pub fn get_outbounds(&self) -> Vec<Vec<MapBox>> {
         self.outbounds
             .iter()
-            .map(|config_chain| {
-                let mut chain = config_chain
-                    .chain
-                    .iter()
-                    .map(|map_config| {
-                        let mut map = map_config.to_map_box();
-                        map.set_chain_tag(&config_chain.tag);
-                        map
-                    })
-                    .collect::<Vec<_>>();
+            .map(|config_chain| self.get_outbound(config_chain, &mut Vec::new()))
+            .collect::<Vec<_>>()
+    }
 
-                if let Some(last_m) = chain.last_mut() {
-                    last_m.set_is_tail_of_chain(true);
-                } else {
-                    warn!("the outbound chain has no maps, {:?}", config_chain.tag);
+    /// Detour 引用的 出站 会被 递归地 转换, 所以 有状态的 Map (如 H2Mux)
+    /// 在 每个 引用处 都是 单独的 实例
+    fn get_outbound(
+        &self,
+        config_chain: &OutMapConfigChain,
+        visiting: &mut Vec<String>,
+    ) -> Vec<MapBox> {
+        visiting.push(config_chain.tag.clone());
+
+        let mut chain = Vec::new();
+        for map_config in config_chain.chain.iter() {
+            let mut map = match map_config {
+                OutMapConfig::Detour { tag, dial_addr } => {
+                    if visiting.contains(tag) {
+                        panic!("detour cycle found: {:?} -> {tag}", visiting)
+                    }
+                    let detour_chain = self
+                        .outbounds
+                        .iter()
+                        .find(|oc| &oc.tag == tag)
+                        .unwrap_or_else(|| panic!("detour tag not found in outbounds: {tag}"));
+                    let outbound = self.get_outbound(detour_chain, visiting);
+
+                    Box::new(detour::Detour::new(
+                        tag.clone(),
+                        detour_dial_addr(dial_addr),
+                        outbound.into_iter().map(Arc::new).collect(),
+                    ))
                 }
+                _ => map_config.to_map_box(),
+            };
+            map.set_chain_tag(&config_chain.tag);
+            chain.push(map);
+        }
 
-                chain
-            })
-            .collect::<Vec<_>>()
+        if let Some(last_m) = chain.last_mut() {
+            last_m.set_is_tail_of_chain(true);
+        } else {
+            warn!("the outbound chain has no maps, {:?}", config_chain.tag);
+        }
+
+        visiting.pop();
+        chain
     }
 
     /// (out_tag, outbound)
@@ -186,6 +358,12 @@ impl StaticConfig {
     }
 }
 
+fn detour_dial_addr(dial_addr: &Option<String>) -> Option<net::Addr> {
+    dial_addr
+        .as_ref()
+        .map(|a| net::Addr::from_name_network_addr_url(a).expect("detour dial_addr is valid"))
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct InMapConfigChain {
     tag: Option<String>,
@@ -255,6 +433,10 @@ pub enum InMapConfig {
     #[cfg(all(feature = "sockopt", target_os = "linux"))]
     TproxyTcpResolver(tproxy::Options),
 
+    /// 将 tun 设备的 ip 包 还原为 tcp/udp 流, 多流发生器
+    #[cfg(feature = "tun")]
+    TunStack(crate::map::tun::Options),
+
     Adder(i8),
     Counter,
     TLS(TlsIn),
@@ -274,8 +456,29 @@ pub enum InMapConfig {
     WebSocket {
         http_config: Option<CommonConfig>,
     },
+    /// websocket over h2 (RFC 8441), 流发生器
+    WebSocketH2 {
+        http_config: Option<CommonConfig>,
+    },
+    /// 101 之后 为 原始数据流 的 http upgrade
+    HttpUpgrade {
+        http_config: Option<CommonConfig>,
+    },
+    /// 上行 为 POST, 下行 为 GET 的 split http. 不产生 代理流 的 POST 连接 会被 消耗
+    SplitHttp {
+        http_config: Option<CommonConfig>,
+    },
     #[cfg(any(feature = "quic", feature = "quinn"))]
     Quic(crate::map::quic_common::ServerConfig),
+
+    #[cfg(feature = "quinn")]
+    Hysteria2(crate::map::hysteria2::ServerConfig),
+
+    #[cfg(feature = "quinn")]
+    Tuic(crate::map::tuic::ServerConfig),
+
+    #[cfg(feature = "quinn")]
+    H3(crate::map::h3::ServerConfig),
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -285,6 +488,13 @@ pub enum OutMapConfig {
     Stdio(Ext),               //单流发生器
     Fileio(FileConfig),       //单流发生器
     BindDialer(DialerConfig), //单流发生器
+
+    /// 单流发生器, 通过 tag 对应的 出站 拨号 dial_addr, 可 代替 BindDialer 以 串联 代理.
+    /// 只在 StaticConfig 的 outbounds 中 有效
+    Detour {
+        tag: String,
+        dial_addr: Option<String>,
+    },
     Adder(i8),
     Counter,
     TLS(TlsOut),
@@ -304,6 +514,15 @@ pub enum OutMapConfig {
     Socks5(Socks5Out),
     Trojan(String),
     WebSocket(CommonConfig),
+    /// websocket over h2 (RFC 8441), 可 复用 h2 连接
+    WebSocketH2(CommonConfig),
+    HttpUpgrade(CommonConfig),
+    /// split http 要 建立 多个 连接, 所以 自己 用 dial 链 拨号, 须 放在 链 的 开头
+    SplitHttp {
+        http_config: CommonConfig,
+        dial: Vec<OutMapConfig>,
+        max_post_size: Option<usize>,
+    },
     H2Single {
         is_grpc: Option<bool>,
 
@@ -316,6 +535,18 @@ pub enum OutMapConfig {
     },
     #[cfg(any(feature = "quic", feature = "quinn"))]
     Quic(crate::map::quic_common::ClientConfig),
+
+    #[cfg(feature = "quinn")]
+    Hysteria2(crate::map::hysteria2::ClientConfig),
+
+    #[cfg(feature = "quinn")]
+    Tuic(crate::map::tuic::ClientConfig),
+
+    #[cfg(feature = "quinn")]
+    H3Single(crate::map::h3::ClientConfig),
+
+    #[cfg(feature = "quinn")]
+    H3Mux(crate::map::h3::ClientConfig),
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -353,6 +584,18 @@ pub struct TlsIn {
     cert: String,
     key: String,
     alpn: Option<Vec<String>>,
+
+    /// 若给出, 则要求客户端提供由该 CA 签发的证书, 验证后的证书可用于 rule_route 的 userset,
+    /// 如 "x509:client1", 见 [`ruci::map::tls::CertUser`]
+    client_ca: Option<String>,
+
+    /// 若给出, 则在后台每隔这么多秒检查证书文件是否有变化, 有变化则重新加载,
+    /// 加载失败时保留当前证书并打印警告. 适用于 certbot 等自动续期证书的情况
+    reload_interval: Option<u64>,
+
+    /// 按客户端的 sni 选择的更多证书, 上面的 cert/key 为默认证书.
+    /// names 可为 *.example.com 这样的通配符; 不给出 names 时使用证书中的域名
+    sni_certs: Option<Vec<tls::sni::SniCert>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -360,12 +603,33 @@ pub struct TlsOut {
     host: String,
     insecure: Option<bool>,
     alpn: Option<Vec<String>>,
+
+    /// ClientHello 的 preset 与 cipher suite, kx group, 版本, sni 的 设置.
+    /// rustls 不能 控制 extension 顺序, GREASE 与 padding, 配置 这些 字段 会 加载 失败,
+    /// 见 [`tls::fingerprint`]
+    client_hello: Option<tls::fingerprint::ClientHelloProfile>,
+
+    /// client certificate and key, for servers that verify clients
+    cert: Option<String>,
+    key: Option<String>,
+
+    /// 用于验证服务端证书的 CA 文件, 给出后不再使用内置的 webpki roots
+    ca: Option<String>,
+    /// 使用系统的证书信任库
+    system_roots: Option<bool>,
+    /// 服务端证书公钥 (SPKI) 的 sha256 的 base64; 与 insecure = true 同用时只验证 pin
+    pinned_spki_sha256: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PlainTextSet {
     userpass: Option<String>,
     more: Option<Vec<String>>,
+    /// 启用 的 运行时 用户 存储 的 名称; 启用 后 即使 没有 用户 也 须 鉴权
+    user_store: Option<String>,
+
+    /// 是否 查询 auth_providers; 启用 后 即使 没有 用户 也 须 鉴权
+    use_auth_providers: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -380,6 +644,11 @@ pub struct Socks5Out {
 pub struct TrojanPassSet {
     password: Option<String>,
     more: Option<Vec<String>>,
+    /// 启用 的 运行时 用户 存储 的 名称
+    user_store: Option<String>,
+
+    /// 是否 查询 auth_providers 中 支持 trojan 的 后端
+    use_auth_providers: Option<bool>,
 }
 
 impl ToMapBox for InMapConfig {
@@ -421,6 +690,9 @@ impl ToMapBox for InMapConfig {
                 cert: PathBuf::from(c.cert.clone()),
                 key: PathBuf::from(c.key.clone()),
                 alpn: c.alpn.clone(),
+                client_ca: c.client_ca.clone().map(PathBuf::from),
+                reload_interval: c.reload_interval.map(Duration::from_secs),
+                sni_certs: c.sni_certs.clone().unwrap_or_default(),
             }
             .to_map_box(),
 
@@ -442,6 +714,8 @@ impl ToMapBox for InMapConfig {
                             .map(|up| ruci::user::PlainText::from(up.to_string()))
                             .collect::<Vec<_>>()
                     }),
+                    user_store: c.user_store.clone(),
+                    use_auth_providers: c.use_auth_providers.unwrap_or_default(),
                     ..Default::default()
                 };
 
@@ -456,6 +730,8 @@ impl ToMapBox for InMapConfig {
                             .map(|up| ruci::user::PlainText::from(up.to_string()))
                             .collect::<Vec<_>>()
                     }),
+                    user_store: c.user_store.clone(),
+                    use_auth_providers: c.use_auth_providers.unwrap_or_default(),
                 };
 
                 so.to_map_box()
@@ -468,6 +744,8 @@ impl ToMapBox for InMapConfig {
                             .map(|up| ruci::user::PlainText::from(up.to_string()))
                             .collect::<Vec<_>>()
                     }),
+                    user_store: c.user_store.clone(),
+                    use_auth_providers: c.use_auth_providers.unwrap_or_default(),
                 };
 
                 so.to_map_box()
@@ -476,6 +754,8 @@ impl ToMapBox for InMapConfig {
                 let so = trojan::server::Config {
                     pass: c.password.clone(),
                     passes: c.more.as_ref().map(|up_v| up_v.to_vec()),
+                    user_store: c.user_store.clone(),
+                    use_auth_providers: c.use_auth_providers.unwrap_or_default(),
                 };
 
                 so.to_map_box()
@@ -486,6 +766,18 @@ impl ToMapBox for InMapConfig {
                 config: config.clone(),
                 ..Default::default()
             }),
+            InMapConfig::WebSocketH2 {
+                http_config: config,
+            } => Box::new(crate::map::ws::h2::Server::new(config.clone())),
+            InMapConfig::HttpUpgrade {
+                http_config: config,
+            } => Box::new(crate::map::http_upgrade::Server {
+                config: config.clone(),
+                ..Default::default()
+            }),
+            InMapConfig::SplitHttp {
+                http_config: config,
+            } => Box::new(crate::map::split_http::server::Server::new(config.clone())),
             InMapConfig::HttpFilter(c) => Box::new(ruci::map::http_filter::Server {
                 config: c.clone(),
                 ..Default::default()
@@ -503,6 +795,20 @@ impl ToMapBox for InMapConfig {
             #[cfg(feature = "quinn")]
             InMapConfig::Quic(c) => Box::new(crate::map::quinn::server::Server::new(c.clone())),
 
+            #[cfg(feature = "quinn")]
+            InMapConfig::Hysteria2(c) => Box::new(
+                crate::map::hysteria2::server::Server::new(c.clone())
+                    .expect("legal hysteria2 server config"),
+            ),
+
+            #[cfg(feature = "quinn")]
+            InMapConfig::Tuic(c) => Box::new(
+                crate::map::tuic::server::Server::new(c.clone()).expect("legal tuic server config"),
+            ),
+
+            #[cfg(feature = "quinn")]
+            InMapConfig::H3(c) => Box::new(crate::map::h3::server::Server::new(c.clone())),
+
             #[cfg(all(feature = "sockopt", target_os = "linux"))]
             InMapConfig::TcpOptListener {
                 listen_addr,
@@ -520,6 +826,9 @@ impl ToMapBox for InMapConfig {
                 Box::new(TcpResolver::new(opts.clone()).expect("ok"))
             }
 
+            #[cfg(feature = "tun")]
+            InMapConfig::TunStack(opts) => Box::new(crate::map::tun::Stack::new(opts.clone())),
+
             #[cfg(all(feature = "sockopt", target_os = "linux"))]
             InMapConfig::TproxyUdpListener {
                 listen_addr,
@@ -556,6 +865,13 @@ impl ToMapBox for OutMapConfig {
             }
             OutMapConfig::Blackhole => Box::<BlackHole>::default(),
 
+            // 引用的 出站 由 StaticConfig::get_outbounds 解析, 这里 得到的 Detour 在 使用时 会 报错
+            OutMapConfig::Detour { tag, dial_addr } => Box::new(detour::Detour::new(
+                tag.clone(),
+                detour_dial_addr(dial_addr),
+                Vec::new(),
+            )),
+
             OutMapConfig::Direct => Box::<Direct>::default(),
             OutMapConfig::BindDialer(dc) => dc.to_map_box(),
             OutMapConfig::Adder(i) => i.to_map_box(),
@@ -565,6 +881,12 @@ impl ToMapBox for OutMapConfig {
                     domain: c.host.clone(),
                     is_insecure: c.insecure.unwrap_or_default(),
                     alpn: c.alpn.clone(),
+                    client_hello: c.client_hello.clone(),
+                    cert: c.cert.clone().map(PathBuf::from),
+                    key: c.key.clone().map(PathBuf::from),
+                    ca: c.ca.clone().map(PathBuf::from),
+                    use_system_roots: c.system_roots.unwrap_or_default(),
+                    pinned_spki_sha256: c.pinned_spki_sha256.clone(),
                 });
                 Box::new(a)
             }
@@ -602,6 +924,21 @@ impl ToMapBox for OutMapConfig {
 
                 Box::new(client)
             }
+            OutMapConfig::WebSocketH2(c) => {
+                Box::new(ws::h2::Client::new(c.clone()).expect("legal websocket h2 client config"))
+            }
+            OutMapConfig::HttpUpgrade(c) => {
+                Box::new(crate::map::http_upgrade::Client::new(c.clone()))
+            }
+            OutMapConfig::SplitHttp {
+                http_config,
+                dial,
+                max_post_size,
+            } => Box::new(crate::map::split_http::client::Client::new(
+                http_config.clone(),
+                dial.iter().map(|c| c.to_map_box()).collect(),
+                *max_post_size,
+            )),
             OutMapConfig::H2Single {
                 http_config: config,
                 is_grpc,
@@ -631,6 +968,28 @@ impl ToMapBox for OutMapConfig {
                     .expect("legal quic client config"),
             ),
 
+            #[cfg(feature = "quinn")]
+            OutMapConfig::Hysteria2(c) => Box::new(
+                crate::map::hysteria2::client::Client::new(c.clone())
+                    .expect("legal hysteria2 client config"),
+            ),
+
+            #[cfg(feature = "quinn")]
+            OutMapConfig::Tuic(c) => Box::new(
+                crate::map::tuic::client::Client::new(c.clone()).expect("legal tuic client config"),
+            ),
+
+            #[cfg(feature = "quinn")]
+            OutMapConfig::H3Single(c) => Box::new(
+                crate::map::h3::client::SingleClient::new(c.clone())
+                    .expect("legal h3 client config"),
+            ),
+
+            #[cfg(feature = "quinn")]
+            OutMapConfig::H3Mux(c) => Box::new(
+                crate::map::h3::client::MuxClient::new(c.clone()).expect("legal h3 client config"),
+            ),
+
             #[cfg(all(feature = "sockopt", target_os = "linux"))]
             OutMapConfig::OptDirect {
                 sockopt,
@@ -665,6 +1024,8 @@ mod test {
                     InMapConfig::Socks5(PlainTextSet {
                         userpass: None,
                         more: None,
+                        user_store: None,
+                        use_auth_providers: None,
                     }),
                 ],
             }],
@@ -680,4 +1041,129 @@ mod test {
         let toml: StaticConfig = toml::from_str(&toml).expect("valid toml");
         println!("{:#?}", toml);
     }
+
+    fn detour_config(detour_tag: &str) -> StaticConfig {
+        StaticConfig {
+            outbounds: vec![
+                OutMapConfigChain {
+                    tag: String::from("b"),
+                    chain: vec![
+                        OutMapConfig::Detour {
+                            tag: detour_tag.to_string(),
+                            dial_addr: Some("tcp://127.0.0.1:443".to_string()),
+                        },
+                        OutMapConfig::Trojan("pass".to_string()),
+                    ],
+                },
+                OutMapConfigChain {
+                    tag: String::from("a"),
+                    chain: vec![
+                        OutMapConfig::BindDialer(DialerConfig {
+                            dial_addr: Some("tcp://127.0.0.1:1080".to_string()),
+                            ..Default::default()
+                        }),
+                        OutMapConfig::Socks5(Socks5Out {
+                            userpass: None,
+                            early_data: None,
+                            ext: None,
+                        }),
+                    ],
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn detour() {
+        let obs = detour_config("a").get_outbounds();
+        assert_eq!(obs[0][0].name(), "detour");
+        assert_eq!(obs[0][0].get_chain_tag(), "b");
+        assert!(obs[0][1].is_tail_of_chain());
+    }
+
+    #[test]
+    fn auth_providers_opt_in() {
+        let socks5 = |use_auth_providers: Option<bool>| InMapConfigChain {
+            tag: Some("s".to_string()),
+            chain: vec![InMapConfig::Socks5(PlainTextSet {
+                userpass: None,
+                more: None,
+                user_store: None,
+                use_auth_providers,
+            })],
+        };
+        let trojan = InMapConfigChain {
+            tag: None,
+            chain: vec![InMapConfig::Trojan(TrojanPassSet {
+                password: None,
+                more: None,
+                user_store: None,
+                use_auth_providers: Some(true),
+            })],
+        };
+
+        // 没有 启用 的 入站 不受 影响
+        let mut sc = StaticConfig {
+            inbounds: vec![socks5(None), socks5(Some(false))],
+            ..Default::default()
+        };
+        assert!(sc.inbounds_using_providers().is_empty());
+        assert!(sc.check_auth_providers().is_ok());
+
+        // 启用 了 却 没有 后端
+        sc.inbounds = vec![socks5(Some(true)), trojan];
+        assert_eq!(sc.inbounds_using_providers(), vec!["s", "#1"]);
+        let e = sc.check_auth_providers().unwrap_err();
+        assert!(e.to_string().contains("[\"s\", \"#1\"]"), "{e}");
+
+        #[cfg(any(
+            feature = "auth_file",
+            feature = "auth_webhook",
+            feature = "auth_sqlite"
+        ))]
+        {
+            sc.auth_providers = Some(vec![]);
+            assert!(sc.check_auth_providers().is_err());
+        }
+        #[cfg(feature = "auth_file")]
+        {
+            sc.auth_providers = Some(vec![auth::AuthProviderConfig::File {
+                path: "users.htpasswd".to_string(),
+                cache_secs: None,
+            }]);
+            assert!(sc.check_auth_providers().is_ok());
+        }
+        #[cfg(not(any(
+            feature = "auth_file",
+            feature = "auth_webhook",
+            feature = "auth_sqlite"
+        )))]
+        {
+            sc.auth_providers = Some(toml::Value::Array(vec![]));
+            assert!(sc.check_auth_providers().is_err());
+        }
+    }
+
+    #[test]
+    fn client_hello_unsupported_fields() {
+        // rustls 不能 控制 extension 的顺序, GREASE 和 padding, 配置 它们 时 报错 而不是 忽略
+        for f in [
+            "grease = true",
+            "padding = 517",
+            "extension_order = [0, 10]",
+        ] {
+            let s = format!("host = \"a\"\nclient_hello = {{ preset = \"chrome\", {f} }}");
+            assert!(toml::from_str::<TlsOut>(&s).is_err(), "{f}");
+        }
+        let t: TlsOut =
+            toml::from_str("host = \"a\"\nclient_hello = { preset = \"chrome\" }").unwrap();
+        assert_eq!(t.client_hello.unwrap().preset.as_deref(), Some("chrome"));
+    }
+
+    #[test]
+    #[should_panic(expected = "detour cycle")]
+    fn detour_cycle() {
+        detour_config("b").get_outbounds();
+    }
 }
@@ -65,6 +65,8 @@ impl Engine {
     }
 
     pub fn load_routes_from(&mut self, sc: StaticConfig) {
+        sc.apply_user_limits();
+        sc.apply_auth_providers();
         self.tag_routes = sc.get_tag_route();
         self.fallback_routes = sc.get_fallback_route();
 
@@ -180,6 +182,13 @@ impl Engine {
         self.outbounds.len()
     }
 
+    /// sorted
+    pub fn outbound_tags(&self) -> Vec<String> {
+        let mut v: Vec<_> = self.outbounds.keys().cloned().collect();
+        v.sort();
+        v
+    }
+
     /// non-blocking. it calls start_with_tasks
     pub async fn run(&self) -> anyhow::Result<JoinSet<anyhow::Result<()>>> {
         let mut set = JoinSet::new();
@@ -29,6 +29,7 @@ pub fn load_in_maps_by_str_and_ld_config(s: &str, c: LDConfig) -> Option<MapBox>
                 cert: PathBuf::from(c.cert.unwrap_or_default()),
                 key: PathBuf::from(c.key.unwrap_or_default()),
                 alpn: c.alpn,
+                ..Default::default()
             });
             Some(Box::new(a))
         }
@@ -85,6 +86,11 @@ pub fn load_out_maps_by_str_and_ld_config(s: &str, c: LDConfig) -> Option<MapBox
                 domain: c.host.unwrap_or_default(),
                 is_insecure: c.insecure.unwrap_or_default(),
                 alpn: c.alpn,
+                client_hello: c.fingerprint.map(|f| tls::fingerprint::ClientHelloProfile {
+                    preset: Some(f),
+                    ..Default::default()
+                }),
+                ..Default::default()
             });
             Some(Box::new(a))
         }
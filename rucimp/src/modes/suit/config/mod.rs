@@ -50,9 +50,10 @@ pub struct LDConfig {
     pub key: Option<String>,    //tls server
     pub insecure: Option<bool>, //tls 是否安全
     pub alpn: Option<Vec<String>>,
-    pub protocol: String,             //代理层协议名
-    pub uuid: Option<String>,         // protocol 的用户识别
-    pub version: Option<u16>,         // protocol 的 version
+    pub fingerprint: Option<String>, //tls client 的 ClientHello preset, 如 chrome, firefox
+    pub protocol: String,            //代理层协议名
+    pub uuid: Option<String>,        // protocol 的用户识别
+    pub version: Option<u16>,        // protocol 的 version
     pub encrypt_algo: Option<String>, //protocol 内部的加密算法选择
 
     pub number_arg: Option<i64>, //for math adder
@@ -68,6 +69,7 @@ pub struct LDConfig {
 
     /// dial part
     pub send_through: Option<String>, //用于发送数据的 IP 地址, 可以是ip:port, 或者 tcp://ip:port udp://ip:port
+    pub detour: Option<String>, //通过 tag 对应的 dial 拨号 本 dial 的地址, 以 串联 代理
 }
 
 #[cfg(test)]
@@ -65,13 +65,7 @@ impl SuitEngine {
             .dial
             .iter()
             .map(|lc| {
-                let mut s = SuitStruct::from(lc.clone());
-                s.set_behavior(ProxyBehavior::ENCODE);
-                s.generate_upper_maps();
-                let r_proxy_out_map = load_out_maps_func(s.protocol(), s.config.clone());
-                if let Some(proxy_out_map) = r_proxy_out_map {
-                    s.push_map(Arc::new(proxy_out_map));
-                }
+                let s = load_client(&c.dial, lc, &load_out_maps_func, &mut Vec::new());
                 let x: Box<dyn Suit> = Box::new(s);
                 Arc::new(x)
             })
@@ -188,6 +182,46 @@ impl SuitEngine {
     }
 }
 
+/// 若 lc 有 detour, 则 递归地 加载 其 引用的 dial, 放到 Detour 中
+///
+/// panic if the detour tag isn't presented in dial or the detours form a cycle
+pub fn load_client<FOutMap>(
+    dial: &[LDConfig],
+    lc: &LDConfig,
+    load_out_maps_func: &FOutMap,
+    visiting: &mut Vec<String>,
+) -> SuitStruct
+where
+    FOutMap: Fn(&str, LDConfig) -> Option<MapBox>,
+{
+    let mut s = SuitStruct::from(lc.clone());
+    s.set_behavior(ProxyBehavior::ENCODE);
+
+    if let Some(tag) = &lc.detour {
+        if visiting.contains(tag) {
+            panic!("detour cycle found: {:?} -> {tag}", visiting)
+        }
+        let detour_lc = dial
+            .iter()
+            .find(|d| d.tag.as_ref() == Some(tag))
+            .unwrap_or_else(|| panic!("detour tag not found in dial: {tag}"));
+
+        visiting.push(tag.clone());
+        let detour_s = load_client(dial, detour_lc, load_out_maps_func, visiting);
+        visiting.pop();
+
+        let d = detour::Detour::new(tag.clone(), s.addr(), detour_s.get_maps_vec());
+        s.push_map(Arc::new(Box::new(d)));
+    }
+
+    s.generate_upper_maps();
+    let r_proxy_out_map = load_out_maps_func(s.protocol(), s.config.clone());
+    if let Some(proxy_out_map) = r_proxy_out_map {
+        s.push_map(Arc::new(proxy_out_map));
+    }
+    s
+}
+
 pub async fn listen_ser(
     ins: Arc<Box<dyn Suit>>,
     out_c: Arc<Box<dyn Suit>>,
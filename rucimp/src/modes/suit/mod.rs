@@ -173,7 +173,9 @@ impl Suit for SuitStruct {
 
         match self.get_behavior() {
             ProxyBehavior::ENCODE => {
-                if self.protocol_str != "direct" && !self.addr_str.is_empty() {
+                // 有 detour 时 由 engine 在 开头 放置 Detour, 代替 BindDialer
+                if self.protocol_str != "direct" && !self.addr_str.is_empty() && c.detour.is_none()
+                {
                     let a = net::Addr::from_network_addr_url(self.addr_str())
                         .expect("self addr str ok");
                     let dialer = network::BindDialer {
@@ -187,6 +189,11 @@ impl Suit for SuitStruct {
                         domain: c.host.unwrap_or_default(),
                         is_insecure: c.insecure.unwrap_or_default(),
                         alpn: c.alpn,
+                        client_hello: c.fingerprint.map(|f| tls::fingerprint::ClientHelloProfile {
+                            preset: Some(f),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
                     });
                     self.push_map(Arc::new(Box::new(a)));
                 }
@@ -198,6 +205,7 @@ impl Suit for SuitStruct {
                         cert: c.cert.expect("need cert file  in config").into(),
                         key: c.key.expect("need key file in config").into(),
                         alpn: c.alpn,
+                        ..Default::default()
                     };
                     let sa = tls::server::Server::new(so);
                     self.in_maps.push(Arc::new(Box::new(sa)));
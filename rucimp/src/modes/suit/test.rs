@@ -1,4 +1,4 @@
-use crate::modes::suit::SuitConfigHolder;
+use crate::modes::suit::{engine::load_client, MapsVec, SuitConfigHolder};
 
 use super::config::Config;
 
@@ -35,3 +35,36 @@ fn init_suit() {
     let c_suit = SuitStruct::from(c.dial.pop().unwrap());
     println!("{:?}", c_suit);
 }
+
+#[test]
+fn detour() {
+    let toml_str = r#"
+    [[listen]]
+    protocol = "socks5"
+    host = "127.0.0.1"
+    port = 12345
+
+    [[dial]]
+    tag = "b"
+    protocol = "trojan"
+    uuid = "pass"
+    host = "127.0.0.1"
+    port = 443
+    detour = "a"
+
+    [[dial]]
+    tag = "a"
+    protocol = "socks5"
+    host = "127.0.0.1"
+    port = 1080
+    "#;
+    let c: Config = toml::from_str(toml_str).unwrap();
+    let s = load_client(
+        &c.dial,
+        &c.dial[0],
+        &crate::modes::suit::config::adapter::load_out_maps_by_str_and_ld_config,
+        &mut Vec::new(),
+    );
+    assert_eq!(s.whole_name(), "detour+trojan_client");
+    assert_eq!(s.get_maps_vec().len(), 2);
+}
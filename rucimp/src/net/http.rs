@@ -1,5 +1,8 @@
+use anyhow::bail;
+use bytes::BytesMut;
 use http::{HeaderValue, Request};
-use ruci::net::http::CommonConfig;
+use ruci::net::http::{CommonConfig, ParsedHttpRequest, HEADER_ENDING_STR, MAX_PARSE_URL_LEN};
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 use lazy_static::lazy_static;
 lazy_static! {
@@ -41,6 +44,16 @@ pub enum HttpMatchError<'a> {
     InvalidContentType { expected: &'a str, found: &'a str },
 }
 
+/// 测试 中 用的 http 配置, authority 为 myhost
+#[cfg(test)]
+pub fn test_config(path: &str) -> CommonConfig {
+    CommonConfig {
+        authority: "myhost".to_string(),
+        path: path.to_string(),
+        ..Default::default()
+    }
+}
+
 #[test]
 fn test_url() {
     let u = http::Uri::builder()
@@ -79,3 +92,96 @@ pub fn match_request_http_header<'a, T: 'a>(
 
     Ok(())
 }
+
+/// 从 r 中 读出 一个 http/1.1 的 头部 (含 结尾的 空行), 返回 头部 与 多读出的 数据.
+///
+/// buf 为 已经读到的 数据, 可为空
+pub async fn read_h1_head<R: AsyncRead + Unpin>(
+    r: &mut R,
+    mut buf: BytesMut,
+) -> anyhow::Result<(BytesMut, BytesMut)> {
+    loop {
+        if let Some(i) = buf
+            .windows(HEADER_ENDING_STR.len())
+            .position(|w| w == HEADER_ENDING_STR.as_bytes())
+        {
+            let head = buf.split_to(i + HEADER_ENDING_STR.len());
+            return Ok((head, buf));
+        }
+        if buf.len() >= MAX_PARSE_URL_LEN {
+            bail!("http head too long")
+        }
+        buf.reserve(MAX_PARSE_URL_LEN - buf.len());
+        if r.read_buf(&mut buf).await? == 0 {
+            bail!("eof before http head ends, got {} bytes", buf.len())
+        }
+    }
+}
+
+/// 返回 http/1.1 响应 首行 中的 状态码
+pub fn parse_h1_status(head: &[u8]) -> anyhow::Result<u16> {
+    let line = head.split(|b| *b == b'\r').next().unwrap_or_default();
+    let line = std::str::from_utf8(line)?;
+    let mut parts = line.split(' ');
+    match (parts.next(), parts.next()) {
+        (Some(v), Some(code)) if v.starts_with("HTTP/1.") => Ok(code.parse()?),
+        _ => bail!("not a http/1.1 response: {line}"),
+    }
+}
+
+/// 不区分 大小写 地 查找 header
+pub fn get_h1_header<'a>(r: &'a ParsedHttpRequest, key: &str) -> Option<&'a str> {
+    r.headers
+        .iter()
+        .find(|h| h.head.eq_ignore_ascii_case(key))
+        .map(|h| h.value.as_str())
+}
+
+/// 与 [`match_request_http_header`] 相同, 但用于 [`ruci::net::http::parse_h1_request`] 的 结果.
+/// c.authority 为空 时 不检查 Host; 路径 由 调用者 检查
+pub fn match_h1_host<'a>(
+    c: &'a CommonConfig,
+    r: &'a ParsedHttpRequest,
+) -> Result<(), HttpMatchError<'a>> {
+    if c.authority.is_empty() {
+        return Ok(());
+    }
+    let given_host = get_h1_header(r, "Host").unwrap_or_default();
+    if c.authority != given_host {
+        return Err(HttpMatchError::InvalidHost {
+            expected: &c.authority,
+            found: given_host,
+        });
+    }
+    Ok(())
+}
+
+/// 写出 CommonConfig 中 除 Host 以外 的 header
+pub fn push_h1_headers(s: &mut String, c: &CommonConfig) {
+    if let Some(h) = &c.headers {
+        for (k, v) in h.iter() {
+            if !k.eq_ignore_ascii_case("Host") {
+                s.push_str(&format!("{k}: {v}\r\n"));
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_read_h1_head() -> anyhow::Result<()> {
+    let data = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\nhello";
+    let (mut r, mut w) = tokio::io::duplex(64);
+    tokio::spawn(async move {
+        use tokio::io::AsyncWriteExt;
+        for c in data.chunks(7) {
+            w.write_all(c).await.unwrap();
+        }
+    });
+    let (head, rest) = read_h1_head(&mut r, BytesMut::new()).await?;
+    assert_eq!(parse_h1_status(&head)?, 101);
+    assert!(head.ends_with(b"\r\n\r\n"));
+    let mut rest = rest.to_vec();
+    r.read_to_end(&mut rest).await?;
+    assert_eq!(rest, b"hello");
+    Ok(())
+}
@@ -33,6 +33,8 @@ pub struct RuleSetConfig {
     pub ta_ipv6: Option<Vec<String>>,
 
     pub ta_domain_matcher: Option<DomainMatcherConfig>,
+
+    pub sni_matcher: Option<DomainMatcherConfig>,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
@@ -116,6 +118,7 @@ impl RuleSetConfig {
             ta_ipv4: ip4,
             ta_ipv6: ip6,
             ta_domain_matcher: dm,
+            sni_matcher: self.sni_matcher.map(|dm| dm.to_dm()),
             ..Default::default()
         }
     }
@@ -53,6 +53,7 @@ impl route::OutSelector for RuleSetOutSelector {
             target_addr: addr.clone(),
             users,
             is_fallback,
+            sni: get_sni_from_opt_data(params),
         };
         let mut out_tag: Option<String> = None;
         for rs in self.outbounds_rules_vec.iter() {
@@ -108,6 +109,10 @@ pub struct RuleSet {
     pub ta_ipv6: Option<IpRange<Ipv6Net>>,
 
     pub ta_domain_matcher: Option<DomainMatcher>,
+
+    /// matches the sni of the tls inbound
+    pub sni_matcher: Option<DomainMatcher>,
+
     /// for geoip, checking ip_countries
     #[cfg(feature = "geoip")]
     pub mmdb_reader: Option<Arc<maxminddb::Reader<Vec<u8>>>>,
@@ -126,6 +131,7 @@ impl Debug for RuleSet {
             .field("ta_ipv4", &self.ta_ipv4)
             .field("ta_ipv6", &self.ta_ipv6)
             .field("ta_domain_matcher", &self.ta_domain_matcher)
+            .field("sni_matcher", &self.sni_matcher)
             //.field("mmdb_reader", &self.mmdb_reader) //print this would spam the console
             .finish()
     }
@@ -137,6 +143,22 @@ pub struct DomainMatcher {
     pub domain_set: Option<HashSet<String>>,
 }
 
+impl DomainMatcher {
+    pub fn matches(&self, domain: &str) -> bool {
+        if let Some(dmr) = &self.domain_regex {
+            if dmr.is_match(domain) {
+                return true;
+            }
+        }
+        if let Some(dms) = &self.domain_set {
+            if dms.contains(domain) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
 impl RuleSet {
     pub fn matches(&self, r: &InboundInfo) -> bool {
         match self.mode {
@@ -174,6 +196,11 @@ impl RuleSet {
             return false;
         }
 
+        let sni_is_in = self.is_in_sni(true, r.sni.as_deref());
+        if !sni_is_in {
+            return false;
+        }
+
         #[cfg(feature = "geoip")]
         {
             let is_in_ta_ip_countries = self.is_in_ta_ip_countries(true, &r.target_addr);
@@ -214,6 +241,11 @@ impl RuleSet {
             return true;
         }
 
+        let sni_is_in = self.is_in_sni(false, r.sni.as_deref());
+        if sni_is_in {
+            return true;
+        }
+
         #[cfg(feature = "geoip")]
         {
             let is_in_ta_ip_countries = self.is_in_ta_ip_countries(true, &r.target_addr);
@@ -262,22 +294,18 @@ impl RuleSet {
         }
     }
 
+    /// false if the rule is set but there is no sni
+    pub fn is_in_sni(&self, true_if_empty: bool, sni: Option<&str>) -> bool {
+        match &self.sni_matcher {
+            Some(dm) => sni.is_some_and(|sni| dm.matches(sni)),
+            None => true_if_empty,
+        }
+    }
+
     pub fn is_in_domain(&self, true_if_empty: bool, addr: &net::Addr) -> bool {
         match &self.ta_domain_matcher {
             Some(dm) => match &addr.addr {
-                NetAddr::Name(domain, _) => {
-                    if let Some(dmr) = &dm.domain_regex {
-                        if dmr.is_match(domain) {
-                            return true;
-                        }
-                    }
-                    if let Some(dms) = &dm.domain_set {
-                        if dms.contains(domain) {
-                            return true;
-                        }
-                    }
-                    false
-                }
+                NetAddr::Name(domain, _) => dm.matches(domain),
                 _ => true_if_empty,
             },
             None => true_if_empty,
@@ -363,6 +391,29 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn rs_sni() -> anyhow::Result<()> {
+        let rs = RuleSet {
+            sni_matcher: Some(DomainMatcher {
+                domain_regex: Some(RegexSet::new([r"\.example\.com$"])?),
+                domain_set: Some(HashSet::from(["exact.test".to_string()])),
+            }),
+            ..Default::default()
+        };
+        let ii = |sni: Option<&str>| InboundInfo {
+            sni: sni.map(|s| s.to_string()),
+            ..Default::default()
+        };
+
+        assert!(rs.matches(&ii(Some("a.example.com"))));
+        assert!(rs.matches(&ii(Some("exact.test"))));
+        assert!(!rs.matches(&ii(Some("other.test"))));
+        assert!(!rs.matches(&ii(None)));
+
+        assert!(RuleSet::default().matches(&ii(Some("other.test"))));
+        Ok(())
+    }
+
     //#[test]
     #[allow(unused)]
     #[cfg(feature = "geoip")]
@@ -0,0 +1,100 @@
+/*!
+htpasswd 风格 的 用户文件, 每行 为 `user:hash`, hash 为 bcrypt 或 argon2 格式.
+空行 与 # 开头 的 行 被 忽略.
+
+trojan 只 发送 密码 的 sha224, 无法 与 这些 hash 比较, 所以 该 后端 不支持 trojan.
+*/
+
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use ruci::user::{AuthProvider, Credential};
+
+use super::hash::verify_hash;
+
+#[derive(Debug, Default)]
+pub struct FileProvider {
+    /// user => hash
+    users: Arc<HashMap<String, String>>,
+}
+
+impl FileProvider {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let s = std::fs::read_to_string(path)
+            .with_context(|| format!("read users file {} failed", path.display()))?;
+        Self::parse(&s)
+    }
+
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        let mut users = HashMap::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((u, h)) = line.split_once(':') else {
+                bail!("users file line {} has no ':'", i + 1)
+            };
+            users.insert(u.to_string(), h.to_string());
+        }
+        Ok(Self {
+            users: Arc::new(users),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+}
+
+#[async_trait]
+impl AuthProvider for FileProvider {
+    fn name(&self) -> &str {
+        "users_file"
+    }
+
+    async fn verify(&self, c: &Credential) -> anyhow::Result<bool> {
+        let Credential::UserPass { user, pass } = c else {
+            return Ok(false);
+        };
+        let Some(h) = self.users.get(user).cloned() else {
+            return Ok(false);
+        };
+        let pass = pass.clone();
+        tokio::task::spawn_blocking(move || verify_hash(&pass, &h)).await?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn verify() -> anyhow::Result<()> {
+        let s = format!(
+            "# comment\n\nu1:{}\nu2:{}\n",
+            bcrypt::hash("p1", 4)?,
+            bcrypt::hash("p2", 4)?
+        );
+        let p = FileProvider::parse(&s)?;
+        assert_eq!(p.len(), 2);
+
+        let c = |u: &str, p: &str| Credential::UserPass {
+            user: u.to_string(),
+            pass: p.to_string(),
+        };
+        assert!(p.verify(&c("u1", "p1")).await?);
+        assert!(!p.verify(&c("u1", "p2")).await?);
+        assert!(!p.verify(&c("u3", "p1")).await?);
+        assert!(!p.verify(&Credential::TrojanHash("h".into())).await?);
+
+        assert!(FileProvider::parse("u1").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+//! 校验 bcrypt 与 argon2 格式 的 密码 hash
+
+use anyhow::bail;
+use argon2::{password_hash::PasswordHash, Argon2, PasswordVerifier};
+
+/// hash 以 $2a$, $2b$, $2y$ (bcrypt) 或 $argon2 开头
+///
+/// 计算 很慢, 应在 spawn_blocking 中 调用
+pub fn verify_hash(pass: &str, hash: &str) -> anyhow::Result<bool> {
+    if hash.starts_with("$2") {
+        Ok(bcrypt::verify(pass, hash)?)
+    } else if hash.starts_with("$argon2") {
+        let h = PasswordHash::new(hash).map_err(|e| anyhow::anyhow!("invalid argon2 hash: {e}"))?;
+        Ok(Argon2::default()
+            .verify_password(pass.as_bytes(), &h)
+            .is_ok())
+    } else {
+        bail!("unsupported hash format")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use argon2::{
+        password_hash::{rand_core::OsRng, SaltString},
+        PasswordHasher,
+    };
+
+    use super::*;
+
+    #[test]
+    fn verify() -> anyhow::Result<()> {
+        let h = bcrypt::hash("p", 4)?;
+        assert!(verify_hash("p", &h)?);
+        assert!(!verify_hash("p2", &h)?);
+
+        let salt = SaltString::generate(&mut OsRng);
+        let h = Argon2::default()
+            .hash_password(b"p", &salt)
+            .map_err(|e| anyhow::anyhow!("{e}"))?
+            .to_string();
+        assert!(verify_hash("p", &h)?);
+        assert!(!verify_hash("p2", &h)?);
+
+        assert!(verify_hash("p", "plain").is_err());
+        Ok(())
+    }
+}
@@ -1,5 +1,18 @@
+/*!
+用户 相关 的 扩展: 从 字符串 解析 用户, 以及 [`ruci::user::AuthProvider`] 的 实现
+*/
+
+#[cfg(feature = "auth_file")]
+pub mod file;
+#[cfg(feature = "auth_hash")]
+pub mod hash;
+#[cfg(feature = "auth_sqlite")]
+pub mod sqlite;
+#[cfg(feature = "auth_webhook")]
+pub mod webhook;
+
 use ruci::{
-    map::trojan,
+    map::{tls, trojan},
     user::{PlainText, UserBox},
 };
 use tracing::warn;
@@ -11,7 +24,7 @@ fn test() {
 
 /// convert string with certain prefix to [`ruci::user::UserBox`]
 ///
-/// support plaintext:xxx, trojan:xxx
+/// support plaintext:xxx, trojan:xxx, x509:xxx
 ///
 pub fn str_to_userbox(str: &str) -> Option<UserBox> {
     let s = String::from(str);
@@ -32,8 +45,12 @@ pub fn str_to_userbox(str: &str) -> Option<UserBox> {
             let p = trojan::User::new(v[1]);
             return Some(UserBox(Box::new(p)));
         }
+        "x509" => {
+            let p = tls::CertUser::new(v[1]);
+            return Some(UserBox(Box::new(p)));
+        }
         _ => {
-            warn!("user format invalid: {str}, you can use like plaintext:u0 p0, or trojan:mypassword")
+            warn!("user format invalid: {str}, you can use like plaintext:u0 p0, trojan:mypassword, or x509:client_cert_cn")
         }
     }
     None
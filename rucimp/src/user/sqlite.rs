@@ -0,0 +1,133 @@
+/*!
+通过 sqlite 数据库 鉴权.
+
+表 须有 `user`, `hash` 与 `trojan_hash` 三列:
+socks5 与 http 用 user 查出 hash (bcrypt 或 argon2) 校验 密码;
+trojan 用 密码 的 sha224 hex 匹配 trojan_hash, 该列 可为 NULL.
+
+```sql
+CREATE TABLE users (user TEXT PRIMARY KEY, hash TEXT, trojan_hash TEXT);
+```
+*/
+
+use std::{path::Path, sync::Arc};
+
+use anyhow::bail;
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use ruci::user::{AuthProvider, Credential};
+use rusqlite::{Connection, OptionalExtension};
+
+use super::hash::verify_hash;
+
+pub const DEFAULT_TABLE: &str = "users";
+
+#[derive(Debug)]
+pub struct SqliteProvider {
+    conn: Arc<Mutex<Connection>>,
+    table: String,
+}
+
+impl SqliteProvider {
+    pub fn open(path: impl AsRef<Path>, table: Option<String>) -> anyhow::Result<Self> {
+        Self::from_conn(Connection::open(path)?, table)
+    }
+
+    pub fn from_conn(conn: Connection, table: Option<String>) -> anyhow::Result<Self> {
+        let table = table.unwrap_or_else(|| DEFAULT_TABLE.to_string());
+        if !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            bail!("invalid sqlite table name: {table}")
+        }
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            table,
+        })
+    }
+}
+
+#[async_trait]
+impl AuthProvider for SqliteProvider {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    fn supports_trojan(&self) -> bool {
+        true
+    }
+
+    async fn verify(&self, c: &Credential) -> anyhow::Result<bool> {
+        let conn = self.conn.clone();
+        let table = self.table.clone();
+        let c = c.clone();
+        tokio::task::spawn_blocking(move || match c {
+            Credential::UserPass { user, pass } => {
+                let h: Option<Option<String>> = conn
+                    .lock()
+                    .query_row(
+                        &format!("SELECT hash FROM {table} WHERE user = ?1"),
+                        [&user],
+                        |r| r.get(0),
+                    )
+                    .optional()?;
+                match h.flatten() {
+                    Some(h) => verify_hash(&pass, &h),
+                    None => Ok(false),
+                }
+            }
+            Credential::TrojanHash(th) => {
+                let found: Option<i64> = conn
+                    .lock()
+                    .query_row(
+                        &format!("SELECT 1 FROM {table} WHERE trojan_hash = ?1"),
+                        [&th],
+                        |r| r.get(0),
+                    )
+                    .optional()?;
+                Ok(found.is_some())
+            }
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ruci::map::trojan::sha224_hex_string_lower_case;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn verify() -> anyhow::Result<()> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute(
+            "CREATE TABLE users (user TEXT PRIMARY KEY, hash TEXT, trojan_hash TEXT)",
+            [],
+        )?;
+        conn.execute(
+            "INSERT INTO users VALUES (?1, ?2, ?3)",
+            [
+                "u1",
+                &bcrypt::hash("p1", 4)?,
+                &sha224_hex_string_lower_case("tp1"),
+            ],
+        )?;
+        let p = SqliteProvider::from_conn(conn, None)?;
+
+        let c = |u: &str, p: &str| Credential::UserPass {
+            user: u.to_string(),
+            pass: p.to_string(),
+        };
+        assert!(p.verify(&c("u1", "p1")).await?);
+        assert!(!p.verify(&c("u1", "p2")).await?);
+        assert!(!p.verify(&c("u2", "p1")).await?);
+
+        let t = |p: &str| Credential::TrojanHash(sha224_hex_string_lower_case(p));
+        assert!(p.verify(&t("tp1")).await?);
+        assert!(!p.verify(&t("tp2")).await?);
+
+        assert!(
+            SqliteProvider::from_conn(Connection::open_in_memory()?, Some("a b".into())).is_err()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+/*!
+通过 http webhook 鉴权.
+
+对 每个 凭证 POST 一个 json 到 url:
+`{"kind":"plaintext","user":"u0","pass":"p0"}` 或 `{"kind":"trojan","hash":"<sha224 hex>"}`.
+2xx 为 通过, 401 与 403 为 不通过, 其它 状态码 视为 后端 出错.
+
+trojan 的 凭证 只在 [`WebhookProvider::trojan`] 为 true 时 发送, 因为 trojan 服务端 收到的
+探测 也会 产生 凭证, 且 请求 的 延迟 会 推迟 回落.
+*/
+
+use std::time::Duration;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use ruci::user::{AuthProvider, Credential};
+use serde_json::json;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub struct WebhookProvider {
+    pub url: String,
+
+    /// 是否 验证 trojan 的 凭证
+    pub trojan: bool,
+    client: reqwest::Client,
+}
+
+impl WebhookProvider {
+    pub fn new(url: String, timeout: Option<Duration>) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout.unwrap_or(DEFAULT_TIMEOUT))
+            .build()?;
+        Ok(Self {
+            url,
+            trojan: false,
+            client,
+        })
+    }
+}
+
+#[async_trait]
+impl AuthProvider for WebhookProvider {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn supports_trojan(&self) -> bool {
+        self.trojan
+    }
+
+    async fn verify(&self, c: &Credential) -> anyhow::Result<bool> {
+        let body = match c {
+            Credential::UserPass { user, pass } => {
+                json!({ "kind": "plaintext", "user": user, "pass": pass })
+            }
+            Credential::TrojanHash(h) => json!({ "kind": "trojan", "hash": h }),
+        };
+        let r = self.client.post(&self.url).json(&body).send().await?;
+        let status = r.status();
+        if status.is_success() {
+            Ok(true)
+        } else if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            Ok(false)
+        } else {
+            bail!("webhook returned {status}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    /// 收到的 body 含 "good" 时 返回 200, 否则 403
+    async fn serve_once(l: &tokio::net::TcpListener) -> anyhow::Result<()> {
+        let (mut c, _) = l.accept().await?;
+        let mut buf = vec![0u8; 4096];
+        let mut n = 0;
+        loop {
+            n += c.read(&mut buf[n..]).await?;
+            let s = String::from_utf8_lossy(&buf[..n]);
+            if s.contains("}") {
+                break;
+            }
+        }
+        let s = String::from_utf8_lossy(&buf[..n]);
+        let status = if s.contains("good") {
+            "200 OK"
+        } else {
+            "403 Forbidden"
+        };
+        c.write_all(format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\n\r\n").as_bytes())
+            .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn verify() -> anyhow::Result<()> {
+        let l = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/auth", l.local_addr()?);
+        let p = WebhookProvider::new(url, None)?;
+
+        let good = Credential::UserPass {
+            user: "u".into(),
+            pass: "good".into(),
+        };
+        let (r, s) = tokio::join!(p.verify(&good), serve_once(&l));
+        s?;
+        assert!(r?);
+
+        let bad = Credential::TrojanHash("bad".into());
+        let (r, s) = tokio::join!(p.verify(&bad), serve_once(&l));
+        s?;
+        assert!(!r?);
+        Ok(())
+    }
+}
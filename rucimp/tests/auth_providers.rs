@@ -0,0 +1,171 @@
+/*!
+测试 启用 了 外部 鉴权 后端 的 socks5 与 http 服务端 即使 没有 配置 用户 也 须 鉴权,
+没有 启用 的 不受 影响.
+
+AUTH_PROVIDERS 是 全局的, 所以 放在 单独的 测试 程序 中
+ */
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use base64::prelude::*;
+use ruci::{
+    map::{http_proxy, socks5, Map, MapParams, ProxyBehavior},
+    net::CID,
+    user::{self, AuthProvider, Credential},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+#[derive(Debug)]
+struct OneUser;
+
+#[async_trait]
+impl AuthProvider for OneUser {
+    fn name(&self) -> &str {
+        "one_user"
+    }
+
+    async fn verify(&self, c: &Credential) -> anyhow::Result<bool> {
+        Ok(matches!(c, Credential::UserPass { user, pass } if user == "u" && pass == "p"))
+    }
+}
+
+fn set_providers() {
+    user::AUTH_PROVIDERS.set(vec![Arc::new(OneUser)]);
+}
+
+async fn run_map(
+    m: impl Map + Send + Sync + 'static,
+) -> (DuplexStream, tokio::task::JoinHandle<bool>) {
+    let (c, s) = tokio::io::duplex(4096);
+    let h = tokio::spawn(async move {
+        let r = m
+            .maps(
+                CID::default(),
+                ProxyBehavior::DECODE,
+                MapParams::new(Box::new(s)),
+            )
+            .await;
+        r.e.is_none()
+    });
+    (c, h)
+}
+
+#[tokio::test]
+async fn socks5_without_users() -> anyhow::Result<()> {
+    set_providers();
+    let m = || {
+        socks5::server::Server::new(socks5::server::Config {
+            use_auth_providers: true,
+            ..Default::default()
+        })
+    };
+
+    // 只 提供 无鉴权 方法 时 被 拒绝
+    let (mut c, h) = run_map(m().await).await;
+    c.write_all(&[socks5::VERSION5, 1, socks5::AUTH_NONE])
+        .await?;
+    let mut buf = [0u8; 2];
+    c.read_exact(&mut buf).await?;
+    assert_eq!(buf, [socks5::VERSION5, socks5::AUTH_NO_ACCEPTABLE]);
+    assert!(!h.await?);
+
+    for (pass, ok) in [("wrong", false), ("p", true)] {
+        let (mut c, h) = run_map(m().await).await;
+        c.write_all(&[
+            socks5::VERSION5,
+            2,
+            socks5::AUTH_NONE,
+            socks5::AUTH_PASSWORD,
+        ])
+        .await?;
+        c.read_exact(&mut buf).await?;
+        assert_eq!(buf, [socks5::VERSION5, socks5::AUTH_PASSWORD]);
+
+        let mut auth = vec![1, 1, b'u', pass.len() as u8];
+        auth.extend_from_slice(pass.as_bytes());
+        c.write_all(&auth).await?;
+        c.read_exact(&mut buf).await?;
+        assert_eq!(buf[1] == 0, ok, "{pass}");
+
+        if ok {
+            c.write_all(&[
+                socks5::VERSION5,
+                socks5::CMD_CONNECT,
+                0,
+                socks5::ATYP_IP4,
+                127,
+                0,
+                0,
+                1,
+                0,
+                80,
+            ])
+            .await?;
+        }
+        assert_eq!(h.await?, ok, "{pass}");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn http_without_users() -> anyhow::Result<()> {
+    set_providers();
+    let m = || {
+        http_proxy::Server::new(http_proxy::Config {
+            use_auth_providers: true,
+            ..Default::default()
+        })
+    };
+
+    for (auth, ok) in [(None, false), (Some("u:wrong"), false), (Some("u:p"), true)] {
+        let (mut c, h) = run_map(m().await).await;
+        let mut req = "CONNECT 127.0.0.1:80 HTTP/1.1\r\nHost: 127.0.0.1:80\r\n".to_string();
+        if let Some(a) = auth {
+            req += &format!(
+                "Proxy-Authorization: Basic {}\r\n",
+                BASE64_STANDARD.encode(a)
+            );
+        }
+        req += "\r\n";
+        c.write_all(req.as_bytes()).await?;
+
+        let mut buf = vec![0u8; 1024];
+        let n = c.read(&mut buf).await?;
+        let reply = String::from_utf8_lossy(&buf[..n]);
+        if ok {
+            assert_eq!(reply, http_proxy::CONNECT_REPLY_STR);
+        } else {
+            assert!(reply.starts_with("HTTP/1.1 407"), "{reply}");
+        }
+        assert_eq!(h.await?, ok);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn not_opted_in() -> anyhow::Result<()> {
+    set_providers();
+
+    let s = socks5::server::Server::new(socks5::server::Config::default()).await;
+    assert!(!s.requires_auth());
+    let (mut c, h) = run_map(s).await;
+    c.write_all(&[socks5::VERSION5, 1, socks5::AUTH_NONE])
+        .await?;
+    let mut buf = [0u8; 2];
+    c.read_exact(&mut buf).await?;
+    assert_eq!(buf, [socks5::VERSION5, socks5::AUTH_NONE]);
+    drop(c);
+    h.await?;
+
+    let s = http_proxy::Server::new(http_proxy::Config::default()).await;
+    assert!(!s.requires_auth());
+    let (mut c, h) = run_map(s).await;
+    c.write_all(b"CONNECT 127.0.0.1:80 HTTP/1.1\r\nHost: 127.0.0.1:80\r\n\r\n")
+        .await?;
+    let mut buf = vec![0u8; 1024];
+    let n = c.read(&mut buf).await?;
+    assert_eq!(&buf[..n], http_proxy::CONNECT_REPLY_STR.as_bytes());
+    assert!(h.await?);
+    Ok(())
+}
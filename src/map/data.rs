@@ -28,6 +28,7 @@ bitflags! {
         const LAddr = 0b0100000000000000;
         const User = 0b0010000000000000;
         const CID = 0b0001000000000000;
+        const SNI = 0b0000100000000000;
 
         const RLAddr = Self::RAddr.bits() | Self::LAddr.bits();
     }
@@ -56,6 +57,11 @@ pub trait Data: Debug + Send + Sync + DynClone {
         None
     }
 
+    /// the server name indication of a tls conn
+    fn get_sni(&self) -> Option<String> {
+        None
+    }
+
     fn get_extra_data(&self) -> Option<Vec<u8>> {
         None
     }
@@ -92,6 +98,14 @@ impl Data for u8 {
     }
 }
 
+/// 前面 的 Map (如 Listener) 给出 的 客户端 ip
+pub fn client_ip(d: &[Option<Box<dyn Data>>]) -> Option<std::net::IpAddr> {
+    d.iter()
+        .flatten()
+        .find_map(|d| d.get_raddr())
+        .and_then(|a| a.get_ip())
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RAddr(pub net::Addr);
 
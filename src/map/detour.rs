@@ -0,0 +1,151 @@
+/*!
+Defines [`Detour`], 通过 另一个 出站 拨号 的 Map
+
+Detour 位于 出站链 的 开头, 代替 BindDialer. 它 以 dial_addr 为 目标地址 累加 被引用的 出站链,
+得到的 流 即 本链 的 基础连接. 如 出站 B = { Detour("A", B的地址), tls, trojan }, 则 流量 为
+client -> A -> B -> target
+
+被引用的 出站链 由 配置 在 初始化 时 解析 并 通过 [`Detour::new`] 传入.
+*/
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use macro_map::{map_ext_fields, MapExt};
+use tracing::debug;
+
+use super::{
+    fold::{fold, DynVecIterWrapper, FoldParams},
+    *,
+};
+use crate::Name;
+
+#[map_ext_fields]
+#[derive(Clone, Debug, Default, MapExt)]
+pub struct Detour {
+    /// 被引用的 出站 的 tag
+    pub tag: String,
+
+    /// 通过 detour 出站 拨号 的 地址, 一般为 本出站 的 服务器地址
+    pub dial_addr: Option<net::Addr>,
+
+    outbound: Vec<Arc<MapBox>>,
+}
+
+impl Name for Detour {
+    fn name(&self) -> &str {
+        "detour"
+    }
+}
+
+impl Detour {
+    pub fn new(tag: String, dial_addr: Option<net::Addr>, outbound: Vec<Arc<MapBox>>) -> Self {
+        Self {
+            tag,
+            dial_addr,
+            outbound,
+            ext_fields: Some(MapExtFields::default()),
+        }
+    }
+
+    async fn dial(&self, cid: CID) -> anyhow::Result<net::Conn> {
+        if self.outbound.is_empty() {
+            anyhow::bail!("detour outbound {} not resolved", self.tag)
+        }
+        let r = fold(FoldParams {
+            cid,
+            behavior: ProxyBehavior::ENCODE,
+            initial_state: MapResult::builder().a(self.dial_addr.clone()).build(),
+            maps: Box::new(DynVecIterWrapper(self.outbound.clone().into_iter())),
+            chain_tag: self.tag.clone(),
+
+            #[cfg(feature = "trace")]
+            trace: Vec::new(),
+        })
+        .await;
+        if let Some(e) = r.e {
+            return Err(e.context(format!("detour via {} failed", self.tag)));
+        }
+        match r.c {
+            Stream::Conn(c) => Ok(c),
+            _ => anyhow::bail!(
+                "detour outbound {} should produce a tcplike stream",
+                self.tag
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl Map for Detour {
+    async fn maps(&self, cid: CID, _behavior: ProxyBehavior, params: MapParams) -> MapResult {
+        if !matches!(params.c, Stream::None) {
+            return MapResult::err_str("detour only support None stream");
+        }
+        debug!(cid = %cid, tag = self.tag, "detour dialing");
+        match self.dial(cid).await {
+            Ok(c) => MapResult::new_c(c).a(params.a).b(params.b).build(),
+            Err(e) => MapResult::from_e(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::map::{
+        math::{AddDirection, Adder},
+        network::BindDialer,
+    };
+
+    #[tokio::test]
+    async fn dial_through_outbound() -> anyhow::Result<()> {
+        let l = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let port = l.local_addr()?.port();
+
+        let d = BindDialer {
+            dial_addr: Some(net::Addr::from_network_addr_url(&format!(
+                "tcp://127.0.0.1:{port}"
+            ))?),
+            ..Default::default()
+        };
+        let a = Adder {
+            add_num: 1,
+            direction: AddDirection::Write,
+            ..Default::default()
+        };
+        let detour = Detour::new(
+            "a".to_string(),
+            Some(net::Addr::from_network_addr_url("tcp://1.2.3.4:443")?),
+            vec![Arc::new(Box::new(d)), Arc::new(Box::new(a))],
+        );
+
+        let r = detour
+            .maps(CID::default(), ProxyBehavior::ENCODE, MapParams::default())
+            .await;
+        let Stream::Conn(mut c) = r.c else {
+            panic!("detour should return a conn, {:?}", r.e)
+        };
+        c.write_all(&[1, 2, 3]).await?;
+
+        let (mut s, _) = l.accept().await?;
+        let mut buf = [0u8; 3];
+        s.read_exact(&mut buf).await?;
+        assert_eq!(buf, [2, 3, 4]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unresolved() {
+        let detour = Detour {
+            tag: "a".to_string(),
+            ..Default::default()
+        };
+        let r = detour
+            .maps(CID::default(), ProxyBehavior::ENCODE, MapParams::default())
+            .await;
+        assert!(r.e.is_some());
+    }
+}
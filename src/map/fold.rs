@@ -107,6 +107,9 @@ pub struct FoldResult {
     pub d: Vec<Option<Box<dyn Data>>>,
     pub e: Option<anyhow::Error>,
 
+    /// e 不为 None 时, 返回 e 的 Map 的 name
+    pub failed_map: Option<String>,
+
     /// 代表 迭代完成后, 最终的 cid
     pub id: CID,
 
@@ -131,6 +134,7 @@ impl Debug for FoldResult {
             .field("c", &self.c)
             .field("d", &self.d)
             .field("e", &self.e)
+            .field("failed_map", &self.failed_map)
             .field("id", &self.id)
             .field("tag", &self.chain_tag)
             .finish()
@@ -181,6 +185,7 @@ pub async fn fold(params: FoldParams) -> FoldResult {
     calculated_output_vec.push(last_r.d);
 
     let mut tag: String = params.chain_tag;
+    let mut failed_map = None;
 
     loop {
         let adder = if maps.requires_no_data() {
@@ -227,10 +232,11 @@ pub async fn fold(params: FoldParams) -> FoldResult {
         #[cfg(feature = "trace")]
         trace.push(adder.name().to_string());
 
-        if last_r.c.is_none_or_generator() {
+        if last_r.e.is_some() {
+            failed_map = Some(adder.name().to_string());
             break;
         }
-        if last_r.e.is_some() {
+        if last_r.c.is_none_or_generator() {
             break;
         }
     } //for
@@ -241,6 +247,7 @@ pub async fn fold(params: FoldParams) -> FoldResult {
         c: last_r.c,
         d: calculated_output_vec,
         e: last_r.e,
+        failed_map,
 
         id: match last_r.new_id {
             Some(nid) => nid,
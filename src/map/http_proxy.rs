@@ -3,6 +3,8 @@ implements Map for http proxy
  */
 
 use std::cmp::min;
+use std::net::IpAddr;
+use std::sync::Arc;
 
 use anyhow::bail;
 use base64::prelude::*;
@@ -15,24 +17,27 @@ use url::Url;
 use crate::map::{self, MapResult};
 use crate::net::http::Method;
 use crate::net::CID;
-use crate::user::{self, AsyncUserAuthenticator};
+use crate::user::{self, UserTrait};
 use crate::utils::buf_to_ob;
 use crate::{
     net::{self, Conn},
-    user::{PlainText, UsersMap},
+    user::{PlainText, UserStore, UsersMap},
     Name,
 };
 
 use super::{MapBox, MapExtFields, Stream, ToMapBox};
 
 pub const CONNECT_REPLY_STR: &str = "HTTP/1.1 200 Connection established\r\n\r\n";
+pub const AUTH_REQUIRED_REPLY_STR: &str = "HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Basic realm=\"ruci\"\r\nContent-Length: 0\r\n\r\n";
 pub const BASIC_AUTH_VALUE_PREFIX: &str = "Basic ";
-pub const PROXY_AUTH_HEADER_STR: &str = "Proxy-Authorization ";
+pub const PROXY_AUTH_HEADER_STR: &str = "Proxy-Authorization";
 
 #[map_ext_fields]
 #[derive(Debug, Clone, MapExt)]
 pub struct Server {
     pub um: Option<UsersMap<PlainText>>,
+    pub store: Option<Arc<UserStore<PlainText>>>,
+    pub use_auth_providers: bool,
     pub only_connect: bool,
 }
 
@@ -47,6 +52,12 @@ pub struct Config {
     pub only_support_connect: bool,
     pub user_whitespace_pass: Option<String>,
     pub user_passes: Option<Vec<PlainText>>,
+
+    /// 使用 的 运行时 用户 存储 的 名称, 见 [`user::store`]
+    pub user_store: Option<String>,
+
+    /// 是否 查询 全局 的 外部 鉴权 后端, 见 [`user::provider`]. 启用 后 即使 没有 用户 也 须 鉴权
+    pub use_auth_providers: bool,
 }
 
 impl ToMapBox for Config {
@@ -78,15 +89,30 @@ impl Server {
         Server {
             only_connect: option.only_support_connect,
             um: if um.is_empty() { None } else { Some(um) },
+            store: option
+                .user_store
+                .map(|n| user::PLAINTEXT_USERS.get_or_create(&n)),
+            use_auth_providers: option.use_auth_providers,
             ext_fields: Some(MapExtFields::default()),
         }
     }
 
+    /// 配置了 用户, 使用了 UserStore, 或 启用了 外部 鉴权 后端 时 须 鉴权
+    pub fn requires_auth(&self) -> bool {
+        self.um.is_some() || self.store.is_some() || self.use_auth_providers
+    }
+
+    fn providers(&self) -> Option<&user::AuthProviders> {
+        self.use_auth_providers.then_some(&*user::AUTH_PROVIDERS)
+    }
+
+    /// client_ip 用于 限制 外部 鉴权 后端 的 失败 次数
     pub async fn handshake(
         &self,
         _cid: CID,
         mut base: Conn,
         pre_read_data: Option<bytes::BytesMut>,
+        client_ip: Option<IpAddr>,
     ) -> anyhow::Result<map::MapResult> {
         let mut buf: BytesMut;
 
@@ -118,11 +144,10 @@ impl Server {
         }
         let mut authed_user: Option<PlainText> = None;
 
-        //todo: add test for auth
-        if self.um.is_some() {
+        if self.requires_auth() {
             let mut ok = false;
             for rh in r.headers.iter() {
-                if rh.head == PROXY_AUTH_HEADER_STR {
+                if rh.head.eq_ignore_ascii_case(PROXY_AUTH_HEADER_STR) {
                     if !rh.value.starts_with(BASIC_AUTH_VALUE_PREFIX) {
                         let e1 = anyhow::anyhow!(
                             "http proxy: auth value not start with BASIC_AUTH_VALUE_PREFIX: , {}",
@@ -153,14 +178,25 @@ impl Server {
 
                     let u = user::PlainText::new(
                         String::from_utf8_lossy(&bs[..colon_index]).to_string(),
-                        String::from_utf8_lossy(&bs[colon_index + 1..n]).to_string(),
+                        String::from_utf8_lossy(&bs[colon_index + 1..]).to_string(),
                     );
 
-                    if let Some(um) = &self.um {
-                        if let Some(u) = um.auth_user_by_authstr(u.auth_str()) {
-                            ok = true;
-                            authed_user = Some(u);
-                        };
+                    if let Some(u) = user::auth_plaintext(
+                        self.um.as_ref(),
+                        self.store.as_deref(),
+                        self.providers(),
+                        &u,
+                        client_ip,
+                    )
+                    .await
+                    {
+                        if let Err(e) = user::USER_LIMITS.check(&u.identity_str()) {
+                            let e = e
+                                .context(format!("http proxy: user {} rejected", u.identity_str()));
+                            return Ok(MapResult::ebc(e, buf, base));
+                        }
+                        ok = true;
+                        authed_user = Some(u);
                     }
 
                     break;
@@ -168,8 +204,9 @@ impl Server {
             } //for header
 
             if !ok {
-                let e1 = anyhow::anyhow!("http proxy: auth failed ,{:?}", &r);
-                return Ok(MapResult::ebc(e1, buf, base));
+                // 已经 回复, 不能 再 回落
+                let _ = base.write_all(AUTH_REQUIRED_REPLY_STR.as_bytes()).await;
+                anyhow::bail!("http proxy: auth failed ,{:?}", &r);
             }
         }
 
@@ -260,7 +297,9 @@ impl map::Map for Server {
     ) -> map::MapResult {
         match params.c {
             map::Stream::Conn(c) => {
-                let r = self.handshake(cid, c, params.b).await;
+                let r = self
+                    .handshake(cid, c, params.b, map::client_ip(&params.d))
+                    .await;
 
                 MapResult::from_result(r)
             }
@@ -12,7 +12,7 @@ use super::*;
 use crate::{
     map::{self, MapBox, MapExtFields, MapResult, ProxyBehavior, ToMapBox, CID},
     net::{self, Addr, Conn},
-    user::{self, AsyncUserAuthenticator, PlainText, UsersMap},
+    user::{self, PlainText, UserStore, UserTrait, UsersMap},
     utils::{buf_to_ob, io_error},
     Name,
 };
@@ -40,6 +40,12 @@ pub struct Config {
     pub support_udp: bool,
     pub user_whitespace_pass: Option<String>,
     pub user_passes: Option<Vec<PlainText>>,
+
+    /// 使用 的 运行时 用户 存储 的 名称, 见 [`user::store`]
+    pub user_store: Option<String>,
+
+    /// 是否 查询 全局 的 外部 鉴权 后端, 见 [`user::provider`]. 启用 后 即使 没有 用户 也 须 鉴权
+    pub use_auth_providers: bool,
 }
 
 impl ToMapBox for Config {
@@ -62,6 +68,8 @@ impl ToMapBox for Config {
 #[derive(Debug, Clone, MapExt)]
 pub struct Server {
     pub um: Option<UsersMap<PlainText>>,
+    pub store: Option<Arc<UserStore<PlainText>>>,
+    pub use_auth_providers: bool,
     pub support_udp: bool,
 }
 
@@ -96,15 +104,30 @@ impl Server {
         Server {
             support_udp: option.support_udp,
             um: if um.is_empty() { None } else { Some(um) },
+            store: option
+                .user_store
+                .map(|n| user::PLAINTEXT_USERS.get_or_create(&n)),
+            use_auth_providers: option.use_auth_providers,
             ext_fields: Some(MapExtFields::default()),
         }
     }
 
+    /// 配置了 用户, 使用了 UserStore, 或 启用了 外部 鉴权 后端 时 须 鉴权
+    pub fn requires_auth(&self) -> bool {
+        self.um.is_some() || self.store.is_some() || self.use_auth_providers
+    }
+
+    fn providers(&self) -> Option<&user::AuthProviders> {
+        self.use_auth_providers.then_some(&*user::AUTH_PROVIDERS)
+    }
+
+    /// client_ip 用于 限制 外部 鉴权 后端 的 失败 次数
     pub async fn handshake(
         &self,
         cid: CID,
         mut base: Conn,
         pre_read_data: Option<bytes::BytesMut>,
+        client_ip: Option<IpAddr>,
     ) -> anyhow::Result<map::MapResult> {
         /*
            todo:
@@ -171,7 +194,7 @@ impl Server {
         }
         let (mut authed, mut dealt_none, mut dealt_pass) = (false, false, false);
 
-        let server_has_user = self.um.is_some();
+        let server_has_user = self.requires_auth();
         let mut opt_e: Option<io::Error> = None;
 
         let mut remain_n = n - nmp2;
@@ -283,17 +306,30 @@ impl Server {
                     A STATUS field of X'00' indicates success. If the server returns a
                     `failure' (STATUS value other than X'00') status, it MUST close the connection.
                     */
-                    if let Some(um) = &self.um {
-                        if um.auth_user_by_authstr(this_up.auth_str()).is_some() {
-                            authed = true;
-                            opt_e = None;
+                    let mut limit_e = None;
+                    if user::auth_plaintext(
+                        self.um.as_ref(),
+                        self.store.as_deref(),
+                        self.providers(),
+                        &this_up,
+                        client_ip,
+                    )
+                    .await
+                    .is_some()
+                    {
+                        match user::USER_LIMITS.check(&this_up.identity_str()) {
+                            Ok(_) => {
+                                authed = true;
+                                opt_e = None;
 
-                            base.write_all(&[USERPASS_SUBNEGOTIATION_VERSION, SUCCESS])
-                                .await?;
+                                base.write_all(&[USERPASS_SUBNEGOTIATION_VERSION, SUCCESS])
+                                    .await?;
 
-                            the_user = Some(this_up);
+                                the_user = Some(this_up);
 
-                            break;
+                                break;
+                            }
+                            Err(e) => limit_e = Some(e),
                         }
                     }
 
@@ -303,7 +339,12 @@ impl Server {
                         .await;
 
                     buf.truncate(n);
-                    let e = anyhow!("socks5: auth failed, {}", this_up.auth_str());
+                    let e = match limit_e {
+                        Some(e) => {
+                            e.context(format!("socks5: user {} rejected", this_up.identity_str()))
+                        }
+                        None => anyhow!("socks5: auth failed, {}", this_up.auth_str()),
+                    };
                     return Ok(MapResult::ebc(e, buf, base));
                 }
                 _ => {} //忽视其它的 auth method
@@ -498,7 +539,9 @@ impl map::Map for Server {
     ) -> map::MapResult {
         match params.c {
             map::Stream::Conn(c) => {
-                let r = self.handshake(cid, c, params.b).await;
+                let r = self
+                    .handshake(cid, c, params.b, map::client_ip(&params.d))
+                    .await;
 
                 MapResult::from_result(r)
             }
@@ -44,6 +44,7 @@ async fn new_3user_socks5_inadder() -> Server {
         support_udp: false,
         user_whitespace_pass: Some("u0 p0".to_string()),
         user_passes: Some(vec![PlainText::new("u1".to_string(), "p1".to_string())]),
+        ..Default::default()
     })
     .await
 }
@@ -4,6 +4,8 @@ Defines a Map that can accept both socks5 and http proxy request.
 It will try socks5 first . If not socks5, fallbacks to http proxy
  */
 
+use std::net::IpAddr;
+
 use futures::executor::block_on;
 use macro_map::*;
 use map::Stream;
@@ -24,6 +26,12 @@ use super::{http_proxy, socks5, MapBox, MapExtFields, ToMapBox};
 pub struct Config {
     pub user_whitespace_pass: Option<String>,
     pub user_passes: Option<Vec<PlainText>>,
+
+    /// 使用 的 运行时 用户 存储 的 名称, 见 [`user::store`]
+    pub user_store: Option<String>,
+
+    /// 是否 查询 全局 的 外部 鉴权 后端, 见 [`user::provider`]. 启用 后 即使 没有 用户 也 须 鉴权
+    pub use_auth_providers: bool,
 }
 
 impl ToMapBox for Config {
@@ -70,14 +78,22 @@ impl Server {
             oum = Some(um);
         }
 
+        let store = option
+            .user_store
+            .map(|n| user::PLAINTEXT_USERS.get_or_create(&n));
+
         Server {
             http_s: http_proxy::Server {
                 um: oum.clone(),
+                store: store.clone(),
+                use_auth_providers: option.use_auth_providers,
                 only_connect: false,
                 ext_fields: Some(MapExtFields::default()),
             },
             socks5_s: socks5::server::Server {
                 um: oum,
+                store,
+                use_auth_providers: option.use_auth_providers,
                 support_udp: true, //默认打开udp 支持
                 ext_fields: Some(MapExtFields::default()),
             },
@@ -90,10 +106,11 @@ impl Server {
         cid: CID,
         base: Conn,
         pre_read_data: Option<bytes::BytesMut>,
+        client_ip: Option<IpAddr>,
     ) -> anyhow::Result<map::MapResult> {
         let r = self
             .socks5_s
-            .handshake(cid.clone(), base, pre_read_data)
+            .handshake(cid.clone(), base, pre_read_data, client_ip)
             .await?;
 
         if let Some(e) = &r.e {
@@ -107,7 +124,7 @@ impl Server {
                 };
                 debug!(cid = %cid, "try http proxy  ",);
 
-                let rr = self.http_s.handshake(cid, c, r.b).await?;
+                let rr = self.http_s.handshake(cid, c, r.b, client_ip).await?;
 
                 return Ok(rr);
             }
@@ -126,7 +143,9 @@ impl map::Map for Server {
     ) -> map::MapResult {
         match params.c {
             map::Stream::Conn(c) => {
-                let r = self.handshake(cid, c, params.b).await;
+                let r = self
+                    .handshake(cid, c, params.b, map::client_ip(&params.d))
+                    .await;
 
                 MapResult::from_result(r)
             }
@@ -192,3 +192,30 @@ fn test_clone_box_and_iter() {
 
     // cloning an iter is cheap
 }
+
+#[tokio::test]
+async fn fold_failed_map() {
+    use crate::map::{
+        detour::Detour,
+        fold::{fold, DynVecIterWrapper, FoldParams},
+        MapResult,
+    };
+
+    let maps: Vec<Arc<MapBox>> = vec![Arc::new(Box::new(Detour::new(
+        "unresolved".to_string(),
+        None,
+        Vec::new(),
+    )))];
+    let r = fold(FoldParams {
+        cid: CID::default(),
+        behavior: ProxyBehavior::ENCODE,
+        initial_state: MapResult::default(),
+        maps: Box::new(DynVecIterWrapper(maps.into_iter())),
+        chain_tag: String::new(),
+        #[cfg(feature = "trace")]
+        trace: Vec::new(),
+    })
+    .await;
+    assert!(r.e.is_some());
+    assert_eq!(r.failed_map.as_deref(), Some("detour"));
+}
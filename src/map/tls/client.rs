@@ -1,13 +1,18 @@
+use anyhow::{anyhow, bail, Context};
+use base64::{engine::general_purpose, Engine};
 use macro_map::{map_ext_fields, MapExt};
 use rustls::{
-    client::danger::ServerCertVerified,
+    client::{danger::ServerCertVerified, WantsClientCert, WebPkiServerVerifier},
+    crypto::WebPkiSupportedAlgorithms,
     pki_types::{CertificateDer, ServerName, UnixTime},
     server::WebPkiClientVerifier,
-    ClientConfig,
+    CertificateError, ClientConfig, ConfigBuilder, RootCertStore,
 };
+use sha2::{Digest, Sha256};
 use tokio::io::AsyncWriteExt;
 
 use self::{
+    fingerprint::ClientHelloProfile,
     map::{MapExt, MapExtFields, CID},
     net::Stream,
 };
@@ -28,10 +33,61 @@ impl<IO> crate::Name for tokio_rustls::client::TlsStream<IO> {
     }
 }
 
-fn default_cc() -> ClientConfig {
-    ClientConfig::builder()
-        .with_root_certificates(default_rcs())
-        .with_no_client_auth()
+fn with_client_auth(
+    b: ConfigBuilder<ClientConfig, WantsClientCert>,
+    opt: &ClientOptions,
+) -> anyhow::Result<ClientConfig> {
+    match (&opt.cert, &opt.key) {
+        (Some(c), Some(k)) => {
+            let certs = load::load_certs(c)?;
+            let key = load::load_keys(k)?;
+            Ok(b.with_client_auth_cert(certs, key)?)
+        }
+        (None, None) => Ok(b.with_no_client_auth()),
+        _ => bail!("tls client cert and key should be given together"),
+    }
+}
+
+fn default_cc(opt: &ClientOptions, roots: Arc<RootCertStore>) -> anyhow::Result<ClientConfig> {
+    with_client_auth(ClientConfig::builder().with_root_certificates(roots), opt)
+}
+
+fn profile_cc(
+    p: &ClientHelloProfile,
+    opt: &ClientOptions,
+    roots: Arc<RootCertStore>,
+) -> anyhow::Result<ClientConfig> {
+    let versions = p.protocol_versions()?;
+    let mut config = with_client_auth(
+        ClientConfig::builder_with_provider(Arc::new(p.to_provider()?))
+            .with_protocol_versions(&versions)?
+            .with_root_certificates(roots),
+        opt,
+    )?;
+
+    if let Some(sni) = p.resolve()?.enable_sni {
+        config.enable_sni = sni;
+    }
+    Ok(config)
+}
+
+/// webpki roots by default; the custom CA file and/or the system trust store if any of them is set
+fn root_store(opt: &ClientOptions) -> anyhow::Result<RootCertStore> {
+    if opt.ca.is_none() && !opt.use_system_roots {
+        return Ok(default_rcs());
+    }
+    let mut roots = RootCertStore::empty();
+    if let Some(ca) = &opt.ca {
+        for c in load::load_certs(ca)? {
+            roots.add(c)?;
+        }
+    }
+    if opt.use_system_roots {
+        let (ok, ignored) =
+            roots.add_parsable_certificates(rustls_native_certs::load_native_certs()?);
+        debug!(ok, ignored, "tls client loaded system roots");
+    }
+    Ok(roots)
 }
 
 #[derive(Debug, Default)]
@@ -39,13 +95,45 @@ pub struct ClientOptions {
     pub domain: String,
     pub is_insecure: bool,
     pub alpn: Option<Vec<String>>,
+
+    /// None means the default ClientHello of rustls
+    pub client_hello: Option<ClientHelloProfile>,
+
+    /// client certificate, for servers that verify clients
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+
+    /// CA bundle used to verify the server, instead of the webpki roots
+    pub ca: Option<PathBuf>,
+
+    /// use the trust store of the operating system, instead of the webpki roots
+    pub use_system_roots: bool,
+
+    /// base64 encoded sha256 of the server's SubjectPublicKeyInfo, the
+    /// `sha256//` prefix is allowed. The server cert must match one of them.
+    ///
+    /// if is_insecure is also true, only the pins are checked, which is the way to
+    /// verify a self-signed server
+    pub pinned_spki_sha256: Option<Vec<String>>,
 }
 
 impl Client {
+    /// panic if opt.client_hello is invalid. See [`Client::try_new`]
     pub fn new(opt: ClientOptions) -> Self {
-        let mut config = default_cc();
+        Self::try_new(opt).expect("tls client options valid")
+    }
+
+    pub fn try_new(opt: ClientOptions) -> anyhow::Result<Self> {
+        let roots = Arc::new(root_store(&opt)?);
+        let mut config = match &opt.client_hello {
+            Some(p) => profile_cc(p, &opt, roots.clone())?,
+            None => default_cc(&opt, roots.clone())?,
+        };
 
-        if opt.is_insecure {
+        if let Some(pins) = &opt.pinned_spki_sha256 {
+            let v = PinnedVer::new(pins, (!opt.is_insecure).then_some(roots))?;
+            config.dangerous().set_certificate_verifier(Arc::new(v));
+        } else if opt.is_insecure {
             config
                 .dangerous()
                 .set_certificate_verifier(Arc::new(SuperDanVer {}));
@@ -54,12 +142,12 @@ impl Client {
             config.alpn_protocols = a.iter().map(|s| s.as_bytes().to_vec()).collect()
         }
 
-        Client {
+        Ok(Client {
             domain: opt.domain,
             is_insecure: opt.is_insecure,
             client_config: Arc::new(config),
             ext_fields: Some(MapExtFields::default()),
-        }
+        })
     }
 }
 
@@ -110,6 +198,95 @@ impl rustls::client::danger::ServerCertVerifier for SuperDanVer {
     }
 }
 
+/// checks the SPKI sha256 of the server cert, after the normal webpki verification
+/// if roots is given
+#[derive(Debug)]
+struct PinnedVer {
+    pins: Vec<[u8; 32]>,
+    inner: Option<Arc<WebPkiServerVerifier>>,
+    algs: WebPkiSupportedAlgorithms,
+}
+
+impl PinnedVer {
+    fn new(pins: &[String], roots: Option<Arc<RootCertStore>>) -> anyhow::Result<Self> {
+        if pins.is_empty() {
+            bail!("pinned_spki_sha256 is empty")
+        }
+        let pins = pins
+            .iter()
+            .map(|p| {
+                let b = general_purpose::STANDARD
+                    .decode(p.trim_start_matches("sha256//"))
+                    .with_context(|| format!("pin {p} is not base64"))?;
+                <[u8; 32]>::try_from(b).map_err(|_| anyhow!("pin {p} is not a sha256"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let inner = match roots {
+            Some(r) => Some(WebPkiServerVerifier::builder(r).build()?),
+            None => None,
+        };
+        Ok(PinnedVer {
+            pins,
+            inner,
+            algs: rustls::crypto::ring::default_provider().signature_verification_algorithms,
+        })
+    }
+}
+
+/// sha256 of the SubjectPublicKeyInfo of the DER cert
+pub fn spki_sha256(der: &[u8]) -> anyhow::Result<[u8; 32]> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der)?;
+    Ok(Sha256::digest(cert.public_key().raw).into())
+}
+
+impl rustls::client::danger::ServerCertVerifier for PinnedVer {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(inner) = &self.inner {
+            inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        }
+        let h = spki_sha256(end_entity)
+            .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+        if self.pins.contains(&h) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            debug!("server cert doesn't match the pinned spki");
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algs)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algs)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.algs.supported_schemes()
+    }
+}
+
 // pub struct ClientTLSConnDescriber {}
 
 impl Client {
@@ -0,0 +1,306 @@
+/*!
+ClientHello shaping for the tls client.
+
+[`ClientHelloProfile`] 决定 tls client 发出的 ClientHello 的样子, 可以在每个 outbound 中单独配置.
+
+rustls 允许调整的部分有: cipher suite 的顺序, key exchange group 的顺序(第一个 group
+就是 TLS1.3 的 key share), 协议版本, 以及是否发送 SNI.
+
+extension 的顺序, GREASE 和 padding 由 rustls 在内部写入 ClientHello, 且 ClientHello
+的原始字节会进入 handshake transcript, 所以不能在发送后改写, profile 中也就没有这些字段.
+profile 拒绝 未知 字段, 以免 这类 配置 被 静默忽略. 因此 chrome/firefox preset 只 模仿 了
+cipher suite 与 group 部分, 其 JA3 与 真的 浏览器 不同; 完整 的 模仿 需要 另一个 tls 后端.
+
+[`parse_client_hello`] 解析一个 ClientHello record, 用于计算 JA3 指纹
+(see <https://github.com/salesforce/ja3>), 测试中用它与参考指纹进行比较.
+ */
+
+use anyhow::{anyhow, bail, Context};
+use rustls::{
+    crypto::{ring, CryptoProvider, SupportedKxGroup},
+    version::{TLS12, TLS13},
+    SupportedCipherSuite, SupportedProtocolVersion,
+};
+use serde::{Deserialize, Serialize};
+
+/// 描述 tls client 的 ClientHello.
+///
+/// 所有字段都为 None 时, 与 rustls 的默认 ClientHello 相同.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientHelloProfile {
+    /// use one of [`PRESETS`] as the base; other fields override it.
+    pub preset: Option<String>,
+
+    /// IANA names, like TLS13_AES_128_GCM_SHA256, in the order to be sent
+    pub cipher_suites: Option<Vec<String>>,
+
+    /// like X25519, secp256r1, secp384r1, in the order to be sent
+    pub kx_groups: Option<Vec<String>>,
+
+    /// "1.2" and/or "1.3"
+    pub versions: Option<Vec<String>>,
+
+    pub enable_sni: Option<bool>,
+}
+
+/// names of the builtin profiles
+pub const PRESETS: [&str; 3] = ["rustls", "chrome", "firefox"];
+
+impl ClientHelloProfile {
+    /// get a builtin profile by its name, see [`PRESETS`]
+    pub fn preset(name: &str) -> Option<Self> {
+        let strs = |v: &[&str]| Some(v.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+        match name {
+            "rustls" => Some(Self::default()),
+
+            // the subset of chrome's cipher suites and groups that rustls supports, in chrome's order
+            "chrome" => Some(Self {
+                cipher_suites: strs(&[
+                    "TLS13_AES_128_GCM_SHA256",
+                    "TLS13_AES_256_GCM_SHA384",
+                    "TLS13_CHACHA20_POLY1305_SHA256",
+                    "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+                    "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+                    "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384",
+                    "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+                    "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+                    "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256",
+                ]),
+                kx_groups: strs(&["X25519", "secp256r1", "secp384r1"]),
+                ..Default::default()
+            }),
+            "firefox" => Some(Self {
+                cipher_suites: strs(&[
+                    "TLS13_AES_128_GCM_SHA256",
+                    "TLS13_CHACHA20_POLY1305_SHA256",
+                    "TLS13_AES_256_GCM_SHA384",
+                    "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+                    "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+                    "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+                    "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256",
+                    "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384",
+                    "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+                ]),
+                kx_groups: strs(&["X25519", "secp256r1", "secp384r1"]),
+                ..Default::default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// fill the None fields with the ones of the preset
+    pub fn resolve(&self) -> anyhow::Result<Self> {
+        let Some(name) = &self.preset else {
+            return Ok(self.clone());
+        };
+        let base = Self::preset(name).ok_or_else(|| {
+            anyhow!(
+                "unknown ClientHello preset: {name}, supported presets: {:?}",
+                PRESETS
+            )
+        })?;
+        Ok(Self {
+            preset: None,
+            cipher_suites: self.cipher_suites.clone().or(base.cipher_suites),
+            kx_groups: self.kx_groups.clone().or(base.kx_groups),
+            versions: self.versions.clone().or(base.versions),
+            enable_sni: self.enable_sni.or(base.enable_sni),
+        })
+    }
+
+    /// the CryptoProvider whose cipher_suites and kx_groups follows the profile
+    pub fn to_provider(&self) -> anyhow::Result<CryptoProvider> {
+        let p = self.resolve()?;
+        let mut provider = ring::default_provider();
+
+        if let Some(names) = &p.cipher_suites {
+            provider.cipher_suites = names
+                .iter()
+                .map(|n| find_cipher_suite(n))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+        }
+        // rustls sends every suite of the provider, even if its version is disabled
+        let versions = p.protocol_versions()?;
+        provider
+            .cipher_suites
+            .retain(|s| versions.iter().any(|v| v.version == s.version().version));
+
+        if let Some(names) = &p.kx_groups {
+            provider.kx_groups = names
+                .iter()
+                .map(|n| find_kx_group(n))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+        }
+        Ok(provider)
+    }
+
+    pub fn protocol_versions(&self) -> anyhow::Result<Vec<&'static SupportedProtocolVersion>> {
+        let p = self.resolve()?;
+        match &p.versions {
+            None => Ok(vec![&TLS13, &TLS12]),
+            Some(vs) => vs
+                .iter()
+                .map(|v| match v.as_str() {
+                    "1.3" => Ok(&TLS13),
+                    "1.2" => Ok(&TLS12),
+                    _ => Err(anyhow!("unsupported tls version: {v}")),
+                })
+                .collect(),
+        }
+    }
+}
+
+fn find_cipher_suite(name: &str) -> anyhow::Result<SupportedCipherSuite> {
+    ring::ALL_CIPHER_SUITES
+        .iter()
+        .find(|s| format!("{:?}", s.suite()) == name)
+        .copied()
+        .ok_or_else(|| anyhow!("unsupported cipher suite: {name}"))
+}
+
+fn find_kx_group(name: &str) -> anyhow::Result<&'static dyn SupportedKxGroup> {
+    ring::ALL_KX_GROUPS
+        .iter()
+        .find(|g| format!("{:?}", g.name()) == name)
+        .copied()
+        .ok_or_else(|| anyhow!("unsupported kx group: {name}"))
+}
+
+/// the fields of a ClientHello that matter for fingerprinting
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientHelloInfo {
+    /// legacy_version in the ClientHello body
+    pub version: u16,
+    pub cipher_suites: Vec<u16>,
+
+    /// extension types, in the order they appear
+    pub extensions: Vec<u16>,
+    pub supported_groups: Vec<u16>,
+    pub ec_point_formats: Vec<u8>,
+    pub sni: Option<String>,
+    pub alpn: Vec<String>,
+}
+
+/// GREASE values (RFC 8701) are ignored by JA3
+pub fn is_grease(v: u16) -> bool {
+    v & 0x0f0f == 0x0a0a && v >> 8 == v & 0xff
+}
+
+impl ClientHelloInfo {
+    /// SSLVersion,Ciphers,Extensions,EllipticCurves,EllipticCurvePointFormats
+    pub fn ja3_string(&self) -> String {
+        fn join<T: ToString>(v: impl Iterator<Item = T>) -> String {
+            v.map(|x| x.to_string()).collect::<Vec<_>>().join("-")
+        }
+        let no_grease = |v: &&u16| !is_grease(**v);
+        format!(
+            "{},{},{},{},{}",
+            self.version,
+            join(self.cipher_suites.iter().filter(no_grease)),
+            join(self.extensions.iter().filter(no_grease)),
+            join(self.supported_groups.iter().filter(no_grease)),
+            join(self.ec_point_formats.iter()),
+        )
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> anyhow::Result<&'a [u8]> {
+        if self.buf.len() < n {
+            bail!("ClientHello truncated")
+        }
+        let (l, r) = self.buf.split_at(n);
+        self.buf = r;
+        Ok(l)
+    }
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+    fn u24(&mut self) -> anyhow::Result<usize> {
+        let b = self.take(3)?;
+        Ok(((b[0] as usize) << 16) | ((b[1] as usize) << 8) | b[2] as usize)
+    }
+    fn vec8(&mut self) -> anyhow::Result<&'a [u8]> {
+        let n = self.u8()? as usize;
+        self.take(n)
+    }
+    fn vec16(&mut self) -> anyhow::Result<&'a [u8]> {
+        let n = self.u16()? as usize;
+        self.take(n)
+    }
+    fn u16s(buf: &[u8]) -> Vec<u16> {
+        buf.chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect()
+    }
+}
+
+/// parse a tls record that contains a ClientHello
+pub fn parse_client_hello(record: &[u8]) -> anyhow::Result<ClientHelloInfo> {
+    let mut r = Reader { buf: record };
+    if r.u8()? != 22 {
+        bail!("not a handshake record")
+    }
+    r.u16()?;
+    let body = r.vec16()?;
+
+    let mut r = Reader { buf: body };
+    if r.u8()? != 1 {
+        bail!("not a ClientHello")
+    }
+    let len = r.u24()?;
+    let mut r = Reader { buf: r.take(len)? };
+
+    let mut info = ClientHelloInfo {
+        version: r.u16()?,
+        ..Default::default()
+    };
+    r.take(32).context("random")?;
+    r.vec8().context("session_id")?;
+    info.cipher_suites = Reader::u16s(r.vec16()?);
+    r.vec8().context("compression_methods")?;
+
+    if r.buf.is_empty() {
+        return Ok(info);
+    }
+    let mut exts = Reader { buf: r.vec16()? };
+    while !exts.buf.is_empty() {
+        let typ = exts.u16()?;
+        let data = exts.vec16()?;
+        info.extensions.push(typ);
+
+        let mut d = Reader { buf: data };
+        match typ {
+            0 => {
+                let mut list = Reader { buf: d.vec16()? };
+                while !list.buf.is_empty() {
+                    let name_type = list.u8()?;
+                    let name = list.vec16()?;
+                    if name_type == 0 {
+                        info.sni = Some(String::from_utf8_lossy(name).to_string());
+                    }
+                }
+            }
+            10 => info.supported_groups = Reader::u16s(d.vec16()?),
+            11 => info.ec_point_formats = d.vec8()?.to_vec(),
+            16 => {
+                let mut list = Reader { buf: d.vec16()? };
+                while !list.buf.is_empty() {
+                    info.alpn
+                        .push(String::from_utf8_lossy(list.vec8()?).to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(info)
+}
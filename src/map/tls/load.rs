@@ -2,8 +2,8 @@ use rustls::{
     pki_types::{
         CertificateDer, PrivateKeyDer, PrivatePkcs1KeyDer, PrivatePkcs8KeyDer, PrivateSec1KeyDer,
     },
-    server::NoClientAuth,
-    ServerConfig,
+    server::{danger::ClientCertVerifier, NoClientAuth, WebPkiClientVerifier},
+    RootCertStore, ServerConfig,
 };
 use std::{fs::File, path::PathBuf, sync::Arc};
 use tracing::debug;
@@ -11,20 +11,41 @@ use tracing::debug;
 use rustls_pemfile::{certs, read_one, Item};
 use std::io::{self, BufReader};
 
-use super::server::ServerOptions;
+use super::{server::ServerOptions, sni::SniResolver};
 
 pub fn load_ser_config(options: &ServerOptions) -> io::Result<ServerConfig> {
-    let c = options.cert.clone();
-    let certs = load_certs(&c)?;
-    debug_assert!(!certs.is_empty());
-    let k = options.key.clone();
-    let key = load_keys(&k)?;
-
-    //todo: we don't use client authentication yet
-    let mut config = rustls::ServerConfig::builder()
-        .with_client_cert_verifier(Arc::new(NoClientAuth))
-        .with_single_cert(certs, key)
-        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let verifier: Arc<dyn ClientCertVerifier> = match &options.client_ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            for c in load_certs(ca)? {
+                roots
+                    .add(c)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            }
+            WebPkiClientVerifier::builder(Arc::new(roots))
+                .build()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?
+        }
+        None => Arc::new(NoClientAuth),
+    };
+
+    let builder = rustls::ServerConfig::builder().with_client_cert_verifier(verifier);
+
+    let mut config = if options.sni_certs.is_empty() {
+        let certs = load_certs(&options.cert)?;
+        debug_assert!(!certs.is_empty());
+        let key = load_keys(&options.key)?;
+
+        builder
+            .with_single_cert(certs, key)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?
+    } else {
+        let default =
+            (!options.cert.as_os_str().is_empty()).then_some((&options.cert, &options.key));
+        let r = SniResolver::new(default, &options.sni_certs)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, format!("{:#}", err)))?;
+        builder.with_cert_resolver(Arc::new(r))
+    };
 
     if let Some(a) = &options.alpn {
         config.alpn_protocols = a.iter().map(|s| s.as_bytes().to_vec()).collect()
@@ -34,7 +55,7 @@ pub fn load_ser_config(options: &ServerOptions) -> io::Result<ServerConfig> {
 }
 
 /// Load the passed certificates file
-fn load_certs(path: &PathBuf) -> io::Result<Vec<CertificateDer<'static>>> {
+pub(crate) fn load_certs(path: &PathBuf) -> io::Result<Vec<CertificateDer<'static>>> {
     Ok(certs(&mut BufReader::new(File::open(path)?))
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{:?}", e)))?
         .into_iter()
@@ -42,7 +63,7 @@ fn load_certs(path: &PathBuf) -> io::Result<Vec<CertificateDer<'static>>> {
         .collect())
 }
 
-fn load_keys(path: &PathBuf) -> io::Result<PrivateKeyDer<'static>> {
+pub(crate) fn load_keys(path: &PathBuf) -> io::Result<PrivateKeyDer<'static>> {
     match read_one(&mut BufReader::new(File::open(path)?)) {
         Ok(Some(Item::PKCS8Key(data))) => {
             debug!("key type PKCS8Key");
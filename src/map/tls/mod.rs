@@ -6,7 +6,11 @@ uses rustls 0.22
 mod load;
 
 pub mod client;
+pub mod fingerprint;
 pub mod server;
+pub mod sni;
+
+pub use load::load_ser_config;
 
 #[cfg(test)]
 mod test;
@@ -17,7 +21,8 @@ pub mod test2;
 use async_trait::async_trait;
 use bytes::BytesMut;
 use rustls::pki_types::{Der, TrustAnchor};
-use std::{fmt, sync::Arc};
+use serde::{Deserialize, Serialize};
+use std::{fmt, mem, sync::Arc};
 use tokio_rustls::{TlsAcceptor, TlsConnector};
 use tracing::debug;
 
@@ -28,7 +33,7 @@ use crate::{
 };
 use std::path::PathBuf;
 
-use super::{MapResult, ProxyBehavior};
+use super::{Data, DataFlags, MapResult, ProxyBehavior};
 
 pub fn default_rcs() -> rustls::RootCertStore {
     let mut root_certs = rustls::RootCertStore::empty();
@@ -44,3 +49,144 @@ pub fn default_rcs() -> rustls::RootCertStore {
     );
     root_certs
 }
+
+/// returned as data by the tls [`server::Server`]
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct ServerConnInfo {
+    /// the server name sent by the client
+    pub sni: Option<String>,
+    pub alpn: Option<String>,
+
+    /// the verified client cert, if the Server requires one
+    pub user: Option<CertUser>,
+}
+
+#[typetag::serde]
+impl Data for ServerConnInfo {
+    fn get_user(&self) -> Option<Box<dyn crate::user::User>> {
+        self.user
+            .clone()
+            .map(|u| Box::new(u) as Box<dyn crate::user::User>)
+    }
+
+    fn take_user(&mut self) -> Option<Box<dyn crate::user::User>> {
+        self.user
+            .take()
+            .map(|u| Box::new(u) as Box<dyn crate::user::User>)
+    }
+
+    fn get_sni(&self) -> Option<String> {
+        self.sni.clone()
+    }
+
+    fn get_flags(&self) -> DataFlags {
+        let mut f = DataFlags::SNI;
+        if self.user.is_some() {
+            f |= DataFlags::User
+        }
+        f
+    }
+}
+
+/// the client certificate verified by the tls [`server::Server`]
+///
+/// identity 为 证书 subject 的 CN, 没有 CN 时取第一个 SAN.
+/// auth_str 为 x509:{identity}, 如 x509:client1
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct CertUser {
+    /// the whole subject, like CN=client1, O=org
+    pub subject: String,
+
+    /// DNS names, emails, URIs and IPs in the subjectAltName extension
+    pub sans: Vec<String>,
+
+    pub identity: String,
+    astr: String,
+}
+
+impl CertUser {
+    pub fn new(identity: &str) -> Self {
+        CertUser {
+            identity: identity.to_string(),
+            astr: format!("x509:{}", identity),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_der(der: &[u8]) -> anyhow::Result<Self> {
+        use x509_parser::prelude::*;
+
+        let (_, cert) = X509Certificate::from_der(der)?;
+
+        let mut sans = Vec::new();
+        if let Some(ext) = cert.subject_alternative_name()? {
+            for n in ext.value.general_names.iter() {
+                match n {
+                    GeneralName::DNSName(s) | GeneralName::RFC822Name(s) | GeneralName::URI(s) => {
+                        sans.push(s.to_string())
+                    }
+                    GeneralName::IPAddress(b) => {
+                        if let Ok(a) = <[u8; 4]>::try_from(*b) {
+                            sans.push(std::net::Ipv4Addr::from(a).to_string())
+                        } else if let Ok(a) = <[u8; 16]>::try_from(*b) {
+                            sans.push(std::net::Ipv6Addr::from(a).to_string())
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let cn = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(|s| s.to_string());
+
+        let subject = cert.subject().to_string();
+        let identity = cn
+            .or_else(|| sans.first().cloned())
+            .unwrap_or_else(|| subject.clone());
+
+        let mut u = CertUser::new(&identity);
+        u.subject = subject;
+        u.sans = sans;
+        Ok(u)
+    }
+}
+
+#[typetag::serde]
+impl Data for CertUser {
+    fn get_user(&self) -> Option<Box<dyn crate::user::User>> {
+        let ub = Box::new(self.clone());
+        Some(ub)
+    }
+
+    fn take_user(&mut self) -> Option<Box<dyn crate::user::User>> {
+        let ub = Box::new(mem::take(self));
+        Some(ub)
+    }
+    fn get_flags(&self) -> DataFlags {
+        DataFlags::User
+    }
+}
+
+#[typetag::serde]
+impl crate::user::UserTrait for CertUser {
+    fn identity_str(&self) -> String {
+        self.identity.clone()
+    }
+
+    fn identity_bytes(&self) -> &[u8] {
+        self.identity.as_bytes()
+    }
+
+    fn auth_str(&self) -> String {
+        self.astr.clone()
+    }
+
+    fn auth_bytes(&self) -> &[u8] {
+        self.astr.as_bytes()
+    }
+}
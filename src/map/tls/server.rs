@@ -1,4 +1,12 @@
+use std::{
+    io,
+    sync::Weak,
+    time::{Duration, SystemTime},
+};
+
 use macro_map::*;
+use parking_lot::{Mutex, RwLock};
+use tracing::{info, warn};
 
 use crate::map::{MapBox, ToMapBox};
 
@@ -12,6 +20,29 @@ pub struct ServerOptions {
     pub cert: PathBuf,
     pub key: PathBuf,
     pub alpn: Option<Vec<String>>,
+
+    /// CA bundle for verifying client certificates. If set, clients must present
+    /// a certificate signed by it, and the Server will return the verified
+    /// [`CertUser`] as data
+    pub client_ca: Option<PathBuf>,
+
+    /// if set, a background task checks the modification time of the cert/key files
+    /// once per interval, and reloads them if changed. If the new files can't be loaded,
+    /// the current cert is kept. Conns that have finished handshake are not affected
+    pub reload_interval: Option<Duration>,
+
+    /// more certs selected by the SNI of the client; cert/key above is the default
+    /// one. cert can be empty if a default cert is not wanted
+    pub sni_certs: Vec<sni::SniCert>,
+}
+
+fn file_mtimes(c: &ServerOptions) -> Vec<Option<SystemTime>> {
+    [Some(&c.cert), Some(&c.key), c.client_ca.as_ref()]
+        .into_iter()
+        .flatten()
+        .chain(c.sni_certs.iter().flat_map(|sc| [&sc.cert, &sc.key]))
+        .map(|p| std::fs::metadata(p).and_then(|m| m.modified()).ok())
+        .collect()
 }
 
 impl ToMapBox for ServerOptions {
@@ -26,7 +57,53 @@ impl ToMapBox for ServerOptions {
 #[derive(Clone, MapExt)]
 pub struct Server {
     pub option_cache: ServerOptions,
-    ta: TlsAcceptor,
+    ta: Arc<RwLock<TlsAcceptor>>,
+    reload_state: Arc<Mutex<ReloadState>>,
+}
+
+#[derive(Default)]
+struct ReloadState {
+    /// modification time of the files loaded by the current TlsAcceptor
+    mtimes: Vec<Option<SystemTime>>,
+
+    /// modification time of the files that failed to load, to warn only once for them
+    failed_mtimes: Option<Vec<Option<SystemTime>>>,
+
+    reloads: u64,
+}
+
+/// 检查 文件 是否 变化, 变化 则 重新加载; 加载 失败 时 保留 当前 的 证书
+fn check_files(c: &ServerOptions, ta: &RwLock<TlsAcceptor>, state: &Mutex<ReloadState>) {
+    let mtimes = file_mtimes(c);
+    {
+        let s = state.lock();
+        if mtimes == s.mtimes || s.failed_mtimes.as_ref() == Some(&mtimes) {
+            return;
+        }
+    }
+    debug!(?mtimes, "tls server cert files changed");
+
+    if let Err(e) = reload_into(c, ta, state) {
+        // maybe the files are being written, they will be tried again once changed
+        warn!(cert = ?c.cert, "tls server reload cert failed, keep the current one: {e}");
+        state.lock().failed_mtimes = Some(mtimes);
+    }
+}
+
+fn reload_into(
+    c: &ServerOptions,
+    ta: &RwLock<TlsAcceptor>,
+    state: &Mutex<ReloadState>,
+) -> io::Result<()> {
+    let mtimes = file_mtimes(c);
+    let config = load::load_ser_config(c)?;
+    *ta.write() = TlsAcceptor::from(Arc::new(config));
+    let mut s = state.lock();
+    s.mtimes = mtimes;
+    s.failed_mtimes = None;
+    s.reloads += 1;
+    info!(cert = ?c.cert, "tls server reloaded cert");
+    Ok(())
 }
 
 impl fmt::Debug for Server {
@@ -42,12 +119,55 @@ impl<IO> crate::Name for tokio_rustls::server::TlsStream<IO> {
 
 impl Server {
     pub fn new(c: ServerOptions) -> Self {
+        let mtimes = file_mtimes(&c);
         let config = load::load_ser_config(&c).expect("tls server config valid");
-        Server {
-            ta: TlsAcceptor::from(Arc::new(config)),
+        let s = Server {
+            ta: Arc::new(RwLock::new(TlsAcceptor::from(Arc::new(config)))),
+            reload_state: Arc::new(Mutex::new(ReloadState {
+                mtimes,
+                ..Default::default()
+            })),
             option_cache: c.clone(),
             ext_fields: Some(MapExtFields::default()),
-        }
+        };
+        s.start_reloader();
+        s
+    }
+
+    /// load the cert/key files again, and use them for new handshakes.
+    ///
+    /// The current config is kept if loading fails.
+    pub fn reload(&self) -> io::Result<()> {
+        reload_into(&self.option_cache, &self.ta, &self.reload_state)
+    }
+
+    /// 成功 重新加载 的 次数
+    pub fn reloads(&self) -> u64 {
+        self.reload_state.lock().reloads
+    }
+
+    /// 每隔 reload_interval 检查 一次 证书 文件, Server 的 所有 clone 都被 drop 后 退出
+    fn start_reloader(&self) {
+        let Some(interval) = self.option_cache.reload_interval else {
+            return;
+        };
+        let Ok(rt) = tokio::runtime::Handle::try_current() else {
+            warn!("tls server created outside of a tokio runtime, reload_interval is ignored");
+            return;
+        };
+        let interval = interval.max(Duration::from_millis(10));
+        let weak: Weak<RwLock<TlsAcceptor>> = Arc::downgrade(&self.ta);
+        let state = self.reload_state.clone();
+        let c = self.option_cache.clone();
+        rt.spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(ta) = weak.upgrade() else {
+                    break;
+                };
+                check_files(&c, &ta, &state);
+            }
+        });
     }
 
     async fn handshake(
@@ -64,10 +184,26 @@ impl Server {
             conn = Box::new(nc);
         }
 
-        let c = self.ta.accept(conn).await?;
+        let ta = self.ta.read().clone();
+        let c = ta.accept(conn).await?;
+
+        let sc = c.get_ref().1;
+        let user = match sc.peer_certificates() {
+            Some(certs) if !certs.is_empty() => Some(CertUser::from_der(&certs[0])?),
+            _ => None,
+        };
+        let info = ServerConnInfo {
+            sni: sc.server_name().map(|s| s.to_string()),
+            alpn: sc
+                .alpn_protocol()
+                .map(|a| String::from_utf8_lossy(a).to_string()),
+            user,
+        };
 
-        // todo add SeverTLSConnDescriber as data
-        Ok(MapResult::new_c(Box::new(c)).a(a).build())
+        Ok(MapResult::new_c(Box::new(c))
+            .a(a)
+            .d(Some(Box::new(info)))
+            .build())
     }
 }
 
@@ -0,0 +1,137 @@
+/*!
+Selects the server cert by the SNI of the ClientHello.
+
+匹配顺序: 完全匹配, 通配符匹配, 默认证书.
+
+通配符 *.example.com 只匹配一级子域名, 如 a.example.com, 不匹配 example.com 和 a.b.example.com
+ */
+
+use std::{collections::HashMap, fmt, path::PathBuf, sync::Arc};
+
+use anyhow::Context;
+use rustls::{
+    crypto::ring::sign::any_supported_type,
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+use serde::{Deserialize, Serialize};
+
+use super::load;
+
+/// a cert/key pair that is selected by SNI
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SniCert {
+    /// like www.example.com, *.example.com.
+    ///
+    /// if None, the DNS names in the subjectAltName and the CN of the cert are used
+    pub names: Option<Vec<String>>,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+pub struct SniResolver {
+    exact: HashMap<String, Arc<CertifiedKey>>,
+
+    /// key is the part after "*."
+    wildcard: HashMap<String, Arc<CertifiedKey>>,
+    default: Option<Arc<CertifiedKey>>,
+}
+
+impl fmt::Debug for SniResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SniResolver")
+            .field("exact", &self.exact.keys())
+            .field("wildcard", &self.wildcard.keys())
+            .field("has_default", &self.default.is_some())
+            .finish()
+    }
+}
+
+fn load_certified_key(cert: &PathBuf, key: &PathBuf) -> anyhow::Result<CertifiedKey> {
+    let certs = load::load_certs(cert)?;
+    let key = load::load_keys(key)?;
+    let key = any_supported_type(&key).with_context(|| format!("invalid key {:?}", key))?;
+    Ok(CertifiedKey::new(certs, key))
+}
+
+/// DNS names in subjectAltName, and the CN
+fn names_in_cert(ck: &CertifiedKey) -> anyhow::Result<Vec<String>> {
+    use x509_parser::prelude::*;
+
+    let der = ck.end_entity_cert()?;
+    let (_, cert) = X509Certificate::from_der(der)?;
+
+    let mut names: Vec<String> = Vec::new();
+    if let Some(ext) = cert.subject_alternative_name()? {
+        for n in ext.value.general_names.iter() {
+            if let GeneralName::DNSName(s) = n {
+                names.push(s.to_string())
+            }
+        }
+    }
+    for cn in cert.subject().iter_common_name() {
+        if let Ok(s) = cn.as_str() {
+            names.push(s.to_string())
+        }
+    }
+    Ok(names)
+}
+
+impl SniResolver {
+    /// default is used when no name matches, or the client doesn't send SNI
+    pub fn new(default: Option<(&PathBuf, &PathBuf)>, certs: &[SniCert]) -> anyhow::Result<Self> {
+        let mut r = SniResolver {
+            exact: HashMap::new(),
+            wildcard: HashMap::new(),
+            default: None,
+        };
+        if let Some((c, k)) = default {
+            r.default = Some(Arc::new(load_certified_key(c, k)?));
+        }
+        for sc in certs {
+            let ck = Arc::new(
+                load_certified_key(&sc.cert, &sc.key)
+                    .with_context(|| format!("load sni cert {:?} failed", sc.cert))?,
+            );
+            let names = match &sc.names {
+                Some(n) => n.clone(),
+                None => names_in_cert(&ck)?,
+            };
+            for n in names {
+                r.add(&n, ck.clone());
+            }
+        }
+        Ok(r)
+    }
+
+    /// the first added one wins if a name is added twice
+    pub fn add(&mut self, name: &str, ck: Arc<CertifiedKey>) {
+        let name = name.to_ascii_lowercase();
+        match name.strip_prefix("*.") {
+            Some(suffix) => self.wildcard.entry(suffix.to_string()).or_insert(ck),
+            None => self.exact.entry(name).or_insert(ck),
+        };
+    }
+
+    pub fn get(&self, sni: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        let Some(sni) = sni else {
+            return self.default.clone();
+        };
+        let sni = sni.to_ascii_lowercase();
+        if let Some(ck) = self.exact.get(&sni) {
+            return Some(ck.clone());
+        }
+        if let Some((_, parent)) = sni.split_once('.') {
+            if let Some(ck) = self.wildcard.get(parent) {
+                return Some(ck.clone());
+            }
+        }
+        self.default.clone()
+    }
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.get(client_hello.server_name())
+    }
+}
@@ -3,10 +3,11 @@ use std::{env::set_var, sync::Arc, time::Duration};
 use crate::{
     map::{
         tls::{self, client::ClientOptions},
-        Map, MapParams, CID,
+        Map, MapParams, MapResult, CID,
     },
     net::{self, gen_random_higher_port, helpers::MockTcpStream},
 };
+use base64::Engine;
 use futures::{join, FutureExt};
 use parking_lot::Mutex;
 use tokio::{
@@ -179,3 +180,443 @@ async fn tls_local_loopback() -> anyhow::Result<()> {
     info!("join end, result: {:?}", r);
     Ok(())
 }
+
+/// run the client handshake against a mock conn, and return the ClientHello it wrote
+async fn capture_client_hello(opt: ClientOptions) -> Vec<u8> {
+    let write_v = Arc::new(Mutex::new(Vec::new()));
+    let client_tcp_s = MockTcpStream {
+        read_data: Vec::new(),
+        write_data: Vec::new(),
+        write_target: Some(write_v.clone()),
+    };
+
+    let a = tls::client::Client::new(opt);
+    let r = a
+        .maps(
+            CID::default(),
+            ProxyBehavior::ENCODE,
+            MapParams::new(Box::new(client_tcp_s)),
+        )
+        .await;
+    assert!(r.e.is_some(), "mock conn has no server hello");
+
+    let v = write_v.lock().clone();
+    v
+}
+
+#[tokio::test]
+async fn client_hello_fingerprint() -> anyhow::Result<()> {
+    use super::fingerprint::{parse_client_hello, ClientHelloProfile};
+
+    let default_ch = capture_client_hello(ClientOptions {
+        domain: "www.example.com".to_string(),
+        ..Default::default()
+    })
+    .await;
+    let info = parse_client_hello(&default_ch)?;
+    assert_eq!(info.sni.as_deref(), Some("www.example.com"));
+    assert_eq!(
+        info.ja3_string(),
+        "771,4866-4865-4867-49196-49195-52393-49200-49199-52392-255,\
+        43-11-10-13-23-5-0-51-45-35,29-23-24,0"
+    );
+
+    let chrome_ch = capture_client_hello(ClientOptions {
+        domain: "www.example.com".to_string(),
+        alpn: Some(vec!["h2".to_string(), "http/1.1".to_string()]),
+        client_hello: ClientHelloProfile::preset("chrome"),
+        ..Default::default()
+    })
+    .await;
+    let info = parse_client_hello(&chrome_ch)?;
+    assert_eq!(info.alpn, vec!["h2", "http/1.1"]);
+    assert_eq!(
+        info.ja3_string(),
+        "771,4865-4866-4867-49195-49199-49196-49200-52393-52392-255,\
+        43-11-10-13-23-5-0-51-45-16-35,29-23-24,0"
+    );
+
+    let tls13_only = capture_client_hello(ClientOptions {
+        domain: "www.example.com".to_string(),
+        client_hello: Some(ClientHelloProfile {
+            preset: Some("firefox".to_string()),
+            kx_groups: Some(vec!["secp256r1".to_string()]),
+            versions: Some(vec!["1.3".to_string()]),
+            enable_sni: Some(false),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+    .await;
+    let info = parse_client_hello(&tls13_only)?;
+    assert_eq!(info.sni, None);
+    // 255 is TLS_EMPTY_RENEGOTIATION_INFO_SCSV, always appended by rustls
+    assert_eq!(info.cipher_suites, vec![4865, 4867, 4866, 255]);
+    assert_eq!(info.supported_groups, vec![23]);
+
+    Ok(())
+}
+
+#[test]
+fn client_hello_profile_unsupported() {
+    use super::fingerprint::ClientHelloProfile;
+
+    for p in [
+        ClientHelloProfile {
+            preset: Some("no_such_preset".to_string()),
+            ..Default::default()
+        },
+        ClientHelloProfile {
+            cipher_suites: Some(vec!["TLS_RSA_WITH_RC4_128_SHA".to_string()]),
+            ..Default::default()
+        },
+    ] {
+        assert!(tls::client::Client::try_new(ClientOptions {
+            domain: "www.example.com".to_string(),
+            client_hello: Some(p),
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
+
+/// spki sha256 of resource/test.crt
+const TEST_CRT_PIN: &str = "nh3X+2TUzdDqFvntdEH4Uf+iYgU3whi4iacDxiDEPp4=";
+
+/// spki sha256 of resource/test_server.crt
+const TEST_SERVER_CRT_PIN: &str = "dMnLH8KF4j/hUrq3Oy7oV3DFqjWUY4W6Cseef6NmQic=";
+
+fn res(f: &str) -> PathBuf {
+    PathBuf::from(format!("{}/resource/{f}", env!("CARGO_MANIFEST_DIR")))
+}
+
+/// handshake over a local tcp conn, returns the MapResults of the client and the server
+async fn loopback(
+    so: tls::server::ServerOptions,
+    co: ClientOptions,
+) -> anyhow::Result<(MapResult, MapResult)> {
+    loopback_with(tls::server::Server::new(so), co).await
+}
+
+async fn loopback_with(
+    server: tls::server::Server,
+    co: ClientOptions,
+) -> anyhow::Result<(MapResult, MapResult)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let laddr = listener.local_addr()?;
+
+    let sh = task::spawn(async move {
+        let (nc, _) = listener.accept().await.unwrap();
+        server
+            .maps(
+                CID::default(),
+                ProxyBehavior::DECODE,
+                MapParams::new(Box::new(nc)),
+            )
+            .await
+    });
+
+    let client = tls::client::Client::try_new(co)?;
+    let mut cr = client
+        .maps(
+            CID::default(),
+            ProxyBehavior::ENCODE,
+            MapParams::new(Box::new(TcpStream::connect(laddr).await?)),
+        )
+        .await;
+
+    // in tls1.3 the client finishes its handshake before the server checks the client cert
+    if let map::Stream::Conn(c) = &mut cr.c {
+        let _ = c.write_all(b"hello").await;
+        let _ = c.flush().await;
+    }
+
+    let sr = sh.await?;
+    Ok((cr, sr))
+}
+
+/// returns the MapResult of the server
+async fn mtls_loopback(client_cert: bool) -> anyhow::Result<MapResult> {
+    let (_, sr) = loopback(
+        tls::server::ServerOptions {
+            cert: res("test.crt"),
+            key: res("test.key"),
+            client_ca: Some(res("test_client_ca.crt")),
+            ..Default::default()
+        },
+        ClientOptions {
+            domain: "www.mytest.com".to_string(),
+            is_insecure: true,
+            cert: client_cert.then(|| res("test_client.crt")),
+            key: client_cert.then(|| res("test_client.key")),
+            ..Default::default()
+        },
+    )
+    .await?;
+    Ok(sr)
+}
+
+#[tokio::test]
+async fn mtls_client_cert_user() -> anyhow::Result<()> {
+    let r = mtls_loopback(true).await?;
+    assert!(r.e.is_none(), "{:?}", r.e);
+
+    let d = r.d.expect("server returns the cert user");
+    let u = d.get_user().expect("is user");
+    assert_eq!(u.identity_str(), "client1");
+    assert_eq!(u.auth_str(), "x509:client1");
+
+    let r = mtls_loopback(false).await?;
+    assert!(r.e.is_some());
+    Ok(())
+}
+
+#[test]
+fn cert_user_from_der() -> anyhow::Result<()> {
+    let c = load::load_certs(&res("test_client.crt"))?;
+    let u = CertUser::from_der(&c[0])?;
+    assert_eq!(u.subject, "CN=client1");
+    assert_eq!(u.sans, vec!["client1.ruci.test", "client1@ruci.test"]);
+    assert_eq!(u, {
+        let mut u2 = CertUser::new("client1");
+        u2.subject = u.subject.clone();
+        u2.sans = u.sans.clone();
+        u2
+    });
+    Ok(())
+}
+
+/// returns whether the client accepts the server cert
+async fn verify_server(
+    server_cert: &str,
+    server_key: &str,
+    co: ClientOptions,
+) -> anyhow::Result<bool> {
+    let (cr, _) = loopback(
+        tls::server::ServerOptions {
+            cert: res(server_cert),
+            key: res(server_key),
+            ..Default::default()
+        },
+        ClientOptions {
+            domain: "www.mytest.com".to_string(),
+            ..co
+        },
+    )
+    .await?;
+    Ok(cr.e.is_none())
+}
+
+#[tokio::test]
+async fn custom_ca_and_pinning() -> anyhow::Result<()> {
+    let c = load::load_certs(&res("test_server.crt"))?;
+    assert_eq!(
+        base64::engine::general_purpose::STANDARD.encode(client::spki_sha256(&c[0])?),
+        TEST_SERVER_CRT_PIN
+    );
+
+    let ca = || Some(res("test_server_ca.crt"));
+    let pins = |p: &str| Some(vec![p.to_string()]);
+
+    assert!(!verify_server("test_server.crt", "test_server.key", Default::default()).await?);
+    assert!(
+        verify_server(
+            "test_server.crt",
+            "test_server.key",
+            ClientOptions {
+                ca: ca(),
+                ..Default::default()
+            }
+        )
+        .await?
+    );
+    assert!(
+        verify_server(
+            "test_server.crt",
+            "test_server.key",
+            ClientOptions {
+                ca: ca(),
+                pinned_spki_sha256: pins(&format!("sha256//{TEST_SERVER_CRT_PIN}")),
+                ..Default::default()
+            }
+        )
+        .await?
+    );
+    assert!(
+        !verify_server(
+            "test_server.crt",
+            "test_server.key",
+            ClientOptions {
+                ca: ca(),
+                pinned_spki_sha256: pins(TEST_CRT_PIN),
+                ..Default::default()
+            }
+        )
+        .await?
+    );
+
+    // self-signed: the pin alone is enough when is_insecure
+    assert!(
+        verify_server(
+            "test.crt",
+            "test.key",
+            ClientOptions {
+                is_insecure: true,
+                pinned_spki_sha256: pins(TEST_CRT_PIN),
+                ..Default::default()
+            }
+        )
+        .await?
+    );
+    assert!(
+        !verify_server(
+            "test.crt",
+            "test.key",
+            ClientOptions {
+                is_insecure: true,
+                pinned_spki_sha256: pins(TEST_SERVER_CRT_PIN),
+                ..Default::default()
+            }
+        )
+        .await?
+    );
+    assert!(
+        !verify_server(
+            "test.crt",
+            "test.key",
+            ClientOptions {
+                pinned_spki_sha256: pins(TEST_CRT_PIN),
+                ..Default::default()
+            }
+        )
+        .await?
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn server_reload_cert() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("ruci_tls_reload_{}", rand::random::<u32>()));
+    std::fs::create_dir_all(&dir)?;
+    let (cert, key) = (dir.join("c.crt"), dir.join("c.key"));
+    std::fs::copy(res("test.crt"), &cert)?;
+    std::fs::copy(res("test.key"), &key)?;
+
+    let server = tls::server::Server::new(tls::server::ServerOptions {
+        cert: cert.clone(),
+        key: key.clone(),
+        reload_interval: Some(Duration::from_millis(20)),
+        ..Default::default()
+    });
+    let pinned = |p: &str| ClientOptions {
+        domain: "www.mytest.com".to_string(),
+        is_insecure: true,
+        pinned_spki_sha256: Some(vec![p.to_string()]),
+        ..Default::default()
+    };
+    let touch = |f: &std::path::Path, secs: u64| -> std::io::Result<()> {
+        std::fs::File::options()
+            .write(true)
+            .open(f)?
+            .set_modified(std::time::SystemTime::now() + Duration::from_secs(secs))
+    };
+
+    let (cr, _) = loopback_with(server.clone(), pinned(TEST_CRT_PIN)).await?;
+    assert!(cr.e.is_none());
+
+    // like certbot renewing the cert. reloaded in the background, without any new conn
+    std::fs::copy(res("test_server.crt"), &cert)?;
+    std::fs::copy(res("test_server.key"), &key)?;
+    touch(&cert, 1)?;
+    touch(&key, 1)?;
+    for _ in 0..100 {
+        if server.reloads() > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(server.reloads(), 1);
+
+    let (cr, _) = loopback_with(server.clone(), pinned(TEST_CRT_PIN)).await?;
+    assert!(cr.e.is_some());
+    let (cr, _) = loopback_with(server.clone(), pinned(TEST_SERVER_CRT_PIN)).await?;
+    assert!(cr.e.is_none());
+
+    // a broken file keeps the current cert
+    std::fs::write(&key, "not a key")?;
+    touch(&key, 2)?;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(server.reloads(), 1);
+    assert!(server.reload().is_err());
+    let (cr, _) = loopback_with(server.clone(), pinned(TEST_SERVER_CRT_PIN)).await?;
+    assert!(cr.e.is_none());
+
+    // fixed later
+    std::fs::copy(res("test.key"), &key)?;
+    std::fs::copy(res("test.crt"), &cert)?;
+    touch(&cert, 3)?;
+    touch(&key, 3)?;
+    for _ in 0..100 {
+        if server.reloads() > 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let (cr, _) = loopback_with(server, pinned(TEST_CRT_PIN)).await?;
+    assert!(cr.e.is_none());
+
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn sni_cert_selection() -> anyhow::Result<()> {
+    let server = tls::server::Server::new(tls::server::ServerOptions {
+        cert: res("test.crt"),
+        key: res("test.key"),
+        sni_certs: vec![sni::SniCert {
+            names: Some(vec!["*.example.com".to_string(), "Exact.test".to_string()]),
+            cert: res("test_server.crt"),
+            key: res("test_server.key"),
+        }],
+        ..Default::default()
+    });
+
+    for (sni, pin) in [
+        ("a.example.com", TEST_SERVER_CRT_PIN),
+        ("exact.test", TEST_SERVER_CRT_PIN),
+        ("a.b.example.com", TEST_CRT_PIN),
+        ("example.com", TEST_CRT_PIN),
+        ("other.test", TEST_CRT_PIN),
+    ] {
+        let (cr, sr) = loopback_with(
+            server.clone(),
+            ClientOptions {
+                domain: sni.to_string(),
+                is_insecure: true,
+                pinned_spki_sha256: Some(vec![pin.to_string()]),
+                ..Default::default()
+            },
+        )
+        .await?;
+        assert!(cr.e.is_none(), "{sni}: {:?}", cr.e);
+        assert_eq!(sr.d.expect("has data").get_sni().as_deref(), Some(sni));
+    }
+    Ok(())
+}
+
+#[test]
+fn sni_names_from_cert() -> anyhow::Result<()> {
+    let r = sni::SniResolver::new(
+        None,
+        &[sni::SniCert {
+            names: None,
+            cert: res("test_server.crt"),
+            key: res("test_server.key"),
+        }],
+    )?;
+    assert!(r.get(Some("www.mytest.com")).is_some());
+    assert!(r.get(Some("mytest.com")).is_none());
+    assert!(r.get(None).is_none());
+    Ok(())
+}
@@ -3,6 +3,7 @@ Implements <https://trojan-gfw.github.io/trojan/protocol> .
  */
 use std::{fmt, mem};
 
+use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha224};
 
@@ -31,6 +32,16 @@ pub const LF: u8 = 0x0a;
 
 const PASS_LEN: usize = 56;
 
+lazy_static! {
+    /// 运行时 可增删 的 trojan 用户, 见 [`user::store`]
+    pub static ref TROJAN_USERS: user::UserStores<User> = user::UserStores::default();
+}
+
+/// 是否 为 PASS_LEN 个 小写 hex 字符, 即 客户端 发送 的 密码 的 sha224
+pub fn is_trojan_hash(bs: &[u8]) -> bool {
+    bs.len() == PASS_LEN && bs.iter().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
 //https://stackoverflow.com/questions/27650312/show-u8-slice-in-hex-representation
 pub struct HexSlice<'a>(&'a [u8]);
 
@@ -90,6 +101,15 @@ impl User {
             astr: format!("trojan:{}", hex),
         }
     }
+
+    /// 只知道 密码 的 sha224 hex 时 使用, 如 通过 [`user::AuthProvider`] 鉴权 的 用户
+    pub fn from_hex(hex: &str) -> Self {
+        User {
+            plain_text_pass: String::new(),
+            hex: hex.to_string(),
+            astr: format!("trojan:{}", hex),
+        }
+    }
 }
 
 #[typetag::serde]
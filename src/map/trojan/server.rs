@@ -2,9 +2,11 @@ use super::*;
 use crate::{
     map::{self, Data, Map, MapBox, MapExtFields, MapResult, ToMapBox, CID},
     net::{self, helpers, Network},
-    user::{AsyncUserAuthenticator, UsersMap},
+    user::{self, AsyncUserAuthenticator, UserTrait, UsersMap},
     utils, Name,
 };
+use std::{net::IpAddr, sync::Arc};
+
 use anyhow::{anyhow, Context};
 use async_trait::async_trait;
 use bytes::{Buf, BytesMut};
@@ -17,6 +19,12 @@ use tracing::debug;
 pub struct Config {
     pub pass: Option<String>,
     pub passes: Option<Vec<String>>,
+
+    /// 使用 的 运行时 用户 存储 的 名称, 见 [`user::store`]
+    pub user_store: Option<String>,
+
+    /// 是否 查询 全局 的 外部 鉴权 后端, 见 [`user::provider`]. 启用 后 即使 没有 用户 也 须 鉴权
+    pub use_auth_providers: bool,
 }
 
 impl ToMapBox for Config {
@@ -30,6 +38,8 @@ impl ToMapBox for Config {
 #[derive(Debug, Clone, MapExt)]
 pub struct Server {
     pub um: UsersMap<User>,
+    pub store: Option<Arc<user::UserStore<User>>>,
+    pub use_auth_providers: bool,
 }
 impl Server {
     pub async fn new(option: Config) -> Self {
@@ -47,21 +57,28 @@ impl Server {
                 um.add_user(uup);
             }
         }
-        if um.is_empty() {
-            panic!("can't init a trojan server without any password");
+        let store = option.user_store.map(|n| TROJAN_USERS.get_or_create(&n));
+        if um.is_empty() && store.is_none() && !option.use_auth_providers {
+            panic!(
+                "can't init a trojan server without any password, user_store or use_auth_providers"
+            );
         }
 
         Server {
             um,
+            store,
+            use_auth_providers: option.use_auth_providers,
             ext_fields: Some(MapExtFields::default()),
         }
     }
 
+    /// client_ip 用于 限制 外部 鉴权 后端 的 失败 次数
     pub async fn handshake(
         &self,
         cid: CID,
         mut base: net::Conn,
         ob: Option<BytesMut>,
+        client_ip: Option<IpAddr>,
     ) -> anyhow::Result<MapResult> {
         //根据 https://www.ihcblog.com/a-better-tls-obfs-proxy/
         //trojan的 CRLF 是为了模拟http服务器的行为, 所以此时不要一次性Read, 而是要Read到CRLF为止
@@ -134,14 +151,39 @@ impl Server {
         let mut trojan_hash = String::from("trojan:");
         trojan_hash.push_str(&hash_str);
 
-        let opt_user = self.um.auth_user_by_authstr(&trojan_hash);
+        let mut opt_user = self.um.auth_user_by_authstr(&trojan_hash).or_else(|| {
+            self.store
+                .as_ref()
+                .and_then(|s| s.auth_user_by_authstr(&trojan_hash))
+        });
+
+        // 不是 hash 的 (如 要 回落 的 http 请求) 不 查询 外部 后端
+        if opt_user.is_none()
+            && self.use_auth_providers
+            && is_trojan_hash(&pass_part)
+            && user::AUTH_PROVIDERS
+                .verify(
+                    &user::Credential::TrojanHash(hash_str.to_string()),
+                    client_ip,
+                )
+                .await
+        {
+            opt_user = Some(User::from_hex(&hash_str));
+        }
 
-        if opt_user.is_none() {
+        let Some(u) = &opt_user else {
             return Ok(MapResult::ebc(
                 anyhow!("trojan hash not match, given hash_str is {}", hash_str),
                 buf,
                 base,
             ));
+        };
+        if let Err(e) = user::USER_LIMITS.check(&u.identity_str()) {
+            return Ok(MapResult::ebc(
+                e.context(format!("trojan user {} rejected", u.identity_str())),
+                buf,
+                base,
+            ));
         }
         let crlf = buf.get_u16();
         if crlf != CRLF {
@@ -270,7 +312,9 @@ impl Map for Server {
     ) -> MapResult {
         match params.c {
             map::Stream::Conn(c) => {
-                let r = self.handshake(cid, c, params.b).await;
+                let r = self
+                    .handshake(cid, c, params.b, map::client_ip(&params.d))
+                    .await;
                 MapResult::from_result(r)
             }
             _ => MapResult::err_str("trojan only support tcplike stream"),
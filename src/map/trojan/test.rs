@@ -24,6 +24,21 @@ fn test224() {
     ); //valid string generated from another trojan implementation.
 }
 
+#[test]
+fn trojan_hash() {
+    assert!(is_trojan_hash(
+        sha224_hex_string_lower_case("pass").as_bytes()
+    ));
+    assert!(!is_trojan_hash(
+        sha224_hex_string_lower_case("pass")
+            .to_uppercase()
+            .as_bytes()
+    ));
+    let http = b"GET / HTTP/1.1\r\nHost: www.example.com\r\nUser-Agent: curl/8\r\n";
+    assert!(!is_trojan_hash(&http[..PASS_LEN]));
+    assert!(!is_trojan_hash(b"abc"));
+}
+
 #[test]
 fn test224_print() {
     let str = sha224_hex_string_lower_case("pass3");
@@ -37,6 +52,7 @@ async fn new_3user_trojan_inadder() -> Server {
             "pass2".to_string(), //a2efc77b5d3c5e14ce7d0520115b32bba3426c1463d93d36a368fed7
             "pass3".to_string(), //aaae8f86690070b538d2fc141d6389dd9ce0e7d8e0a4d800384f9454
         ]),
+        ..Default::default()
     })
     .await
 }
@@ -124,6 +140,64 @@ async fn auth_tcp_in_mem_earlydata() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn runtime_user() -> anyhow::Result<()> {
+    let a = Server::new(Config {
+        user_store: Some("runtime_user".to_string()),
+        ..Default::default()
+    })
+    .await;
+    // 未 启用 存储 的 服务端 不受 影响
+    let other = new_3user_trojan_inadder().await;
+    let pass = "runtime_user_pass";
+
+    let handshake = |a: Server| async move {
+        let mut buf = BytesMut::with_capacity(100);
+        buf.put(sha224_hex_string_lower_case(pass).as_bytes());
+        buf.put_u16(CRLF);
+        buf.put_u8(CMD_CONNECT);
+        let addr = Addr::from_strs("tcp", "www.b", "", 43).unwrap();
+        net::helpers::addr_to_socks5_bytes(&addr, &mut buf);
+        buf.put_u16(CRLF);
+
+        let client_tcps = net::helpers::MockTcpStream {
+            read_data: buf.to_vec(),
+            write_data: Vec::new(),
+            write_target: None,
+        };
+        a.maps(
+            CID::default(),
+            ProxyBehavior::DECODE,
+            MapParams::new(Box::new(client_tcps)),
+        )
+        .await
+    };
+
+    // 启用 了 存储 的 服务端 即使 没有 用户 也 须 鉴权
+    assert!(handshake(a.clone()).await.e.is_some());
+
+    TROJAN_USERS
+        .get_or_create("runtime_user")
+        .add(User::new(pass));
+    let r = handshake(a.clone()).await;
+    assert!(r.e.is_none(), "{:?}", r.e);
+    assert!(handshake(other.clone()).await.e.is_some());
+
+    let other_store = Server::new(Config {
+        user_store: Some("runtime_user_other".to_string()),
+        ..Default::default()
+    })
+    .await;
+    assert!(handshake(other_store).await.e.is_some());
+
+    assert!(TROJAN_USERS
+        .get("runtime_user")
+        .unwrap()
+        .remove(&sha224_hex_string_lower_case(pass)));
+    assert!(handshake(a.clone()).await.e.is_some());
+    Ok(())
+}
+
 #[tokio::test]
 async fn udp() -> anyhow::Result<()> {
     //写两遍, 一遍错一遍对, 然后在 另一端写一遍. same as test for socks5 udp
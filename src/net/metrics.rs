@@ -0,0 +1,194 @@
+/*!
+握手 失败, 累加 耗时 与 出站 健康 的 统计, 供 api server 导出 为 prometheus 格式
+
+由 relay 在 有 [`super::GlobalTrafficRecorder`] 时 记录.
+*/
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use parking_lot::RwLock;
+
+/// 累加 耗时 直方图 的 上界, in seconds
+pub const FOLD_LATENCY_BOUNDS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// key 到 计数 的 映射
+#[derive(Debug, Default)]
+pub struct CountMap(RwLock<HashMap<String, Arc<AtomicU64>>>);
+
+impl CountMap {
+    pub fn inc(&self, key: &str) {
+        if let Some(c) = self.0.read().get(key) {
+            c.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.0
+            .write()
+            .entry(key.to_string())
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// sorted by key
+    pub fn snapshot(&self) -> BTreeMap<String, u64> {
+        self.0
+            .read()
+            .iter()
+            .map(|(k, c)| (k.clone(), c.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+/// 固定 上界 为 [`FOLD_LATENCY_BOUNDS`] 的 直方图
+#[derive(Debug, Default)]
+pub struct Histogram {
+    /// 每个 上界 的 计数, 不累积; 最后 一个 为 +Inf
+    buckets: [AtomicU64; FOLD_LATENCY_BOUNDS.len() + 1],
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+/// (累积的 bucket 计数, sum in seconds, count)
+pub type HistogramSnapshot = (Vec<(f64, u64)>, f64, u64);
+
+impl Histogram {
+    pub fn observe(&self, d: Duration) {
+        let secs = d.as_secs_f64();
+        let i = FOLD_LATENCY_BOUNDS
+            .iter()
+            .position(|b| secs <= *b)
+            .unwrap_or(FOLD_LATENCY_BOUNDS.len());
+        self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(d.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// bucket 为 (上界, 累积 计数), 最后 一个 上界 为 f64::INFINITY
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let mut acc = 0;
+        let buckets = FOLD_LATENCY_BOUNDS
+            .iter()
+            .chain(std::iter::once(&f64::INFINITY))
+            .zip(self.buckets.iter())
+            .map(|(b, c)| {
+                acc += c.load(Ordering::Relaxed);
+                (*b, acc)
+            })
+            .collect();
+        (
+            buckets,
+            self.sum_micros.load(Ordering::Relaxed) as f64 / 1e6,
+            self.count.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// 一个 出站 的 拨号 结果
+#[derive(Debug, Default)]
+pub struct OutboundStat {
+    pub ok: AtomicU64,
+    pub failed: AtomicU64,
+
+    /// 最近 一次 拨号 是否 成功
+    pub up: AtomicBool,
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// 入站 握手 失败 次数, key 为 出错 的 Map 的 name
+    pub in_failures: CountMap,
+
+    /// 出站 握手 失败 次数, key 为 出错 的 Map 的 name
+    pub out_failures: CountMap,
+
+    pub in_fold_latency: Histogram,
+    pub out_fold_latency: Histogram,
+
+    /// 按 出站 tag
+    outbounds: RwLock<HashMap<String, Arc<OutboundStat>>>,
+}
+
+impl Metrics {
+    pub fn record_outbound(&self, tag: &str, ok: bool) {
+        let s = self.outbounds.read().get(tag).cloned();
+        let s = match s {
+            Some(s) => s,
+            None => self
+                .outbounds
+                .write()
+                .entry(tag.to_string())
+                .or_default()
+                .clone(),
+        };
+        if ok {
+            s.ok.fetch_add(1, Ordering::Relaxed);
+        } else {
+            s.failed.fetch_add(1, Ordering::Relaxed);
+        }
+        s.up.store(ok, Ordering::Relaxed);
+    }
+
+    /// tag => (ok, failed, up), sorted by tag
+    pub fn outbounds(&self) -> BTreeMap<String, (u64, u64, bool)> {
+        self.outbounds
+            .read()
+            .iter()
+            .map(|(k, s)| {
+                (
+                    k.clone(),
+                    (
+                        s.ok.load(Ordering::Relaxed),
+                        s.failed.load(Ordering::Relaxed),
+                        s.up.load(Ordering::Relaxed),
+                    ),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn histogram() {
+        let h = Histogram::default();
+        h.observe(Duration::from_millis(1));
+        h.observe(Duration::from_millis(30));
+        h.observe(Duration::from_secs(20));
+
+        let (b, sum, count) = h.snapshot();
+        assert_eq!(count, 3);
+        assert!((sum - 20.031).abs() < 1e-6);
+        assert_eq!(b.len(), FOLD_LATENCY_BOUNDS.len() + 1);
+        assert_eq!(b[0], (0.005, 1));
+        assert_eq!(b[3], (0.05, 2));
+        assert_eq!(b[10], (10.0, 2));
+        assert_eq!(b[11], (f64::INFINITY, 3));
+    }
+
+    #[test]
+    fn outbounds() {
+        let m = Metrics::default();
+        m.record_outbound("o1", true);
+        m.record_outbound("o1", false);
+        m.record_outbound("o2", true);
+        m.in_failures.inc("socks5");
+        m.in_failures.inc("socks5");
+
+        let o = m.outbounds();
+        assert_eq!(o["o1"], (1, 1, false));
+        assert_eq!(o["o2"], (1, 0, true));
+        assert_eq!(m.in_failures.snapshot()["socks5"], 2);
+    }
+}
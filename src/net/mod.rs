@@ -12,6 +12,8 @@ pub mod dns;
 pub mod helpers;
 pub mod http;
 pub mod listen;
+pub mod metrics;
+pub mod traffic;
 pub mod udp;
 mod udp_fixed_listen;
 
@@ -410,6 +412,43 @@ pub struct GlobalTrafficRecorder {
 
     /// total uploaded bytes
     pub ub: AtomicU64,
+
+    /// 按 用户 统计 的 流量, key 为 [`crate::user::UserTrait::identity_str`]
+    pub user_traffic: traffic::TrafficMap,
+
+    /// 按 入站 tag 统计 的 流量
+    pub in_tag_traffic: traffic::TrafficMap,
+
+    /// 按 出站 tag 统计 的 流量
+    pub out_tag_traffic: traffic::TrafficMap,
+
+    /// 握手 失败, 累加 耗时 与 出站 健康
+    pub metrics: metrics::Metrics,
+
+    /// 正在 转发 的 连接
+    pub active_conns: Arc<crate::relay::ActiveConns>,
+}
+
+impl GlobalTrafficRecorder {
+    /// 一个 连接 要 累加到 的 所有 计数. 空的 tag 不计
+    pub fn get_traffic_counters(
+        &self,
+        user: Option<&str>,
+        in_tag: &str,
+        out_tag: &str,
+    ) -> Vec<Arc<traffic::Traffic>> {
+        let mut v = Vec::new();
+        if let Some(u) = user {
+            v.push(self.user_traffic.get_or_insert(u));
+        }
+        if !in_tag.is_empty() {
+            v.push(self.in_tag_traffic.get_or_insert(in_tag));
+        }
+        if !out_tag.is_empty() {
+            v.push(self.out_tag_traffic.get_or_insert(out_tag));
+        }
+        v
+    }
 }
 
 /// AsyncConn 将 可异步读写的功能抽象出来.
@@ -0,0 +1,270 @@
+/*!
+按 key (如 用户名, 入站/出站 的 tag) 分别 统计 流量
+
+[`TrafficConn`] 与 [`crate::map::counter::CounterConn`] 类似, 但 同时 累加到 多个 [`Traffic`] 上,
+且 读为 下载, 写为 上传, 所以 应包装 出站 的 流.
+*/
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use parking_lot::RwLock;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::{
+    addr_conn::{AddrConn, AddrReadTrait, AddrWriteTrait, AsyncReadAddr, AsyncWriteAddr},
+    Addr, Conn, Stream,
+};
+use crate::Name;
+
+/// 一组 上传/下载 计数
+#[derive(Debug, Default)]
+pub struct Traffic {
+    pub ub: AtomicU64,
+    pub db: AtomicU64,
+}
+
+impl Traffic {
+    /// (ub, db)
+    pub fn get(&self) -> (u64, u64) {
+        (
+            self.ub.load(Ordering::Relaxed),
+            self.db.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn reset(&self) {
+        self.ub.store(0, Ordering::Relaxed);
+        self.db.store(0, Ordering::Relaxed);
+    }
+}
+
+/// key 到 [`Traffic`] 的 映射
+///
+/// reset 只 清零 计数, 不移除 key, 以使 正在 进行的 连接 继续 计数
+#[derive(Debug, Default)]
+pub struct TrafficMap(RwLock<HashMap<String, Arc<Traffic>>>);
+
+impl TrafficMap {
+    pub fn get_or_insert(&self, key: &str) -> Arc<Traffic> {
+        if let Some(t) = self.0.read().get(key) {
+            return t.clone();
+        }
+        self.0.write().entry(key.to_string()).or_default().clone()
+    }
+
+    /// (ub, db)
+    pub fn get(&self, key: &str) -> Option<(u64, u64)> {
+        self.0.read().get(key).map(|t| t.get())
+    }
+
+    /// key => (ub, db), sorted by key
+    pub fn snapshot(&self) -> BTreeMap<String, (u64, u64)> {
+        self.0
+            .read()
+            .iter()
+            .map(|(k, t)| (k.clone(), t.get()))
+            .collect()
+    }
+
+    /// returns false if the key isn't presented
+    pub fn reset(&self, key: &str) -> bool {
+        match self.0.read().get(key) {
+            Some(t) => {
+                t.reset();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn reset_all(&self) {
+        self.0.read().values().for_each(|t| t.reset());
+    }
+}
+
+fn add_all(counters: &[Arc<Traffic>], n: usize, is_upload: bool) {
+    for t in counters {
+        if is_upload {
+            t.ub.fetch_add(n as u64, Ordering::Relaxed);
+        } else {
+            t.db.fetch_add(n as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+/// 读 计入 db, 写 计入 ub
+pub struct TrafficConn {
+    base: Conn,
+    counters: Arc<[Arc<Traffic>]>,
+}
+
+impl AsyncRead for TrafficConn {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let previous_len = buf.filled().len();
+        let r = Pin::new(&mut self.base).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = &r {
+            add_all(&self.counters, buf.filled().len() - previous_len, false);
+        }
+        r
+    }
+}
+
+impl AsyncWrite for TrafficConn {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let r = Pin::new(&mut self.base).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = &r {
+            add_all(&self.counters, *n, true);
+        }
+        r
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.base).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.base).poll_shutdown(cx)
+    }
+}
+
+struct TrafficAddrReader {
+    base: Box<dyn AddrReadTrait>,
+    counters: Arc<[Arc<Traffic>]>,
+}
+
+impl Name for TrafficAddrReader {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+}
+
+impl AsyncReadAddr for TrafficAddrReader {
+    fn poll_read_addr(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, Addr)>> {
+        let r = Pin::new(&mut self.base).poll_read_addr(cx, buf);
+        if let Poll::Ready(Ok((n, _))) = &r {
+            add_all(&self.counters, *n, false);
+        }
+        r
+    }
+}
+
+struct TrafficAddrWriter {
+    base: Box<dyn AddrWriteTrait>,
+    counters: Arc<[Arc<Traffic>]>,
+}
+
+impl Name for TrafficAddrWriter {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+}
+
+impl AsyncWriteAddr for TrafficAddrWriter {
+    fn poll_write_addr(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        addr: &Addr,
+    ) -> Poll<io::Result<usize>> {
+        let r = Pin::new(&mut self.base).poll_write_addr(cx, buf, addr);
+        if let Poll::Ready(Ok(n)) = &r {
+            add_all(&self.counters, *n, true);
+        }
+        r
+    }
+
+    fn poll_flush_addr(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.base).poll_flush_addr(cx)
+    }
+
+    fn poll_close_addr(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.base).poll_close_addr(cx)
+    }
+}
+
+/// 给 Conn 或 AddrConn 加上 计数, 其它 Stream 原样返回
+pub fn count_stream(s: Stream, counters: Vec<Arc<Traffic>>) -> Stream {
+    if counters.is_empty() {
+        return s;
+    }
+    let counters: Arc<[Arc<Traffic>]> = counters.into();
+    match s {
+        Stream::Conn(base) => Stream::Conn(Box::new(TrafficConn { base, counters })),
+        Stream::AddrConn(ac) => {
+            let AddrConn {
+                r,
+                w,
+                default_write_to,
+                ..
+            } = ac;
+            let mut ac = AddrConn::new(
+                Box::new(TrafficAddrReader {
+                    base: r,
+                    counters: counters.clone(),
+                }),
+                Box::new(TrafficAddrWriter { base: w, counters }),
+            );
+            ac.default_write_to = default_write_to;
+            Stream::AddrConn(ac)
+        }
+        s => s,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn count() -> anyhow::Result<()> {
+        let m = TrafficMap::default();
+        let (c, mut s) = tokio::io::duplex(100);
+        let counters = vec![m.get_or_insert("u1"), m.get_or_insert("tag1")];
+        let Stream::Conn(mut c) = count_stream(Stream::Conn(Box::new(c)), counters) else {
+            panic!("should be conn")
+        };
+
+        c.write_all(b"hello").await?;
+        let mut buf = [0u8; 5];
+        s.read_exact(&mut buf).await?;
+        s.write_all(b"abc").await?;
+        c.read_exact(&mut buf[..3]).await?;
+
+        assert_eq!(m.get("u1"), Some((5, 3)));
+        assert_eq!(m.get("tag1"), Some((5, 3)));
+        assert_eq!(m.snapshot().len(), 2);
+
+        assert!(m.reset("u1"));
+        assert!(!m.reset("u2"));
+        assert_eq!(m.get("u1"), Some((0, 0)));
+
+        c.write_all(b"x").await?;
+        assert_eq!(m.get("u1"), Some((1, 0)));
+        m.reset_all();
+        assert_eq!(m.get("tag1"), Some((0, 0)));
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+/*!
+正在 转发 的 连接 的 登记表, 用于 api 查看 与 关闭 单个 连接
+
+连接 在 开始 转发 前 登记, 转发 结束 或 被 kill 后 注销
+*/
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::{broadcast, oneshot};
+
+use super::NewConnInfo;
+use crate::net::{traffic::Traffic, CID};
+
+/// 一个 正在 转发 的 连接
+#[derive(Debug)]
+pub struct ActiveConn {
+    pub info: NewConnInfo,
+
+    pub start: SystemTime,
+
+    /// 该连接 自己 的 上传/下载 计数, 实时 更新
+    pub traffic: Arc<Traffic>,
+
+    kill_tx: Mutex<Option<oneshot::Sender<()>>>,
+}
+
+#[derive(Debug)]
+pub struct ActiveConns {
+    conns: RwLock<BTreeMap<CID, Arc<ActiveConn>>>,
+
+    /// 注销 时 发出, 见 [`ActiveConns::subscribe_closed`]
+    closed_tx: broadcast::Sender<Arc<ActiveConn>>,
+}
+
+impl Default for ActiveConns {
+    fn default() -> Self {
+        Self {
+            conns: RwLock::default(),
+            closed_tx: broadcast::channel(256).0,
+        }
+    }
+}
+
+impl ActiveConns {
+    /// 登记 一个 连接, 返回 给 转发 用的 shutdown_rx.
+    ///
+    /// 转发 结束 时 drop 掉 shutdown_rx 即 注销; [`ActiveConns::kill`] 会 让
+    /// shutdown_rx 收到 消息, 并 注销
+    pub fn register(
+        self: &Arc<Self>,
+        info: NewConnInfo,
+        traffic: Arc<Traffic>,
+    ) -> oneshot::Receiver<()> {
+        let cid = info.cid.clone();
+        let (kill_tx, kill_rx) = oneshot::channel();
+        let (mut shutdown_tx, shutdown_rx) = oneshot::channel();
+
+        self.conns.write().insert(
+            cid.clone(),
+            Arc::new(ActiveConn {
+                info,
+                start: SystemTime::now(),
+                traffic,
+                kill_tx: Mutex::new(Some(kill_tx)),
+            }),
+        );
+
+        let conns = self.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = shutdown_tx.closed() => {}
+                r = kill_rx => {
+                    if r.is_ok() {
+                        let _ = shutdown_tx.send(());
+                    }
+                }
+            }
+            let c = conns.conns.write().remove(&cid);
+            if let Some(c) = c {
+                let _ = conns.closed_tx.send(c);
+            }
+        });
+        shutdown_rx
+    }
+
+    /// 关闭 一个 连接. 连接 不存在 时 返回 false
+    pub fn kill(&self, cid: &CID) -> bool {
+        let Some(c) = self.conns.read().get(cid).cloned() else {
+            return false;
+        };
+        let tx = c.kill_tx.lock().take();
+        match tx {
+            Some(tx) => tx.send(()).is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn get(&self, cid: &CID) -> Option<Arc<ActiveConn>> {
+        self.conns.read().get(cid).cloned()
+    }
+
+    /// 按 cid 排序, 只返回 不小于 from 的
+    pub fn list(&self, from: Option<&CID>) -> Vec<Arc<ActiveConn>> {
+        let m = self.conns.read();
+        match from {
+            Some(f) => m.range(f.clone()..).map(|(_, c)| c.clone()).collect(),
+            None => m.values().cloned().collect(),
+        }
+    }
+
+    /// 收到 注销 的 连接, 其 traffic 为 最终 流量
+    pub fn subscribe_closed(&self) -> broadcast::Receiver<Arc<ActiveConn>> {
+        self.closed_tx.subscribe()
+    }
+
+    pub fn len(&self) -> usize {
+        self.conns.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conns.read().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::net;
+
+    fn cid(s: &str) -> CID {
+        s.parse().unwrap()
+    }
+
+    fn info(id: &str) -> NewConnInfo {
+        NewConnInfo {
+            cid: cid(id),
+            in_tag: "in".to_string(),
+            out_tag: "out".to_string(),
+            target_addr: net::Addr::from_network_addr_url("127.0.0.1:80").unwrap(),
+            user: None,
+
+            #[cfg(feature = "trace")]
+            in_trace: Vec::new(),
+
+            #[cfg(feature = "trace")]
+            out_trace: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn register_kill() {
+        let conns = Arc::new(ActiveConns::default());
+        let mut closed = conns.subscribe_closed();
+        let rx1 = conns.register(info("1"), Arc::default());
+        let rx2 = conns.register(info("2"), Arc::default());
+        assert_eq!(conns.len(), 2);
+        assert_eq!(conns.list(Some(&cid("2"))).len(), 1);
+
+        assert!(conns.kill(&cid("1")));
+        assert!(!conns.kill(&cid("3")));
+        tokio::time::timeout(Duration::from_secs(1), rx1)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // 转发 结束
+        drop(rx2);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(conns.is_empty());
+
+        let mut closed_cids = vec![
+            closed.recv().await.unwrap().info.cid.clone(),
+            closed.recv().await.unwrap().info.cid.clone(),
+        ];
+        closed_cids.sort();
+        assert_eq!(closed_cids, vec![cid("1"), cid("2")]);
+    }
+}
@@ -9,40 +9,63 @@ use scopeguard::defer;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use tokio::io::AsyncWriteExt;
-use tracing::{debug, warn};
+use tokio::sync::oneshot;
+use tracing::{debug, info, warn};
 
 /// copy between two [`net::Conn`]
 ///
-/// non-blocking, spawns new task to do actual relay
+/// non-blocking, spawns new task to do actual relay.
+/// 收到 shutdown_rx 时 停止 转发 并 关闭 两端
 pub fn cp_conn(
     cid: CID,
     in_conn: net::Conn,
     out_conn: net::Conn,
     pre_read_data: Option<bytes::BytesMut>,
     gtr: Option<Arc<net::GlobalTrafficRecorder>>,
+    shutdown_rx: Option<oneshot::Receiver<()>>,
 
     #[cfg(feature = "trace")] updater: net::OptUpdater,
 ) {
-    match (pre_read_data, gtr) {
-        (None, None) => tokio::spawn(no_gtr_no_ed(cid, in_conn, out_conn)),
-        (None, Some(t)) => tokio::spawn(gtr_no_ed(
-            cid,
-            in_conn,
-            out_conn,
-            t,
-            #[cfg(feature = "trace")]
-            updater,
-        )),
-        (Some(ed), None) => tokio::spawn(no_gtr_ed(cid, in_conn, out_conn, ed)),
-        (Some(ed), Some(t)) => tokio::spawn(gtr_ed(
-            cid,
-            in_conn,
-            out_conn,
-            t,
-            ed,
-            #[cfg(feature = "trace")]
-            updater,
-        )),
+    let cid_c = cid.clone();
+    let f = async move {
+        match (pre_read_data, gtr) {
+            (None, None) => no_gtr_no_ed(cid, in_conn, out_conn).await,
+            (None, Some(t)) => {
+                gtr_no_ed(
+                    cid,
+                    in_conn,
+                    out_conn,
+                    t,
+                    #[cfg(feature = "trace")]
+                    updater,
+                )
+                .await
+            }
+            (Some(ed), None) => no_gtr_ed(cid, in_conn, out_conn, ed).await,
+            (Some(ed), Some(t)) => {
+                gtr_ed(
+                    cid,
+                    in_conn,
+                    out_conn,
+                    t,
+                    ed,
+                    #[cfg(feature = "trace")]
+                    updater,
+                )
+                .await
+            }
+        }
+    };
+    match shutdown_rx {
+        Some(rx) => tokio::spawn(async move {
+            tokio::select! {
+                _ = f => {}
+                _ = rx => {
+                    info!(cid = %cid_c, "relay got shutdown");
+                }
+            }
+        }),
+        None => tokio::spawn(f),
     };
 }
 
@@ -3,11 +3,13 @@ relay 包定义了一种转发逻辑, 但是它不是强制性的, 可用于参
 具体实现 中可以有不同的转发逻辑
 
 */
+pub mod active;
 mod cp_ac;
 mod cp_conn;
 pub mod record;
 pub mod route;
 
+pub use active::*;
 pub use cp_ac::*;
 pub use cp_conn::*;
 pub use record::*;
@@ -50,6 +52,7 @@ pub async fn handle_in_stream(
     };
 
     let cid_c = cid.clone();
+    let start = std::time::Instant::now();
     let listen_result = tokio::time::timeout(
         Duration::from_secs(READ_HANDSHAKE_TIMEOUT),
         fold::fold(FoldParams {
@@ -71,10 +74,19 @@ pub async fn handle_in_stream(
                 cid = %cid,
                 "fold inbound failed with io::Error, {e}"
             );
+            if let Some(gtr) = &gtr {
+                gtr.metrics.in_failures.inc("timeout");
+            }
 
             return Err(e.into());
         }
     };
+    if let Some(gtr) = &gtr {
+        gtr.metrics.in_fold_latency.observe(start.elapsed());
+        if let Some(m) = &listen_result.failed_map {
+            gtr.metrics.in_failures.inc(m);
+        }
+    }
 
     handle_in_fold_result(
         listen_result,
@@ -188,6 +200,7 @@ pub async fn handle_in_fold_result(
 
     let cid_c = cid.clone();
     let ta_clone = target_addr.clone();
+    let start = std::time::Instant::now();
     let dial_result =
         tokio::time::timeout(Duration::from_secs(READ_HANDSHAKE_TIMEOUT), async move {
             fold::fold(FoldParams {
@@ -211,11 +224,25 @@ pub async fn handle_in_fold_result(
         Ok(d) => d,
         Err(e) => {
             warn!(cid = %cid, is_fallback = is_fallback, "fold outbound timeout, {e}",);
+            if let Some(tr) = &tr {
+                tr.metrics.out_failures.inc("timeout");
+            }
             return Err(e.into());
         }
     };
     let cid = dial_result.id;
 
+    if let Some(tr) = &tr {
+        tr.metrics.out_fold_latency.observe(start.elapsed());
+        if let Some(m) = &dial_result.failed_map {
+            tr.metrics.out_failures.inc(m);
+        }
+        if !dial_result.chain_tag.is_empty() {
+            tr.metrics
+                .record_outbound(&dial_result.chain_tag, dial_result.e.is_none());
+        }
+    }
+
     if let Some(e) = dial_result.e {
         warn!(cid = %cid, is_fallback = is_fallback, "fold outbound failed, {:#}", e);
         return Err(e);
@@ -260,34 +287,65 @@ pub async fn handle_in_fold_result(
         }
     }
 
-    if let Some(r) = newc_recorder {
-        let cid = cid.clone();
+    let user = route::get_user_from_opt_data(&listen_result.d)
+        .await
+        .and_then(|uv| uv.0.first().map(|u| u.0.identity_str()));
 
-        r.send(NewConnInfo {
-            cid,
-            in_tag: listen_result.chain_tag,
-            out_tag: dial_result.chain_tag,
-            target_addr,
+    let mut out_stream = dial_result.c;
+    let mut conn_traffic = None;
+    if let Some(tr) = &tr {
+        let mut counters = tr.get_traffic_counters(
+            user.as_deref(),
+            &listen_result.chain_tag,
+            &dial_result.chain_tag,
+        );
+        let t = Arc::new(net::traffic::Traffic::default());
+        counters.push(t.clone());
+        conn_traffic = Some(t);
+        out_stream = net::traffic::count_stream(out_stream, counters);
+    }
+    if let Some(state) = user
+        .as_deref()
+        .and_then(|u| crate::user::USER_LIMITS.get(u))
+    {
+        debug!(cid = %cid, user = user.as_deref(), "user limited");
+        out_stream = crate::user::limit_stream(out_stream, state);
+    }
 
-            #[cfg(feature = "trace")]
-            in_trace: listen_result.trace,
+    let info = NewConnInfo {
+        cid: cid.clone(),
+        in_tag: listen_result.chain_tag,
+        out_tag: dial_result.chain_tag,
+        target_addr,
+        user,
 
-            #[cfg(feature = "trace")]
-            out_trace: dial_result.trace,
-        })
-        .await?;
+        #[cfg(feature = "trace")]
+        in_trace: listen_result.trace,
+
+        #[cfg(feature = "trace")]
+        out_trace: dial_result.trace,
+    };
+
+    let shutdown_rx = match (&tr, conn_traffic) {
+        (Some(tr), Some(t)) => Some(tr.active_conns.register(info.clone(), t)),
+        _ => None,
+    };
+
+    if let Some(r) = newc_recorder {
+        r.send(info).await?;
     }
 
     cp_stream(CpStreamArgs {
         cid,
         in_stream: listen_result.c,
-        out_stream: dial_result.c,
+        out_stream,
         ed: dial_result.b,
         first_target: dial_result.a,
         tr,
         no_timeout: dial_result.no_timeout,
         shutdown_in_rx: listen_result.shutdown_rx,
         shutdown_out_rx: dial_result.shutdown_rx,
+        shutdown_rx,
         #[cfg(feature = "trace")]
         updater,
     })
@@ -307,10 +365,36 @@ pub struct CpStreamArgs {
     pub shutdown_in_rx: Option<tokio::sync::oneshot::Receiver<()>>,
     pub shutdown_out_rx: Option<tokio::sync::oneshot::Receiver<()>>,
 
+    /// 关闭 整个 连接, 如 [`ActiveConns::kill`]
+    pub shutdown_rx: Option<tokio::sync::oneshot::Receiver<()>>,
+
     #[cfg(feature = "trace")]
     pub updater: net::OptUpdater,
 }
 
+/// 合并 两个 shutdown_rx, 任一 收到 即 关闭
+fn merge_shutdown_rx(
+    a: Option<tokio::sync::oneshot::Receiver<()>>,
+    b: Option<tokio::sync::oneshot::Receiver<()>>,
+) -> Option<tokio::sync::oneshot::Receiver<()>> {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let (mut tx, rx) = tokio::sync::oneshot::channel();
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = a => {}
+                    _ = b => {}
+                    _ = tx.closed() => return,
+                }
+                let _ = tx.send(());
+            });
+            Some(rx)
+        }
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
 /// copy between two [`Stream`]
 ///
 /// non-blocking,
@@ -323,6 +407,7 @@ pub async fn cp_stream(args: CpStreamArgs) {
     let tr = args.tr;
     let shutdown_in_rx = args.shutdown_in_rx;
     let shutdown_out_rx = args.shutdown_out_rx;
+    let shutdown_rx = args.shutdown_rx;
     let no_timeout = args.no_timeout;
 
     //todo 原计划是 add trace for udp, 但因为 trace 功能用得少, 就先搁置.
@@ -334,6 +419,7 @@ pub async fn cp_stream(args: CpStreamArgs) {
             o,
             ed,
             tr,
+            shutdown_rx,
             #[cfg(feature = "trace")]
             args.updater,
         ),
@@ -347,7 +433,7 @@ pub async fn cp_stream(args: CpStreamArgs) {
                 first_target,
                 gtr: tr,
                 no_timeout,
-                shutdown_ac_rx: shutdown_out_rx,
+                shutdown_ac_rx: merge_shutdown_rx(shutdown_out_rx, shutdown_rx),
             }));
         }
         (Stream::AddrConn(i), Stream::Conn(o)) => {
@@ -360,7 +446,7 @@ pub async fn cp_stream(args: CpStreamArgs) {
                 first_target,
                 gtr: tr,
                 no_timeout,
-                shutdown_ac_rx: shutdown_in_rx,
+                shutdown_ac_rx: merge_shutdown_rx(shutdown_in_rx, shutdown_rx),
             }));
         }
         (Stream::AddrConn(i), Stream::AddrConn(o)) => {
@@ -373,7 +459,7 @@ pub async fn cp_stream(args: CpStreamArgs) {
                 tr,
                 no_timeout,
                 shutdown_in_rx,
-                shutdown_out_rx,
+                shutdown_out_rx: merge_shutdown_rx(shutdown_out_rx, shutdown_rx),
             })
             .await;
         }
@@ -9,6 +9,9 @@ pub struct NewConnInfo {
     pub out_tag: String,
     pub target_addr: net::Addr,
 
+    /// 入站 鉴权 得到的 用户, 见 [`crate::user::UserTrait::identity_str`]
+    pub user: Option<String>,
+
     #[cfg(feature = "trace")]
     pub in_trace: Vec<String>,
 
@@ -23,6 +26,9 @@ impl std::fmt::Display for NewConnInfo {
             "{} {} -> {} => {} , ",
             self.cid, self.in_tag, self.out_tag, self.target_addr
         )?;
+        if let Some(u) = &self.user {
+            write!(f, "user: {u} , ")?;
+        }
         #[cfg(not(feature = "trace"))]
         return Ok(());
 
@@ -44,6 +50,7 @@ fn test() {
         in_tag: "in_tag1".to_string(),
         out_tag: "out_t1".to_string(),
         target_addr: net::Addr::from_network_addr_url("127.1.2.3:389").unwrap(),
+        user: Some("u1".to_string()),
 
         #[cfg(feature = "trace")]
         in_trace: Vec::new(),
@@ -65,6 +65,11 @@ pub async fn get_user_from_opt_data(adv: &[Option<Box<dyn Data>>]) -> Option<Use
     }
 }
 
+/// the first SNI in the data
+pub fn get_sni_from_opt_data(adv: &[Option<Box<dyn Data>>]) -> Option<String> {
+    adv.iter().flatten().find_map(|d| d.get_sni())
+}
+
 #[derive(Debug)]
 pub struct FixedOutSelector {
     pub default: DMIterBox,
@@ -148,6 +153,7 @@ impl OutSelector for TagOutSelector {
 /// 2. 从哪里进来的:    in_tag
 /// 3. 要到哪里去:      target_addr
 /// 4. 是否为 fallback
+/// 5. tls 握手中客户端发来的 sni
 ///
 #[derive(Hash, Debug, PartialEq, Eq, Default)]
 pub struct InboundInfo {
@@ -156,6 +162,9 @@ pub struct InboundInfo {
     pub in_tag: String,
     pub target_addr: net::Addr,
     pub is_fallback: bool,
+
+    /// [`InboundInfoOutSelector`] 是精确匹配, 不使用该项, 其总为 None
+    pub sni: Option<String>,
 }
 
 /// (k,v), v 为 out_tag, k 为 所有能对应 v的 rule 值的集合
@@ -195,6 +204,7 @@ impl OutSelector for InboundInfoOutSelector {
             target_addr: addr.clone(),
             users,
             is_fallback,
+            sni: None,
         };
         let mut out_tag: Option<String> = None;
         for rs in self.outbounds_ruleset_vec.iter() {
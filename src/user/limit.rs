@@ -0,0 +1,437 @@
+/*!
+用户 的 流量配额, 到期时间 与 限速
+
+限制 存在 全局的 [`static@USER_LIMITS`] 中, key 为 [`super::UserTrait::identity_str`].
+没有 限制 的 用户 不受影响.
+
+鉴权 Map 在 鉴权 成功后 调用 [`UserLimits::check`] 拒绝 过期 或 超额 的 用户;
+relay 用 [`limit_stream`] 包装 出站流, 以 限速, 并在 会话中 配额 用尽 或 到期 时 断开 连接.
+*/
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::bail;
+use lazy_static::lazy_static;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Sleep,
+};
+
+use crate::{
+    net::{
+        addr_conn::{AddrConn, AddrReadTrait, AddrWriteTrait, AsyncReadAddr, AsyncWriteAddr},
+        Addr, Conn, Stream,
+    },
+    Name,
+};
+
+lazy_static! {
+    pub static ref USER_LIMITS: UserLimits = UserLimits::default();
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserLimit {
+    /// 上传 与 下载 的 总 字节数
+    pub quota: Option<u64>,
+
+    /// unix timestamp, in seconds
+    pub expire_at: Option<u64>,
+
+    /// 每个方向 每秒 的 字节数
+    pub rate_limit: Option<u64>,
+}
+
+/// 令牌桶, 容量 为 一秒 的 流量
+#[derive(Debug, Default)]
+struct Bucket {
+    tokens: f64,
+    last: Option<Instant>,
+}
+
+impl Bucket {
+    /// 返回 需要 等待 的 时间
+    fn consume(&mut self, n: usize, rate: u64) -> Option<Duration> {
+        let rate = rate as f64;
+        let now = Instant::now();
+        self.tokens = match self.last {
+            Some(last) => (self.tokens + now.duration_since(last).as_secs_f64() * rate).min(rate),
+            None => rate,
+        };
+        self.last = Some(now);
+        self.tokens -= n as f64;
+        if self.tokens < 0.0 {
+            Some(Duration::from_secs_f64(-self.tokens / rate))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct UserState {
+    limit: RwLock<UserLimit>,
+
+    /// 已用的 流量
+    pub used: AtomicU64,
+
+    up: Mutex<Bucket>,
+    down: Mutex<Bucket>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl UserState {
+    pub fn limit(&self) -> UserLimit {
+        self.limit.read().clone()
+    }
+
+    /// 过期 或 配额 用尽 时 返回 Err
+    pub fn check(&self) -> anyhow::Result<()> {
+        let l = self.limit.read();
+        if let Some(e) = l.expire_at {
+            if now_secs() >= e {
+                bail!("user expired at {e}")
+            }
+        }
+        if let Some(q) = l.quota {
+            if self.used.load(Ordering::Relaxed) >= q {
+                bail!("user quota {q} exhausted")
+            }
+        }
+        Ok(())
+    }
+
+    /// 记录 n 字节, 返回 限速 所需 等待 的 时间
+    fn consume(&self, n: usize, is_upload: bool) -> Option<Duration> {
+        self.used.fetch_add(n as u64, Ordering::Relaxed);
+        let rate = self.limit.read().rate_limit.filter(|r| *r > 0)?;
+        let bucket = if is_upload { &self.up } else { &self.down };
+        bucket.lock().consume(n, rate)
+    }
+}
+
+/// identity_str => [`UserState`]
+#[derive(Debug, Default)]
+pub struct UserLimits(RwLock<HashMap<String, Arc<UserState>>>);
+
+impl UserLimits {
+    /// 设置 限制, 已用 流量 保持 不变
+    pub fn set(&self, id: &str, limit: UserLimit) {
+        let s = self.0.write().entry(id.to_string()).or_default().clone();
+        *s.limit.write() = limit;
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<UserState>> {
+        self.0.read().get(id).cloned()
+    }
+
+    /// returns false if the id isn't presented
+    pub fn remove(&self, id: &str) -> bool {
+        self.0.write().remove(id).is_some()
+    }
+
+    /// returns false if the id isn't presented
+    pub fn reset_used(&self, id: &str) -> bool {
+        match self.get(id) {
+            Some(s) => {
+                s.used.store(0, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 没有 限制 的 用户 返回 Ok
+    pub fn check(&self, id: &str) -> anyhow::Result<()> {
+        match self.get(id) {
+            Some(s) => s.check(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Throttle {
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl Throttle {
+    /// 等待 限速, 并 检查 配额 与 到期
+    fn poll_ready(&mut self, cx: &mut Context<'_>, state: &UserState) -> Poll<io::Result<()>> {
+        if let Some(s) = self.sleep.as_mut() {
+            if s.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.sleep = None;
+        }
+        Poll::Ready(state.check().map_err(io::Error::other))
+    }
+
+    fn consume(&mut self, state: &UserState, n: usize, is_upload: bool) {
+        if let Some(d) = state.consume(n, is_upload) {
+            self.sleep = Some(Box::pin(tokio::time::sleep(d)));
+        }
+    }
+}
+
+/// 读 为 下载, 写 为 上传
+struct LimitConn {
+    base: Conn,
+    state: Arc<UserState>,
+    r: Throttle,
+    w: Throttle,
+}
+
+impl AsyncRead for LimitConn {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if let Err(e) = futures::ready!(this.r.poll_ready(cx, &this.state)) {
+            return Poll::Ready(Err(e));
+        }
+        let previous_len = buf.filled().len();
+        let r = Pin::new(&mut this.base).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = &r {
+            this.r
+                .consume(&this.state, buf.filled().len() - previous_len, false);
+        }
+        r
+    }
+}
+
+impl AsyncWrite for LimitConn {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if let Err(e) = futures::ready!(this.w.poll_ready(cx, &this.state)) {
+            return Poll::Ready(Err(e));
+        }
+        let r = Pin::new(&mut this.base).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = &r {
+            this.w.consume(&this.state, *n, true);
+        }
+        r
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.base).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.base).poll_shutdown(cx)
+    }
+}
+
+struct LimitAddrReader {
+    base: Box<dyn AddrReadTrait>,
+    state: Arc<UserState>,
+    t: Throttle,
+}
+
+impl Name for LimitAddrReader {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+}
+
+impl AsyncReadAddr for LimitAddrReader {
+    fn poll_read_addr(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, Addr)>> {
+        let this = &mut *self;
+        if let Err(e) = futures::ready!(this.t.poll_ready(cx, &this.state)) {
+            return Poll::Ready(Err(e));
+        }
+        let r = Pin::new(&mut this.base).poll_read_addr(cx, buf);
+        if let Poll::Ready(Ok((n, _))) = &r {
+            this.t.consume(&this.state, *n, false);
+        }
+        r
+    }
+}
+
+struct LimitAddrWriter {
+    base: Box<dyn AddrWriteTrait>,
+    state: Arc<UserState>,
+    t: Throttle,
+}
+
+impl Name for LimitAddrWriter {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+}
+
+impl AsyncWriteAddr for LimitAddrWriter {
+    fn poll_write_addr(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        addr: &Addr,
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if let Err(e) = futures::ready!(this.t.poll_ready(cx, &this.state)) {
+            return Poll::Ready(Err(e));
+        }
+        let r = Pin::new(&mut this.base).poll_write_addr(cx, buf, addr);
+        if let Poll::Ready(Ok(n)) = &r {
+            this.t.consume(&this.state, *n, true);
+        }
+        r
+    }
+
+    fn poll_flush_addr(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.base).poll_flush_addr(cx)
+    }
+
+    fn poll_close_addr(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.base).poll_close_addr(cx)
+    }
+}
+
+/// 给 出站 的 Conn 或 AddrConn 加上 限制, 其它 Stream 原样返回
+pub fn limit_stream(s: Stream, state: Arc<UserState>) -> Stream {
+    match s {
+        Stream::Conn(base) => Stream::Conn(Box::new(LimitConn {
+            base,
+            state,
+            r: Throttle::default(),
+            w: Throttle::default(),
+        })),
+        Stream::AddrConn(ac) => {
+            let AddrConn {
+                r,
+                w,
+                default_write_to,
+                ..
+            } = ac;
+            let mut ac = AddrConn::new(
+                Box::new(LimitAddrReader {
+                    base: r,
+                    state: state.clone(),
+                    t: Throttle::default(),
+                }),
+                Box::new(LimitAddrWriter {
+                    base: w,
+                    state,
+                    t: Throttle::default(),
+                }),
+            );
+            ac.default_write_to = default_write_to;
+            Stream::AddrConn(ac)
+        }
+        s => s,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[test]
+    fn check() {
+        let ls = UserLimits::default();
+        assert!(ls.check("u1").is_ok());
+
+        ls.set(
+            "u1",
+            UserLimit {
+                expire_at: Some(1),
+                ..Default::default()
+            },
+        );
+        assert!(ls.check("u1").is_err());
+
+        ls.set(
+            "u1",
+            UserLimit {
+                quota: Some(10),
+                ..Default::default()
+            },
+        );
+        assert!(ls.check("u1").is_ok());
+        ls.get("u1").unwrap().used.store(10, Ordering::Relaxed);
+        assert!(ls.check("u1").is_err());
+
+        assert!(ls.reset_used("u1"));
+        assert!(ls.check("u1").is_ok());
+        assert!(ls.remove("u1"));
+        assert!(!ls.remove("u1"));
+    }
+
+    #[tokio::test]
+    async fn quota_cut() -> anyhow::Result<()> {
+        let state = Arc::new(UserState::default());
+        *state.limit.write() = UserLimit {
+            quota: Some(8),
+            ..Default::default()
+        };
+        let (c, mut s) = tokio::io::duplex(100);
+        let Stream::Conn(mut c) = limit_stream(Stream::Conn(Box::new(c)), state.clone()) else {
+            panic!("should be conn")
+        };
+
+        c.write_all(b"hello").await?;
+        s.write_all(b"abc").await?;
+        let mut buf = [0u8; 3];
+        c.read_exact(&mut buf).await?;
+        assert_eq!(state.used.load(Ordering::Relaxed), 8);
+
+        assert!(c.write_all(b"x").await.is_err());
+        assert!(c.read(&mut buf).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rate_limit() -> anyhow::Result<()> {
+        let state = Arc::new(UserState::default());
+        *state.limit.write() = UserLimit {
+            rate_limit: Some(1000),
+            ..Default::default()
+        };
+        let (c, mut s) = tokio::io::duplex(10000);
+        let Stream::Conn(mut c) = limit_stream(Stream::Conn(Box::new(c)), state) else {
+            panic!("should be conn")
+        };
+
+        let start = Instant::now();
+        // 桶中 初始 有 1000, 之后 每秒 1000
+        for _ in 0..3 {
+            c.write_all(&[0u8; 500]).await?;
+        }
+        c.write_all(b"x").await?;
+        assert!(start.elapsed() >= Duration::from_millis(450));
+
+        let mut buf = vec![0u8; 1501];
+        s.read_exact(&mut buf).await?;
+        Ok(())
+    }
+}
@@ -9,6 +9,13 @@ use dyn_clone::DynClone;
 use serde::{Deserialize, Serialize};
 use std::hash::Hash;
 
+pub mod limit;
+pub mod provider;
+pub mod store;
+pub use limit::*;
+pub use provider::*;
+pub use store::*;
+
 /// 用于用户鉴权
 #[typetag::serde]
 pub trait UserTrait: Debug + Send + Sync {
@@ -211,6 +218,25 @@ impl<T: UserTrait + Clone> UsersMap<T> {
         inner.a_map.insert(uc.auth_str(), uc);
     }
 
+    /// returns false if the user isn't presented
+    pub fn remove_user(&mut self, identity_str: &str) -> bool {
+        let inner = &mut self.m;
+        match inner.id_map.remove(identity_str) {
+            Some(u) => {
+                inner.a_map.remove(&u.auth_str());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// identity_str of all users, sorted
+    pub fn identities(&self) -> Vec<String> {
+        let mut v: Vec<_> = self.m.id_map.keys().cloned().collect();
+        v.sort();
+        v
+    }
+
     pub fn len(&self) -> usize {
         self.m.id_map.len()
     }
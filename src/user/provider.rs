@@ -0,0 +1,412 @@
+/*!
+外部 鉴权 后端
+
+[`AuthProvider`] 由 启用 了 外部 后端 (`use_auth_providers`) 的 服务端 Map 在 配置 中 的 用户
+与 [`super::UserStore`] 都 不匹配 时 调用, 按 顺序 尝试 全局 [`static@AUTH_PROVIDERS`] 中 的
+各个 后端, 有一个 通过 即 鉴权 成功. 未 启用 的 入站 不受 后端 影响.
+
+rucimp 中 有 用户文件, http webhook 与 sqlite 的 实现. 后端 可能 很慢 (bcrypt, 网络请求),
+所以 可用 [`CachedProvider`] 缓存 成功 的 结果.
+
+trojan 服务端 收到的 任何 请求 (包括 探测 与 要 回落 的 http 请求) 都 可能 被当作 凭证,
+所以 只 查询 [`AuthProvider::supports_trojan`] 的 后端. [`AuthProviders`] 先 查 各 后端 的
+成功 缓存, 再 短暂 缓存 失败 的 结果, 并 按 来源 (客户端 ip, 没有 时 为 用户名) 限制 每秒 失败 的
+次数, 超过 时 不再 为 该 来源 查询 后端, 直接 视为 不通过; 其它 来源 与 已缓存 的 用户 不受 影响.
+*/
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use parking_lot::{Mutex, RwLock};
+use tracing::warn;
+
+use crate::map::trojan::sha224_hex_string_lower_case;
+
+use super::{AsyncUserAuthenticator, PlainText, UserStore, UsersMap};
+
+lazy_static! {
+    pub static ref AUTH_PROVIDERS: AuthProviders = AuthProviders::default();
+}
+
+/// 客户端 给出的 凭证
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credential {
+    /// socks5 与 http
+    UserPass { user: String, pass: String },
+
+    /// trojan 只 发送 密码 的 sha224 hex
+    TrojanHash(String),
+}
+
+impl Credential {
+    /// 用作 缓存 的 key, 不直接 存 密码
+    fn cache_key(&self) -> String {
+        match self {
+            Credential::UserPass { user, pass } => {
+                sha224_hex_string_lower_case(&format!("plaintext:{user}\n{pass}"))
+            }
+            Credential::TrojanHash(h) => format!("trojan:{h}"),
+        }
+    }
+}
+
+#[async_trait]
+pub trait AuthProvider: Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    /// 凭证 无效 时 返回 Ok(false); 后端 出错 时 返回 Err
+    async fn verify(&self, c: &Credential) -> anyhow::Result<bool>;
+
+    /// 是否 可 验证 [`Credential::TrojanHash`]. 默认 不可, 此时 不会 用 trojan 的 凭证 查询
+    fn supports_trojan(&self) -> bool {
+        false
+    }
+
+    /// 凭证 是否 在 成功 缓存 中. 在 失败 限制 之前 查询, 所以 不能 慢
+    fn is_cached(&self, _c: &Credential) -> bool {
+        false
+    }
+}
+
+fn supports(p: &dyn AuthProvider, c: &Credential) -> bool {
+    match c {
+        Credential::UserPass { .. } => true,
+        Credential::TrojanHash(_) => p.supports_trojan(),
+    }
+}
+
+/// 缓存 通过 鉴权 的 凭证 ttl 时长, 失败 的 结果 不缓存
+#[derive(Debug)]
+pub struct CachedProvider {
+    inner: Box<dyn AuthProvider>,
+    ttl: Duration,
+    cache: Mutex<HashMap<String, Instant>>,
+}
+
+impl CachedProvider {
+    pub fn new(inner: Box<dyn AuthProvider>, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for CachedProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn verify(&self, c: &Credential) -> anyhow::Result<bool> {
+        let key = c.cache_key();
+        {
+            let mut cache = self.cache.lock();
+            match cache.get(&key) {
+                Some(t) if t.elapsed() < self.ttl => return Ok(true),
+                Some(_) => {
+                    cache.remove(&key);
+                }
+                None => {}
+            }
+        }
+        let ok = self.inner.verify(c).await?;
+        if ok {
+            let mut cache = self.cache.lock();
+            cache.retain(|_, t| t.elapsed() < self.ttl);
+            cache.insert(key, Instant::now());
+        }
+        Ok(ok)
+    }
+
+    fn supports_trojan(&self) -> bool {
+        self.inner.supports_trojan()
+    }
+
+    fn is_cached(&self, c: &Credential) -> bool {
+        self.cache
+            .lock()
+            .get(&c.cache_key())
+            .is_some_and(|t| t.elapsed() < self.ttl)
+    }
+}
+
+/// 失败 的 结果 的 缓存 时长
+pub const MISS_TTL: Duration = Duration::from_secs(10);
+
+/// 每个 来源 每秒 最多 失败 的 次数, 超过 后 该秒 内 不再 为 该 来源 查询 后端
+pub const MAX_MISSES_PER_SEC: u32 = 20;
+
+const MAX_MISS_ENTRIES: usize = 4096;
+
+/// 失败 限制 的 来源: 客户端 ip, 没有 时 为 用户名; trojan 没有 用户名, 共用 一个
+fn source_key(c: &Credential, from: Option<IpAddr>) -> String {
+    match (from, c) {
+        (Some(ip), _) => ip.to_string(),
+        (None, Credential::UserPass { user, .. }) => format!("user:{user}"),
+        (None, Credential::TrojanHash(_)) => String::from("trojan"),
+    }
+}
+
+/// 超过 上限 时 先 清理 过期 的, 仍 超过 则 清空
+fn bound<T>(m: &mut HashMap<String, T>, expired: impl Fn(&T) -> bool) {
+    if m.len() >= MAX_MISS_ENTRIES {
+        m.retain(|_, v| !expired(v));
+        if m.len() >= MAX_MISS_ENTRIES {
+            m.clear();
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Misses {
+    /// 失败 的 凭证
+    recent: HashMap<String, Instant>,
+
+    /// 每个 来源 当前 一秒 窗口 的 开始 时间 与 失败 次数
+    windows: HashMap<String, (Instant, u32)>,
+}
+
+impl Misses {
+    fn allow(&self, key: &str, source: &str) -> bool {
+        if self.recent.get(key).is_some_and(|t| t.elapsed() < MISS_TTL) {
+            return false;
+        }
+        match self.windows.get(source) {
+            Some((t, n)) if t.elapsed() < Duration::from_secs(1) => *n < MAX_MISSES_PER_SEC,
+            _ => true,
+        }
+    }
+
+    fn add(&mut self, key: String, source: String) {
+        bound(&mut self.windows, |(t, _)| {
+            t.elapsed() >= Duration::from_secs(1)
+        });
+        let w = self.windows.entry(source).or_insert((Instant::now(), 0));
+        if w.0.elapsed() >= Duration::from_secs(1) {
+            *w = (Instant::now(), 0);
+        }
+        w.1 += 1;
+
+        bound(&mut self.recent, |t| t.elapsed() >= MISS_TTL);
+        self.recent.insert(key, Instant::now());
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AuthProviders {
+    ps: RwLock<Vec<Arc<dyn AuthProvider>>>,
+    misses: Mutex<Misses>,
+}
+
+impl AuthProviders {
+    /// 替换 所有 后端
+    pub fn set(&self, v: Vec<Arc<dyn AuthProvider>>) {
+        *self.ps.write() = v;
+        *self.misses.lock() = Misses::default();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ps.read().is_empty()
+    }
+
+    /// 依次 尝试 支持 该 凭证 的 各 后端; 出错 的 后端 视为 不通过.
+    /// from 为 客户端 的 ip, 用于 限制 失败 次数
+    pub async fn verify(&self, c: &Credential, from: Option<IpAddr>) -> bool {
+        let v: Vec<_> = self
+            .ps
+            .read()
+            .iter()
+            .filter(|p| supports(p.as_ref(), c))
+            .cloned()
+            .collect();
+        if v.is_empty() {
+            return false;
+        }
+        if v.iter().any(|p| p.is_cached(c)) {
+            return true;
+        }
+        let key = c.cache_key();
+        let source = source_key(c, from);
+        if !self.misses.lock().allow(&key, &source) {
+            return false;
+        }
+        for p in v {
+            match p.verify(c).await {
+                Ok(true) => return true,
+                Ok(false) => {}
+                Err(e) => warn!(provider = p.name(), "auth provider failed: {e:#}"),
+            }
+        }
+        self.misses.lock().add(key, source);
+        false
+    }
+}
+
+/// socks5 与 http 服务端 的 鉴权: 依次 查 配置 中 的 用户, 入站 使用 的 [`UserStore`]
+/// 与 入站 启用 的 外部 后端 (即 [`static@AUTH_PROVIDERS`]). from 为 客户端 的 ip
+pub async fn auth_plaintext(
+    um: Option<&UsersMap<PlainText>>,
+    store: Option<&UserStore<PlainText>>,
+    providers: Option<&AuthProviders>,
+    u: &PlainText,
+    from: Option<IpAddr>,
+) -> Option<PlainText> {
+    let found = um
+        .and_then(|um| um.auth_user_by_authstr(u.auth_str()))
+        .or_else(|| store.and_then(|s| s.auth_user_by_authstr(u.auth_str())));
+    if found.is_some() {
+        return found;
+    }
+    let c = Credential::UserPass {
+        user: u.user.clone(),
+        pass: u.pass.clone(),
+    };
+    match providers {
+        Some(ps) => ps.verify(&c, from).await.then(|| u.clone()),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct CountProvider(AtomicUsize);
+
+    #[async_trait]
+    impl AuthProvider for CountProvider {
+        fn name(&self) -> &str {
+            "count"
+        }
+
+        async fn verify(&self, c: &Credential) -> anyhow::Result<bool> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            match c {
+                Credential::UserPass { user, pass } => {
+                    Ok((user == "u" || user == "v") && pass == "p")
+                }
+                Credential::TrojanHash(_) => anyhow::bail!("not supported"),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct Shared(Arc<CountProvider>);
+
+    #[async_trait]
+    impl AuthProvider for Shared {
+        fn name(&self) -> &str {
+            self.0.name()
+        }
+
+        async fn verify(&self, c: &Credential) -> anyhow::Result<bool> {
+            self.0.verify(c).await
+        }
+    }
+
+    #[tokio::test]
+    async fn cache() -> anyhow::Result<()> {
+        let count = Arc::new(CountProvider::default());
+        let p = CachedProvider::new(Box::new(Shared(count.clone())), Duration::from_secs(60));
+        let ok = Credential::UserPass {
+            user: "u".into(),
+            pass: "p".into(),
+        };
+        let wrong = Credential::UserPass {
+            user: "u".into(),
+            pass: "p2".into(),
+        };
+
+        assert!(p.verify(&ok).await?);
+        assert!(p.verify(&ok).await?);
+        assert_eq!(count.0.load(Ordering::Relaxed), 1);
+
+        assert!(!p.verify(&wrong).await?);
+        assert!(!p.verify(&wrong).await?);
+        assert_eq!(count.0.load(Ordering::Relaxed), 3);
+
+        let ps = AuthProviders::default();
+        ps.set(vec![Arc::new(p)]);
+        assert!(ps.verify(&ok, None).await);
+        assert!(!ps.verify(&Credential::TrojanHash("h".into()), None).await);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn misses() {
+        let count = Arc::new(CountProvider::default());
+        let n = || count.0.load(Ordering::Relaxed);
+        let ps = AuthProviders::default();
+        ps.set(vec![Arc::new(Shared(count.clone()))]);
+
+        // 不支持 trojan 的 后端 不会 被 查询
+        assert!(!ps.verify(&Credential::TrojanHash("h".into()), None).await);
+        assert_eq!(n(), 0);
+
+        let wrong = |i: u32| Credential::UserPass {
+            user: "u".into(),
+            pass: format!("w{i}"),
+        };
+        assert!(!ps.verify(&wrong(0), None).await);
+        assert!(!ps.verify(&wrong(0), None).await);
+        assert_eq!(n(), 1);
+
+        for i in 1..MAX_MISSES_PER_SEC * 2 {
+            assert!(!ps.verify(&wrong(i), None).await);
+        }
+        assert_eq!(n(), MAX_MISSES_PER_SEC as usize);
+    }
+
+    #[tokio::test]
+    async fn misses_per_source() {
+        let count = Arc::new(CountProvider::default());
+        let ps = AuthProviders::default();
+        ps.set(vec![Arc::new(CachedProvider::new(
+            Box::new(Shared(count.clone())),
+            Duration::from_secs(60),
+        ))]);
+        let up = |user: &str, pass: &str| Credential::UserPass {
+            user: user.into(),
+            pass: pass.into(),
+        };
+        let ip = |i: u8| Some(IpAddr::from([10, 0, 0, i]));
+
+        assert!(ps.verify(&up("u", "p"), ip(1)).await);
+
+        // 一个 来源 不断 给出 错误 的 密码
+        for i in 0..MAX_MISSES_PER_SEC * 2 {
+            assert!(!ps.verify(&up("u", &format!("w{i}")), ip(2)).await);
+        }
+        let n = count.0.load(Ordering::Relaxed);
+        assert_eq!(n, 1 + MAX_MISSES_PER_SEC as usize);
+
+        // 该 来源 被 限制, 但 已缓存 的 用户 仍可 通过, 即使 来自 同一 来源
+        assert!(!ps.verify(&up("v", "p"), ip(2)).await);
+        assert!(ps.verify(&up("u", "p"), ip(2)).await);
+
+        // 其它 来源 的 用户 不受 影响
+        assert!(ps.verify(&up("v", "p"), ip(3)).await);
+
+        // 没有 ip 时 按 用户名 限制
+        for i in 0..MAX_MISSES_PER_SEC * 2 {
+            assert!(!ps.verify(&up("x", &format!("w{i}")), None).await);
+        }
+        ps.set(vec![Arc::new(Shared(count.clone()))]);
+        for i in 0..MAX_MISSES_PER_SEC * 2 {
+            assert!(!ps.verify(&up("x", &format!("w{i}")), None).await);
+        }
+        assert!(ps.verify(&up("v", "p"), None).await);
+    }
+}
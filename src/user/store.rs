@@ -0,0 +1,129 @@
+/*!
+运行时 可修改 的 用户 存储
+
+配置 中 的 用户 在 加载时 就 固定 在 各个 Map 里. [`UserStore`] 则 可在 运行时 增删.
+
+UserStore 按 名称 区分, 须 由 入站 在 配置 中 给出 user_store 名称 来 使用, 名称 相同 的 入站
+共用 一个, 其它 入站 不受 影响. 使用了 UserStore 的 入站 即使 没有 配置 用户 也 须 鉴权.
+socks5 与 http 的 在 [`static@PLAINTEXT_USERS`] 中, trojan 的 在 `ruci::map::trojan::TROJAN_USERS` 中.
+*/
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+
+use super::{AsyncUserAuthenticator, PlainText, UserTrait, UsersMap};
+
+lazy_static! {
+    pub static ref PLAINTEXT_USERS: UserStores<PlainText> = UserStores::default();
+}
+
+/// 按 名称 区分 的 多个 [`UserStore`]
+#[derive(Debug)]
+pub struct UserStores<T: UserTrait + Clone>(RwLock<BTreeMap<String, Arc<UserStore<T>>>>);
+
+impl<T: UserTrait + Clone> Default for UserStores<T> {
+    fn default() -> Self {
+        Self(RwLock::new(BTreeMap::new()))
+    }
+}
+
+impl<T: UserTrait + Clone> UserStores<T> {
+    /// 由 入站 在 创建 时 调用, 不存在 时 创建
+    pub fn get_or_create(&self, name: &str) -> Arc<UserStore<T>> {
+        self.0.write().entry(name.to_string()).or_default().clone()
+    }
+
+    /// 没有 入站 使用 该名称 时 返回 None
+    pub fn get(&self, name: &str) -> Option<Arc<UserStore<T>>> {
+        self.0.read().get(name).cloned()
+    }
+
+    /// sorted
+    pub fn names(&self) -> Vec<String> {
+        self.0.read().keys().cloned().collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct UserStore<T: UserTrait + Clone>(RwLock<UsersMap<T>>);
+
+impl<T: UserTrait + Clone> Default for UserStore<T> {
+    fn default() -> Self {
+        Self(RwLock::new(UsersMap::new()))
+    }
+}
+
+impl<T: UserTrait + Clone> UserStore<T> {
+    /// 同 identity_str 的 用户 会被 替换
+    pub fn add(&self, u: T) {
+        let mut m = self.0.write();
+        m.remove_user(&u.identity_str());
+        m.add_user(u);
+    }
+
+    /// returns false if the user isn't presented
+    pub fn remove(&self, identity_str: &str) -> bool {
+        self.0.write().remove_user(identity_str)
+    }
+
+    /// identity_str of all users, sorted
+    pub fn list(&self) -> Vec<String> {
+        self.0.read().identities()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.read().is_empty()
+    }
+}
+
+impl<T: UserTrait + Clone> AsyncUserAuthenticator<T> for UserStore<T> {
+    fn auth_user_by_authstr(&self, authstr: &str) -> Option<T> {
+        self.0.read().auth_user_by_authstr(authstr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn add_remove() {
+        let s: UserStore<PlainText> = UserStore::default();
+        s.add(PlainText::new("u".into(), "p".into()));
+        s.add(PlainText::new("u2".into(), "p2".into()));
+        assert_eq!(s.list(), vec!["u".to_string(), "u2".to_string()]);
+        assert!(s.auth_user_by_authstr("plaintext:u\np").is_some());
+
+        s.add(PlainText::new("u".into(), "p3".into()));
+        assert_eq!(s.len(), 2);
+        assert!(s.auth_user_by_authstr("plaintext:u\np").is_none());
+        assert!(s.auth_user_by_authstr("plaintext:u\np3").is_some());
+
+        assert!(s.remove("u"));
+        assert!(!s.remove("u"));
+        assert!(s.auth_user_by_authstr("plaintext:u\np3").is_none());
+        assert_eq!(s.len(), 1);
+    }
+
+    #[test]
+    fn stores() {
+        let ss: UserStores<PlainText> = UserStores::default();
+        assert!(ss.get("a").is_none());
+
+        let a = ss.get_or_create("a");
+        let b = ss.get_or_create("b");
+        a.add(PlainText::new("u".into(), "p".into()));
+        assert!(ss
+            .get_or_create("a")
+            .auth_user_by_authstr("plaintext:u\np")
+            .is_some());
+        assert!(b.auth_user_by_authstr("plaintext:u\np").is_none());
+        assert_eq!(ss.names(), vec!["a".to_string(), "b".to_string()]);
+    }
+}